@@ -0,0 +1,279 @@
+//! Forced alignment of a known transcript to its recording.
+//!
+//! The audio is reduced to a level contour (30 ms windows, 10 ms apart).
+//! Each word is given a share of the speech frames in proportion to its
+//! estimated syllable count, which places every word boundary roughly; a
+//! dynamic program then moves each boundary within a window around that
+//! estimate to where the level dips, trading dip depth against how far
+//! word durations stray from their estimates. Words are finally trimmed
+//! to the speech inside them, so pauses fall between words.
+
+use crate::dsp::mixdown;
+use crate::project::WordTiming;
+
+const HOP_SECONDS: f64 = 0.01;
+const WINDOW_SECONDS: f64 = 0.03;
+/// Anything quieter is silence whatever the floor.
+const SILENCE_DBFS: f32 = -70.0;
+/// Level contrast below which the recording is treated as all speech.
+const MIN_CONTRAST_DB: f32 = 6.0;
+/// Speech is anything this far from the floor towards the speech level.
+const SPEECH_FRACTION: f32 = 0.3;
+/// How far a boundary may move from its estimate.
+const SEARCH_SECONDS: f64 = 1.5;
+const MIN_WORD_SECONDS: f64 = 0.05;
+/// Cost of a boundary at full speech level relative to a squared log
+/// duration ratio.
+const LEVEL_WEIGHT: f32 = 4.0;
+
+struct Contour {
+    /// Per-frame level, 0 at the floor and 1 at the speech level.
+    levels: Vec<f32>,
+    speech: Vec<bool>,
+    /// Floor to speech level, dB.
+    contrast: f32,
+}
+
+fn contour(sample_rate: u32, signal: &[f32]) -> Contour {
+    let hop = ((HOP_SECONDS * sample_rate as f64) as usize).max(1);
+    let half = ((WINDOW_SECONDS * sample_rate as f64) as usize / 2).max(1);
+    let frames = signal.len().div_ceil(hop);
+    let db: Vec<f32> = (0..frames)
+        .map(|frame| {
+            let centre = frame * hop;
+            let window = &signal[centre.saturating_sub(half)..(centre + half).min(signal.len())];
+            let mean_square = window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32;
+            10.0 * mean_square.max(1e-12).log10()
+        })
+        .collect();
+
+    let floor = percentile(&db, 0.1).max(SILENCE_DBFS);
+    let peak = percentile(&db, 0.95).max(floor);
+    let contrast = peak - floor;
+    if contrast < MIN_CONTRAST_DB {
+        return Contour {
+            levels: vec![1.0; frames],
+            speech: vec![true; frames],
+            contrast,
+        };
+    }
+    let levels: Vec<f32> = db
+        .iter()
+        .map(|level| ((level - floor) / contrast).clamp(0.0, 1.0))
+        .collect();
+    let speech = levels
+        .iter()
+        .map(|&level| level > SPEECH_FRACTION)
+        .collect();
+    Contour {
+        levels,
+        speech,
+        contrast,
+    }
+}
+
+fn percentile(values: &[f32], fraction: f32) -> f32 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    sorted[((sorted.len() - 1) as f32 * fraction) as usize]
+}
+
+/// Rough syllable count: vowel groups for Latin script, one per character
+/// for scripts without them.
+fn syllables(word: &str) -> f32 {
+    let mut groups = 0;
+    let mut in_vowel = false;
+    for c in word.chars().map(|c| c.to_ascii_lowercase()) {
+        let vowel = "aeiouy".contains(c);
+        if vowel && !in_vowel {
+            groups += 1;
+        }
+        in_vowel = vowel;
+    }
+    if word.chars().any(|c| c.is_ascii_alphabetic()) {
+        return groups.max(1) as f32;
+    }
+    // Digits get read out, and other scripts are roughly syllabic.
+    word.chars().filter(|c| c.is_alphanumeric()).count().max(1) as f32
+}
+
+/// Times the words of `transcript`, split on whitespace like the word
+/// timeline, against `channels`.
+pub fn align(sample_rate: u32, channels: &[Vec<f32>], transcript: &str) -> Vec<WordTiming> {
+    let words: Vec<&str> = transcript.split_whitespace().collect();
+    let signal = mixdown(channels);
+    if words.is_empty() || signal.is_empty() {
+        return words
+            .iter()
+            .map(|word| WordTiming {
+                text: word.to_string(),
+                start: 0.0,
+                end: 0.0,
+                confidence: Some(0.0),
+            })
+            .collect();
+    }
+    let contour = contour(sample_rate, &signal);
+    let frames = contour.levels.len();
+    let mut speech_frames: Vec<usize> = (0..frames).filter(|&f| contour.speech[f]).collect();
+    if speech_frames.is_empty() {
+        speech_frames = (0..frames).collect();
+    }
+
+    // Each word's share of the speech frames.
+    let weights: Vec<f32> = words.iter().map(|word| syllables(word)).collect();
+    let total: f32 = weights.iter().sum();
+    let expected: Vec<f32> = weights
+        .iter()
+        .map(|w| (w / total * speech_frames.len() as f32).max(1.0))
+        .collect();
+    let first = speech_frames[0];
+    let last = speech_frames[speech_frames.len() - 1] + 1;
+    let mut estimates = vec![first];
+    let mut cumulative = 0.0;
+    for weight in &weights[..words.len() - 1] {
+        cumulative += weight;
+        let index = (cumulative / total * speech_frames.len() as f32) as usize;
+        estimates.push(speech_frames[index.min(speech_frames.len() - 1)]);
+    }
+    estimates.push(last);
+
+    let boundaries =
+        search(&contour, &estimates, &expected).unwrap_or_else(|| spread(first, last, &weights));
+
+    let to_seconds =
+        |frame: usize| (frame as f64 * HOP_SECONDS).min(signal.len() as f64 / sample_rate as f64);
+    let contrast = (contour.contrast / 30.0).clamp(0.0, 1.0);
+    words
+        .iter()
+        .enumerate()
+        .map(|(index, word)| {
+            let span = boundaries[index]..boundaries[index + 1];
+            let voiced: Vec<usize> = span.clone().filter(|&f| contour.speech[f]).collect();
+            let (start, end) = match (voiced.first(), voiced.last()) {
+                (Some(&start), Some(&end)) => (start, end + 1),
+                _ => (span.start, span.end),
+            };
+            let fit = (span.len() as f32 / expected[index]).ln();
+            let coverage = voiced.len() as f32 / span.len().max(1) as f32;
+            WordTiming {
+                text: word.to_string(),
+                start: to_seconds(start),
+                end: to_seconds(end),
+                confidence: Some(coverage.max(0.2) * (-0.5 * fit * fit).exp() * contrast),
+            }
+        })
+        .collect()
+}
+
+/// Finds the boundaries minimizing level at the boundaries plus squared
+/// log duration error, each within the search window of its estimate.
+/// `None` when the words can't fit at their minimum length.
+fn search(contour: &Contour, estimates: &[usize], expected: &[f32]) -> Option<Vec<usize>> {
+    let reach = (SEARCH_SECONDS / HOP_SECONDS) as usize;
+    let min_frames = (MIN_WORD_SECONDS / HOP_SECONDS) as usize;
+    let (first, last) = (estimates[0], estimates[estimates.len() - 1]);
+    let words = expected.len();
+
+    // Candidate frames and their best cost and predecessor, per boundary.
+    let mut candidates: Vec<Vec<(usize, f32, usize)>> = vec![vec![(first, 0.0, 0)]];
+    for boundary in 1..=words {
+        let range = if boundary == words {
+            last..last + 1
+        } else {
+            let low = estimates[boundary]
+                .saturating_sub(reach)
+                .max(first + boundary * min_frames);
+            let high = (estimates[boundary] + reach)
+                .min(last.saturating_sub((words - boundary) * min_frames));
+            low..high + 1
+        };
+        let previous = &candidates[boundary - 1];
+        let mut layer = Vec::with_capacity(range.len());
+        for frame in range {
+            let level = if boundary == words {
+                0.0
+            } else {
+                contour.levels[frame] * LEVEL_WEIGHT
+            };
+            let best = previous
+                .iter()
+                .enumerate()
+                .filter(|(_, &(from, cost, _))| cost.is_finite() && frame >= from + min_frames)
+                .map(|(index, &(from, cost, _))| {
+                    let ratio = ((frame - from) as f32 / expected[boundary - 1]).ln();
+                    (cost + ratio * ratio + level, index)
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            if let Some((cost, from)) = best {
+                layer.push((frame, cost, from));
+            }
+        }
+        if layer.is_empty() {
+            return None;
+        }
+        candidates.push(layer);
+    }
+
+    let mut boundaries = vec![0; words + 1];
+    let mut index = 0;
+    for boundary in (0..=words).rev() {
+        let (frame, _, from) = candidates[boundary][index];
+        boundaries[boundary] = frame;
+        index = from;
+    }
+    Some(boundaries)
+}
+
+/// Boundaries in plain proportion to `weights`, for audio too short to
+/// search.
+fn spread(first: usize, last: usize, weights: &[f32]) -> Vec<usize> {
+    let total: f32 = weights.iter().sum();
+    let mut boundaries = vec![first];
+    let mut cumulative = 0.0;
+    for weight in weights {
+        cumulative += weight;
+        boundaries.push(first + ((last - first) as f32 * cumulative / total) as usize);
+    }
+    boundaries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_words_on_their_bursts_of_sound() {
+        let rate = 16000;
+        let burst = |seconds: f64, frequency: f32| {
+            (0..(seconds * rate as f64) as usize).map(move |i| {
+                (i as f32 * frequency / rate as f32 * std::f32::consts::TAU).sin() * 0.4
+            })
+        };
+        let pause = |seconds: f64| std::iter::repeat_n(0.0, (seconds * rate as f64) as usize);
+        // "Hello" runs long for two syllables and "big" short, so an even
+        // split by syllables would put both boundaries in the wrong place.
+        let signal: Vec<f32> = pause(0.4)
+            .chain(burst(0.7, 220.0))
+            .chain(pause(0.25))
+            .chain(burst(0.2, 300.0))
+            .chain(pause(0.3))
+            .chain(burst(0.6, 180.0))
+            .chain(pause(0.5))
+            .collect();
+
+        let words = align(rate, &[signal], "Hello big\n wonderful");
+        let spans: Vec<(f64, f64)> = words.iter().map(|w| (w.start, w.end)).collect();
+        let expected = [(0.4, 1.1), (1.35, 1.55), (1.85, 2.45)];
+        for ((start, end), (want_start, want_end)) in spans.iter().zip(expected) {
+            assert!(
+                (start - want_start).abs() < 0.04 && (end - want_end).abs() < 0.04,
+                "{spans:?}"
+            );
+        }
+        assert_eq!(words[2].text, "wonderful");
+        assert!(words.iter().all(|w| w.confidence.unwrap() > 0.3));
+
+        assert!(align(rate, &[vec![0.0; 1600]], "  ").is_empty());
+    }
+}
@@ -0,0 +1,471 @@
+//! Recognition of live capture.
+//!
+//! Frames are decoded on a worker fed through a [`FrameQueue`]. The
+//! [`LiveTranscriber`] there grows an utterance until a pause (or the
+//! model's 30 second window) ends it, decoding the latest few seconds every
+//! second for interim results and the whole utterance once for the final.
+
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::audio::buffer::AudioBuffer;
+use crate::audio::capture::{frame_queue, CaptureEngine, FrameQueue, SubscriptionId};
+
+use super::{
+    alternatives, model_input, AsrError, DecodeOptions, RecognitionConfig, RecognitionResult,
+    SpeechModel,
+};
+
+/// Seconds per energy measurement.
+const BLOCK: f64 = 0.03;
+/// Blocks quieter than this are silence.
+const SPEECH_THRESHOLD_DB: f32 = -45.0;
+/// Seconds of silence that end an utterance.
+const ENDPOINT: f64 = 0.8;
+/// Seconds of audio between interim decodes.
+const INTERIM_INTERVAL: f64 = 1.0;
+/// Seconds an interim decode covers at most. Past that, the text read so
+/// far is kept and later decodes start where the last one ended.
+const INTERIM_WINDOW: f64 = 5.0;
+/// Whisper sees at most 30 seconds at once; cut a little before that.
+const MAX_UTTERANCE: f64 = 28.0;
+/// Seconds of silence kept ahead of the first speech, so soft onsets
+/// aren't clipped.
+const PRE_ROLL: f64 = 0.3;
+
+pub enum LiveEvent {
+    Result(RecognitionResult),
+    Error(AsrError),
+    /// Recognition stopped; carries everything finalized.
+    End(String),
+}
+
+/// Splits mono audio into utterances and decodes them.
+pub struct LiveTranscriber {
+    model: Arc<dyn SpeechModel>,
+    options: DecodeOptions,
+    config: RecognitionConfig,
+    sample_rate: u32,
+    block: usize,
+    /// Audio not yet measured, shorter than a block.
+    pending: Vec<f32>,
+    utterance: Vec<f32>,
+    heard_speech: bool,
+    trailing_silence: usize,
+    since_decode: usize,
+    /// Where in the utterance the current interim window starts.
+    interim_start: usize,
+    /// Where the last interim decode ended, and what it read.
+    interim_end: usize,
+    interim_window: String,
+    /// Interim text of the windows before the current one.
+    interim_kept: String,
+    full: String,
+    confidence: f32,
+}
+
+impl LiveTranscriber {
+    pub fn new(
+        model: Arc<dyn SpeechModel>,
+        config: RecognitionConfig,
+        sample_rate: u32,
+    ) -> Result<Self, AsrError> {
+        let options = DecodeOptions::new(model.as_ref(), &config)?;
+        Ok(Self {
+            model,
+            options,
+            config,
+            sample_rate,
+            block: ((BLOCK * sample_rate as f64) as usize).max(1),
+            pending: Vec::new(),
+            utterance: Vec::new(),
+            heard_speech: false,
+            trailing_silence: 0,
+            since_decode: 0,
+            interim_start: 0,
+            interim_end: 0,
+            interim_window: String::new(),
+            interim_kept: String::new(),
+            full: String::new(),
+            confidence: 0.0,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Feeds mono audio at the transcriber's rate. Returns `false` once a
+    /// non-continuous session has its final result.
+    pub fn push(
+        &mut self,
+        samples: &[f32],
+        emit: &mut dyn FnMut(RecognitionResult),
+    ) -> Result<bool, AsrError> {
+        self.pending.extend_from_slice(samples);
+        let pending = std::mem::take(&mut self.pending);
+        let mut blocks = pending.chunks_exact(self.block);
+        for block in &mut blocks {
+            if !self.measure(block, emit)? {
+                return Ok(false);
+            }
+        }
+        self.pending = blocks.remainder().to_vec();
+        Ok(true)
+    }
+
+    /// Finalizes the utterance in progress, if any, because audio was lost
+    /// after it. Returns `false` as [`push`](Self::push) does.
+    pub fn gap(&mut self, emit: &mut dyn FnMut(RecognitionResult)) -> Result<bool, AsrError> {
+        self.pending.clear();
+        if !self.heard_speech {
+            self.utterance.clear();
+            return Ok(true);
+        }
+        let finalized = self.finalize(emit)?;
+        Ok(self.config.continuous || !finalized)
+    }
+
+    /// Finalizes the utterance in progress, if any.
+    pub fn finish(&mut self, emit: &mut dyn FnMut(RecognitionResult)) -> Result<(), AsrError> {
+        let pending = std::mem::take(&mut self.pending);
+        self.utterance.extend_from_slice(&pending);
+        if self.heard_speech {
+            self.finalize(emit)?;
+        }
+        Ok(())
+    }
+
+    fn measure(
+        &mut self,
+        block: &[f32],
+        emit: &mut dyn FnMut(RecognitionResult),
+    ) -> Result<bool, AsrError> {
+        let rms = (block.iter().map(|s| s * s).sum::<f32>() / block.len() as f32).sqrt();
+        let speech = 20.0 * rms.max(1e-10).log10() > SPEECH_THRESHOLD_DB;
+        self.utterance.extend_from_slice(block);
+        if speech {
+            self.heard_speech = true;
+            self.trailing_silence = 0;
+        } else {
+            self.trailing_silence += block.len();
+        }
+        if !self.heard_speech {
+            let keep = self.seconds(PRE_ROLL);
+            if self.utterance.len() > keep {
+                self.utterance.drain(..self.utterance.len() - keep);
+            }
+            return Ok(true);
+        }
+
+        self.since_decode += block.len();
+        if self.trailing_silence >= self.seconds(ENDPOINT)
+            || self.utterance.len() >= self.seconds(MAX_UTTERANCE)
+        {
+            let finalized = self.finalize(emit)?;
+            return Ok(self.config.continuous || !finalized);
+        }
+        if self.config.interim_results && self.since_decode >= self.seconds(INTERIM_INTERVAL) {
+            self.since_decode = 0;
+            self.interim(emit)?;
+        }
+        Ok(true)
+    }
+
+    /// Decodes the utterance as a final result and starts the next one.
+    /// Returns whether the model heard any words.
+    fn finalize(&mut self, emit: &mut dyn FnMut(RecognitionResult)) -> Result<bool, AsrError> {
+        let utterance = std::mem::take(&mut self.utterance);
+        self.heard_speech = false;
+        self.trailing_silence = 0;
+        self.since_decode = 0;
+        self.interim_start = 0;
+        self.interim_end = 0;
+        self.interim_window.clear();
+        self.interim_kept.clear();
+        let hypotheses = self.decode(utterance, &self.options)?;
+        let Some(best) = hypotheses.first() else {
+            return Ok(false);
+        };
+        let text = best.text.trim();
+        if text.is_empty() {
+            return Ok(false);
+        }
+        join(&mut self.full, text);
+        self.confidence = self.confidence.max(best.confidence);
+        emit(RecognitionResult {
+            final_text: text.to_string(),
+            interim: String::new(),
+            full: self.full.clone(),
+            confidence: self.confidence,
+            alternatives: alternatives(&hypotheses, self.options.alternatives),
+        });
+        Ok(true)
+    }
+
+    /// Decodes the current interim window, so each interim decode costs
+    /// at most [`INTERIM_WINDOW`] plus [`INTERIM_INTERVAL`] seconds.
+    fn interim(&mut self, emit: &mut dyn FnMut(RecognitionResult)) -> Result<(), AsrError> {
+        if self.utterance.len() - self.interim_start > self.seconds(INTERIM_WINDOW) {
+            join(
+                &mut self.interim_kept,
+                &std::mem::take(&mut self.interim_window),
+            );
+            self.interim_start = self.interim_end;
+        }
+        // Only the final carries alternatives.
+        let options = DecodeOptions {
+            alternatives: 1,
+            ..self.options.clone()
+        };
+        let hypotheses = self.decode(self.utterance[self.interim_start..].to_vec(), &options)?;
+        self.interim_end = self.utterance.len();
+        self.interim_window = hypotheses
+            .first()
+            .map(|best| best.text.trim().to_string())
+            .unwrap_or_default();
+        let mut interim = self.interim_kept.clone();
+        join(&mut interim, &self.interim_window);
+        if !interim.is_empty() {
+            emit(RecognitionResult {
+                final_text: String::new(),
+                interim,
+                full: self.full.clone(),
+                confidence: self.confidence,
+                alternatives: alternatives(&hypotheses, 1),
+            });
+        }
+        Ok(())
+    }
+
+    fn decode(
+        &self,
+        utterance: Vec<f32>,
+        options: &DecodeOptions,
+    ) -> Result<Vec<super::Hypothesis>, AsrError> {
+        let samples = model_input(&AudioBuffer::new(self.sample_rate, vec![utterance]));
+        self.model.transcribe(&samples, options)
+    }
+
+    fn seconds(&self, seconds: f64) -> usize {
+        (seconds * self.sample_rate as f64) as usize
+    }
+}
+
+/// Appends `text` to `to`, with a space between.
+fn join(to: &mut String, text: &str) {
+    if !to.is_empty() && !text.is_empty() {
+        to.push(' ');
+    }
+    to.push_str(text);
+}
+
+/// A running recognition of the capture engine's frames.
+pub struct LiveSession {
+    subscription: SubscriptionId,
+    worker: JoinHandle<()>,
+}
+
+impl LiveSession {
+    pub fn start(
+        engine: &CaptureEngine,
+        model: Arc<dyn SpeechModel>,
+        config: RecognitionConfig,
+        mut emit: impl FnMut(LiveEvent) + Send + 'static,
+    ) -> Result<Self, AsrError> {
+        // Check the language before anything starts.
+        DecodeOptions::new(model.as_ref(), &config)?;
+        let (sender, frames) = frame_queue();
+        let worker = thread::Builder::new()
+            .name("speech-recognition".into())
+            .spawn(move || {
+                let mut full = String::new();
+                let mut emit_result = |result| emit(LiveEvent::Result(result));
+                if let Err(e) = run(frames, model, config, &mut full, &mut emit_result) {
+                    emit(LiveEvent::Error(e));
+                }
+                emit(LiveEvent::End(full));
+            })
+            .map_err(|e| AsrError::Engine(e.to_string()))?;
+        let subscription = engine.forward(sender);
+        Ok(Self {
+            subscription,
+            worker,
+        })
+    }
+
+    /// Whether the worker ended on its own, after a non-continuous
+    /// session's final result or an error.
+    pub fn finished(&self) -> bool {
+        self.worker.is_finished()
+    }
+
+    /// Stops feeding the worker. It finalizes what it has and emits the
+    /// last result and [`LiveEvent::End`] in the background.
+    pub fn stop(self, engine: &CaptureEngine) {
+        engine.unsubscribe(self.subscription);
+    }
+}
+
+fn run(
+    frames: FrameQueue,
+    model: Arc<dyn SpeechModel>,
+    config: RecognitionConfig,
+    full: &mut String,
+    emit: &mut dyn FnMut(RecognitionResult),
+) -> Result<(), AsrError> {
+    let mut transcriber: Option<LiveTranscriber> = None;
+    while let Some(frame) = frames.recv() {
+        let transcriber = match &mut transcriber {
+            Some(transcriber) => transcriber,
+            None => transcriber.insert(LiveTranscriber::new(
+                Arc::clone(&model),
+                config.clone(),
+                frame.sample_rate,
+            )?),
+        };
+        // Audio on either side of dropped frames isn't one utterance.
+        if frames.overrun() && !transcriber.gap(emit)? {
+            full.clone_from(&transcriber.full);
+            return Ok(());
+        }
+        let mono = frame.mono(transcriber.sample_rate());
+        let listening = transcriber.push(&mono, emit)?;
+        full.clone_from(&transcriber.full);
+        if !listening {
+            return Ok(());
+        }
+    }
+    if let Some(transcriber) = &mut transcriber {
+        transcriber.finish(emit)?;
+        full.clone_from(&transcriber.full);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asr::tests::FakeModel;
+    use crate::asr::Hypothesis;
+
+    fn tone(seconds: f64) -> Vec<f32> {
+        (0..(seconds * 16000.0) as usize)
+            .map(|i| (i as f32 * 0.1).sin() * 0.3)
+            .collect()
+    }
+
+    #[test]
+    fn emits_interim_results_then_a_final_one_per_utterance() {
+        let model = Arc::new(FakeModel {
+            words: "hello",
+            english_only: false,
+        });
+        let config = RecognitionConfig {
+            continuous: true,
+            ..Default::default()
+        };
+        let mut transcriber = LiveTranscriber::new(model.clone(), config, 16000).unwrap();
+        let mut results = Vec::new();
+        let mut emit = |result| results.push(result);
+        let silence = vec![0.0; 16000];
+        for chunk in [&silence[..], &tone(2.5), &silence, &tone(0.5)] {
+            // Feed in capture-sized frames.
+            for frame in chunk.chunks(4096) {
+                assert!(transcriber.push(frame, &mut emit).unwrap());
+            }
+        }
+        transcriber.finish(&mut emit).unwrap();
+
+        let finals: Vec<_> = results
+            .iter()
+            .filter(|r| !r.final_text.is_empty())
+            .collect();
+        assert_eq!(finals.len(), 2);
+        assert_eq!(finals[1].full, "hello hello");
+        assert_eq!(finals[0].alternatives.len(), 2);
+        assert_eq!(finals[0].confidence, 0.9);
+        // One a second through the first utterance and its closing pause.
+        let interims = results.iter().filter(|r| !r.interim.is_empty()).count();
+        assert_eq!(interims, 3);
+
+        // Without `continuous`, the first final result ends the session.
+        let mut once = LiveTranscriber::new(model, RecognitionConfig::default(), 16000).unwrap();
+        let mut finals = 0;
+        let mut emit = |result: RecognitionResult| finals += !result.final_text.is_empty() as usize;
+        assert!(once.push(&tone(1.0), &mut emit).unwrap());
+        assert!(!once.push(&silence, &mut emit).unwrap());
+        assert_eq!(finals, 1);
+    }
+
+    /// Notes the longest audio it's asked to read with one alternative.
+    struct Measured {
+        inner: FakeModel,
+        longest_interim: std::sync::Mutex<usize>,
+    }
+
+    impl SpeechModel for Measured {
+        fn english_only(&self) -> bool {
+            false
+        }
+
+        fn transcribe(
+            &self,
+            samples: &[f32],
+            options: &DecodeOptions,
+        ) -> Result<Vec<Hypothesis>, AsrError> {
+            if options.alternatives == 1 {
+                let mut longest = self.longest_interim.lock().unwrap();
+                *longest = (*longest).max(samples.len());
+            }
+            self.inner.transcribe(samples, options)
+        }
+    }
+
+    #[test]
+    fn interim_decodes_cover_a_bounded_window() {
+        let model = Arc::new(Measured {
+            inner: FakeModel {
+                words: "hello",
+                english_only: false,
+            },
+            longest_interim: Default::default(),
+        });
+        let mut transcriber =
+            LiveTranscriber::new(model.clone(), RecognitionConfig::default(), 16000).unwrap();
+        let mut interims = Vec::new();
+        let mut emit = |result: RecognitionResult| interims.push(result.interim);
+        for frame in tone(20.0).chunks(4096) {
+            assert!(transcriber.push(frame, &mut emit).unwrap());
+        }
+
+        let longest = *model.longest_interim.lock().unwrap() as f64 / 16000.0;
+        assert!(
+            longest <= INTERIM_WINDOW + INTERIM_INTERVAL + 0.1,
+            "{longest} s"
+        );
+        // Earlier windows' text is kept ahead of the current one's.
+        assert_eq!(interims.first().map(String::as_str), Some("hello"));
+        assert!(interims.last().unwrap().starts_with("hello hello hello"));
+    }
+
+    #[test]
+    fn a_gap_ends_the_utterance() {
+        let model = Arc::new(FakeModel {
+            words: "hello",
+            english_only: false,
+        });
+        let config = RecognitionConfig {
+            continuous: true,
+            ..Default::default()
+        };
+        let mut transcriber = LiveTranscriber::new(model, config, 16000).unwrap();
+        let mut finals = Vec::new();
+        let mut emit = |result: RecognitionResult| finals.push(result.final_text);
+        assert!(transcriber.push(&tone(1.0), &mut emit).unwrap());
+        assert!(transcriber.gap(&mut emit).unwrap());
+        assert!(transcriber.push(&tone(1.0), &mut emit).unwrap());
+        transcriber.finish(&mut emit).unwrap();
+        assert_eq!(finals, ["hello", "hello"]);
+    }
+}
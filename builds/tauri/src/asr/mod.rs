@@ -0,0 +1,461 @@
+//! Offline speech recognition.
+//!
+//! A [`SpeechModel`] turns 16 kHz mono audio into ranked hypotheses. Models
+//! are GGML whisper files dropped into the models directory; the
+//! [`Recognizer`] lists them, loads one on first use and keeps it until a
+//! different one is asked for. Project buffers are transcribed in one pass
+//! with [`transcribe`]; live capture goes through [`live`]. [`align`] times
+//! a known transcript against its recording without a model.
+
+pub mod align;
+pub mod live;
+#[cfg(feature = "whisper")]
+mod whisper;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::audio::buffer::AudioBuffer;
+use crate::audio::capture::CaptureEngine;
+use crate::audio::resample::resample;
+use crate::audio::AudioError;
+use crate::dsp::mixdown;
+
+use self::live::{LiveEvent, LiveSession};
+
+/// The rate every model consumes.
+pub const SAMPLE_RATE: u32 = 16000;
+
+const MODEL_EXTENSION: &str = "bin";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AsrError {
+    #[error("no speech models installed; add a whisper model to {0}")]
+    NoModels(PathBuf),
+    #[error("speech model not found: {0}")]
+    ModelNotFound(String),
+    #[error("this model only recognizes English, not {0:?}")]
+    UnsupportedLanguage(String),
+    #[error("this build was made without speech recognition")]
+    Unavailable,
+    #[error("speech recognition is already running")]
+    AlreadyRunning,
+    #[error("speech recognition failed: {0}")]
+    Engine(String),
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+}
+
+impl AsrError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AsrError::NoModels(_) => "noSpeechModels",
+            AsrError::ModelNotFound(_) => "speechModelNotFound",
+            AsrError::UnsupportedLanguage(_) => "unsupportedLanguage",
+            AsrError::Unavailable => "recognitionUnavailable",
+            AsrError::AlreadyRunning => "recognitionRunning",
+            AsrError::Engine(_) => "recognitionFailed",
+            AsrError::Io { .. } => "io",
+            AsrError::Audio(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for AsrError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// Mirrors the parts of the `voice.recognition` section of
+/// `config/default.json` that shape decoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RecognitionConfig {
+    /// BCP-47 tag such as `en-US`; empty or `auto` detects the language.
+    pub language: String,
+    /// Keep listening after the first final result.
+    pub continuous: bool,
+    pub interim_results: bool,
+    pub max_alternatives: usize,
+}
+
+impl Default for RecognitionConfig {
+    fn default() -> Self {
+        Self {
+            language: "en-US".into(),
+            continuous: false,
+            interim_results: true,
+            max_alternatives: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alternative {
+    pub transcript: String,
+    pub confidence: f32,
+}
+
+/// Same shape as the payload of `VoiceRecognizer.onResult`, plus the
+/// ranked alternatives for the latest utterance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecognitionResult {
+    /// Text finalized by this result; empty for interim results.
+    #[serde(rename = "final")]
+    pub final_text: String,
+    pub interim: String,
+    /// Everything finalized so far.
+    pub full: String,
+    /// Highest confidence of any final result so far, 0 to 1.
+    pub confidence: f32,
+    pub alternatives: Vec<Alternative>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    pub text: String,
+    /// Seconds.
+    pub start: f64,
+    pub end: f64,
+    pub confidence: f32,
+}
+
+/// One reading of an utterance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hypothesis {
+    pub text: String,
+    /// Mean token probability, 0 to 1.
+    pub confidence: f32,
+    pub segments: Vec<Segment>,
+}
+
+/// What a model is asked to do with an utterance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// ISO 639-1 code; `None` detects the language.
+    pub language: Option<String>,
+    /// How many hypotheses to return at most; never less than one.
+    pub alternatives: usize,
+}
+
+impl DecodeOptions {
+    pub fn new(model: &dyn SpeechModel, config: &RecognitionConfig) -> Result<Self, AsrError> {
+        let language = language_code(&config.language);
+        if model.english_only() && language.as_deref().is_some_and(|code| code != "en") {
+            return Err(AsrError::UnsupportedLanguage(config.language.clone()));
+        }
+        Ok(Self {
+            language,
+            alternatives: config.max_alternatives.max(1),
+        })
+    }
+}
+
+pub trait SpeechModel: Send + Sync {
+    fn english_only(&self) -> bool;
+
+    /// Decodes `samples`, 16 kHz mono, into at most `options.alternatives`
+    /// distinct hypotheses, best first. Silence yields none.
+    fn transcribe(
+        &self,
+        samples: &[f32],
+        options: &DecodeOptions,
+    ) -> Result<Vec<Hypothesis>, AsrError>;
+}
+
+/// Reduces a BCP-47 tag to the language code models are keyed by.
+pub fn language_code(tag: &str) -> Option<String> {
+    let primary = tag.trim().split(['-', '_']).next().unwrap_or_default();
+    if primary.is_empty() || primary.eq_ignore_ascii_case("auto") {
+        return None;
+    }
+    Some(primary.to_ascii_lowercase())
+}
+
+/// A recognition of a whole buffer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transcript {
+    pub text: String,
+    pub confidence: f32,
+    /// Best first, including the chosen transcript.
+    pub alternatives: Vec<Alternative>,
+    pub segments: Vec<Segment>,
+}
+
+/// Mixes `buffer` down and resamples it to what models consume.
+pub fn model_input(buffer: &AudioBuffer) -> Vec<f32> {
+    let mono = AudioBuffer::new(buffer.sample_rate, vec![mixdown(&buffer.channels)]);
+    resample(&mono, SAMPLE_RATE)
+        .channels
+        .pop()
+        .unwrap_or_default()
+}
+
+/// Transcribes `buffer` in one pass.
+pub fn transcribe(
+    model: &dyn SpeechModel,
+    buffer: &AudioBuffer,
+    config: &RecognitionConfig,
+) -> Result<Transcript, AsrError> {
+    let options = DecodeOptions::new(model, config)?;
+    let hypotheses = model.transcribe(&model_input(buffer), &options)?;
+    let Some(best) = hypotheses.first() else {
+        return Ok(Transcript {
+            text: String::new(),
+            confidence: 0.0,
+            alternatives: Vec::new(),
+            segments: Vec::new(),
+        });
+    };
+    Ok(Transcript {
+        text: best.text.clone(),
+        confidence: best.confidence,
+        alternatives: alternatives(&hypotheses, options.alternatives),
+        segments: best.segments.clone(),
+    })
+}
+
+fn alternatives(hypotheses: &[Hypothesis], limit: usize) -> Vec<Alternative> {
+    hypotheses
+        .iter()
+        .take(limit)
+        .map(|hypothesis| Alternative {
+            transcript: hypothesis.text.clone(),
+            confidence: hypothesis.confidence,
+        })
+        .collect()
+}
+
+/// A model file in the models directory.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    /// File name without the extension, e.g. `ggml-base.en`.
+    pub id: String,
+    pub size: u64,
+    pub english_only: bool,
+}
+
+type LoadedModel = (String, Arc<dyn SpeechModel>);
+
+/// The models directory, the loaded model and the live session. Clones
+/// share all three, so commands can move one onto the blocking pool.
+#[derive(Clone)]
+pub struct Recognizer {
+    models_dir: PathBuf,
+    loaded: Arc<Mutex<Option<LoadedModel>>>,
+    live: Arc<Mutex<Option<LiveSession>>>,
+}
+
+impl Recognizer {
+    pub fn new(models_dir: PathBuf) -> Self {
+        Self {
+            models_dir,
+            loaded: Arc::new(Mutex::new(None)),
+            live: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Installed models, by id. A missing directory just means none.
+    pub fn models(&self) -> Result<Vec<ModelInfo>, AsrError> {
+        let io_error = |source| AsrError::Io {
+            path: self.models_dir.clone(),
+            source,
+        };
+        let entries = match fs::read_dir(&self.models_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io_error(e)),
+        };
+        let mut models = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(io_error)?;
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(MODEL_EXTENSION) {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let metadata = entry.metadata().map_err(io_error)?;
+            if metadata.is_file() {
+                models.push(ModelInfo {
+                    id: id.to_string(),
+                    size: metadata.len(),
+                    english_only: is_english_only(id),
+                });
+            }
+        }
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(models)
+    }
+
+    /// The model `id`, loading it if it isn't the one already loaded. With
+    /// no id, the loaded model or else the first installed one.
+    pub fn model(&self, id: Option<&str>) -> Result<Arc<dyn SpeechModel>, AsrError> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((loaded_id, model)) = loaded.as_ref() {
+            if id.is_none_or(|id| id == loaded_id) {
+                return Ok(Arc::clone(model));
+            }
+        }
+        let models = self.models()?;
+        let info = match id {
+            Some(id) => models
+                .into_iter()
+                .find(|model| model.id == id)
+                .ok_or_else(|| AsrError::ModelNotFound(id.to_string()))?,
+            None => models
+                .into_iter()
+                .next()
+                .ok_or_else(|| AsrError::NoModels(self.models_dir.clone()))?,
+        };
+        let path = self
+            .models_dir
+            .join(format!("{}.{MODEL_EXTENSION}", info.id));
+        // Release the previous model before loading the next; both can be
+        // hundreds of megabytes.
+        *loaded = None;
+        let model = load(&path, info.english_only)?;
+        *loaded = Some((info.id, Arc::clone(&model)));
+        Ok(model)
+    }
+
+    /// Starts transcribing `engine`'s frames with `model`. Capture itself
+    /// is opened and started separately.
+    pub fn start_live(
+        &self,
+        engine: &CaptureEngine,
+        model: Arc<dyn SpeechModel>,
+        config: RecognitionConfig,
+        emit: impl FnMut(LiveEvent) + Send + 'static,
+    ) -> Result<(), AsrError> {
+        let mut live = self.live.lock().unwrap_or_else(|e| e.into_inner());
+        if live.as_ref().is_some_and(|session| !session.finished()) {
+            return Err(AsrError::AlreadyRunning);
+        }
+        if let Some(session) = live.take() {
+            session.stop(engine);
+        }
+        *live = Some(LiveSession::start(engine, model, config, emit)?);
+        Ok(())
+    }
+
+    /// Stops listening and finalizes whatever was heard. Stopping when
+    /// nothing is running is a no-op.
+    pub fn stop_live(&self, engine: &CaptureEngine) {
+        let session = self.live.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(session) = session {
+            session.stop(engine);
+        }
+    }
+}
+
+/// Whisper's English-only checkpoints are suffixed `.en`.
+fn is_english_only(id: &str) -> bool {
+    id.ends_with(".en")
+}
+
+#[cfg(feature = "whisper")]
+fn load(path: &Path, english_only: bool) -> Result<Arc<dyn SpeechModel>, AsrError> {
+    Ok(Arc::new(whisper::WhisperModel::load(path, english_only)?))
+}
+
+#[cfg(not(feature = "whisper"))]
+fn load(_path: &Path, _english_only: bool) -> Result<Arc<dyn SpeechModel>, AsrError> {
+    Err(AsrError::Unavailable)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Hears anything louder than -40 dBFS as `words`, with a second,
+    /// less likely reading.
+    pub(crate) struct FakeModel {
+        pub words: &'static str,
+        pub english_only: bool,
+    }
+
+    impl SpeechModel for FakeModel {
+        fn english_only(&self) -> bool {
+            self.english_only
+        }
+
+        fn transcribe(
+            &self,
+            samples: &[f32],
+            options: &DecodeOptions,
+        ) -> Result<Vec<Hypothesis>, AsrError> {
+            if samples.iter().all(|s| s.abs() < 0.01) {
+                return Ok(Vec::new());
+            }
+            let duration = samples.len() as f64 / SAMPLE_RATE as f64;
+            let readings = [
+                (self.words.to_string(), 0.9),
+                (self.words.to_uppercase(), 0.4),
+            ];
+            Ok(readings
+                .into_iter()
+                .take(options.alternatives)
+                .map(|(text, confidence)| Hypothesis {
+                    segments: vec![Segment {
+                        text: text.clone(),
+                        start: 0.0,
+                        end: duration,
+                        confidence,
+                    }],
+                    text,
+                    confidence,
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn transcribes_buffers_in_the_configured_language() {
+        assert_eq!(language_code("en-US").as_deref(), Some("en"));
+        assert_eq!(language_code("pt_BR").as_deref(), Some("pt"));
+        assert_eq!(language_code("auto"), None);
+
+        let model = FakeModel {
+            words: "hello there",
+            english_only: true,
+        };
+        // Two seconds of stereo 44.1 kHz tone comes out as two seconds.
+        let tone: Vec<f32> = (0..88200).map(|i| (i as f32 * 0.06).sin() * 0.5).collect();
+        let buffer = AudioBuffer::new(44100, vec![tone.clone(), tone]);
+        let config = RecognitionConfig {
+            max_alternatives: 1,
+            ..Default::default()
+        };
+        let transcript = transcribe(&model, &buffer, &config).unwrap();
+        assert_eq!(transcript.text, "hello there");
+        assert_eq!(transcript.alternatives.len(), 1);
+        assert!((transcript.segments[0].end - 2.0).abs() < 1e-3);
+
+        let french = RecognitionConfig {
+            language: "fr-FR".into(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            transcribe(&model, &buffer, &french),
+            Err(AsrError::UnsupportedLanguage(_))
+        ));
+    }
+}
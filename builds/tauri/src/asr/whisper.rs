@@ -0,0 +1,361 @@
+//! Whisper models through whisper.cpp.
+//!
+//! whisper.cpp doesn't hand back its beams, so alternatives come from the
+//! same decode as the best hypothesis: a logits filter records the likeliest
+//! text tokens at every step, and each alternative swaps one token of the
+//! best hypothesis for a runner-up at that step.
+
+use std::collections::HashMap;
+use std::ffi::{c_int, c_void};
+use std::path::Path;
+use std::sync::Mutex;
+
+use whisper_rs::whisper_rs_sys::{self, whisper_context, whisper_state, whisper_token_data};
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+    WhisperToken,
+};
+
+use super::{AsrError, DecodeOptions, Hypothesis, Segment, SpeechModel};
+
+const BEAM_SIZE: i32 = 5;
+/// Text tokens recorded per decoding step, the chosen one included.
+const CANDIDATES: usize = 4;
+/// How far below the chosen token, in log probability, a runner-up may be
+/// and still make an alternative.
+const MAX_LOSS: f32 = 5.0;
+
+pub struct WhisperModel {
+    context: WhisperContext,
+    english_only: bool,
+}
+
+fn engine(error: impl std::fmt::Display) -> AsrError {
+    AsrError::Engine(error.to_string())
+}
+
+/// A text token and its log probability.
+type Candidate = (WhisperToken, f32);
+
+/// What the logits filter saw during one decode.
+struct Steps {
+    n_vocab: usize,
+    eot: WhisperToken,
+    /// How many segments there were as each 30 s window started, so
+    /// segment `i` comes from the last window starting at or before it.
+    windows: Vec<usize>,
+    /// The likeliest text tokens at each step, with log probabilities,
+    /// keyed by window and the text decoded before the step. Beams that
+    /// agree on the text so far add several entries under one key.
+    candidates: HashMap<(usize, Vec<WhisperToken>), Vec<Vec<Candidate>>>,
+}
+
+impl Steps {
+    fn window_of(&self, segment: usize) -> usize {
+        self.windows
+            .partition_point(|&start| start <= segment)
+            .saturating_sub(1)
+    }
+}
+
+/// The logits filter: records the step's candidates in the `Steps` that
+/// `user_data` points to. Beams are filtered from several threads.
+unsafe extern "C" fn record_step(
+    _: *mut whisper_context,
+    state: *mut whisper_state,
+    tokens: *const whisper_token_data,
+    n_tokens: c_int,
+    logits: *mut f32,
+    user_data: *mut c_void,
+) {
+    // SAFETY: `decode` points `user_data` at a `Mutex<Steps>` that outlives
+    // the decode, and whisper.cpp passes `n_tokens` tokens and a logit per
+    // vocabulary entry.
+    let steps = unsafe { &*(user_data as *const Mutex<Steps>) };
+    let mut steps = steps.lock().unwrap_or_else(|e| e.into_inner());
+    let tokens = match n_tokens {
+        0 => &[][..],
+        n => unsafe { std::slice::from_raw_parts(tokens, n as usize) },
+    };
+    let logits = unsafe { std::slice::from_raw_parts(logits, steps.n_vocab) };
+    if tokens.is_empty() {
+        // A window's first step runs once, on the decoding thread, before
+        // the beams split.
+        let segments = unsafe { whisper_rs_sys::whisper_full_n_segments_from_state(state) };
+        steps.windows.push(segments.max(0) as usize);
+    }
+    let eot = steps.eot;
+    let window = steps.windows.len().saturating_sub(1);
+    let text = tokens.iter().map(|t| t.id).filter(|&id| id < eot).collect();
+    let top = top_text_tokens(logits, eot);
+    steps
+        .candidates
+        .entry((window, text))
+        .or_default()
+        .push(top);
+}
+
+/// The likeliest text tokens under `logits`, with their log probabilities
+/// over the whole vocabulary.
+fn top_text_tokens(logits: &[f32], eot: WhisperToken) -> Vec<Candidate> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let log_sum = max + logits.iter().map(|&l| (l - max).exp()).sum::<f32>().ln();
+    let mut top: Vec<Candidate> = Vec::with_capacity(CANDIDATES + 1);
+    for (id, &logit) in logits[..eot as usize].iter().enumerate() {
+        if top.len() == CANDIDATES && logit <= top[CANDIDATES - 1].1 {
+            continue;
+        }
+        let at = top.partition_point(|&(_, l)| l >= logit);
+        top.insert(at, (id as WhisperToken, logit));
+        top.truncate(CANDIDATES);
+    }
+    top.into_iter()
+        .map(|(id, logit)| (id, logit - log_sum))
+        .filter(|(_, logprob)| logprob.is_finite())
+        .collect()
+}
+
+/// The text tokens of one whisper segment, with their probabilities.
+struct DecodedSegment {
+    tokens: Vec<(WhisperToken, f32)>,
+    /// Where the segment is in the hypothesis; segments without text
+    /// aren't there.
+    index: Option<usize>,
+}
+
+/// One token of the best hypothesis and a runner-up for it.
+struct Swap {
+    segment: usize,
+    token: usize,
+    replacement: WhisperToken,
+    probability: f32,
+    loss: f32,
+}
+
+impl WhisperModel {
+    pub fn load(path: &Path, english_only: bool) -> Result<Self, AsrError> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| AsrError::Engine(format!("{}: path is not UTF-8", path.display())))?;
+        let context = WhisperContext::new_with_params(path, WhisperContextParameters::default())
+            .map_err(engine)?;
+        Ok(Self {
+            context,
+            english_only,
+        })
+    }
+
+    /// One beam search decode, and the steps it went through.
+    fn decode(
+        &self,
+        samples: &[f32],
+        language: Option<&str>,
+    ) -> Result<(WhisperState, Steps), AsrError> {
+        let mut params = FullParams::new(SamplingStrategy::BeamSearch {
+            beam_size: BEAM_SIZE,
+            patience: -1.0,
+        });
+        params.set_language(Some(language.unwrap_or("auto")));
+        params.set_temperature(0.0);
+        // Falling back to sampling would decode windows again.
+        params.set_temperature_inc(0.0);
+        params.set_n_threads(threads());
+        params.set_suppress_blank(true);
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+
+        let steps = Mutex::new(Steps {
+            n_vocab: self.context.n_vocab() as usize,
+            eot: self.context.token_eot(),
+            windows: Vec::new(),
+            candidates: HashMap::new(),
+        });
+        // SAFETY: `record_step` only reads through its pointers, and
+        // `steps` outlives `full`, the only time the filter runs.
+        unsafe {
+            params.set_filter_logits_callback(Some(record_step));
+            params
+                .set_filter_logits_callback_user_data(&steps as *const Mutex<Steps> as *mut c_void);
+        }
+        let mut state = self.context.create_state().map_err(engine)?;
+        state.full(params, samples).map_err(engine)?;
+        let steps = steps.into_inner().unwrap_or_else(|e| e.into_inner());
+        Ok((state, steps))
+    }
+
+    fn hypothesis(
+        &self,
+        state: &WhisperState,
+    ) -> Result<(Hypothesis, Vec<DecodedSegment>), AsrError> {
+        let eot = self.context.token_eot();
+        let mut segments = Vec::new();
+        let mut decoded = Vec::new();
+        let (mut probability, mut tokens) = (0.0f32, 0usize);
+        for i in 0..state.full_n_segments().map_err(engine)? {
+            let text = state.full_get_segment_text(i).map_err(engine)?;
+            let mut segment_tokens = Vec::new();
+            for j in 0..state.full_n_tokens(i).map_err(engine)? {
+                // Timestamps and other special tokens sort after end-of-text.
+                let id = state.full_get_token_id(i, j).map_err(engine)?;
+                if id >= eot {
+                    continue;
+                }
+                segment_tokens.push((id, state.full_get_token_prob(i, j).map_err(engine)?));
+            }
+            let segment_probability: f32 = segment_tokens.iter().map(|(_, p)| p).sum();
+            let count = segment_tokens.len();
+            probability += segment_probability;
+            tokens += count;
+            let text = text.trim();
+            let index = (!text.is_empty()).then_some(segments.len());
+            decoded.push(DecodedSegment {
+                tokens: segment_tokens,
+                index,
+            });
+            if index.is_none() {
+                continue;
+            }
+            segments.push(Segment {
+                text: text.to_string(),
+                // Whisper counts in centiseconds.
+                start: state.full_get_segment_t0(i).map_err(engine)? as f64 / 100.0,
+                end: state.full_get_segment_t1(i).map_err(engine)? as f64 / 100.0,
+                confidence: segment_probability / count.max(1) as f32,
+            });
+        }
+        let hypothesis = Hypothesis {
+            text: joined(&segments),
+            confidence: probability / tokens.max(1) as f32,
+            segments,
+        };
+        Ok((hypothesis, decoded))
+    }
+
+    /// Runners-up to the tokens of `decoded`, closest first.
+    fn swaps(&self, decoded: &[DecodedSegment], steps: &Steps) -> Vec<Swap> {
+        let mut swaps = Vec::new();
+        let mut text = Vec::new();
+        let mut current = None;
+        for (i, segment) in decoded.iter().enumerate() {
+            let window = steps.window_of(i);
+            if current.replace(window) != Some(window) {
+                text.clear();
+            }
+            for (j, &(id, _)) in segment.tokens.iter().enumerate() {
+                let key = (window, text.clone());
+                // Of the beams that got here, the one likeliest to pick
+                // what was picked.
+                let step = steps.candidates.get(&key).and_then(|entries| {
+                    entries
+                        .iter()
+                        .filter_map(|top| {
+                            let (_, chosen) = top.iter().find(|(token, _)| *token == id)?;
+                            Some((top, *chosen))
+                        })
+                        .max_by(|a, b| a.1.total_cmp(&b.1))
+                });
+                if let Some((top, chosen)) = step {
+                    swaps.extend(
+                        top.iter()
+                            .filter(|&&(token, logprob)| {
+                                token != id && chosen - logprob <= MAX_LOSS
+                            })
+                            .map(|&(replacement, logprob)| Swap {
+                                segment: i,
+                                token: j,
+                                replacement,
+                                probability: logprob.exp(),
+                                loss: chosen - logprob,
+                            }),
+                    );
+                }
+                text.push(id);
+            }
+        }
+        swaps.sort_by(|a, b| a.loss.total_cmp(&b.loss));
+        swaps
+    }
+
+    /// `best` with `swap` made.
+    fn swapped(
+        &self,
+        best: &Hypothesis,
+        decoded: &[DecodedSegment],
+        swap: &Swap,
+    ) -> Option<Hypothesis> {
+        let segment = &decoded[swap.segment];
+        let index = segment.index?;
+        let mut bytes = Vec::new();
+        for (j, &(id, _)) in segment.tokens.iter().enumerate() {
+            let id = if j == swap.token {
+                swap.replacement
+            } else {
+                id
+            };
+            bytes.extend_from_slice(self.context.token_to_cstr(id).ok()?.to_bytes());
+        }
+        let text = String::from_utf8_lossy(&bytes).trim().to_string();
+        if text.is_empty() {
+            return None;
+        }
+        let change = swap.probability - segment.tokens[swap.token].1;
+        let total: usize = decoded.iter().map(|segment| segment.tokens.len()).sum();
+        let mut segments = best.segments.clone();
+        segments[index].text = text;
+        segments[index].confidence += change / segment.tokens.len() as f32;
+        Some(Hypothesis {
+            text: joined(&segments),
+            confidence: best.confidence + change / total as f32,
+            segments,
+        })
+    }
+}
+
+fn joined(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl SpeechModel for WhisperModel {
+    fn english_only(&self) -> bool {
+        self.english_only
+    }
+
+    fn transcribe(
+        &self,
+        samples: &[f32],
+        options: &DecodeOptions,
+    ) -> Result<Vec<Hypothesis>, AsrError> {
+        let (state, steps) = self.decode(samples, options.language.as_deref())?;
+        let (best, decoded) = self.hypothesis(&state)?;
+        if best.text.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut hypotheses = vec![best];
+        for swap in self.swaps(&decoded, &steps) {
+            if hypotheses.len() >= options.alternatives {
+                break;
+            }
+            let Some(candidate) = self.swapped(&hypotheses[0], &decoded, &swap) else {
+                continue;
+            };
+            let repeat = hypotheses
+                .iter()
+                .any(|h| h.text.eq_ignore_ascii_case(&candidate.text));
+            if !repeat {
+                hypotheses.push(candidate);
+            }
+        }
+        hypotheses[1..].sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        Ok(hypotheses)
+    }
+}
+
+fn threads() -> i32 {
+    std::thread::available_parallelism().map_or(4, |n| n.get().min(8) as i32)
+}
@@ -0,0 +1,140 @@
+//! In-memory audio owned by the backend.
+//!
+//! The frontend never holds sample data for project audio; it refers to
+//! buffers by id and asks the backend to decode, process or encode them.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use super::AudioError;
+
+pub type BufferId = u64;
+
+/// Planar floating-point audio: one `Vec` per channel, all the same length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioBuffer {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl AudioBuffer {
+    pub fn new(sample_rate: u32, channels: Vec<Vec<f32>>) -> Self {
+        debug_assert!(channels.windows(2).all(|w| w[0].len() == w[1].len()));
+        Self {
+            sample_rate,
+            channels,
+        }
+    }
+
+    pub fn interleaved(&self) -> Vec<f32> {
+        let mut samples = Vec::with_capacity(self.frames() * self.channels.len());
+        for i in 0..self.frames() {
+            samples.extend(self.channels.iter().map(|channel| channel[i]));
+        }
+        samples
+    }
+
+    pub fn channel_count(&self) -> u16 {
+        self.channels.len() as u16
+    }
+
+    pub fn frames(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.frames() as f64 / self.sample_rate as f64
+    }
+
+    /// Converts a `[start, end)` span in seconds to a frame range, clamped
+    /// to the buffer. Fails on empty or inverted spans.
+    pub fn region(&self, start: f64, end: f64) -> Result<Range<usize>, AudioError> {
+        let to_frame = |seconds: f64| {
+            ((seconds.max(0.0) * self.sample_rate as f64).round() as usize).min(self.frames())
+        };
+        let range = to_frame(start)..to_frame(end);
+        if !(start.is_finite() && end.is_finite()) || range.is_empty() {
+            return Err(AudioError::InvalidArgument(format!(
+                "empty region {start:.3}s..{end:.3}s"
+            )));
+        }
+        Ok(range)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BufferInfo {
+    pub id: BufferId,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub frames: usize,
+    pub duration: f64,
+}
+
+impl BufferInfo {
+    pub fn new(id: BufferId, buffer: &AudioBuffer) -> Self {
+        Self {
+            id,
+            sample_rate: buffer.sample_rate,
+            channels: buffer.channel_count(),
+            frames: buffer.frames(),
+            duration: buffer.duration(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct StoreInner {
+    next_id: BufferId,
+    buffers: HashMap<BufferId, Arc<AudioBuffer>>,
+}
+
+/// Project buffers shared between commands. Buffers are handed out as `Arc`s
+/// so long renders don't hold the lock.
+#[derive(Default)]
+pub struct BufferStore {
+    inner: Mutex<StoreInner>,
+}
+
+impl BufferStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, buffer: AudioBuffer) -> BufferInfo {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        let info = BufferInfo::new(id, &buffer);
+        inner.buffers.insert(id, Arc::new(buffer));
+        info
+    }
+
+    pub fn get(&self, id: BufferId) -> Result<Arc<AudioBuffer>, AudioError> {
+        self.inner
+            .lock()
+            .unwrap()
+            .buffers
+            .get(&id)
+            .cloned()
+            .ok_or(AudioError::UnknownBuffer(id))
+    }
+
+    pub fn info(&self, id: BufferId) -> Result<BufferInfo, AudioError> {
+        self.get(id).map(|buffer| BufferInfo::new(id, &buffer))
+    }
+
+    pub fn remove(&self, id: BufferId) -> Result<(), AudioError> {
+        self.inner
+            .lock()
+            .unwrap()
+            .buffers
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(AudioError::UnknownBuffer(id))
+    }
+}
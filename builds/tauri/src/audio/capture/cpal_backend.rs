@@ -0,0 +1,260 @@
+//! Capture through the platform audio host (ALSA/PulseAudio, CoreAudio,
+//! WASAPI) via cpal.
+//!
+//! `cpal::Stream` is not `Send`, so each open stream lives on its own thread
+//! and is driven over a control channel.
+
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample};
+
+use super::{
+    CaptureBackend, CaptureConfig, CaptureError, CaptureStream, DeviceInfo, ErrorCallback,
+    NativeFormat, OpenedStream, RawCallback,
+};
+
+enum Control {
+    Start(SyncSender<Result<(), CaptureError>>),
+    Stop(SyncSender<Result<(), CaptureError>>),
+}
+
+pub struct CpalBackend;
+
+impl CpalBackend {
+    pub fn new() -> Self {
+        CpalBackend
+    }
+}
+
+impl Default for CpalBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn backend_error(error: impl std::fmt::Display) -> CaptureError {
+    CaptureError::Backend(error.to_string())
+}
+
+fn describe(device: &cpal::Device, default_name: Option<&str>) -> Option<DeviceInfo> {
+    let name = device.name().ok()?;
+    let config = device.default_input_config().ok()?;
+    let max_channels = device
+        .supported_input_configs()
+        .ok()
+        .and_then(|configs| configs.map(|c| c.channels()).max())
+        .unwrap_or_else(|| config.channels());
+    Some(DeviceInfo {
+        id: name.clone(),
+        is_default: default_name == Some(name.as_str()),
+        name,
+        default_sample_rate: config.sample_rate().0,
+        max_channels,
+    })
+}
+
+fn find_device(host: &cpal::Host, device_id: Option<&str>) -> Result<cpal::Device, CaptureError> {
+    match device_id {
+        None => host
+            .default_input_device()
+            .ok_or(CaptureError::NoDefaultDevice),
+        Some(id) => host
+            .input_devices()
+            .map_err(backend_error)?
+            .find(|d| d.name().is_ok_and(|n| n == id))
+            .ok_or_else(|| CaptureError::DeviceNotFound(id.to_string())),
+    }
+}
+
+/// Prefers an exact match for the requested rate and channel count, then any
+/// configuration at the requested rate, then the device default.
+fn choose_config(
+    device: &cpal::Device,
+    config: &CaptureConfig,
+) -> Result<cpal::SupportedStreamConfig, CaptureError> {
+    let rate = cpal::SampleRate(config.sample_rate);
+    let ranges: Vec<_> = device
+        .supported_input_configs()
+        .map_err(backend_error)?
+        .filter(|r| r.min_sample_rate() <= rate && rate <= r.max_sample_rate())
+        .collect();
+
+    let exact = ranges
+        .iter()
+        .filter(|r| r.channels() == config.channel_count)
+        .find(|r| r.sample_format() == SampleFormat::F32)
+        .or_else(|| ranges.iter().find(|r| r.channels() == config.channel_count));
+    if let Some(range) = exact.or_else(|| ranges.first()) {
+        return Ok(range.with_sample_rate(rate));
+    }
+    device.default_input_config().map_err(backend_error)
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    native: NativeFormat,
+    mut on_data: RawCallback,
+    mut on_error: ErrorCallback,
+) -> Result<cpal::Stream, CaptureError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let mut scratch = Vec::new();
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                scratch.clear();
+                scratch.extend(data.iter().map(|s| s.to_sample::<f32>()));
+                on_data(native, &scratch);
+            },
+            move |error| on_error(backend_error(error)),
+            None,
+        )
+        .map_err(backend_error)
+}
+
+impl CaptureBackend for CpalBackend {
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>, CaptureError> {
+        let host = cpal::default_host();
+        let default_name = host.default_input_device().and_then(|d| d.name().ok());
+        Ok(host
+            .input_devices()
+            .map_err(backend_error)?
+            .filter_map(|d| describe(&d, default_name.as_deref()))
+            .collect())
+    }
+
+    fn open(
+        &self,
+        device_id: Option<&str>,
+        config: &CaptureConfig,
+        on_data: RawCallback,
+        on_error: ErrorCallback,
+    ) -> Result<OpenedStream, CaptureError> {
+        let device_id = device_id.map(str::to_string);
+        let config = *config;
+        let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+        let (control_tx, control_rx) = mpsc::sync_channel(1);
+
+        let thread = thread::Builder::new()
+            .name("cpal-capture".into())
+            .spawn(move || {
+                let opened = (|| {
+                    let host = cpal::default_host();
+                    let device = find_device(&host, device_id.as_deref())?;
+                    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+                    let info = describe(&device, default_name.as_deref()).ok_or_else(|| {
+                        CaptureError::Backend("device disappeared while opening".into())
+                    })?;
+                    let supported = choose_config(&device, &config)?;
+                    let stream_config = supported.config();
+                    let native = NativeFormat {
+                        sample_rate: stream_config.sample_rate.0,
+                        channels: stream_config.channels,
+                    };
+                    let stream = match supported.sample_format() {
+                        SampleFormat::F32 => {
+                            build_stream::<f32>(&device, &stream_config, native, on_data, on_error)
+                        }
+                        SampleFormat::I16 => {
+                            build_stream::<i16>(&device, &stream_config, native, on_data, on_error)
+                        }
+                        SampleFormat::U16 => {
+                            build_stream::<u16>(&device, &stream_config, native, on_data, on_error)
+                        }
+                        SampleFormat::I32 => {
+                            build_stream::<i32>(&device, &stream_config, native, on_data, on_error)
+                        }
+                        other => Err(CaptureError::UnsupportedConfig(format!(
+                            "sample format {other:?}"
+                        ))),
+                    }?;
+                    // Some hosts start streams as soon as they are built.
+                    stream.pause().map_err(backend_error)?;
+                    Ok((stream, info, native))
+                })();
+
+                match opened {
+                    Ok((stream, info, native)) => {
+                        let _ = ready_tx.send(Ok((info, native)));
+                        run_stream(stream, control_rx);
+                    }
+                    Err(error) => {
+                        let _ = ready_tx.send(Err(error));
+                    }
+                }
+            })
+            .map_err(backend_error)?;
+
+        let (device, native) = ready_rx
+            .recv()
+            .map_err(|_| CaptureError::Backend("capture thread exited while opening".into()))??;
+        Ok(OpenedStream {
+            device,
+            native,
+            stream: Box::new(CpalStream {
+                control: Some(control_tx),
+                thread: Some(thread),
+            }),
+        })
+    }
+}
+
+/// Owns the stream until the control channel is dropped.
+fn run_stream(stream: cpal::Stream, control: Receiver<Control>) {
+    while let Ok(message) = control.recv() {
+        match message {
+            Control::Start(reply) => {
+                let _ = reply.send(stream.play().map_err(backend_error));
+            }
+            Control::Stop(reply) => {
+                let _ = reply.send(stream.pause().map_err(backend_error));
+            }
+        }
+    }
+}
+
+struct CpalStream {
+    control: Option<SyncSender<Control>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CpalStream {
+    fn request(
+        &self,
+        make: fn(SyncSender<Result<(), CaptureError>>) -> Control,
+    ) -> Result<(), CaptureError> {
+        let closed = || CaptureError::Backend("capture thread has exited".into());
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        self.control
+            .as_ref()
+            .ok_or_else(closed)?
+            .send(make(reply_tx))
+            .map_err(|_| closed())?;
+        reply_rx.recv().map_err(|_| closed())?
+    }
+}
+
+impl CaptureStream for CpalStream {
+    fn start(&mut self) -> Result<(), CaptureError> {
+        self.request(Control::Start)
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        self.request(Control::Stop)
+    }
+}
+
+impl Drop for CpalStream {
+    fn drop(&mut self) {
+        self.control.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
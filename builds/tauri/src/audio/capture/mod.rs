@@ -0,0 +1,627 @@
+//! Native audio capture.
+//!
+//! A [`CaptureBackend`] enumerates input devices and opens streams that push
+//! interleaved samples in whatever format the device runs at. The
+//! [`CaptureEngine`] owns the single active stream, reshapes the device data
+//! into frames matching `audio.input` (sample rate, channel count, buffer
+//! size) and fans them out to subscribers.
+
+#[cfg(feature = "native-audio")]
+mod cpal_backend;
+mod null;
+mod queue;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use super::buffer::AudioBuffer;
+use super::resample::resample;
+use super::wav;
+
+#[cfg(feature = "native-audio")]
+pub use cpal_backend::CpalBackend;
+pub use null::NullBackend;
+pub use queue::{frame_queue, FrameQueue, FrameSender};
+
+/// Set to `null` to force the null backend, e.g. on headless CI machines, or
+/// to `file:<path>` to capture from a WAV file instead of a device.
+pub const BACKEND_ENV: &str = "NEURALVOICE_AUDIO_BACKEND";
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum CaptureError {
+    #[error("input device not found: {0}")]
+    DeviceNotFound(String),
+    #[error("no default input device available")]
+    NoDefaultDevice,
+    #[error("unsupported capture configuration: {0}")]
+    UnsupportedConfig(String),
+    #[error("no capture stream is open")]
+    NotOpen,
+    #[error("a capture stream is already open")]
+    AlreadyOpen,
+    #[error("audio backend error: {0}")]
+    Backend(String),
+}
+
+impl CaptureError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CaptureError::DeviceNotFound(_) => "deviceNotFound",
+            CaptureError::NoDefaultDevice => "noDefaultDevice",
+            CaptureError::UnsupportedConfig(_) => "unsupportedConfig",
+            CaptureError::NotOpen => "notOpen",
+            CaptureError::AlreadyOpen => "alreadyOpen",
+            CaptureError::Backend(_) => "backend",
+        }
+    }
+}
+
+impl Serialize for CaptureError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// Mirrors the `audio.input` section of `config/default.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CaptureConfig {
+    pub sample_rate: u32,
+    pub channel_count: u16,
+    /// Frames per emitted [`CaptureFrame`].
+    pub buffer_size: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 16000,
+            channel_count: 1,
+            buffer_size: 4096,
+        }
+    }
+}
+
+impl CaptureConfig {
+    pub fn validate(&self) -> Result<(), CaptureError> {
+        if !(8000..=192_000).contains(&self.sample_rate) {
+            return Err(CaptureError::UnsupportedConfig(format!(
+                "sample rate {} Hz is out of range",
+                self.sample_rate
+            )));
+        }
+        if !(1..=8).contains(&self.channel_count) {
+            return Err(CaptureError::UnsupportedConfig(format!(
+                "channel count {} is out of range",
+                self.channel_count
+            )));
+        }
+        if !(64..=65536).contains(&self.buffer_size) {
+            return Err(CaptureError::UnsupportedConfig(format!(
+                "buffer size {} is out of range",
+                self.buffer_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInfo {
+    pub id: String,
+    pub name: String,
+    pub is_default: bool,
+    pub default_sample_rate: u32,
+    pub max_channels: u16,
+}
+
+/// The format a device stream actually delivers samples in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NativeFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// One block of captured audio, interleaved, in the configured format.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureFrame {
+    pub sequence: u64,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+    pub rms: f32,
+    pub peak: f32,
+}
+
+impl CaptureFrame {
+    /// The frame averaged to one channel at `sample_rate`. Frames are
+    /// resampled one by one, which is fine for analysis but not for
+    /// listening.
+    pub fn mono(&self, sample_rate: u32) -> Vec<f32> {
+        let channels = self.channels.max(1) as usize;
+        let mono: Vec<f32> = self
+            .samples
+            .chunks(channels)
+            .map(|samples| samples.iter().sum::<f32>() / channels as f32)
+            .collect();
+        if self.sample_rate == sample_rate {
+            return mono;
+        }
+        resample(&AudioBuffer::new(self.sample_rate, vec![mono]), sample_rate)
+            .channels
+            .pop()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CaptureEvent {
+    Frame(CaptureFrame),
+    Error(CaptureError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CaptureState {
+    Closed,
+    Open,
+    Running,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureStatus {
+    pub state: CaptureState,
+    pub device: Option<DeviceInfo>,
+    pub config: Option<CaptureConfig>,
+    pub native: Option<NativeFormat>,
+    pub frames_emitted: u64,
+}
+
+pub type RawCallback = Box<dyn FnMut(NativeFormat, &[f32]) + Send>;
+pub type ErrorCallback = Box<dyn FnMut(CaptureError) + Send>;
+
+pub trait CaptureStream: Send {
+    fn start(&mut self) -> Result<(), CaptureError>;
+    fn stop(&mut self) -> Result<(), CaptureError>;
+}
+
+pub struct OpenedStream {
+    pub device: DeviceInfo,
+    pub native: NativeFormat,
+    pub stream: Box<dyn CaptureStream>,
+}
+
+pub trait CaptureBackend: Send + Sync {
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>, CaptureError>;
+
+    /// Opens `device_id` (or the default device) in a paused state. The
+    /// backend should get as close to `config` as the device allows; the
+    /// engine converts whatever native format it settles on.
+    fn open(
+        &self,
+        device_id: Option<&str>,
+        config: &CaptureConfig,
+        on_data: RawCallback,
+        on_error: ErrorCallback,
+    ) -> Result<OpenedStream, CaptureError>;
+}
+
+/// Picks the platform backend unless [`BACKEND_ENV`] asks for the null one.
+pub fn default_backend() -> Box<dyn CaptureBackend> {
+    match std::env::var(BACKEND_ENV) {
+        Ok(value) if value == "null" => return Box::new(NullBackend::new()),
+        Ok(value) if value.starts_with("file:") => {
+            let path = &value["file:".len()..];
+            match file_backend(path) {
+                Ok(backend) => return Box::new(backend),
+                Err(error) => log::warn!("{BACKEND_ENV}: cannot use {path}: {error}"),
+            }
+        }
+        _ => {}
+    }
+    #[cfg(feature = "native-audio")]
+    {
+        Box::new(CpalBackend::new())
+    }
+    #[cfg(not(feature = "native-audio"))]
+    {
+        Box::new(NullBackend::new())
+    }
+}
+
+fn file_backend(path: &str) -> Result<NullBackend, Box<dyn std::error::Error>> {
+    let buffer = wav::decode(&std::fs::read(path)?)?;
+    let format = NativeFormat {
+        sample_rate: buffer.sample_rate,
+        channels: buffer.channel_count(),
+    };
+    Ok(NullBackend::with_source(format, buffer.interleaved()))
+}
+
+pub type SubscriptionId = u64;
+type Subscriber = Arc<Mutex<dyn FnMut(&CaptureEvent) + Send>>;
+
+#[derive(Default)]
+struct Subscribers {
+    next_id: SubscriptionId,
+    list: Vec<(SubscriptionId, Subscriber)>,
+}
+
+/// Calls every subscriber with `event`. The list is copied out first, so
+/// callbacks run without the list locked and may subscribe or unsubscribe.
+fn publish(subscribers: &Mutex<Subscribers>, event: &CaptureEvent) {
+    let list: Vec<Subscriber> = subscribers
+        .lock()
+        .unwrap()
+        .list
+        .iter()
+        .map(|(_, subscriber)| Arc::clone(subscriber))
+        .collect();
+    for subscriber in list {
+        (subscriber.lock().unwrap_or_else(|e| e.into_inner()))(event);
+    }
+}
+
+struct Session {
+    device: DeviceInfo,
+    config: CaptureConfig,
+    native: NativeFormat,
+    state: CaptureState,
+    stream: Box<dyn CaptureStream>,
+    frames: Arc<AtomicU64>,
+}
+
+pub struct CaptureEngine {
+    backend: Box<dyn CaptureBackend>,
+    session: Mutex<Option<Session>>,
+    subscribers: Arc<Mutex<Subscribers>>,
+}
+
+impl CaptureEngine {
+    pub fn new(backend: Box<dyn CaptureBackend>) -> Self {
+        Self {
+            backend,
+            session: Mutex::new(None),
+            subscribers: Arc::new(Mutex::new(Subscribers::default())),
+        }
+    }
+
+    pub fn list_devices(&self) -> Result<Vec<DeviceInfo>, CaptureError> {
+        self.backend.list_devices()
+    }
+
+    /// Registers a callback for every frame and stream error. Callbacks run
+    /// on the audio thread, so anything expensive must be handed off.
+    /// A callback may still see an event already being published when it is
+    /// unsubscribed.
+    pub fn subscribe(
+        &self,
+        subscriber: impl FnMut(&CaptureEvent) + Send + 'static,
+    ) -> SubscriptionId {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.next_id += 1;
+        let id = subscribers.next_id;
+        subscribers
+            .list
+            .push((id, Arc::new(Mutex::new(subscriber))));
+        id
+    }
+
+    /// Drops the callback registered as `id`. Unknown ids are ignored.
+    pub fn unsubscribe(&self, id: SubscriptionId) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.list.retain(|(subscribed, _)| *subscribed != id);
+    }
+
+    pub fn open(
+        &self,
+        device_id: Option<&str>,
+        config: CaptureConfig,
+    ) -> Result<CaptureStatus, CaptureError> {
+        config.validate()?;
+        let mut session = self.session.lock().unwrap();
+        if session.is_some() {
+            return Err(CaptureError::AlreadyOpen);
+        }
+
+        let frames = Arc::new(AtomicU64::new(0));
+        let mut assembler = FrameAssembler::new(config);
+        let on_data: RawCallback = {
+            let subscribers = Arc::clone(&self.subscribers);
+            let frames = Arc::clone(&frames);
+            Box::new(move |native, data| {
+                assembler.push(native, data, |frame| {
+                    frames.fetch_add(1, Ordering::Relaxed);
+                    publish(&subscribers, &CaptureEvent::Frame(frame));
+                });
+            })
+        };
+        let on_error: ErrorCallback = {
+            let subscribers = Arc::clone(&self.subscribers);
+            Box::new(move |error| publish(&subscribers, &CaptureEvent::Error(error)))
+        };
+
+        let opened = self.backend.open(device_id, &config, on_data, on_error)?;
+        *session = Some(Session {
+            device: opened.device,
+            config,
+            native: opened.native,
+            state: CaptureState::Open,
+            stream: opened.stream,
+            frames,
+        });
+        Ok(Self::describe(session.as_ref()))
+    }
+
+    pub fn start(&self) -> Result<CaptureStatus, CaptureError> {
+        self.transition(CaptureState::Running, |stream| stream.start())
+    }
+
+    pub fn stop(&self) -> Result<CaptureStatus, CaptureError> {
+        self.transition(CaptureState::Stopped, |stream| stream.stop())
+    }
+
+    /// Stops and releases the device. Closing when nothing is open is a no-op.
+    pub fn close(&self) -> CaptureStatus {
+        let mut session = self.session.lock().unwrap();
+        if let Some(mut open) = session.take() {
+            // The stream is dropped either way; a failing pause changes nothing.
+            let _ = open.stream.stop();
+        }
+        Self::describe(None)
+    }
+
+    pub fn status(&self) -> CaptureStatus {
+        Self::describe(self.session.lock().unwrap().as_ref())
+    }
+
+    fn transition(
+        &self,
+        next: CaptureState,
+        action: impl FnOnce(&mut dyn CaptureStream) -> Result<(), CaptureError>,
+    ) -> Result<CaptureStatus, CaptureError> {
+        let mut session = self.session.lock().unwrap();
+        let open = session.as_mut().ok_or(CaptureError::NotOpen)?;
+        if open.state != next {
+            action(open.stream.as_mut())?;
+            open.state = next;
+        }
+        Ok(Self::describe(session.as_ref()))
+    }
+
+    fn describe(session: Option<&Session>) -> CaptureStatus {
+        match session {
+            Some(s) => CaptureStatus {
+                state: s.state,
+                device: Some(s.device.clone()),
+                config: Some(s.config),
+                native: Some(s.native),
+                frames_emitted: s.frames.load(Ordering::Relaxed),
+            },
+            None => CaptureStatus {
+                state: CaptureState::Closed,
+                device: None,
+                config: None,
+                native: None,
+                frames_emitted: 0,
+            },
+        }
+    }
+}
+
+/// Converts native device data to the configured channel layout and sample
+/// rate and slices it into fixed-size frames.
+///
+/// Rate conversion is linear interpolation: cheap enough for the audio
+/// thread and adequate for monitoring and speech, which is what capture at
+/// `audio.input` rates feeds.
+struct FrameAssembler {
+    config: CaptureConfig,
+    native: Option<NativeFormat>,
+    /// Channel-mapped input not yet consumed by the resampler, interleaved.
+    pending: Vec<f32>,
+    /// Fractional read position into `pending`, in frames.
+    position: f64,
+    output: Vec<f32>,
+    sequence: u64,
+}
+
+impl FrameAssembler {
+    fn new(config: CaptureConfig) -> Self {
+        Self {
+            config,
+            native: None,
+            pending: Vec::new(),
+            position: 0.0,
+            output: Vec::with_capacity(config.buffer_size * config.channel_count as usize),
+            sequence: 0,
+        }
+    }
+
+    fn push(&mut self, native: NativeFormat, data: &[f32], mut emit: impl FnMut(CaptureFrame)) {
+        if native.channels == 0 || native.sample_rate == 0 {
+            return;
+        }
+        if self.native != Some(native) {
+            self.native = Some(native);
+            self.pending.clear();
+            self.position = 0.0;
+        }
+
+        let in_channels = native.channels as usize;
+        let out_channels = self.config.channel_count as usize;
+        for frame in data.chunks_exact(in_channels) {
+            if out_channels == 1 {
+                self.pending
+                    .push(frame.iter().sum::<f32>() / in_channels as f32);
+            } else {
+                for c in 0..out_channels {
+                    self.pending.push(frame[c.min(in_channels - 1)]);
+                }
+            }
+        }
+
+        let step = native.sample_rate as f64 / self.config.sample_rate as f64;
+        let available = self.pending.len() / out_channels;
+        while self.position + 1.0 < available as f64 {
+            let index = self.position as usize;
+            let frac = (self.position - index as f64) as f32;
+            for c in 0..out_channels {
+                let a = self.pending[index * out_channels + c];
+                let b = self.pending[(index + 1) * out_channels + c];
+                self.output.push(a + (b - a) * frac);
+            }
+            self.position += step;
+
+            if self.output.len() == self.config.buffer_size * out_channels {
+                emit(self.take_frame());
+            }
+        }
+
+        let consumed = (self.position as usize).min(available);
+        self.pending.drain(..consumed * out_channels);
+        self.position -= consumed as f64;
+    }
+
+    fn take_frame(&mut self) -> CaptureFrame {
+        let capacity = self.output.capacity();
+        let samples = std::mem::replace(&mut self.output, Vec::with_capacity(capacity));
+        let (sum, peak) = samples.iter().fold((0.0f32, 0.0f32), |(sum, peak), s| {
+            (sum + s * s, peak.max(s.abs()))
+        });
+        let frame = CaptureFrame {
+            sequence: self.sequence,
+            sample_rate: self.config.sample_rate,
+            channels: self.config.channel_count,
+            rms: (sum / samples.len().max(1) as f32).sqrt(),
+            peak,
+            samples,
+        };
+        self.sequence += 1;
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn sine(rate: u32, channels: u16, seconds: f32) -> Vec<f32> {
+        let frames = (rate as f32 * seconds) as usize;
+        (0..frames)
+            .flat_map(|i| {
+                let s = (i as f32 * 440.0 * std::f32::consts::TAU / rate as f32).sin() * 0.5;
+                std::iter::repeat_n(s, channels as usize)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn null_device_frames_match_config() {
+        let native = NativeFormat {
+            sample_rate: 48000,
+            channels: 2,
+        };
+        let backend = NullBackend::with_source(native, sine(48000, 2, 1.0)).unpaced();
+        let engine = CaptureEngine::new(Box::new(backend));
+        let (tx, rx) = mpsc::channel();
+        engine.subscribe(move |event| {
+            if let CaptureEvent::Frame(frame) = event {
+                tx.send(frame.clone()).unwrap();
+            }
+        });
+
+        let config = CaptureConfig {
+            sample_rate: 16000,
+            channel_count: 1,
+            buffer_size: 1600,
+        };
+        let status = engine.open(None, config).unwrap();
+        assert_eq!(status.state, CaptureState::Open);
+        assert_eq!(status.native, Some(native));
+        engine.start().unwrap();
+
+        let frames: Vec<CaptureFrame> = (0..9)
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        engine.close();
+
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.sequence, i as u64);
+            assert_eq!(frame.sample_rate, 16000);
+            assert_eq!(frame.channels, 1);
+            assert_eq!(frame.samples.len(), 1600);
+            assert!((frame.peak - 0.5).abs() < 0.01);
+            assert!((frame.rms - 0.5 / 2f32.sqrt()).abs() < 0.01);
+        }
+        assert_eq!(engine.status().state, CaptureState::Closed);
+    }
+
+    #[test]
+    fn subscribers_may_unsubscribe_from_their_callback() {
+        let native = NativeFormat {
+            sample_rate: 16000,
+            channels: 1,
+        };
+        let backend = NullBackend::with_source(native, sine(16000, 1, 1.0)).unpaced();
+        let engine = Arc::new(CaptureEngine::new(Box::new(backend)));
+        let (tx, rx) = mpsc::channel();
+        let id = Arc::new(AtomicU64::new(0));
+        let subscribed = engine.subscribe({
+            let engine = Arc::downgrade(&engine);
+            let id = Arc::clone(&id);
+            move |event| {
+                if let (CaptureEvent::Frame(frame), Some(engine)) = (event, engine.upgrade()) {
+                    engine.unsubscribe(id.load(Ordering::Relaxed));
+                    tx.send(frame.sequence).unwrap();
+                }
+            }
+        });
+        id.store(subscribed, Ordering::Relaxed);
+
+        engine.open(None, CaptureConfig::default()).unwrap();
+        engine.start().unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 0);
+        engine.close();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn open_rejects_second_stream_and_bad_config() {
+        let engine = CaptureEngine::new(Box::new(NullBackend::new()));
+        let bad = CaptureConfig {
+            buffer_size: 0,
+            ..CaptureConfig::default()
+        };
+        assert!(matches!(
+            engine.open(None, bad),
+            Err(CaptureError::UnsupportedConfig(_))
+        ));
+        assert!(matches!(engine.start(), Err(CaptureError::NotOpen)));
+
+        engine.open(None, CaptureConfig::default()).unwrap();
+        assert!(matches!(
+            engine.open(None, CaptureConfig::default()),
+            Err(CaptureError::AlreadyOpen)
+        ));
+        assert!(matches!(
+            engine.open(Some("missing"), CaptureConfig::default()),
+            Err(CaptureError::AlreadyOpen)
+        ));
+        engine.close();
+        assert!(matches!(
+            engine.open(Some("missing"), CaptureConfig::default()),
+            Err(CaptureError::DeviceNotFound(_))
+        ));
+    }
+}
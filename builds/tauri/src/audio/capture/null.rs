@@ -0,0 +1,174 @@
+//! A device-less backend for headless machines and tests.
+//!
+//! The single `null` device produces silence, or plays back a fixed sample
+//! source (such as a WAV file) once and then goes quiet.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{
+    CaptureBackend, CaptureConfig, CaptureError, CaptureStream, DeviceInfo, ErrorCallback,
+    NativeFormat, OpenedStream, RawCallback,
+};
+
+pub const NULL_DEVICE_ID: &str = "null";
+
+/// How much audio each callback delivers.
+const CHUNK_MS: u32 = 10;
+
+pub struct NullBackend {
+    /// `None` means the device adopts whatever format is requested.
+    format: Option<NativeFormat>,
+    source: Arc<Vec<f32>>,
+    paced: bool,
+}
+
+impl NullBackend {
+    /// A silent device running at the requested format.
+    pub fn new() -> Self {
+        Self {
+            format: None,
+            source: Arc::new(Vec::new()),
+            paced: true,
+        }
+    }
+
+    /// A device that plays interleaved `samples` in `format`, then silence.
+    pub fn with_source(format: NativeFormat, samples: Vec<f32>) -> Self {
+        Self {
+            format: Some(format),
+            source: Arc::new(samples),
+            paced: true,
+        }
+    }
+
+    /// Delivers chunks as fast as they are consumed instead of in real time.
+    #[cfg(test)]
+    pub fn unpaced(mut self) -> Self {
+        self.paced = false;
+        self
+    }
+
+    fn device_info(&self) -> DeviceInfo {
+        DeviceInfo {
+            id: NULL_DEVICE_ID.to_string(),
+            name: "Null input".to_string(),
+            is_default: true,
+            default_sample_rate: self.format.map_or(48000, |f| f.sample_rate),
+            max_channels: self.format.map_or(8, |f| f.channels),
+        }
+    }
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CaptureBackend for NullBackend {
+    fn list_devices(&self) -> Result<Vec<DeviceInfo>, CaptureError> {
+        Ok(vec![self.device_info()])
+    }
+
+    fn open(
+        &self,
+        device_id: Option<&str>,
+        config: &CaptureConfig,
+        mut on_data: RawCallback,
+        _on_error: ErrorCallback,
+    ) -> Result<OpenedStream, CaptureError> {
+        if let Some(id) = device_id {
+            if id != NULL_DEVICE_ID {
+                return Err(CaptureError::DeviceNotFound(id.to_string()));
+            }
+        }
+
+        let native = self.format.unwrap_or(NativeFormat {
+            sample_rate: config.sample_rate,
+            channels: config.channel_count,
+        });
+        let chunk_frames = (native.sample_rate * CHUNK_MS / 1000).max(1) as usize;
+        let chunk_len = chunk_frames * native.channels as usize;
+        let source = Arc::clone(&self.source);
+        let paced = self.paced;
+
+        let running = Arc::new(AtomicBool::new(false));
+        let closed = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let running = Arc::clone(&running);
+            let closed = Arc::clone(&closed);
+            thread::Builder::new()
+                .name("null-capture".into())
+                .spawn(move || {
+                    let silence = vec![0.0f32; chunk_len];
+                    let mut offset = 0;
+                    while !closed.load(Ordering::Acquire) {
+                        if !running.load(Ordering::Acquire) {
+                            thread::park_timeout(Duration::from_millis(CHUNK_MS as u64));
+                            continue;
+                        }
+                        if offset < source.len() {
+                            let end = (offset + chunk_len).min(source.len());
+                            on_data(native, &source[offset..end]);
+                            offset = end;
+                        } else if source.is_empty() || paced {
+                            on_data(native, &silence);
+                        } else {
+                            // An unpaced source that has run dry has nothing
+                            // left to say; don't spin.
+                            thread::park_timeout(Duration::from_millis(CHUNK_MS as u64));
+                            continue;
+                        }
+                        if paced {
+                            thread::sleep(Duration::from_millis(CHUNK_MS as u64));
+                        }
+                    }
+                })
+                .map_err(|e| CaptureError::Backend(e.to_string()))?
+        };
+
+        Ok(OpenedStream {
+            device: self.device_info(),
+            native,
+            stream: Box::new(NullStream {
+                running,
+                closed,
+                thread: Some(thread),
+            }),
+        })
+    }
+}
+
+struct NullStream {
+    running: Arc<AtomicBool>,
+    closed: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CaptureStream for NullStream {
+    fn start(&mut self) -> Result<(), CaptureError> {
+        self.running.store(true, Ordering::Release);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), CaptureError> {
+        self.running.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Drop for NullStream {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Handing capture frames to a worker thread.
+//!
+//! Subscribers run on the audio thread, so live analysis copies each frame
+//! into a bounded queue and does its work on a worker that reads it. A
+//! worker that's behind loses frames rather than queueing them without
+//! bound, and learns that it did on the next frame it takes.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+
+use super::{CaptureEngine, CaptureEvent, CaptureFrame, SubscriptionId};
+
+/// Frames queued for the worker, some 20 seconds of capture at usual
+/// buffer sizes. Frames that arrive while it's full are dropped.
+const FRAME_QUEUE: usize = 256;
+
+/// The audio thread's end of a frame queue, see [`CaptureEngine::forward`].
+pub struct FrameSender {
+    frames: SyncSender<CaptureFrame>,
+    overrun: Arc<AtomicBool>,
+}
+
+/// The worker's end of a frame queue. It runs dry once the sending
+/// subscription is dropped.
+pub struct FrameQueue {
+    frames: Receiver<CaptureFrame>,
+    overrun: Arc<AtomicBool>,
+}
+
+/// A new, empty frame queue.
+pub fn frame_queue() -> (FrameSender, FrameQueue) {
+    let (sender, receiver) = mpsc::sync_channel(FRAME_QUEUE);
+    let overrun = Arc::new(AtomicBool::new(false));
+    let sender = FrameSender {
+        frames: sender,
+        overrun: Arc::clone(&overrun),
+    };
+    let queue = FrameQueue {
+        frames: receiver,
+        overrun,
+    };
+    (sender, queue)
+}
+
+impl FrameSender {
+    fn send(&self, frame: &CaptureFrame) {
+        // A worker that's done doesn't need the frame.
+        if let Err(TrySendError::Full(_)) = self.frames.try_send(frame.clone()) {
+            self.overrun.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl FrameQueue {
+    /// Blocks for the next frame, or returns `None` once the sender is gone
+    /// and the queue is empty.
+    pub fn recv(&self) -> Option<CaptureFrame> {
+        self.frames.recv().ok()
+    }
+
+    /// Whether frames were dropped since the last call.
+    pub fn overrun(&self) -> bool {
+        self.overrun.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl CaptureEngine {
+    /// Subscribes `sender` to the captured frames. Unsubscribing drops it,
+    /// which ends its queue.
+    pub fn forward(&self, sender: FrameSender) -> SubscriptionId {
+        self.subscribe(move |event| {
+            if let CaptureEvent::Frame(frame) = event {
+                sender.send(frame);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(sequence: u64) -> CaptureFrame {
+        CaptureFrame {
+            sequence,
+            sample_rate: 16000,
+            channels: 1,
+            samples: vec![0.0; 160],
+            rms: 0.0,
+            peak: 0.0,
+        }
+    }
+
+    #[test]
+    fn a_full_queue_drops_frames_and_flags_the_overrun() {
+        let (sender, queue) = frame_queue();
+        for sequence in 0..FRAME_QUEUE as u64 + 2 {
+            sender.send(&frame(sequence));
+        }
+        drop(sender);
+
+        assert!(queue.overrun());
+        assert!(!queue.overrun());
+        let sequences: Vec<u64> = std::iter::from_fn(|| queue.recv())
+            .map(|frame| frame.sequence)
+            .collect();
+        assert_eq!(sequences, (0..FRAME_QUEUE as u64).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,599 @@
+//! Rendering a buffer to a delivery format.
+//!
+//! WAV and FLAC are encoded in-tree. Ogg Vorbis, MP3 and Opus go through
+//! the reference encoders (libvorbis with the aoTuV tunings, LAME and
+//! libopus), built from source and linked statically; builds without the
+//! `vorbis`, `mp3` or `opus` feature report those formats as unsupported.
+//! Opus packets are put in Ogg pages in-tree.
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use super::buffer::AudioBuffer;
+use super::wav::{self, SampleFormat};
+use super::{flac, resample, AudioError};
+
+/// Frames handed to the lossy encoders at a time, and so how often
+/// progress is reported.
+#[cfg(any(feature = "vorbis", feature = "mp3", feature = "opus"))]
+const CHUNK_FRAMES: usize = 1 << 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("{0} export isn't available in this build")]
+    Unsupported(ExportFormat),
+    #[error("invalid export settings: {0}")]
+    Invalid(String),
+    #[error("{format} encoder failed: {message}")]
+    #[cfg_attr(
+        not(any(feature = "vorbis", feature = "mp3", feature = "opus")),
+        allow(dead_code)
+    )]
+    Encoder {
+        format: ExportFormat,
+        message: String,
+    },
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+}
+
+impl ExportError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ExportError::Unsupported(_) => "unsupportedFormat",
+            ExportError::Invalid(_) => "invalidExport",
+            ExportError::Encoder { .. } => "encoderFailed",
+            ExportError::Audio(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for ExportError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    #[default]
+    Wav,
+    Flac,
+    /// Ogg Vorbis.
+    Vorbis,
+    Mp3,
+    /// Ogg Opus.
+    Opus,
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ExportFormat::Wav => "WAV",
+            ExportFormat::Flac => "FLAC",
+            ExportFormat::Vorbis => "Ogg Vorbis",
+            ExportFormat::Mp3 => "MP3",
+            ExportFormat::Opus => "Ogg Opus",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Bits per sample for WAV (8, 16, 24 or 32; 32 is floating point) and
+    /// FLAC (8, 16 or 24). The lossy formats ignore it.
+    pub bit_depth: u16,
+    /// Output rate; `None` keeps the buffer's. Opus always encodes at
+    /// 48 kHz and records this as the rate players should play back at.
+    pub sample_rate: Option<u32>,
+    /// 0 to 1. Vorbis VBR quality, the MP3 bitrate (96 to 320 kbit/s), the
+    /// Opus bitrate (24 to 128 kbit/s per channel) or how hard FLAC searches
+    /// for a smaller coding.
+    pub quality: f32,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Wav,
+            bit_depth: 16,
+            sample_rate: None,
+            quality: 0.6,
+        }
+    }
+}
+
+/// Rates an MP3 stream can carry (MPEG-1, 2 and 2.5 layer III).
+const MP3_RATES: [u32; 9] = [8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000];
+
+/// The rate Opus always codes at.
+const OPUS_RATE: u32 = 48000;
+
+impl ExportOptions {
+    fn validate(&self, channels: usize) -> Result<(), ExportError> {
+        let invalid = |message: String| Err(ExportError::Invalid(message));
+        if !(0.0..=1.0).contains(&self.quality) {
+            return invalid(format!(
+                "quality must be between 0 and 1, got {}",
+                self.quality
+            ));
+        }
+        if self.sample_rate == Some(0) {
+            return invalid("sample rate must be positive".into());
+        }
+        match self.format {
+            ExportFormat::Wav if ![8, 16, 24, 32].contains(&self.bit_depth) => invalid(format!(
+                "WAV bit depth must be 8, 16, 24 or 32, got {}",
+                self.bit_depth
+            )),
+            ExportFormat::Flac if ![8, 16, 24].contains(&self.bit_depth) => invalid(format!(
+                "FLAC bit depth must be 8, 16 or 24, got {}",
+                self.bit_depth
+            )),
+            ExportFormat::Flac if channels > 8 => {
+                invalid(format!("FLAC holds at most 8 channels, got {channels}"))
+            }
+            ExportFormat::Mp3 if channels > 2 => {
+                invalid(format!("MP3 holds at most 2 channels, got {channels}"))
+            }
+            // libopus's multistream mappings aren't wrapped.
+            ExportFormat::Opus if channels > 2 => invalid(format!(
+                "Opus export holds at most 2 channels, got {channels}"
+            )),
+            ExportFormat::Vorbis if channels > 255 => {
+                invalid(format!("Vorbis holds at most 255 channels, got {channels}"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Encodes `buffer` with `options`, calling `progress` with the fraction
+/// done as encoding goes.
+pub fn export(
+    buffer: &AudioBuffer,
+    options: &ExportOptions,
+    mut progress: impl FnMut(f32),
+) -> Result<Vec<u8>, ExportError> {
+    options.validate(buffer.channels.len())?;
+    if buffer.channels.is_empty() {
+        return Err(ExportError::Invalid("buffer has no channels".into()));
+    }
+    let rate = options.sample_rate.unwrap_or(buffer.sample_rate);
+    if options.format == ExportFormat::Mp3 && !MP3_RATES.contains(&rate) {
+        return Err(ExportError::Invalid(format!(
+            "MP3 can't be encoded at {rate} Hz; pick one of {MP3_RATES:?}"
+        )));
+    }
+    let input_rate = rate;
+    let rate = match options.format {
+        ExportFormat::Opus => OPUS_RATE,
+        _ => rate,
+    };
+    let resampled;
+    let buffer = if rate == buffer.sample_rate {
+        buffer
+    } else {
+        resampled = resample::resample(buffer, rate);
+        &resampled
+    };
+
+    match options.format {
+        ExportFormat::Wav => {
+            let format = match options.bit_depth {
+                8 => SampleFormat::Pcm8,
+                16 => SampleFormat::Pcm16,
+                24 => SampleFormat::Pcm24,
+                _ => SampleFormat::Float32,
+            };
+            let bytes = wav::encode(buffer, format)?;
+            progress(1.0);
+            Ok(bytes)
+        }
+        ExportFormat::Flac => Ok(flac::encode(
+            buffer,
+            options.bit_depth,
+            options.quality,
+            progress,
+        )),
+        ExportFormat::Vorbis => vorbis(buffer, options.quality, progress),
+        ExportFormat::Mp3 => mp3(buffer, options.quality, progress),
+        ExportFormat::Opus => opus(buffer, input_rate, options.quality, progress),
+    }
+}
+
+#[cfg(feature = "vorbis")]
+fn vorbis(
+    buffer: &AudioBuffer,
+    quality: f32,
+    mut progress: impl FnMut(f32),
+) -> Result<Vec<u8>, ExportError> {
+    use std::num::{NonZeroU32, NonZeroU8};
+
+    use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoderBuilder};
+
+    let error = |e: vorbis_rs::VorbisError| ExportError::Encoder {
+        format: ExportFormat::Vorbis,
+        message: e.to_string(),
+    };
+    let rate = NonZeroU32::new(buffer.sample_rate).expect("validated rate");
+    let channels = NonZeroU8::new(buffer.channels.len() as u8).expect("validated channels");
+    let mut out = Vec::new();
+    let mut encoder = VorbisEncoderBuilder::new(rate, channels, &mut out)
+        .map_err(error)?
+        // libvorbis quality runs from -0.1 to 1.0; below 0 sounds poor.
+        .bitrate_management_strategy(VorbisBitrateManagementStrategy::QualityVbr {
+            target_quality: quality,
+        })
+        .build()
+        .map_err(error)?;
+    let frames = buffer.frames();
+    for start in (0..frames).step_by(CHUNK_FRAMES) {
+        let end = (start + CHUNK_FRAMES).min(frames);
+        let block: Vec<&[f32]> = buffer.channels.iter().map(|c| &c[start..end]).collect();
+        encoder.encode_audio_block(&block).map_err(error)?;
+        progress(end as f32 / frames as f32);
+    }
+    encoder.finish().map_err(error)?;
+    progress(1.0);
+    Ok(out)
+}
+
+#[cfg(not(feature = "vorbis"))]
+fn vorbis(_: &AudioBuffer, _: f32, _: impl FnMut(f32)) -> Result<Vec<u8>, ExportError> {
+    Err(ExportError::Unsupported(ExportFormat::Vorbis))
+}
+
+#[cfg(feature = "mp3")]
+fn mp3(
+    buffer: &AudioBuffer,
+    quality: f32,
+    mut progress: impl FnMut(f32),
+) -> Result<Vec<u8>, ExportError> {
+    use mp3lame_encoder::{Bitrate, Builder, DualPcm, FlushNoGap, MonoPcm, Quality};
+
+    let error = |message: String| ExportError::Encoder {
+        format: ExportFormat::Mp3,
+        message,
+    };
+    let bitrate = match (quality * 5.0).round() as u32 {
+        0 => Bitrate::Kbps96,
+        1 => Bitrate::Kbps128,
+        2 => Bitrate::Kbps160,
+        3 => Bitrate::Kbps192,
+        4 => Bitrate::Kbps256,
+        _ => Bitrate::Kbps320,
+    };
+    let mut builder = Builder::new().ok_or_else(|| error("LAME failed to start".into()))?;
+    builder
+        .set_num_channels(buffer.channels.len() as u8)
+        .map_err(|e| error(e.to_string()))?;
+    builder
+        .set_sample_rate(buffer.sample_rate)
+        .map_err(|e| error(e.to_string()))?;
+    builder
+        .set_brate(bitrate)
+        .map_err(|e| error(e.to_string()))?;
+    builder
+        .set_quality(Quality::Best)
+        .map_err(|e| error(e.to_string()))?;
+    let mut encoder = builder.build().map_err(|e| error(e.to_string()))?;
+
+    let pcm = |channel: &[f32]| -> Vec<i16> {
+        channel
+            .iter()
+            .map(|&s| wav::quantize(s, 16) as i16)
+            .collect()
+    };
+    let frames = buffer.frames();
+    let mut out = Vec::new();
+    for start in (0..frames).step_by(CHUNK_FRAMES) {
+        let end = (start + CHUNK_FRAMES).min(frames);
+        let channels: Vec<Vec<i16>> = buffer
+            .channels
+            .iter()
+            .map(|c| pcm(&c[start..end]))
+            .collect();
+        out.reserve(mp3lame_encoder::max_required_buffer_size(end - start));
+        let written = match channels.as_slice() {
+            [mono] => encoder.encode(MonoPcm(mono.as_slice()), out.spare_capacity_mut()),
+            [left, right] => encoder.encode(
+                DualPcm {
+                    left: left.as_slice(),
+                    right: right.as_slice(),
+                },
+                out.spare_capacity_mut(),
+            ),
+            _ => unreachable!("validated channel count"),
+        }
+        .map_err(|e| error(e.to_string()))?;
+        // SAFETY: the encoder initialized `written` bytes of the spare
+        // capacity it was given.
+        unsafe { out.set_len(out.len() + written) };
+        progress(end as f32 / frames as f32);
+    }
+    out.reserve(7200);
+    let written = encoder
+        .flush::<FlushNoGap>(out.spare_capacity_mut())
+        .map_err(|e| error(e.to_string()))?;
+    // SAFETY: as above.
+    unsafe { out.set_len(out.len() + written) };
+    progress(1.0);
+    Ok(out)
+}
+
+#[cfg(not(feature = "mp3"))]
+fn mp3(_: &AudioBuffer, _: f32, _: impl FnMut(f32)) -> Result<Vec<u8>, ExportError> {
+    Err(ExportError::Unsupported(ExportFormat::Mp3))
+}
+
+/// Frames per Opus packet: 20 ms.
+#[cfg(feature = "opus")]
+const OPUS_FRAME: usize = 960;
+
+/// Pages are closed every second of packets so players can seek.
+#[cfg(feature = "opus")]
+const OPUS_PACKETS_PER_PAGE: usize = 50;
+
+/// `buffer` is at 48 kHz; `input_rate` goes in the header.
+#[cfg(feature = "opus")]
+fn opus(
+    buffer: &AudioBuffer,
+    input_rate: u32,
+    quality: f32,
+    mut progress: impl FnMut(f32),
+) -> Result<Vec<u8>, ExportError> {
+    use audiopus::coder::Encoder;
+    use audiopus::{Application, Bitrate, Channels, SampleRate};
+
+    use super::ogg::OggWriter;
+
+    let error = |e: audiopus::Error| ExportError::Encoder {
+        format: ExportFormat::Opus,
+        message: e.to_string(),
+    };
+    let channels = buffer.channels.len();
+    let layout = match channels {
+        1 => Channels::Mono,
+        _ => Channels::Stereo,
+    };
+    let mut encoder =
+        Encoder::new(SampleRate::Hz48000, layout, Application::Audio).map_err(error)?;
+    let bitrate = (24_000.0 + quality * 104_000.0) as i32 * channels as i32;
+    encoder
+        .set_bitrate(Bitrate::BitsPerSecond(bitrate))
+        .map_err(error)?;
+    // Decoders drop this many frames from the start to undo the encoder's
+    // delay.
+    let pre_skip = encoder.lookahead().map_err(error)?;
+
+    // A single stream in a file of its own, so any serial number will do.
+    let mut ogg = OggWriter::new(0x4f70_7573);
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    head.push(channels as u8);
+    head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+    head.extend_from_slice(&input_rate.to_le_bytes());
+    // No output gain; mapping family 0, mono or stereo.
+    head.extend_from_slice(&[0, 0, 0]);
+    ogg.packet(&head, 0);
+    ogg.flush();
+    let vendor = env!("CARGO_PKG_NAME").as_bytes();
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor);
+    tags.extend_from_slice(&0u32.to_le_bytes());
+    ogg.packet(&tags, 0);
+    ogg.flush();
+
+    // Code past the end far enough to cover the delay; the last granule
+    // position then trims the padding back off.
+    let frames = buffer.frames();
+    let total = frames + pre_skip as usize;
+    let packets = total.div_ceil(OPUS_FRAME);
+    let mut pcm = vec![0.0f32; OPUS_FRAME * channels];
+    // The packet size libopus recommends allowing for.
+    let mut packet = vec![0u8; 4000];
+    for index in 0..packets {
+        let start = index * OPUS_FRAME;
+        let end = (start + OPUS_FRAME).min(frames);
+        pcm.fill(0.0);
+        for (c, channel) in buffer.channels.iter().enumerate() {
+            for (i, &sample) in channel.get(start..end).unwrap_or(&[]).iter().enumerate() {
+                pcm[i * channels + c] = sample;
+            }
+        }
+        let len = encoder.encode_float(&pcm, &mut packet).map_err(error)?;
+        let granule = ((index + 1) * OPUS_FRAME).min(total);
+        ogg.packet(&packet[..len], granule as u64);
+        if (index + 1) % OPUS_PACKETS_PER_PAGE == 0 {
+            ogg.flush();
+        }
+        if (index + 1) % (CHUNK_FRAMES / OPUS_FRAME) == 0 {
+            progress((index + 1) as f32 / packets as f32);
+        }
+    }
+    progress(1.0);
+    Ok(ogg.finish())
+}
+
+#[cfg(not(feature = "opus"))]
+fn opus(_: &AudioBuffer, _: u32, _: f32, _: impl FnMut(f32)) -> Result<Vec<u8>, ExportError> {
+    Err(ExportError::Unsupported(ExportFormat::Opus))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_settings_and_resamples() {
+        let buffer = AudioBuffer::new(8000, vec![vec![0.0; 800], vec![0.0; 800]]);
+        let options = |format, bit_depth| ExportOptions {
+            format,
+            bit_depth,
+            ..ExportOptions::default()
+        };
+        assert!(matches!(
+            export(&buffer, &options(ExportFormat::Flac, 32), |_| {}),
+            Err(ExportError::Invalid(_))
+        ));
+        let mp3 = ExportOptions {
+            sample_rate: Some(96000),
+            ..options(ExportFormat::Mp3, 16)
+        };
+        assert!(matches!(
+            export(&buffer, &mp3, |_| {}),
+            Err(ExportError::Invalid(_))
+        ));
+
+        let mut progress = Vec::new();
+        let wav = ExportOptions {
+            sample_rate: Some(16000),
+            ..options(ExportFormat::Wav, 24)
+        };
+        let bytes = export(&buffer, &wav, |p| progress.push(p)).unwrap();
+        let decoded = wav::decode(&bytes).unwrap();
+        assert_eq!((decoded.sample_rate, decoded.frames()), (16000, 1600));
+        assert_eq!(progress, [1.0]);
+    }
+
+    fn tone(rate: u32, channels: usize) -> AudioBuffer {
+        let frames = rate as usize;
+        let channel = |c: usize| -> Vec<f32> {
+            (0..frames)
+                .map(|i| {
+                    let t = i as f32 / rate as f32;
+                    (std::f32::consts::TAU * 440.0 * (c + 1) as f32 * t).sin() * 0.5
+                })
+                .collect()
+        };
+        AudioBuffer::new(rate, (0..channels).map(channel).collect())
+    }
+
+    fn ogg_packets(bytes: Vec<u8>) -> Vec<ogg::Packet> {
+        let mut reader = ogg::PacketReader::new(std::io::Cursor::new(bytes));
+        std::iter::from_fn(|| reader.read_packet().unwrap()).collect()
+    }
+
+    #[test]
+    fn flac_export_reads_back_bit_exact() {
+        let buffer = tone(44100, 2);
+        let options = ExportOptions {
+            format: ExportFormat::Flac,
+            bit_depth: 24,
+            sample_rate: Some(48000),
+            ..ExportOptions::default()
+        };
+        let bytes = export(&buffer, &options, |_| {}).unwrap();
+        let expected = resample::resample(&buffer, 48000);
+
+        let mut reader = claxon::FlacReader::new(std::io::Cursor::new(bytes)).unwrap();
+        let info = reader.streaminfo();
+        assert_eq!((info.sample_rate, info.channels), (48000, 2));
+        let samples: Vec<i32> = reader.samples().map(Result::unwrap).collect();
+        assert_eq!(samples.len(), expected.frames() * 2);
+        for (i, &sample) in samples.iter().enumerate() {
+            let original = expected.channels[i % 2][i / 2];
+            assert_eq!(sample, wav::quantize(original, 24));
+        }
+    }
+
+    #[cfg(feature = "vorbis")]
+    #[test]
+    fn vorbis_export_is_an_ogg_vorbis_stream() {
+        let buffer = tone(44100, 2);
+        let options = ExportOptions {
+            format: ExportFormat::Vorbis,
+            ..ExportOptions::default()
+        };
+        let mut progress = Vec::new();
+        let packets = ogg_packets(export(&buffer, &options, |p| progress.push(p)).unwrap());
+        assert_eq!(progress.last(), Some(&1.0));
+
+        let identification = &packets[0].data;
+        assert_eq!(&identification[..7], b"\x01vorbis");
+        assert_eq!(identification[11], 2);
+        assert_eq!(&identification[12..16], &44100u32.to_le_bytes());
+        let last = packets.last().unwrap();
+        assert!(last.last_in_stream());
+        assert_eq!(last.absgp_page(), buffer.frames() as u64);
+    }
+
+    #[cfg(feature = "mp3")]
+    #[test]
+    fn mp3_export_starts_with_an_mpeg1_layer3_frame() {
+        let buffer = tone(44100, 2);
+        let options = ExportOptions {
+            format: ExportFormat::Mp3,
+            quality: 0.0,
+            ..ExportOptions::default()
+        };
+        let mut progress = Vec::new();
+        let bytes = export(&buffer, &options, |p| progress.push(p)).unwrap();
+        assert_eq!(progress.last(), Some(&1.0));
+
+        // Sync, MPEG-1, layer III; then the 96 kbit/s index and 44.1 kHz.
+        assert_eq!(bytes[0], 0xff);
+        assert_eq!(bytes[1] & 0xfe, 0xfa);
+        assert_eq!(bytes[2] >> 4, 0b0111);
+        assert_eq!(bytes[2] >> 2 & 0b11, 0b00);
+        // About a second at 96 kbit/s.
+        assert!((10_000..14_000).contains(&bytes.len()), "{}", bytes.len());
+    }
+
+    #[cfg(feature = "opus")]
+    #[test]
+    fn opus_export_is_an_ogg_opus_stream() {
+        let buffer = tone(44100, 2);
+        let options = ExportOptions {
+            format: ExportFormat::Opus,
+            ..ExportOptions::default()
+        };
+        let mut progress = Vec::new();
+        let packets = ogg_packets(export(&buffer, &options, |p| progress.push(p)).unwrap());
+        assert_eq!(progress.last(), Some(&1.0));
+
+        let head = &packets[0].data;
+        assert_eq!(&head[..8], b"OpusHead");
+        assert_eq!((head[8], head[9]), (1, 2));
+        let pre_skip = u16::from_le_bytes([head[10], head[11]]) as u64;
+        assert_eq!(&head[12..16], &44100u32.to_le_bytes());
+        assert!(packets[0].last_in_page());
+        assert_eq!(&packets[1].data[..8], b"OpusTags");
+        assert!(packets[1].last_in_page());
+
+        // A second of audio at 48 kHz, plus the encoder delay.
+        let audio = &packets[2..];
+        assert_eq!(
+            audio.len(),
+            (48000 + pre_skip as usize).div_ceil(OPUS_FRAME)
+        );
+        let last = audio.last().unwrap();
+        assert!(last.last_in_stream());
+        assert_eq!(last.absgp_page(), 48000 + pre_skip);
+    }
+
+    #[test]
+    fn opus_takes_mono_or_stereo() {
+        let options = ExportOptions {
+            format: ExportFormat::Opus,
+            ..ExportOptions::default()
+        };
+        assert!(matches!(
+            export(&tone(8000, 3), &options, |_| {}),
+            Err(ExportError::Invalid(_))
+        ));
+        let mono = export(&tone(8000, 1), &options, |_| {});
+        if cfg!(feature = "opus") {
+            assert_eq!(&ogg_packets(mono.unwrap())[0].data[..8], b"OpusHead");
+        } else {
+            assert!(matches!(
+                mono,
+                Err(ExportError::Unsupported(ExportFormat::Opus))
+            ));
+        }
+    }
+}
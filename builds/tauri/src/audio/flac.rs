@@ -0,0 +1,704 @@
+//! FLAC encoding.
+//!
+//! A straightforward encoder in the style of `flac -2`: fixed-size blocks,
+//! the fixed polynomial predictors of orders 0 to 4, partitioned Rice
+//! coding of the residual and, for stereo, whichever of the four channel
+//! decorrelations codes smallest. Every choice is made on estimated bit
+//! counts, so the cost per block stays linear in its length.
+
+use super::buffer::AudioBuffer;
+use super::wav::quantize;
+
+const BLOCK_SIZE: usize = 4096;
+/// Residual coding method 0 (`RICE`) has 4-bit parameters, method 1
+/// (`RICE2`) 5-bit ones; the all-ones value is reserved for escapes.
+const MAX_RICE_PARAM: [u32; 2] = [14, 30];
+
+/// Appends big-endian bit fields to a byte buffer.
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    /// Writes the low `bits` (at most 32) of `value`.
+    fn write(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        self.acc = (self.acc << bits) | (value & ((1u64 << bits) - 1));
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.acc >> self.bits) as u8);
+        }
+        self.acc &= (1u64 << self.bits) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    /// `zeros` zero bits followed by a one.
+    fn write_unary(&mut self, mut zeros: u64) {
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        }
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// The frame number in FLAC's extended UTF-8 coding.
+fn write_utf8(writer: &mut BitWriter, value: u32) {
+    if value < 0x80 {
+        writer.write(value as u64, 8);
+        return;
+    }
+    let continuation = match value {
+        0x80..=0x7ff => 1,
+        0x800..=0xffff => 2,
+        0x1_0000..=0x1f_ffff => 3,
+        0x20_0000..=0x3ff_ffff => 4,
+        _ => 5,
+    };
+    let lead_mask = 0xffu32 << (7 - continuation) & 0xff;
+    writer.write((lead_mask | (value >> (6 * continuation))) as u64 & 0xff, 8);
+    for i in (0..continuation).rev() {
+        writer.write((0x80 | ((value >> (6 * i)) & 0x3f)) as u64, 8);
+    }
+}
+
+/// Residual of the fixed predictor of `order` for every sample after the
+/// warm-up ones.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    samples
+        .windows(order + 1)
+        .map(|w| match order {
+            0 => w[0],
+            1 => w[1] - w[0],
+            2 => w[2] - 2 * w[1] + w[0],
+            3 => w[3] - 3 * w[2] + 3 * w[1] - w[0],
+            _ => w[4] - 4 * w[3] + 6 * w[2] - 4 * w[1] + w[0],
+        })
+        .collect()
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Rice parameter and estimated cost in bits for `count` values whose
+/// zigzagged sum is `sum`.
+fn rice_param(sum: u64, count: usize, max: u32) -> (u32, u64) {
+    let count = count as u64;
+    if count == 0 {
+        return (0, 0);
+    }
+    let mean = sum / count;
+    let k = (u64::BITS - mean.leading_zeros()).min(max);
+    (k, count * (k as u64 + 1) + (sum >> k))
+}
+
+struct RicePlan {
+    order: u32,
+    params: Vec<u32>,
+    bits: u64,
+}
+
+/// Picks the partition order and per-partition parameters for `residual`,
+/// which follows `warmup` unpredicted samples in a block.
+fn plan_rice(residual: &[i64], warmup: usize, max_order: u32) -> RicePlan {
+    let block = residual.len() + warmup;
+    let folded: Vec<u64> = residual.iter().map(|&r| zigzag(r)).collect();
+    let mut best: Option<RicePlan> = None;
+    for order in 0..=max_order {
+        let partitions = 1usize << order;
+        if !block.is_multiple_of(partitions) || block / partitions <= warmup {
+            break;
+        }
+        let mut params = Vec::with_capacity(partitions);
+        let mut bits = 0;
+        let mut start = 0;
+        for p in 0..partitions {
+            let len = block / partitions - if p == 0 { warmup } else { 0 };
+            let sum = folded[start..start + len].iter().sum();
+            let (k, cost) = rice_param(sum, len, MAX_RICE_PARAM[1]);
+            params.push(k);
+            bits += 5 + cost;
+            start += len;
+        }
+        if best.as_ref().is_none_or(|b| bits < b.bits) {
+            best = Some(RicePlan {
+                order,
+                params,
+                bits,
+            });
+        }
+    }
+    best.expect("partition order 0 always fits")
+}
+
+enum Subframe {
+    Constant(i64),
+    Verbatim,
+    Fixed {
+        order: usize,
+        residual: Vec<i64>,
+        rice: RicePlan,
+    },
+}
+
+/// The cheapest coding of one channel of a block, and its size in bits.
+fn plan_subframe(samples: &[i64], bps: u32, max_partition_order: u32) -> (Subframe, u64) {
+    if samples.iter().all(|&s| s == samples[0]) {
+        return (Subframe::Constant(samples[0]), 8 + bps as u64);
+    }
+    let mut best = (Subframe::Verbatim, 8 + samples.len() as u64 * bps as u64);
+    for order in 0..=4.min(samples.len() - 1) {
+        let residual = fixed_residual(samples, order);
+        let rice = plan_rice(&residual, order, max_partition_order);
+        let bits = 8 + order as u64 * bps as u64 + 6 + rice.bits;
+        if bits < best.1 {
+            best = (
+                Subframe::Fixed {
+                    order,
+                    residual,
+                    rice,
+                },
+                bits,
+            );
+        }
+    }
+    best
+}
+
+fn write_subframe(writer: &mut BitWriter, subframe: &Subframe, samples: &[i64], bps: u32) {
+    match subframe {
+        Subframe::Constant(value) => {
+            writer.write(0b0000_0000, 8);
+            writer.write_signed(*value, bps);
+        }
+        Subframe::Verbatim => {
+            writer.write(0b0000_0010, 8);
+            for &sample in samples {
+                writer.write_signed(sample, bps);
+            }
+        }
+        Subframe::Fixed {
+            order,
+            residual,
+            rice,
+        } => {
+            writer.write(0b0001_0000 | (*order as u64) << 1, 8);
+            for &sample in &samples[..*order] {
+                writer.write_signed(sample, bps);
+            }
+            let method = usize::from(rice.params.iter().any(|&k| k > MAX_RICE_PARAM[0]));
+            writer.write(method as u64, 2);
+            writer.write(rice.order as u64, 4);
+            let partitions = 1usize << rice.order;
+            let mut start = 0;
+            for (p, &k) in rice.params.iter().enumerate() {
+                writer.write(k as u64, 4 + method as u32);
+                let len = samples.len() / partitions - if p == 0 { *order } else { 0 };
+                for &value in &residual[start..start + len] {
+                    let folded = zigzag(value);
+                    writer.write_unary(folded >> k);
+                    writer.write(folded, k);
+                }
+                start += len;
+            }
+        }
+    }
+}
+
+/// How a block's channels are coded: the channel assignment code from the
+/// frame header, and each subframe with its samples and bit depth.
+struct Coding {
+    assignment: u64,
+    subframes: Vec<(Vec<i64>, u32, Subframe)>,
+    bits: u64,
+}
+
+impl Coding {
+    fn new(assignment: u64, channels: Vec<(Vec<i64>, u32)>, max_partition_order: u32) -> Self {
+        let mut bits = 0;
+        let subframes = channels
+            .into_iter()
+            .map(|(samples, bps)| {
+                let (subframe, cost) = plan_subframe(&samples, bps, max_partition_order);
+                bits += cost;
+                (samples, bps, subframe)
+            })
+            .collect();
+        Self {
+            assignment,
+            subframes,
+            bits,
+        }
+    }
+}
+
+/// The smallest coding of a block: channels independently or, for stereo,
+/// the best of left/side, side/right and mid/side as well.
+fn plan_block(channels: &[Vec<i64>], bps: u32, max_partition_order: u32) -> Coding {
+    let independent: Vec<(Vec<i64>, u32)> = channels.iter().map(|c| (c.clone(), bps)).collect();
+    let independent = Coding::new(channels.len() as u64 - 1, independent, max_partition_order);
+    let [left, right] = channels else {
+        return independent;
+    };
+    // Side needs one more bit than the channels it is the difference of.
+    let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+    let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+    [
+        (8, vec![(left.clone(), bps), (side.clone(), bps + 1)]),
+        (9, vec![(side.clone(), bps + 1), (right.clone(), bps)]),
+        (10, vec![(mid, bps), (side, bps + 1)]),
+    ]
+    .into_iter()
+    .map(|(assignment, coded)| Coding::new(assignment, coded, max_partition_order))
+    .fold(independent, |best, coding| {
+        if coding.bits < best.bits {
+            coding
+        } else {
+            best
+        }
+    })
+}
+
+/// The frame header's sample rate code, plus the field that follows the
+/// block size for rates without a code of their own. Rates no field can
+/// hold fall back to STREAMINFO.
+fn sample_rate_code(rate: u32) -> (u64, Option<(u64, u32)>) {
+    let code = match rate {
+        88_200 => 0b0001,
+        176_400 => 0b0010,
+        192_000 => 0b0011,
+        8_000 => 0b0100,
+        16_000 => 0b0101,
+        22_050 => 0b0110,
+        24_000 => 0b0111,
+        32_000 => 0b1000,
+        44_100 => 0b1001,
+        48_000 => 0b1010,
+        96_000 => 0b1011,
+        _ if rate.is_multiple_of(1000) && rate / 1000 <= 0xff => {
+            return (0b1100, Some((rate as u64 / 1000, 8)))
+        }
+        _ if rate <= 0xffff => return (0b1101, Some((rate as u64, 16))),
+        _ if rate.is_multiple_of(10) && rate / 10 <= 0xffff => {
+            return (0b1110, Some((rate as u64 / 10, 16)))
+        }
+        _ => 0b0000,
+    };
+    (code, None)
+}
+
+/// The frame header's sample size code; depths without one fall back to
+/// STREAMINFO.
+fn depth_code(bps: u32) -> u64 {
+    match bps {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        _ => 0b000,
+    }
+}
+
+fn write_frame(
+    out: &mut Vec<u8>,
+    number: u32,
+    channels: &[Vec<i64>],
+    sample_rate: u32,
+    bps: u32,
+    max_partition_order: u32,
+) {
+    let len = channels[0].len();
+    let coding = plan_block(channels, bps, max_partition_order);
+    let (rate_code, rate_field) = sample_rate_code(sample_rate);
+
+    let mut writer = BitWriter::new();
+    // Sync code, fixed-blocksize stream; block size as a 16-bit field at
+    // the end of the header, followed by the rate when it has no code.
+    // Some decoders ignore STREAMINFO here, so rate and depth are explicit.
+    writer.write(0xfff8, 16);
+    writer.write(0b0111, 4);
+    writer.write(rate_code, 4);
+    writer.write(coding.assignment, 4);
+    writer.write(depth_code(bps), 3);
+    writer.write(0, 1);
+    write_utf8(&mut writer, number);
+    writer.write(len as u64 - 1, 16);
+    if let Some((value, bits)) = rate_field {
+        writer.write(value, bits);
+    }
+    let crc = crc8(&writer.bytes);
+    writer.write(crc as u64, 8);
+
+    for (samples, bps, subframe) in &coding.subframes {
+        write_subframe(&mut writer, subframe, samples, *bps);
+    }
+    writer.align();
+    let crc = crc16(&writer.bytes);
+    writer.write(crc as u64, 16);
+    out.extend_from_slice(&writer.bytes);
+}
+
+/// Encodes `buffer` as FLAC at `bits_per_sample` (8 to 24). `effort` from
+/// 0 to 1 trades speed for size. `progress` is called with the fraction
+/// done after every block.
+pub fn encode(
+    buffer: &AudioBuffer,
+    bits_per_sample: u16,
+    effort: f32,
+    mut progress: impl FnMut(f32),
+) -> Vec<u8> {
+    debug_assert!((8..=24).contains(&bits_per_sample));
+    debug_assert!((1..=8).contains(&buffer.channels.len()));
+    let bps = bits_per_sample as u32;
+    let max_partition_order = 2 + (effort.clamp(0.0, 1.0) * 6.0).round() as u32;
+    let frames = buffer.frames();
+
+    let mut encoded = Vec::new();
+    let mut frame_sizes = (u32::MAX, 0u32);
+    let blocks = frames.div_ceil(BLOCK_SIZE);
+    for (number, start) in (0..frames).step_by(BLOCK_SIZE).enumerate() {
+        let end = (start + BLOCK_SIZE).min(frames);
+        let channels: Vec<Vec<i64>> = buffer
+            .channels
+            .iter()
+            .map(|channel| {
+                channel[start..end]
+                    .iter()
+                    .map(|&s| quantize(s, bits_per_sample) as i64)
+                    .collect()
+            })
+            .collect();
+        let before = encoded.len();
+        write_frame(
+            &mut encoded,
+            number as u32,
+            &channels,
+            buffer.sample_rate,
+            bps,
+            max_partition_order,
+        );
+        let size = (encoded.len() - before) as u32;
+        frame_sizes = (frame_sizes.0.min(size), frame_sizes.1.max(size));
+        progress((number + 1) as f32 / blocks as f32);
+    }
+    if blocks == 0 {
+        frame_sizes = (0, 0);
+        progress(1.0);
+    }
+
+    let mut header = BitWriter::new();
+    header.write(u32::from_be_bytes(*b"fLaC") as u64, 32);
+    // Last-metadata-block flag, STREAMINFO type, 34-byte body.
+    header.write(1, 1);
+    header.write(0, 7);
+    header.write(34, 24);
+    header.write(BLOCK_SIZE as u64, 16);
+    header.write(BLOCK_SIZE as u64, 16);
+    header.write(frame_sizes.0 as u64, 24);
+    header.write(frame_sizes.1 as u64, 24);
+    header.write(buffer.sample_rate as u64, 20);
+    header.write(buffer.channels.len() as u64 - 1, 3);
+    header.write(bps as u64 - 1, 5);
+    header.write((frames as u64) >> 32, 4);
+    header.write(frames as u64, 32);
+    // No MD5 of the audio; all zeros means "not computed".
+    for _ in 0..4 {
+        header.write(0, 32);
+    }
+    let mut out = header.bytes;
+    out.extend(encoded);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads big-endian bit fields, for decoding what the encoder wrote.
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl BitReader<'_> {
+        fn read(&mut self, bits: u32) -> u64 {
+            (0..bits).fold(0, |value, _| {
+                let bit = self.bytes[self.position / 8] >> (7 - self.position % 8) & 1;
+                self.position += 1;
+                value << 1 | bit as u64
+            })
+        }
+
+        fn read_signed(&mut self, bits: u32) -> i64 {
+            let value = self.read(bits) as i64;
+            value << (64 - bits) >> (64 - bits)
+        }
+
+        fn byte_position(&self) -> usize {
+            self.position / 8
+        }
+    }
+
+    fn decode_subframe(reader: &mut BitReader, len: usize, bps: u32) -> Vec<i64> {
+        assert_eq!(reader.read(1), 0);
+        let kind = reader.read(6);
+        assert_eq!(reader.read(1), 0, "no wasted bits");
+        match kind {
+            0 => vec![reader.read_signed(bps); len],
+            1 => (0..len).map(|_| reader.read_signed(bps)).collect(),
+            8..=12 => {
+                let order = (kind - 8) as usize;
+                let mut samples: Vec<i64> = (0..order).map(|_| reader.read_signed(bps)).collect();
+                let method = reader.read(2);
+                let partition_order = reader.read(4);
+                let partitions = 1usize << partition_order;
+                let mut residual = Vec::new();
+                for p in 0..partitions {
+                    let k = reader.read(4 + method as u32) as u32;
+                    let count = len / partitions - if p == 0 { order } else { 0 };
+                    for _ in 0..count {
+                        let mut q = 0;
+                        while reader.read(1) == 0 {
+                            q += 1;
+                        }
+                        let folded = q << k | reader.read(k);
+                        residual.push((folded >> 1) as i64 ^ -((folded & 1) as i64));
+                    }
+                }
+                for r in residual {
+                    let n = samples.len();
+                    let s = &samples;
+                    let prediction = match order {
+                        0 => 0,
+                        1 => s[n - 1],
+                        2 => 2 * s[n - 1] - s[n - 2],
+                        3 => 3 * s[n - 1] - 3 * s[n - 2] + s[n - 3],
+                        _ => 4 * s[n - 1] - 6 * s[n - 2] + 4 * s[n - 3] - s[n - 4],
+                    };
+                    samples.push(prediction + r);
+                }
+                samples
+            }
+            other => panic!("unexpected subframe type {other}"),
+        }
+    }
+
+    /// Decodes the subset of FLAC the encoder produces.
+    fn decode(bytes: &[u8]) -> (u32, u32, Vec<Vec<i64>>) {
+        assert_eq!(&bytes[..4], b"fLaC");
+        let mut reader = BitReader {
+            bytes,
+            position: 32,
+        };
+        assert_eq!(reader.read(1), 1);
+        assert_eq!(reader.read(7), 0);
+        assert_eq!(reader.read(24), 34);
+        reader.read(16 + 16 + 24 + 24);
+        let rate = reader.read(20) as u32;
+        let channel_count = reader.read(3) as usize + 1;
+        let bps = reader.read(5) as u32 + 1;
+        let total = reader.read(36) as usize;
+        reader.read(128);
+
+        let mut channels = vec![Vec::new(); channel_count];
+        while channels[0].len() < total {
+            let start = reader.byte_position();
+            assert_eq!(reader.read(16), 0xfff8);
+            assert_eq!(reader.read(4), 0b0111);
+            let (rate_code, rate_field) = sample_rate_code(rate);
+            assert_eq!(reader.read(4), rate_code);
+            let assignment = reader.read(4);
+            assert_eq!(reader.read(3), depth_code(bps));
+            assert_eq!(reader.read(1), 0);
+            let lead = reader.read(8);
+            for _ in 0..(lead as u8).leading_ones().saturating_sub(1) {
+                reader.read(8);
+            }
+            let len = reader.read(16) as usize + 1;
+            if let Some((value, bits)) = rate_field {
+                assert_eq!(reader.read(bits), value);
+            }
+            let crc = crc8(&bytes[start..reader.byte_position()]);
+            assert_eq!(reader.read(8), crc as u64);
+
+            let depth = |side: bool| bps + side as u32;
+            let block: Vec<Vec<i64>> = match assignment {
+                8 => {
+                    let left = decode_subframe(&mut reader, len, depth(false));
+                    let side = decode_subframe(&mut reader, len, depth(true));
+                    let right = left.iter().zip(&side).map(|(l, s)| l - s).collect();
+                    vec![left, right]
+                }
+                9 => {
+                    let side = decode_subframe(&mut reader, len, depth(true));
+                    let right = decode_subframe(&mut reader, len, depth(false));
+                    let left = side.iter().zip(&right).map(|(s, r)| s + r).collect();
+                    vec![left, right]
+                }
+                10 => {
+                    let mid = decode_subframe(&mut reader, len, depth(false));
+                    let side = decode_subframe(&mut reader, len, depth(true));
+                    let (left, right) = mid
+                        .iter()
+                        .zip(&side)
+                        .map(|(&m, &s)| {
+                            let sum = m * 2 + (s & 1);
+                            ((sum + s) >> 1, (sum - s) >> 1)
+                        })
+                        .unzip();
+                    vec![left, right]
+                }
+                n => (0..=n)
+                    .map(|_| decode_subframe(&mut reader, len, bps))
+                    .collect(),
+            };
+            reader.position = reader.position.div_ceil(8) * 8;
+            let crc = crc16(&bytes[start..reader.byte_position()]);
+            assert_eq!(reader.read(16), crc as u64);
+            for (channel, samples) in channels.iter_mut().zip(block) {
+                channel.extend(samples);
+            }
+        }
+        assert_eq!(reader.byte_position(), bytes.len());
+        (rate, bps, channels)
+    }
+
+    #[test]
+    fn decodes_back_to_the_quantized_samples() {
+        let rate = 22050;
+        let frames = BLOCK_SIZE * 2 + 1234;
+        let tone = |f: f32| -> Vec<f32> {
+            (0..frames)
+                .map(|i| (std::f32::consts::TAU * f * i as f32 / rate as f32).sin() * 0.4)
+                .collect()
+        };
+        let mut left = tone(440.0);
+        // A silent stretch codes as constant subframes.
+        left[..BLOCK_SIZE].fill(0.0);
+        let right: Vec<f32> = tone(440.0)
+            .iter()
+            .zip(tone(3000.0))
+            .map(|(a, b)| a + b * 0.3)
+            .collect();
+
+        for bits in [16, 24] {
+            let buffer = AudioBuffer::new(rate, vec![left.clone(), right.clone()]);
+            let mut reported = Vec::new();
+            let bytes = encode(&buffer, bits, 0.5, |p| reported.push(p));
+            assert_eq!(reported.last(), Some(&1.0));
+            let raw = frames * 2 * bits as usize / 8;
+            assert!(bytes.len() < raw * 3 / 4, "{} of {raw} bytes", bytes.len());
+
+            let (decoded_rate, bps, channels) = decode(&bytes);
+            assert_eq!((decoded_rate, bps), (rate, bits as u32));
+            for (decoded, original) in channels.iter().zip(&buffer.channels) {
+                let expected: Vec<i64> =
+                    original.iter().map(|&s| quantize(s, bits) as i64).collect();
+                assert_eq!(decoded, &expected);
+            }
+        }
+
+        // Mono, and noise that only verbatim subframes can hold.
+        let mut state = 0x2545_f491u32;
+        let noise: Vec<f32> = (0..1000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as f32 / u32::MAX as f32 * 2.0 - 1.0
+            })
+            .collect();
+        let buffer = AudioBuffer::new(8000, vec![noise.clone()]);
+        let (_, _, channels) = decode(&encode(&buffer, 8, 1.0, |_| {}));
+        let expected: Vec<i64> = noise.iter().map(|&s| quantize(s, 8) as i64).collect();
+        assert_eq!(channels[0], expected);
+    }
+
+    #[test]
+    fn an_independent_decoder_reads_every_depth_and_rate() {
+        let frames = BLOCK_SIZE + 700;
+        let channel = |phase: f32| -> Vec<f32> {
+            (0..frames)
+                .map(|i| (i as f32 * 0.05 + phase).sin() * 0.6)
+                .collect()
+        };
+        // Coded rates, and ones carried in kHz, Hz and tens of Hz.
+        for (rate, bits, channels) in [
+            (44_100, 16, 2),
+            (48_000, 24, 1),
+            (8_000, 8, 2),
+            (100_000, 16, 1),
+            (11_025, 24, 2),
+            (176_410, 8, 1),
+        ] {
+            let buffer = AudioBuffer::new(rate, (0..channels).map(|c| channel(c as f32)).collect());
+            let bytes = encode(&buffer, bits, 0.5, |_| {});
+
+            let mut reader = claxon::FlacReader::new(std::io::Cursor::new(bytes)).unwrap();
+            let info = reader.streaminfo();
+            assert_eq!(
+                (info.sample_rate, info.bits_per_sample),
+                (rate, bits as u32)
+            );
+            let samples: Vec<i32> = reader.samples().map(Result::unwrap).collect();
+            let expected: Vec<i32> = (0..frames)
+                .flat_map(|i| buffer.channels.iter().map(move |c| c[i]))
+                .map(|s| quantize(s, bits))
+                .collect();
+            assert_eq!(samples, expected, "{rate} Hz, {bits} bits");
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Native audio I/O and buffer handling.
+
+pub mod buffer;
+pub mod capture;
+pub mod export;
+pub mod flac;
+#[cfg_attr(not(feature = "opus"), allow(dead_code))]
+mod ogg;
+pub mod resample;
+pub mod wav;
+
+use serde::{Serialize, Serializer};
+
+use crate::sandbox::SandboxError;
+
+use self::buffer::BufferId;
+use self::wav::WavError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AudioError {
+    #[error("no audio buffer with id {0}")]
+    UnknownBuffer(BufferId),
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("buffers must share a sample rate ({0} Hz vs {1} Hz)")]
+    SampleRateMismatch(u32, u32),
+    #[error(transparent)]
+    Wav(#[from] WavError),
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+}
+
+impl AudioError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AudioError::UnknownBuffer(_) => "unknownBuffer",
+            AudioError::InvalidArgument(_) => "invalidArgument",
+            AudioError::SampleRateMismatch(..) => "sampleRateMismatch",
+            AudioError::Wav(e) => e.kind(),
+            AudioError::Sandbox(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for AudioError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
@@ -0,0 +1,131 @@
+//! Ogg pages, the container Opus export is carried in.
+//!
+//! Only what a single logical stream of short packets needs: packets are
+//! laced onto a page until it runs out of segments or is flushed, and no
+//! packet spans two pages.
+
+/// Lacing values a page can hold.
+const MAX_SEGMENTS: usize = 255;
+
+const BEGINNING_OF_STREAM: u8 = 0x02;
+const END_OF_STREAM: u8 = 0x04;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |mut crc, &byte| {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Packs the packets of one logical stream into Ogg pages.
+pub(super) struct OggWriter {
+    serial: u32,
+    sequence: u32,
+    out: Vec<u8>,
+    lacing: Vec<u8>,
+    body: Vec<u8>,
+    granule: u64,
+}
+
+impl OggWriter {
+    pub(super) fn new(serial: u32) -> Self {
+        Self {
+            serial,
+            sequence: 0,
+            out: Vec::new(),
+            lacing: Vec::new(),
+            body: Vec::new(),
+            granule: 0,
+        }
+    }
+
+    /// Adds a packet to the open page. `granule` is the codec's position at
+    /// the packet's end, and becomes the page's once the page is closed.
+    pub(super) fn packet(&mut self, packet: &[u8], granule: u64) {
+        let segments = packet.len() / 255 + 1;
+        assert!(segments <= MAX_SEGMENTS, "packet too long for one page");
+        if self.lacing.len() + segments > MAX_SEGMENTS {
+            self.flush();
+        }
+        self.lacing
+            .extend(std::iter::repeat_n(255, packet.len() / 255));
+        self.lacing.push((packet.len() % 255) as u8);
+        self.body.extend_from_slice(packet);
+        self.granule = granule;
+    }
+
+    /// Closes the open page, so the next packet starts a new one.
+    pub(super) fn flush(&mut self) {
+        if !self.lacing.is_empty() {
+            self.page(0);
+        }
+    }
+
+    /// Closes the last page, marking the end of the stream.
+    pub(super) fn finish(mut self) -> Vec<u8> {
+        self.page(END_OF_STREAM);
+        self.out
+    }
+
+    fn page(&mut self, mut flags: u8) {
+        if self.sequence == 0 {
+            flags |= BEGINNING_OF_STREAM;
+        }
+        let start = self.out.len();
+        self.out.extend_from_slice(b"OggS");
+        self.out.push(0);
+        self.out.push(flags);
+        self.out.extend_from_slice(&self.granule.to_le_bytes());
+        self.out.extend_from_slice(&self.serial.to_le_bytes());
+        self.out.extend_from_slice(&self.sequence.to_le_bytes());
+        // The checksum covers the page with its own field zeroed.
+        self.out.extend_from_slice(&[0; 4]);
+        self.out.push(self.lacing.len() as u8);
+        self.out.append(&mut self.lacing);
+        self.out.append(&mut self.body);
+        let crc = crc32(&self.out[start..]);
+        self.out[start + 22..start + 26].copy_from_slice(&crc.to_le_bytes());
+        self.sequence += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_independent_reader_gets_the_packets_back() {
+        let packets: Vec<Vec<u8>> = (0..300usize)
+            .map(|i| (0..i * 7 % 600).map(|b| (b ^ i) as u8).collect())
+            .collect();
+        let mut writer = OggWriter::new(0x1234_5678);
+        writer.packet(b"head", 0);
+        writer.flush();
+        for (i, packet) in packets.iter().enumerate() {
+            writer.packet(packet, (i as u64 + 1) * 960);
+        }
+        let bytes = writer.finish();
+
+        let mut reader = ogg::PacketReader::new(std::io::Cursor::new(bytes));
+        let head = reader.read_packet().unwrap().unwrap();
+        assert_eq!(head.data, b"head");
+        assert!(head.first_in_stream() && head.last_in_page());
+        assert_eq!((head.absgp_page(), head.stream_serial()), (0, 0x1234_5678));
+        for (i, packet) in packets.iter().enumerate() {
+            let read = reader.read_packet().unwrap().unwrap();
+            assert_eq!(&read.data, packet, "packet {i}");
+            if read.last_in_page() {
+                assert_eq!(read.absgp_page(), (i as u64 + 1) * 960);
+            }
+            assert_eq!(read.last_in_stream(), i == packets.len() - 1);
+        }
+        assert!(reader.read_packet().unwrap().is_none());
+    }
+}
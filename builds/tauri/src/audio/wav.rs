@@ -0,0 +1,378 @@
+//! RIFF/WAVE decoding and encoding.
+//!
+//! Decoding accepts integer PCM at 8, 16, 24 and 32 bits and 32-bit IEEE
+//! float, with any channel count, in both the classic and
+//! `WAVE_FORMAT_EXTENSIBLE` layouts. Encoding writes the extensible layout
+//! only where the format requires it (more than two channels or integer
+//! samples wider than 16 bits).
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use super::buffer::AudioBuffer;
+use super::AudioError;
+
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Tail of the KSDATAFORMAT_SUBTYPE GUIDs; the first two bytes carry the
+/// classic format tag.
+const SUBFORMAT_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum WavError {
+    #[error("not a RIFF/WAVE file")]
+    NotWave,
+    #[error("WAV file has no `{0}` chunk")]
+    MissingChunk(&'static str),
+    #[error("malformed WAV file: {0}")]
+    Malformed(String),
+    #[error("unsupported WAV encoding: {0}")]
+    Unsupported(String),
+}
+
+impl WavError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WavError::NotWave => "notWave",
+            WavError::MissingChunk(_) => "missingChunk",
+            WavError::Malformed(_) => "malformedWav",
+            WavError::Unsupported(_) => "unsupportedWav",
+        }
+    }
+}
+
+impl Serialize for WavError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// Sample encoding written by [`encode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SampleFormat {
+    Pcm8,
+    #[default]
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    Float32,
+}
+
+impl SampleFormat {
+    pub fn bits(self) -> u16 {
+        match self {
+            SampleFormat::Pcm8 => 8,
+            SampleFormat::Pcm16 => 16,
+            SampleFormat::Pcm24 => 24,
+            SampleFormat::Pcm32 | SampleFormat::Float32 => 32,
+        }
+    }
+
+    fn bytes(self) -> usize {
+        self.bits() as usize / 8
+    }
+}
+
+struct Format {
+    tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    block_align: u16,
+    bits: u16,
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn parse_format(chunk: &[u8]) -> Result<Format, WavError> {
+    if chunk.len() < 16 {
+        return Err(WavError::Malformed("fmt chunk is too short".into()));
+    }
+    let mut format = Format {
+        tag: u16_at(chunk, 0),
+        channels: u16_at(chunk, 2),
+        sample_rate: u32_at(chunk, 4),
+        block_align: u16_at(chunk, 12),
+        bits: u16_at(chunk, 14),
+    };
+    if format.tag == FORMAT_EXTENSIBLE {
+        if chunk.len() < 40 {
+            return Err(WavError::Malformed(
+                "extensible fmt chunk is too short".into(),
+            ));
+        }
+        if chunk[26..40] != SUBFORMAT_TAIL {
+            return Err(WavError::Unsupported(
+                "unknown extensible sub-format".into(),
+            ));
+        }
+        format.tag = u16_at(chunk, 24);
+    }
+    if format.channels == 0 || format.sample_rate == 0 {
+        return Err(WavError::Malformed("zero channels or sample rate".into()));
+    }
+    Ok(format)
+}
+
+/// Decodes a complete WAV file into planar floating-point samples.
+pub fn decode(bytes: &[u8]) -> Result<AudioBuffer, WavError> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(WavError::NotWave);
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let declared = u32_at(bytes, offset + 4) as usize;
+        let body = offset + 8;
+        // Streaming writers leave the size at 0 or u32::MAX; a truncated file
+        // over-declares it. Either way, take what is there.
+        let end = body.saturating_add(declared).min(bytes.len());
+        match id {
+            b"fmt " => format = Some(parse_format(&bytes[body..end])?),
+            b"data" => {
+                let end = if declared == 0 { bytes.len() } else { end };
+                data = Some(&bytes[body..end]);
+            }
+            _ => {}
+        }
+        // Chunks are word aligned.
+        offset = body.saturating_add(declared).saturating_add(declared & 1);
+    }
+
+    let format = format.ok_or(WavError::MissingChunk("fmt "))?;
+    let data = data.ok_or(WavError::MissingChunk("data"))?;
+
+    let width = match (format.tag, format.bits) {
+        (FORMAT_PCM, 8) => 1,
+        (FORMAT_PCM, 16) => 2,
+        (FORMAT_PCM, 24) => 3,
+        (FORMAT_PCM, 32) | (FORMAT_IEEE_FLOAT, 32) => 4,
+        (tag, bits) => {
+            return Err(WavError::Unsupported(format!(
+                "format tag {tag:#06x} at {bits} bits"
+            )))
+        }
+    };
+    let channel_count = format.channels as usize;
+    let stride = (format.block_align as usize).max(width * channel_count);
+
+    let frames = data.len() / stride;
+    let mut channels = vec![Vec::with_capacity(frames); channel_count];
+    for frame in data.chunks_exact(stride) {
+        for (c, channel) in channels.iter_mut().enumerate() {
+            let s = &frame[c * width..(c + 1) * width];
+            channel.push(match (format.tag, width) {
+                (FORMAT_IEEE_FLOAT, _) => f32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+                (_, 1) => (s[0] as f32 - 128.0) / 128.0,
+                (_, 2) => i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0,
+                // Place the 24-bit sample in the top of an i32 to sign-extend it.
+                (_, 3) => i32::from_le_bytes([0, s[0], s[1], s[2]]) as f32 / 2_147_483_648.0,
+                _ => i32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f32 / 2_147_483_648.0,
+            });
+        }
+    }
+
+    Ok(AudioBuffer::new(format.sample_rate, channels))
+}
+
+pub(super) fn quantize(sample: f32, bits: u16) -> i32 {
+    let scale = (1i64 << (bits - 1)) as f64;
+    (sample as f64 * scale).round().clamp(-scale, scale - 1.0) as i32
+}
+
+fn channel_mask(channels: u16) -> u32 {
+    match channels {
+        1 => 0x4,
+        2 => 0x3,
+        _ => 0,
+    }
+}
+
+/// Encodes `buffer` as a WAV file in `format`. Samples outside [-1, 1] are
+/// clipped for integer formats. Audio whose sizes don't fit the header's
+/// 32-bit fields is rejected.
+pub fn encode(buffer: &AudioBuffer, format: SampleFormat) -> Result<Vec<u8>, AudioError> {
+    let channels = buffer.channel_count();
+    let frames = buffer.frames();
+    let width = format.bytes();
+    let block_align = width * channels as usize;
+    let too_large = || {
+        AudioError::InvalidArgument(format!(
+            "{frames} frames of {channels} channels don't fit a WAV file"
+        ))
+    };
+    let data_len = frames.checked_mul(block_align).ok_or_else(too_large)?;
+
+    let tag = if format == SampleFormat::Float32 {
+        FORMAT_IEEE_FLOAT
+    } else {
+        FORMAT_PCM
+    };
+    let extensible = channels > 2 || (tag == FORMAT_PCM && format.bits() > 16);
+    let fmt_len: usize = if extensible {
+        40
+    } else if tag == FORMAT_IEEE_FLOAT {
+        18
+    } else {
+        16
+    };
+    // Non-PCM formats carry a `fact` chunk with the frame count.
+    let fact_len = if tag == FORMAT_PCM { 0 } else { 12 };
+    let pad = data_len & 1;
+    let riff_len = 4 + (8 + fmt_len) + fact_len + (8 + pad);
+    let riff_len = data_len.checked_add(riff_len).ok_or_else(too_large)?;
+    let header = |len: usize| u32::try_from(len).map_err(|_| too_large());
+    let byte_rate = buffer
+        .sample_rate
+        .checked_mul(header(block_align)?)
+        .ok_or_else(too_large)?;
+    let block_align = u16::try_from(block_align).map_err(|_| too_large())?;
+
+    let mut out = Vec::with_capacity(8 + riff_len);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&header(riff_len)?.to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&(fmt_len as u32).to_le_bytes());
+    out.extend_from_slice(&(if extensible { FORMAT_EXTENSIBLE } else { tag }).to_le_bytes());
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&buffer.sample_rate.to_le_bytes());
+    out.extend_from_slice(&byte_rate.to_le_bytes());
+    out.extend_from_slice(&block_align.to_le_bytes());
+    out.extend_from_slice(&format.bits().to_le_bytes());
+    if extensible {
+        out.extend_from_slice(&22u16.to_le_bytes());
+        out.extend_from_slice(&format.bits().to_le_bytes());
+        out.extend_from_slice(&channel_mask(channels).to_le_bytes());
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&SUBFORMAT_TAIL);
+    } else if tag == FORMAT_IEEE_FLOAT {
+        out.extend_from_slice(&0u16.to_le_bytes());
+    }
+
+    if fact_len > 0 {
+        out.extend_from_slice(b"fact");
+        out.extend_from_slice(&4u32.to_le_bytes());
+        out.extend_from_slice(&header(frames)?.to_le_bytes());
+    }
+
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&header(data_len)?.to_le_bytes());
+    for i in 0..frames {
+        for channel in &buffer.channels {
+            let sample = channel[i];
+            match format {
+                SampleFormat::Pcm8 => out.push((quantize(sample, 8) + 128) as u8),
+                SampleFormat::Pcm16 => {
+                    out.extend_from_slice(&(quantize(sample, 16) as i16).to_le_bytes())
+                }
+                SampleFormat::Pcm24 => {
+                    out.extend_from_slice(&quantize(sample, 24).to_le_bytes()[..3])
+                }
+                SampleFormat::Pcm32 => out.extend_from_slice(&quantize(sample, 32).to_le_bytes()),
+                SampleFormat::Float32 => out.extend_from_slice(&sample.to_le_bytes()),
+            }
+        }
+    }
+    if pad == 1 {
+        out.push(0);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_buffer(channels: usize, frames: usize) -> AudioBuffer {
+        let data = (0..channels)
+            .map(|c| {
+                (0..frames)
+                    .map(|i| (i as f32 * 0.01 + c as f32).sin() * 0.9)
+                    .collect()
+            })
+            .collect();
+        AudioBuffer::new(44100, data)
+    }
+
+    fn max_error(a: &AudioBuffer, b: &AudioBuffer) -> f32 {
+        a.channels
+            .iter()
+            .zip(&b.channels)
+            .flat_map(|(x, y)| x.iter().zip(y).map(|(p, q)| (p - q).abs()))
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        let cases = [
+            (SampleFormat::Pcm8, 1.0 / 128.0),
+            (SampleFormat::Pcm16, 1.0 / 32768.0),
+            (SampleFormat::Pcm24, 1.0 / 8_388_608.0),
+            (SampleFormat::Pcm32, 1e-6),
+            (SampleFormat::Float32, 0.0),
+        ];
+        for channels in [1, 2, 6] {
+            let original = test_buffer(channels, 1001);
+            for (format, tolerance) in cases {
+                let decoded = decode(&encode(&original, format).unwrap()).unwrap();
+                assert_eq!(decoded.sample_rate, 44100);
+                assert_eq!(decoded.channel_count() as usize, channels);
+                assert_eq!(decoded.frames(), 1001);
+                let error = max_error(&original, &decoded);
+                assert!(error <= tolerance, "{format:?} x{channels}: error {error}");
+            }
+        }
+    }
+
+    #[test]
+    fn clips_integer_formats() {
+        let loud = AudioBuffer::new(8000, vec![vec![1.5, -1.5, 1.0, -1.0]]);
+        let decoded = decode(&encode(&loud, SampleFormat::Pcm16).unwrap()).unwrap();
+        assert_eq!(
+            decoded.channels[0],
+            vec![32767.0 / 32768.0, -1.0, 32767.0 / 32768.0, -1.0]
+        );
+    }
+
+    #[test]
+    fn odd_length_data_is_padded_and_skipped() {
+        let mut bytes = encode(&test_buffer(1, 3), SampleFormat::Pcm8).unwrap();
+        assert_eq!(bytes.len() % 2, 0);
+        // A trailing chunk after the padded data chunk must still be found.
+        bytes.extend_from_slice(b"LIST\x04\x00\x00\x00abcd");
+        let riff_len = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_len.to_le_bytes());
+        assert_eq!(decode(&bytes).unwrap().frames(), 3);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(matches!(decode(b"not a wav file"), Err(WavError::NotWave)));
+        let mut bytes = encode(&test_buffer(1, 4), SampleFormat::Pcm16).unwrap();
+        bytes[34] = 12;
+        assert!(matches!(decode(&bytes), Err(WavError::Unsupported(_))));
+    }
+
+    #[test]
+    fn rejects_audio_too_large_for_the_header() {
+        let wide = test_buffer(20_000, 1);
+        assert!(matches!(
+            encode(&wide, SampleFormat::Float32),
+            Err(AudioError::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,857 @@
+//! Autosave and crash recovery.
+//!
+//! Each run of the app keeps a session directory in app data:
+//!
+//! ```text
+//! <id>/session.json            name, project path, whether it has unsaved changes
+//! <id>/snapshot-<n>.vosproj    the whole project, as a project file
+//! <id>/journal-<n>.jsonl       one Record per line, made after snapshot n
+//! <id>/clip-<n>-<hash>.wav      audio an edit in journal n holds, by hash
+//! ```
+//!
+//! Every recorded edit, undo and redo is appended to the journal as it
+//! happens, and the audio it holds is written just after, off the thread
+//! that made it. The project is snapshotted every `storage.autoSaveInterval`
+//! into a new generation `n`, after which older generations are deleted. A
+//! snapshot is only renamed into place once written, so recovery takes the
+//! newest one (or an empty project if there is none yet) and replays every
+//! journal from its generation on. A session left with unsaved changes by
+//! a crash can then be listed and restored on the next run.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::audio::buffer::{AudioBuffer, BufferStore};
+use crate::audio::wav::{self, SampleFormat};
+use crate::history::{Change, Edit, History};
+use crate::mixer::{Layer, Mixer};
+use crate::project::{self, Contents, ProjectError, Session};
+use crate::sandbox;
+
+const INFO_FILE: &str = "session.json";
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+const SNAPSHOT_EXTENSION: &str = ".vosproj";
+const JOURNAL_PREFIX: &str = "journal-";
+const JOURNAL_EXTENSION: &str = ".jsonl";
+const CLIP_PREFIX: &str = "clip-";
+const CLIP_EXTENSION: &str = ".wav";
+
+#[derive(Debug, thiserror::Error)]
+pub enum AutosaveError {
+    #[error("no unsaved session {0:?}")]
+    UnknownSession(String),
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    Project(#[from] ProjectError),
+}
+
+impl AutosaveError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AutosaveError::UnknownSession(_) => "unknownSession",
+            AutosaveError::Io { .. } => "io",
+            AutosaveError::Project(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for AutosaveError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> AutosaveError + '_ {
+    move |source| AutosaveError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// One line of the journal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Record {
+    Edit {
+        edit: Edit,
+        /// Milliseconds since the Unix epoch.
+        timestamp: u64,
+    },
+    Undo,
+    Redo,
+    /// The frontend's parts of the project changed.
+    Session {
+        session: Session,
+    },
+}
+
+impl From<&Change> for Record {
+    fn from(change: &Change) -> Self {
+        match change {
+            Change::Recorded { edit, timestamp } => Record::Edit {
+                edit: edit.as_ref().clone(),
+                timestamp: *timestamp,
+            },
+            Change::Undone => Record::Undo,
+            Change::Redone => Record::Redo,
+        }
+    }
+}
+
+/// What `session.json` holds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Meta {
+    name: String,
+    path: Option<String>,
+    unsaved: bool,
+}
+
+/// A session left behind by an earlier run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub id: String,
+    pub name: String,
+    /// The project file it was opened from or last saved to, if any.
+    pub path: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    pub modified: u64,
+    /// Whether it has changes its project file doesn't.
+    pub unsaved: bool,
+}
+
+struct State {
+    session: Session,
+    path: Option<String>,
+    unsaved: bool,
+    enabled: bool,
+    generation: u64,
+    /// The current generation's journal, opened on first append.
+    journal: Option<File>,
+    /// Whether anything was journaled since the last snapshot.
+    pending: bool,
+    /// Set when a change went unjournaled, so the journal no longer adds
+    /// up to the project until the next snapshot.
+    stale: bool,
+    /// What `session.json` was last written with.
+    written: Option<Meta>,
+    /// Audio of journaled edits still to be written, by file.
+    clips: Vec<(PathBuf, Arc<AudioBuffer>)>,
+}
+
+impl State {
+    fn meta(&self) -> Meta {
+        Meta {
+            name: self.session.name.clone(),
+            path: self.path.clone(),
+            unsaved: self.unsaved,
+        }
+    }
+
+    /// Rewrites `session.json` in `dir` if the name, path or unsaved flag
+    /// changed since it was last written.
+    fn sync_meta(&mut self, dir: &Path) -> Result<(), AutosaveError> {
+        let meta = self.meta();
+        if self.written.as_ref() != Some(&meta) {
+            write_meta(dir, &meta)?;
+            self.written = Some(meta);
+        }
+        Ok(())
+    }
+}
+
+/// This run's session, and the sessions earlier runs left behind.
+pub struct Autosave {
+    root: PathBuf,
+    id: String,
+    state: Mutex<State>,
+    /// Signalled when audio is queued in `State::clips`.
+    queued: Condvar,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// The generation in a snapshot or journal file name.
+fn generation(name: &str, prefix: &str, extension: &str) -> Option<u64> {
+    name.strip_prefix(prefix)?
+        .strip_suffix(extension)?
+        .parse()
+        .ok()
+}
+
+fn snapshot_name(generation: u64) -> String {
+    format!("{SNAPSHOT_PREFIX}{generation}{SNAPSHOT_EXTENSION}")
+}
+
+fn journal_name(generation: u64) -> String {
+    format!("{JOURNAL_PREFIX}{generation}{JOURNAL_EXTENSION}")
+}
+
+fn clip_name(generation: u64, hash: &str) -> String {
+    format!("{CLIP_PREFIX}{generation}-{hash}{CLIP_EXTENSION}")
+}
+
+/// The generation and hash of the clip file `name`, if it is one.
+fn clip_of(name: &str) -> Option<(u64, &str)> {
+    let (generation, hash) = name
+        .strip_prefix(CLIP_PREFIX)?
+        .strip_suffix(CLIP_EXTENSION)?
+        .split_once('-')?;
+    Some((generation.parse().ok()?, hash))
+}
+
+/// [`sandbox::write_atomic`], with errors naming `path`.
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), AutosaveError> {
+    sandbox::write_atomic(path, bytes).map_err(io_error(path))
+}
+
+fn write_meta(dir: &Path, meta: &Meta) -> Result<(), AutosaveError> {
+    let bytes = serde_json::to_vec_pretty(meta).expect("session info serializes");
+    write_atomic(&dir.join(INFO_FILE), &bytes)
+}
+
+fn read_meta(dir: &Path) -> Option<Meta> {
+    serde_json::from_slice(&fs::read(dir.join(INFO_FILE)).ok()?).ok()
+}
+
+/// The latest modification time of anything in `dir`, in milliseconds
+/// since the Unix epoch.
+fn modified(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+        .filter_map(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Parses a journal, stopping at the first line that doesn't parse: a
+/// crash mid-append leaves at most the last one torn.
+fn read_journal(bytes: &[u8]) -> Vec<Record> {
+    bytes
+        .split(|&byte| byte == b'\n')
+        .map(serde_json::from_slice)
+        .map_while(Result::ok)
+        .collect()
+}
+
+impl Autosave {
+    /// Starts this run's session under `root`, journaling only while
+    /// `enabled`.
+    pub fn open(root: impl Into<PathBuf>, enabled: bool) -> Result<Self, AutosaveError> {
+        let root = root.into();
+        fs::create_dir_all(&root).map_err(io_error(&root))?;
+        // The start time names the session; a clash just means another
+        // session started the same millisecond.
+        let mut started = now();
+        let id = loop {
+            let id = started.to_string();
+            let dir = root.join(&id);
+            match fs::create_dir(&dir) {
+                Ok(()) => break id,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => started += 1,
+                Err(e) => return Err(io_error(&dir)(e)),
+            }
+        };
+        Ok(Self {
+            root,
+            id,
+            state: Mutex::new(State {
+                session: Session::default(),
+                path: None,
+                unsaved: false,
+                enabled,
+                generation: 0,
+                journal: None,
+                pending: false,
+                stale: false,
+                written: None,
+                clips: Vec::new(),
+            }),
+            queued: Condvar::new(),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.root.join(&self.id)
+    }
+
+    /// Turns journaling and snapshots on or off. Changes made while off
+    /// are caught up by the next snapshot.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.state().enabled = enabled;
+    }
+
+    /// The frontend's parts of the project, as last set.
+    pub fn session(&self) -> Session {
+        self.state().session.clone()
+    }
+
+    /// Records new frontend parts of the project.
+    pub fn set_session(&self, session: Session) -> Result<(), AutosaveError> {
+        self.state().session = session.clone();
+        self.append(&Record::Session { session })
+    }
+
+    /// Appends `record` to the journal, marking the session unsaved. Audio
+    /// the record holds is queued for [`write_clips`](Self::write_clips)
+    /// rather than written here.
+    pub fn append(&self, record: &Record) -> Result<(), AutosaveError> {
+        let mut state = self.state();
+        let state = &mut *state;
+        state.unsaved = true;
+        if !state.enabled || state.stale {
+            state.stale = true;
+            return Ok(());
+        }
+        let dir = self.dir();
+        state.sync_meta(&dir)?;
+        // Journal lines refer to audio by hash. If a crash comes before the
+        // audio is written, replay stops at the edit, as at a torn line.
+        if let Record::Edit { edit, .. } = record {
+            for clip in edit.clips() {
+                let path = dir.join(clip_name(state.generation, clip.hash()));
+                if let Some(audio) = clip.audio() {
+                    if !state.clips.iter().any(|(queued, _)| *queued == path) {
+                        state.clips.push((path, audio.clone()));
+                        self.queued.notify_all();
+                    }
+                }
+            }
+        }
+        let journal = match &mut state.journal {
+            Some(journal) => journal,
+            journal => {
+                let path = dir.join(journal_name(state.generation));
+                journal.insert(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&path)
+                        .map_err(io_error(&path))?,
+                )
+            }
+        };
+        let mut line = serde_json::to_vec(record).expect("journal records serialize");
+        line.push(b'\n');
+        journal
+            .write_all(&line)
+            .map_err(io_error(&dir.join(journal_name(state.generation))))?;
+        state.pending = true;
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for audio to be queued by `append`.
+    pub fn wait_for_clips(&self, timeout: Duration) {
+        let state = self.state();
+        if state.clips.is_empty() {
+            let _ = self.queued.wait_timeout(state, timeout);
+        }
+    }
+
+    /// Writes the audio queued by `append`. If that fails, the journal no
+    /// longer adds up until the next snapshot, so one is called for.
+    pub fn write_clips(&self) -> Result<(), AutosaveError> {
+        let clips = std::mem::take(&mut self.state().clips);
+        for (path, audio) in clips {
+            if path.exists() {
+                continue;
+            }
+            let written = wav::encode(&audio, SampleFormat::Float32)
+                .map_err(|e| ProjectError::from(e).into())
+                .and_then(|bytes| write_atomic(&path, &bytes));
+            if let Err(e) = written {
+                self.state().stale = true;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a snapshot is called for: one is always needed to catch up
+    /// after an unjournaled change, and otherwise once `interval_elapsed`
+    /// if anything was journaled.
+    pub fn is_due(&self, interval_elapsed: bool) -> bool {
+        let state = self.state();
+        state.enabled && (state.stale || (state.pending && interval_elapsed))
+    }
+
+    /// Snapshots the project into a new generation. `capture` takes what
+    /// will be saved while appends wait, so nothing lands between the
+    /// snapshot and the new journal; `encode` then builds the project file
+    /// without holding them up. Hold the history
+    /// ([`History::hold`]) from before calling this until `capture` is
+    /// done, so no edit is half made while it runs.
+    pub fn snapshot<T>(
+        &self,
+        capture: impl FnOnce(&Session) -> Result<T, ProjectError>,
+        encode: impl FnOnce(T) -> Result<Vec<u8>, ProjectError>,
+    ) -> Result<(), AutosaveError> {
+        let dir = self.dir();
+        let (generation, captured) = {
+            let mut state = self.state();
+            if !state.enabled {
+                state.stale = true;
+                return Ok(());
+            }
+            let captured = capture(&state.session)?;
+            state.generation += 1;
+            state.journal = None;
+            state.pending = false;
+            state.stale = false;
+            state.sync_meta(&dir)?;
+            (state.generation, captured)
+        };
+        let bytes = encode(captured)?;
+        write_atomic(&dir.join(snapshot_name(generation)), &bytes)?;
+
+        for entry in fs::read_dir(&dir).map_err(io_error(&dir))?.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let old = generation_of(&name).is_some_and(|old| old < generation);
+            if old {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the project as matching `bytes`, a project file saved to or
+    /// opened from `path` (`None` for a new project), and snapshots it.
+    pub fn checkpoint(
+        &self,
+        path: Option<String>,
+        session: Session,
+        bytes: Vec<u8>,
+    ) -> Result<(), AutosaveError> {
+        {
+            let mut state = self.state();
+            state.session = session;
+            state.path = path;
+            state.unsaved = false;
+        }
+        self.snapshot(|_| Ok(bytes), Ok)
+    }
+
+    /// Takes over a restored session that was last saved to `path`. It
+    /// stays unsaved until saved again; snapshot it once restored.
+    pub fn recovered(&self, path: Option<String>, session: Session) {
+        let mut state = self.state();
+        state.session = session;
+        state.path = path;
+        state.unsaved = true;
+    }
+
+    /// Removes this run's session if it has nothing unsaved, e.g. when the
+    /// app exits.
+    pub fn close(&self) {
+        if !self.state().unsaved {
+            let _ = fs::remove_dir_all(self.dir());
+        }
+    }
+
+    /// Sessions other runs left with unsaved changes, newest first. Ones
+    /// with nothing unsaved are cleared away.
+    pub fn list(&self) -> Result<Vec<SessionInfo>, AutosaveError> {
+        let mut sessions = Vec::new();
+        for entry in fs::read_dir(&self.root)
+            .map_err(io_error(&self.root))?
+            .flatten()
+        {
+            let id = entry.file_name().to_string_lossy().into_owned();
+            let dir = entry.path();
+            if id == self.id || !dir.is_dir() {
+                continue;
+            }
+            match read_meta(&dir) {
+                Some(meta) if meta.unsaved => sessions.push(SessionInfo {
+                    id,
+                    name: meta.name,
+                    path: meta.path,
+                    modified: modified(&dir),
+                    unsaved: meta.unsaved,
+                }),
+                _ => {
+                    let _ = fs::remove_dir_all(&dir);
+                }
+            }
+        }
+        sessions.sort_by_key(|session| Reverse(session.modified));
+        Ok(sessions)
+    }
+
+    /// The directory of the left-behind session `id`, looked up among the
+    /// listed ones so `id` can't name anything else.
+    fn session_dir(&self, id: &str) -> Result<(SessionInfo, PathBuf), AutosaveError> {
+        let info = self
+            .list()?
+            .into_iter()
+            .find(|info| info.id == id)
+            .ok_or_else(|| AutosaveError::UnknownSession(id.into()))?;
+        let dir = self.root.join(&info.id);
+        Ok((info, dir))
+    }
+
+    /// Reads back the left-behind session `id`.
+    pub fn load(&self, id: &str) -> Result<Recovered, AutosaveError> {
+        let (info, dir) = self.session_dir(id)?;
+        let mut snapshots = Vec::new();
+        let mut journals = Vec::new();
+        let mut clips = HashMap::new();
+        for entry in fs::read_dir(&dir).map_err(io_error(&dir))?.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(n) = generation(&name, SNAPSHOT_PREFIX, SNAPSHOT_EXTENSION) {
+                snapshots.push(n);
+            } else if let Some(n) = generation(&name, JOURNAL_PREFIX, JOURNAL_EXTENSION) {
+                journals.push(n);
+            } else if let Some((_, hash)) = clip_of(&name) {
+                clips.insert(hash.to_string(), entry.path());
+            }
+        }
+        let base_generation = snapshots.into_iter().max();
+        let base = match base_generation {
+            Some(n) => {
+                let path = dir.join(snapshot_name(n));
+                Some(project::decode(&fs::read(&path).map_err(io_error(&path))?)?)
+            }
+            None => None,
+        };
+        journals.retain(|&n| n >= base_generation.unwrap_or(0));
+        journals.sort_unstable();
+        let mut records = Vec::new();
+        for n in journals {
+            let path = dir.join(journal_name(n));
+            records.extend(read_journal(&fs::read(&path).map_err(io_error(&path))?));
+        }
+
+        // Edits whose audio can't be read stop the replay when reached,
+        // like a torn journal line.
+        let mut audio = HashMap::new();
+        for record in &records {
+            let Record::Edit { edit, .. } = record else {
+                continue;
+            };
+            for clip in edit.clips() {
+                if clip.audio().is_some() || audio.contains_key(clip.hash()) {
+                    continue;
+                }
+                let buffer = clips
+                    .get(clip.hash())
+                    .and_then(|path| fs::read(path).ok())
+                    .and_then(|bytes| wav::decode(&bytes).ok());
+                if let Some(buffer) = buffer {
+                    audio.insert(clip.hash().to_string(), Arc::new(buffer));
+                }
+            }
+        }
+        for record in &mut records {
+            if let Record::Edit { edit, .. } = record {
+                edit.attach(&audio);
+            }
+        }
+        Ok(Recovered {
+            info,
+            base,
+            records,
+        })
+    }
+
+    /// Deletes the left-behind session `id`.
+    pub fn discard(&self, id: &str) -> Result<(), AutosaveError> {
+        let (_, dir) = self.session_dir(id)?;
+        fs::remove_dir_all(&dir).map_err(io_error(&dir))
+    }
+}
+
+/// The generation of a snapshot, journal or clip file, whichever `name`
+/// is.
+fn generation_of(name: &str) -> Option<u64> {
+    generation(name, SNAPSHOT_PREFIX, SNAPSHOT_EXTENSION)
+        .or_else(|| generation(name, JOURNAL_PREFIX, JOURNAL_EXTENSION))
+        .or_else(|| clip_of(name).map(|(generation, _)| generation))
+}
+
+/// A left-behind session read back from disk.
+pub struct Recovered {
+    pub info: SessionInfo,
+    /// The newest snapshot; `None` if the session never got one, in which
+    /// case it started from an empty project.
+    pub base: Option<Contents>,
+    /// Everything journaled since, oldest first.
+    pub records: Vec<Record>,
+}
+
+impl Recovered {
+    /// Loads the snapshot into `mixer`, `store` and `history` and replays
+    /// the journal over it, returning the session and its layers. A record
+    /// that no longer applies ends the replay there, keeping what came
+    /// before it.
+    pub fn restore(
+        self,
+        mixer: &Mixer,
+        store: &BufferStore,
+        history: &History,
+    ) -> (Session, Vec<Layer>) {
+        let mut session = match self.base {
+            Some(contents) => {
+                let session = contents.session.clone();
+                history.load(contents.history.clone(), session.effects.clone());
+                mixer.replace(contents.load(store));
+                session
+            }
+            None => {
+                let session = Session::default();
+                history.load(Default::default(), session.effects.clone());
+                mixer.replace(Vec::new());
+                session
+            }
+        };
+        for record in self.records {
+            let replayed = match record {
+                Record::Edit { edit, timestamp } => history.replay(edit, timestamp, mixer, store),
+                Record::Undo => history.undo(mixer, store).map(drop),
+                Record::Redo => history.redo(mixer, store).map(drop),
+                Record::Session { session: next } => {
+                    // Project config is the config manager's, not the
+                    // frontend's.
+                    session = Session {
+                        config: session.config,
+                        ..next
+                    };
+                    Ok(())
+                }
+            };
+            if let Err(e) = replayed {
+                log::warn!("session {}: stopping replay: {e}", self.info.id);
+                break;
+            }
+        }
+        session.effects = history.effects();
+        (session, mixer.layers())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::buffer::AudioBuffer;
+    use crate::mixer::LayerUpdate;
+    use crate::project::EffectSettings;
+
+    fn pitch(semitones: f32) -> EffectSettings {
+        EffectSettings {
+            pitch: semitones,
+            ..Default::default()
+        }
+    }
+
+    fn snapshot(autosave: &Autosave, mixer: &Mixer, store: &BufferStore, history: &History) {
+        let changing = history.hold();
+        autosave
+            .snapshot(
+                move |session| {
+                    // As `commands::project::current_session` fills it in.
+                    let session = Session {
+                        effects: history.effects(),
+                        ..session.clone()
+                    };
+                    let layers = mixer.layers();
+                    let audio = project::layer_audio(&layers, store)?;
+                    let saved = history.saved();
+                    drop(changing);
+                    Ok((session, layers, audio, saved))
+                },
+                |(session, layers, audio, saved)| {
+                    project::encode(&session, &layers, &audio, &saved)
+                },
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn recovers_snapshot_and_journal_after_a_crash() {
+        let root = std::env::temp_dir().join(format!("autosave-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (mixer, store, history) = (Mixer::new(), BufferStore::new(), History::new());
+        let autosave = Arc::new(Autosave::open(&root, true).unwrap());
+        let journal = autosave.clone();
+        history.subscribe(move |change| journal.append(&change.into()).unwrap());
+
+        history.set_effects(pitch(2.0));
+        let voice = store.insert(AudioBuffer::new(100, vec![vec![0.5; 10]]));
+        let layer = mixer.add_layer(None).unwrap();
+        let layer = mixer
+            .update_layer(
+                layer.id,
+                LayerUpdate {
+                    buffer: Some(voice.id),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        history.record(Edit::add_layer(0, layer.clone(), &store).unwrap());
+        assert!(autosave.is_due(true) && !autosave.is_due(false));
+        snapshot(&autosave, &mixer, &store, &history);
+        assert!(!autosave.is_due(true));
+
+        // After the snapshot: a gain change, an undone layer and a rename.
+        let before = mixer.layer(layer.id).unwrap();
+        let after = mixer
+            .update_layer(
+                layer.id,
+                LayerUpdate {
+                    gain: Some(-6.0),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        history.record(Edit::update_layer(before, after, &store).unwrap());
+        let music = store.insert(AudioBuffer::new(100, vec![vec![0.25; 4]]));
+        let extra = mixer.add_layer(Some("Extra".into())).unwrap();
+        let extra = mixer
+            .update_layer(
+                extra.id,
+                LayerUpdate {
+                    buffer: Some(music.id),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        history.record(Edit::add_layer(1, extra, &store).unwrap());
+        history.undo(&mixer, &store).unwrap();
+        autosave
+            .set_session(Session {
+                name: "Episode 2".into(),
+                ..Default::default()
+            })
+            .unwrap();
+        let expected_history = history.list();
+        autosave.write_clips().unwrap();
+        // The crash: the session is never closed.
+        drop(autosave);
+
+        let next = Autosave::open(&root, true).unwrap();
+        let sessions = next.list().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].name, "Episode 2");
+        assert!(sessions[0].unsaved);
+
+        let (mixer, store, history) = (Mixer::new(), BufferStore::new(), History::new());
+        let (session, layers) = next
+            .load(&sessions[0].id)
+            .unwrap()
+            .restore(&mixer, &store, &history);
+        assert_eq!(session.name, "Episode 2");
+        assert_eq!(session.effects, pitch(2.0));
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].gain, -6.0);
+        let audio = store.get(layers[0].buffer.unwrap()).unwrap();
+        assert_eq!(audio.channels, [vec![0.5; 10]]);
+        assert_eq!(history.list(), expected_history);
+        // The journaled layer's audio came back from its clip file.
+        history.redo(&mixer, &store).unwrap();
+        let extra = store.get(mixer.layers()[1].buffer.unwrap()).unwrap();
+        assert_eq!(extra.channels, [vec![0.25; 4]]);
+
+        next.discard(&sessions[0].id).unwrap();
+        assert!(next.list().unwrap().is_empty());
+        assert!(matches!(
+            next.load("../elsewhere"),
+            Err(AutosaveError::UnknownSession(_))
+        ));
+
+        // A session with nothing unsaved goes when closed.
+        next.close();
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn a_snapshot_waits_for_the_edit_being_made() {
+        let root = std::env::temp_dir().join(format!("autosave-race-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (mixer, store, history) = (
+            Arc::new(Mixer::new()),
+            Arc::new(BufferStore::new()),
+            Arc::new(History::new()),
+        );
+        let autosave = Arc::new(Autosave::open(&root, true).unwrap());
+        let journal = autosave.clone();
+        history.subscribe(move |change| journal.append(&change.into()).unwrap());
+
+        // A snapshot is asked for after the layer is added but before the
+        // edit is recorded and journaled.
+        let (started, snapshotted) = (std::sync::mpsc::channel(), std::sync::mpsc::channel());
+        let snapshotter = std::thread::spawn({
+            let (autosave, mixer, store, history) = (
+                autosave.clone(),
+                mixer.clone(),
+                store.clone(),
+                history.clone(),
+            );
+            let (started, snapshotted) = (started.1, snapshotted.0);
+            move || {
+                started.recv().unwrap();
+                snapshot(&autosave, &mixer, &store, &history);
+                snapshotted.send(()).unwrap();
+            }
+        });
+        let voice = store.insert(AudioBuffer::new(100, vec![vec![0.5; 10]]));
+        history
+            .edit(|| {
+                let layer = mixer.add_layer(None)?;
+                let layer = mixer.update_layer(
+                    layer.id,
+                    LayerUpdate {
+                        buffer: Some(voice.id),
+                        ..Default::default()
+                    },
+                )?;
+                started.0.send(()).unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                let edit = Edit::add_layer(0, layer, &store)?;
+                Ok::<_, crate::mixer::MixerError>(((), edit))
+            })
+            .unwrap();
+        assert!(snapshotted.1.try_recv().is_err());
+        snapshotter.join().unwrap();
+        history.undo(&mixer, &store).unwrap();
+        autosave.write_clips().unwrap();
+        let expected_history = history.list();
+        drop(autosave);
+
+        // Recovery neither adds the layer twice nor stops early.
+        let next = Autosave::open(&root, true).unwrap();
+        let id = next.list().unwrap()[0].id.clone();
+        let (mixer, store, history) = (Mixer::new(), BufferStore::new(), History::new());
+        let (_, layers) = next.load(&id).unwrap().restore(&mixer, &store, &history);
+        assert!(layers.is_empty());
+        assert_eq!(history.list(), expected_history);
+        history.redo(&mixer, &store).unwrap();
+        assert_eq!(mixer.layers().len(), 1);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,427 @@
+//! Captions from the word timeline, and back.
+//!
+//! Timed words are laid out into cues of at most `maxLines` lines of at
+//! most `maxCharsPerLine` characters, breaking at sentence ends where
+//! asked, and written as SRT, WebVTT or TTML. The same formats can be read
+//! back into cues, whose text can then be re-aligned against the audio.
+
+pub mod srt;
+pub mod ttml;
+pub mod vtt;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::project::WordTiming;
+use crate::sandbox::SandboxError;
+use crate::text;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptionError {
+    #[error("invalid caption settings: {0}")]
+    Invalid(String),
+    #[error("{format} captions, line {line}: {message}")]
+    Malformed {
+        format: CaptionFormat,
+        line: usize,
+        message: String,
+    },
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+}
+
+impl CaptionError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CaptionError::Invalid(_) => "invalidCaptionSettings",
+            CaptionError::Malformed { .. } => "malformedCaptions",
+            CaptionError::Sandbox(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for CaptionError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CaptionFormat {
+    Srt,
+    WebVtt,
+    Ttml,
+}
+
+impl CaptionFormat {
+    /// Guesses the format of caption file contents: WebVTT has its header,
+    /// TTML is XML, and anything else is taken for SRT.
+    pub fn detect(text: &str) -> Self {
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if text.starts_with("WEBVTT") {
+            CaptionFormat::WebVtt
+        } else if text.starts_with('<') {
+            CaptionFormat::Ttml
+        } else {
+            CaptionFormat::Srt
+        }
+    }
+}
+
+impl std::fmt::Display for CaptionFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CaptionFormat::Srt => "SRT",
+            CaptionFormat::WebVtt => "WebVTT",
+            CaptionFormat::Ttml => "TTML",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CaptionOptions {
+    pub max_chars_per_line: usize,
+    pub max_lines: usize,
+    /// Seconds a cue stays up at least, unless the next one starts sooner.
+    pub min_duration: f64,
+    /// End cues where sentences end, so no cue holds the end of one
+    /// sentence and the start of the next.
+    pub break_sentences: bool,
+}
+
+impl Default for CaptionOptions {
+    fn default() -> Self {
+        Self {
+            max_chars_per_line: 42,
+            max_lines: 2,
+            min_duration: 1.0,
+            break_sentences: true,
+        }
+    }
+}
+
+impl CaptionOptions {
+    fn validate(&self) -> Result<(), CaptionError> {
+        if self.max_chars_per_line == 0 || self.max_lines == 0 {
+            return Err(CaptionError::Invalid(
+                "cues need room for at least one character and line".into(),
+            ));
+        }
+        if !self.min_duration.is_finite() || self.min_duration < 0.0 {
+            return Err(CaptionError::Invalid(format!(
+                "minimum duration must be a non-negative number of seconds, not {}",
+                self.min_duration
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Text on screen over a stretch of time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cue {
+    /// Seconds.
+    pub start: f64,
+    pub end: f64,
+    pub lines: Vec<String>,
+}
+
+/// Cues read from a caption file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedCaptions {
+    pub format: CaptionFormat,
+    pub cues: Vec<Cue>,
+    /// The cues' text run together, ready to align against the audio.
+    pub transcript: String,
+}
+
+/// Breaks timed words into cues. A line holds as many words as fit;
+/// a word longer than a line gets one to itself.
+pub fn layout(words: &[WordTiming], options: &CaptionOptions) -> Result<Vec<Cue>, CaptionError> {
+    options.validate()?;
+    let words: Vec<&WordTiming> = words.iter().filter(|w| !w.text.trim().is_empty()).collect();
+
+    // Find sentence ends in the words run together, as the transcript
+    // reads.
+    let mut joined = String::new();
+    let mut offsets = Vec::with_capacity(words.len());
+    for word in &words {
+        if !joined.is_empty() {
+            joined.push(' ');
+        }
+        offsets.push(joined.len());
+        joined.push_str(word.text.trim());
+    }
+    let mut ends_sentence = vec![false; words.len()];
+    for sentence in text::sentences(&joined) {
+        let last = offsets.partition_point(|&offset| offset < sentence.end) - 1;
+        ends_sentence[last] = true;
+    }
+
+    let mut cues: Vec<Cue> = Vec::new();
+    let mut current: Option<Cue> = None;
+    for (i, word) in words.iter().enumerate() {
+        let text = word.text.trim();
+        let len = text.chars().count();
+        let cue = match current.take() {
+            Some(mut cue) => {
+                let line = cue.lines.last_mut().expect("cues have a line");
+                if line.chars().count() + 1 + len <= options.max_chars_per_line {
+                    line.push(' ');
+                    line.push_str(text);
+                } else if cue.lines.len() < options.max_lines {
+                    cue.lines.push(text.to_string());
+                } else {
+                    cues.push(cue);
+                    cue = Cue {
+                        start: word.start,
+                        end: word.end,
+                        lines: vec![text.to_string()],
+                    };
+                }
+                cue.end = cue.end.max(word.end);
+                cue
+            }
+            None => Cue {
+                start: word.start,
+                end: word.end,
+                lines: vec![text.to_string()],
+            },
+        };
+        if options.break_sentences && ends_sentence[i] {
+            cues.push(cue);
+        } else {
+            current = Some(cue);
+        }
+    }
+    cues.extend(current);
+
+    let starts: Vec<f64> = cues.iter().map(|cue| cue.start).collect();
+    for (cue, next) in cues
+        .iter_mut()
+        .zip(starts.iter().skip(1).map(Some).chain([None]))
+    {
+        cue.end = cue.end.max(cue.start + options.min_duration);
+        if let Some(&next) = next {
+            cue.end = cue.end.min(next).max(cue.start);
+        }
+    }
+    Ok(cues)
+}
+
+/// Writes `cues` as `format`. `language` is the BCP-47 tag TTML declares;
+/// the other formats don't record one.
+pub fn write(format: CaptionFormat, cues: &[Cue], language: &str) -> String {
+    match format {
+        CaptionFormat::Srt => srt::write(cues),
+        CaptionFormat::WebVtt => vtt::write(cues),
+        CaptionFormat::Ttml => ttml::write(cues, language),
+    }
+}
+
+/// Reads cues from `text`, in `format` or, without one, whichever it looks
+/// like.
+pub fn read(text: &str, format: Option<CaptionFormat>) -> Result<ImportedCaptions, CaptionError> {
+    let format = format.unwrap_or_else(|| CaptionFormat::detect(text));
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let cues = match format {
+        CaptionFormat::Srt => srt::read(&text)?,
+        CaptionFormat::WebVtt => vtt::read(&text)?,
+        CaptionFormat::Ttml => ttml::read(&text)?,
+    };
+    let transcript = cues
+        .iter()
+        .flat_map(|cue| &cue.lines)
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Ok(ImportedCaptions {
+        format,
+        cues,
+        transcript,
+    })
+}
+
+/// `hh:mm:ss` and milliseconds after `separator`, as SRT and WebVTT write
+/// times.
+fn clock(seconds: f64, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    let (hours, minutes) = (millis / 3_600_000, millis / 60_000 % 60);
+    let (secs, millis) = (millis / 1000 % 60, millis % 1000);
+    format!("{hours:02}:{minutes:02}:{secs:02}{separator}{millis:03}")
+}
+
+/// Seconds from `[hh:]mm:ss` with a fraction after `,` or `.`.
+fn parse_clock(time: &str) -> Option<f64> {
+    let (whole, fraction) = match time.trim().split_once([',', '.']) {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (time.trim(), "0"),
+    };
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !digits(fraction) {
+        return None;
+    }
+    let parts: Vec<&str> = whole.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 || !parts.iter().all(|part| digits(part)) {
+        return None;
+    }
+    let seconds = parts.iter().fold(0.0, |total, part| {
+        total * 60.0 + part.parse::<f64>().unwrap_or(0.0)
+    });
+    let fraction: f64 = format!("0.{fraction}").parse().ok()?;
+    Some(seconds + fraction)
+}
+
+/// The `start --> end` of an SRT or WebVTT cue; anything after the end
+/// time, like WebVTT cue settings, is ignored.
+fn parse_timing(line: &str) -> Option<(f64, f64)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    Some((parse_clock(start)?, parse_clock(end)?))
+}
+
+/// Escapes `&`, `<` and `>`, which WebVTT and TTML reserve and SRT players
+/// take for markup.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Drops markup like `<i>` or `<v Speaker>` and resolves entities, leaving
+/// the text as read. A `<` that doesn't open a well-formed tag, as in
+/// "a <3", is text.
+fn plain(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        plain.push_str(&rest[..open]);
+        rest = &rest[open..];
+        match tag_len(rest) {
+            Some(len) => rest = &rest[len..],
+            None => {
+                plain.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    plain.push_str(rest);
+    plain
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&amp;", "&")
+}
+
+/// The length of the tag `text` starts with, if it's a well-formed one:
+/// a name after `<` or `</`, like `<i>` or `<c.loud>`, or a WebVTT
+/// timestamp like `<00:01.500>`.
+fn tag_len(text: &str) -> Option<usize> {
+    let close = text.find('>')?;
+    let inner = &text[1..close];
+    let name = inner.strip_prefix('/').unwrap_or(inner);
+    let well_formed = if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        !name.contains('<')
+    } else {
+        name.len() == inner.len()
+            && !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == ':' || c == '.')
+    };
+    well_formed.then_some(close + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timed(text: &str) -> Vec<WordTiming> {
+        text.split_whitespace()
+            .enumerate()
+            .map(|(i, word)| WordTiming {
+                text: word.into(),
+                start: i as f64 * 0.5,
+                end: i as f64 * 0.5 + 0.4,
+                confidence: None,
+            })
+            .collect()
+    }
+
+    fn lines(cues: &[Cue]) -> Vec<Vec<&str>> {
+        cues.iter()
+            .map(|cue| cue.lines.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn lays_words_out_by_line_length_and_sentence() {
+        let words = timed("Welcome back to the show. Today we talk about captions and timing!");
+        let options = CaptionOptions {
+            max_chars_per_line: 16,
+            max_lines: 2,
+            min_duration: 0.0,
+            break_sentences: true,
+        };
+        let cues = layout(&words, &options).unwrap();
+        assert_eq!(
+            lines(&cues),
+            [
+                vec!["Welcome back to", "the show."],
+                vec!["Today we talk", "about captions"],
+                vec!["and timing!"],
+            ]
+        );
+        assert_eq!((cues[0].start, cues[0].end), (0.0, 2.4));
+        assert_eq!(cues[1].start, 2.5);
+
+        // Without sentence breaks, cues fill up regardless.
+        let run_on = CaptionOptions {
+            break_sentences: false,
+            ..options
+        };
+        let cues = layout(&words, &run_on).unwrap();
+        assert_eq!(lines(&cues)[0], ["Welcome back to", "the show. Today"]);
+
+        // Short cues are held up to the minimum, but never past the next.
+        let held = CaptionOptions {
+            min_duration: 3.0,
+            ..options
+        };
+        let cues = layout(&words, &held).unwrap();
+        assert_eq!(cues[0].end, cues[1].start);
+        assert_eq!(cues[2].end, cues[2].start + 3.0);
+
+        let none = CaptionOptions {
+            max_lines: 0,
+            ..options
+        };
+        assert!(matches!(
+            layout(&words, &none),
+            Err(CaptionError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn reads_and_writes_clock_times() {
+        assert_eq!(clock(3723.0456, ','), "01:02:03,046");
+        assert_eq!(clock(-1.0, '.'), "00:00:00.000");
+        assert_eq!(parse_clock("01:02:03,046"), Some(3723.046));
+        assert_eq!(parse_clock("02:03.5"), Some(123.5));
+        assert_eq!(parse_clock("3.5"), None);
+        assert_eq!(
+            parse_timing("00:00:01.000 --> 00:00:02.500 align:start"),
+            Some((1.0, 2.5))
+        );
+        assert_eq!(plain("<v Ann>Fish &amp; <i>chips</i></v>"), "Fish & chips");
+        assert_eq!(plain("<00:01.500>a <3"), "a <3");
+        assert_eq!(plain("1 < 2 > 0 <"), "1 < 2 > 0 <");
+        assert_eq!(plain("<<b>bold</b>"), "<bold");
+    }
+}
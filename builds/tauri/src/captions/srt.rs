@@ -0,0 +1,94 @@
+//! SubRip: numbered cues, `hh:mm:ss,mmm` times, plain text with `<`, `>`
+//! and `&` escaped so players don't take them for markup.
+
+use std::fmt::Write;
+
+use super::{clock, escape, parse_timing, plain, CaptionError, CaptionFormat, Cue};
+
+pub fn write(cues: &[Cue]) -> String {
+    let mut out = String::new();
+    for (i, cue) in cues.iter().enumerate() {
+        let _ = writeln!(out, "{}", i + 1);
+        let _ = writeln!(out, "{} --> {}", clock(cue.start, ','), clock(cue.end, ','));
+        for line in &cue.lines {
+            let _ = writeln!(out, "{}", escape(line));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Reads cues separated by blank lines. Numbers are optional, as some
+/// tools leave them out, and formatting tags like `<i>` are dropped.
+pub fn read(text: &str) -> Result<Vec<Cue>, CaptionError> {
+    let mut cues = Vec::new();
+    let mut lines = text.lines().enumerate().peekable();
+    while lines.peek().is_some() {
+        let block: Vec<(usize, &str)> = lines
+            .by_ref()
+            .skip_while(|(_, line)| line.trim().is_empty())
+            .take_while(|(_, line)| !line.trim().is_empty())
+            .collect();
+        let Some(&(first, number)) = block.first() else {
+            break;
+        };
+        let skip = usize::from(!number.contains("-->"));
+        let Some(&(at, timing)) = block.get(skip) else {
+            return Err(malformed(first, "cue has no timing line"));
+        };
+        let (start, end) = parse_timing(timing)
+            .ok_or_else(|| malformed(at, &format!("can't read cue timing \"{timing}\"")))?;
+        cues.push(Cue {
+            start,
+            end,
+            lines: block[skip + 1..]
+                .iter()
+                .map(|(_, line)| plain(line.trim()))
+                .collect(),
+        });
+    }
+    Ok(cues)
+}
+
+fn malformed(index: usize, message: &str) -> CaptionError {
+    CaptionError::Malformed {
+        format: CaptionFormat::Srt,
+        line: index + 1,
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_reads_loose_files() {
+        let cues = vec![
+            Cue {
+                start: 1.0,
+                end: 2.5,
+                lines: vec!["Hello there.".into(), "How are you?".into()],
+            },
+            Cue {
+                start: 61.25,
+                end: 63.0,
+                lines: vec!["Fine.".into(), "a <3 & <b>x</b>".into()],
+            },
+        ];
+        let text = write(&cues);
+        assert!(text.starts_with("1\n00:00:01,000 --> 00:00:02,500\nHello there.\n"));
+        assert!(text.contains("a &lt;3 &amp; &lt;b&gt;x&lt;/b&gt;\n"));
+        assert_eq!(read(&text).unwrap(), cues);
+
+        let loose = "\n\n00:00:01.000 --> 00:00:02.000\n<i>No number</i>\n\n\n\n2\n00:00:03,000 --> 00:00:04,000\nSecond\n";
+        let read_back = read(loose).unwrap();
+        assert_eq!(read_back.len(), 2);
+        assert_eq!(read_back[0].lines, ["No number"]);
+
+        match read("1\n00:00:01 -> 00:00:02\nBroken\n") {
+            Err(CaptionError::Malformed { line, .. }) => assert_eq!(line, 2),
+            other => panic!("expected a malformed file, got {other:?}"),
+        }
+    }
+}
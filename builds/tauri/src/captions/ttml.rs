@@ -0,0 +1,233 @@
+//! TTML: XML with a `<p begin end>` per cue and `<br/>` between lines.
+//!
+//! Reading is a scan for paragraphs rather than a full XML parse, enough
+//! for the captions tools and broadcasters produce: timing on the
+//! paragraphs themselves, styling spans inside them.
+
+use std::fmt::Write;
+
+use super::{clock, escape, plain, CaptionError, CaptionFormat, Cue};
+
+/// Frames per second when a file counts in frames but doesn't say how
+/// fast, as the TTML spec has it.
+const DEFAULT_FRAME_RATE: f64 = 30.0;
+
+pub fn write(cues: &[Cue], language: &str) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        out,
+        "<tt xmlns=\"http://www.w3.org/ns/ttml\" xml:lang=\"{}\">",
+        escape(language).replace('"', "&quot;")
+    );
+    out.push_str("  <body>\n    <div>\n");
+    for cue in cues {
+        let lines: Vec<String> = cue.lines.iter().map(|line| escape(line)).collect();
+        let _ = writeln!(
+            out,
+            "      <p begin=\"{}\" end=\"{}\">{}</p>",
+            clock(cue.start, '.'),
+            clock(cue.end, '.'),
+            lines.join("<br/>")
+        );
+    }
+    out.push_str("    </div>\n  </body>\n</tt>\n");
+    out
+}
+
+/// A paragraph being read: its timing and its lines so far.
+struct Paragraph {
+    start: f64,
+    end: f64,
+    lines: Vec<String>,
+}
+
+pub fn read(text: &str) -> Result<Vec<Cue>, CaptionError> {
+    let line_at = |at: usize| text[..at].matches('\n').count();
+    let mut frame_rate = DEFAULT_FRAME_RATE;
+    let mut cues = Vec::new();
+    let mut paragraph: Option<Paragraph> = None;
+    let mut at = 0;
+    while let Some(open) = text[at..].find('<').map(|open| at + open) {
+        if let Some(paragraph) = &mut paragraph {
+            let last = paragraph.lines.last_mut().expect("paragraphs have a line");
+            last.push_str(&text[at..open]);
+        }
+        let terminator = if text[open..].starts_with("<!--") {
+            "-->"
+        } else {
+            ">"
+        };
+        let Some(close) = text[open..].find(terminator).map(|close| open + close) else {
+            return Err(malformed(line_at(open), "tag is never closed"));
+        };
+        at = close + terminator.len();
+        let tag = &text[open + 1..close];
+        if tag.starts_with(['!', '?']) {
+            continue;
+        }
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag.trim_end_matches('/')),
+        };
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        let name = name.rsplit(':').next().unwrap_or_default();
+        match (name, closing) {
+            ("tt", false) => {
+                if let Some(rate) = attribute(tag, "ttp:frameRate").and_then(|r| r.parse().ok()) {
+                    frame_rate = rate;
+                }
+            }
+            ("p", false) => {
+                let time = |name| {
+                    attribute(tag, name)
+                        .map(|value| {
+                            parse_time(value, frame_rate).ok_or_else(|| {
+                                malformed(line_at(open), &format!("can't read {name} \"{value}\""))
+                            })
+                        })
+                        .transpose()
+                };
+                let Some(start) = time("begin")? else {
+                    return Err(malformed(line_at(open), "paragraph has no begin time"));
+                };
+                let end = match (time("end")?, time("dur")?) {
+                    (Some(end), _) => end,
+                    (None, Some(duration)) => start + duration,
+                    (None, None) => {
+                        return Err(malformed(line_at(open), "paragraph has no end time"))
+                    }
+                };
+                paragraph = Some(Paragraph {
+                    start,
+                    end,
+                    lines: vec![String::new()],
+                });
+            }
+            ("br", false) => {
+                if let Some(paragraph) = &mut paragraph {
+                    paragraph.lines.push(String::new());
+                }
+            }
+            ("p", true) => {
+                if let Some(paragraph) = paragraph.take() {
+                    cues.push(Cue {
+                        start: paragraph.start,
+                        end: paragraph.end,
+                        // XML whitespace collapses, as it does on screen.
+                        lines: paragraph
+                            .lines
+                            .iter()
+                            .map(|line| {
+                                plain(&line.split_whitespace().collect::<Vec<_>>().join(" "))
+                            })
+                            .filter(|line| !line.is_empty())
+                            .collect(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    if paragraph.is_some() {
+        return Err(malformed(line_at(text.len()), "paragraph is never closed"));
+    }
+    Ok(cues)
+}
+
+/// The value of attribute `name` in the tag contents `tag`.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].split_whitespace().last().unwrap_or_default();
+        let value = rest[equals + 1..].trim_start();
+        let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let value = &value[1..];
+        let close = value.find(quote)?;
+        if key == name {
+            return Some(&value[..close]);
+        }
+        rest = &value[close + 1..];
+    }
+    None
+}
+
+/// Seconds from a TTML time: a clock time (`hh:mm:ss.fff`, or
+/// `hh:mm:ss:ff` in frames) or an offset (`1.5s`, `1500ms`, `2m`, `1h`,
+/// `45f`). Ticks aren't supported.
+fn parse_time(value: &str, frame_rate: f64) -> Option<f64> {
+    let value = value.trim();
+    // Units as a fraction of a second, "ms" before "m" and "s".
+    let units = [
+        ("ms", 1.0, 1000.0),
+        ("h", 3600.0, 1.0),
+        ("m", 60.0, 1.0),
+        ("s", 1.0, 1.0),
+        ("f", 1.0, frame_rate),
+    ];
+    for (unit, seconds, per) in units {
+        if let Some(number) = value.strip_suffix(unit) {
+            return number.parse::<f64>().ok().map(|n| n * seconds / per);
+        }
+    }
+    let parts: Vec<f64> = value
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [hours, minutes, seconds] => Some(hours * 3600.0 + minutes * 60.0 + seconds),
+        [hours, minutes, seconds, frames] => {
+            Some(hours * 3600.0 + minutes * 60.0 + seconds + frames / frame_rate)
+        }
+        _ => None,
+    }
+}
+
+fn malformed(index: usize, message: &str) -> CaptionError {
+    CaptionError::Malformed {
+        format: CaptionFormat::Ttml,
+        line: index + 1,
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_reads_broadcast_files() {
+        let cues = vec![Cue {
+            start: 1.0,
+            end: 2.5,
+            lines: vec!["Fish & chips".into(), "<please>".into()],
+        }];
+        let text = write(&cues, "en-GB");
+        assert!(text.contains("xml:lang=\"en-GB\""));
+        assert!(text.contains(
+            "<p begin=\"00:00:01.000\" end=\"00:00:02.500\">Fish &amp; chips<br/>&lt;please&gt;</p>"
+        ));
+        assert_eq!(read(&text).unwrap(), cues);
+
+        let file = r#"<?xml version="1.0"?>
+<!-- exported <somewhere> -->
+<tt:tt xmlns:tt="http://www.w3.org/ns/ttml" xmlns:ttp="http://www.w3.org/ns/ttml#parameter" ttp:frameRate="50">
+  <tt:body><tt:div>
+    <tt:p begin="00:00:01:25" dur="2s">
+      <tt:span tts:color="yellow">Hello</tt:span>
+      there<tt:br />General
+    </tt:p>
+    <tt:p begin='3500ms' end="4.25s">Kenobi</tt:p>
+  </tt:div></tt:body>
+</tt:tt>"#;
+        let read_back = read(file).unwrap();
+        assert_eq!(read_back.len(), 2);
+        assert_eq!((read_back[0].start, read_back[0].end), (1.5, 3.5));
+        assert_eq!(read_back[0].lines, ["Hello there", "General"]);
+        assert_eq!((read_back[1].start, read_back[1].end), (3.5, 4.25));
+
+        assert!(matches!(
+            read("<tt><body><p>No timing</p></body></tt>"),
+            Err(CaptionError::Malformed { line: 1, .. })
+        ));
+    }
+}
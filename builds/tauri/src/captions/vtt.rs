@@ -0,0 +1,111 @@
+//! WebVTT: a `WEBVTT` header, then cues with `hh:mm:ss.mmm` times.
+
+use std::fmt::Write;
+
+use super::{clock, escape, parse_timing, plain, CaptionError, CaptionFormat, Cue};
+
+pub fn write(cues: &[Cue]) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for cue in cues {
+        let _ = writeln!(out, "{} --> {}", clock(cue.start, '.'), clock(cue.end, '.'));
+        for line in &cue.lines {
+            let _ = writeln!(out, "{}", escape(line));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Reads the cues, skipping the header and `NOTE`, `STYLE` and `REGION`
+/// blocks. Cue identifiers, settings and markup are dropped.
+pub fn read(text: &str) -> Result<Vec<Cue>, CaptionError> {
+    let mut lines = text.lines().enumerate().peekable();
+    let header = lines.next().map(|(_, line)| line).unwrap_or_default();
+    let signed = header
+        .strip_prefix("WEBVTT")
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t']));
+    if !signed {
+        return Err(malformed(0, "file doesn't start with WEBVTT"));
+    }
+    // The rest of the header block.
+    lines
+        .by_ref()
+        .take_while(|(_, line)| !line.trim().is_empty())
+        .for_each(drop);
+
+    let mut cues = Vec::new();
+    while lines.peek().is_some() {
+        let block: Vec<(usize, &str)> = lines
+            .by_ref()
+            .skip_while(|(_, line)| line.trim().is_empty())
+            .take_while(|(_, line)| !line.trim().is_empty())
+            .collect();
+        let Some(&(first, head)) = block.first() else {
+            break;
+        };
+        if ["NOTE", "STYLE", "REGION"]
+            .iter()
+            .any(|keyword| head.split_whitespace().next() == Some(keyword))
+        {
+            continue;
+        }
+        let Some(at) = block
+            .iter()
+            .take(2)
+            .position(|(_, line)| line.contains("-->"))
+        else {
+            return Err(malformed(first, "cue has no timing line"));
+        };
+        let (index, timing) = block[at];
+        let (start, end) = parse_timing(timing)
+            .ok_or_else(|| malformed(index, &format!("can't read cue timing \"{timing}\"")))?;
+        cues.push(Cue {
+            start,
+            end,
+            lines: block[at + 1..]
+                .iter()
+                .map(|(_, line)| plain(line.trim()))
+                .collect(),
+        });
+    }
+    Ok(cues)
+}
+
+fn malformed(index: usize, message: &str) -> CaptionError {
+    CaptionError::Malformed {
+        format: CaptionFormat::WebVtt,
+        line: index + 1,
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_skips_non_cue_blocks() {
+        let cues = vec![Cue {
+            start: 0.5,
+            end: 3725.0,
+            lines: vec!["Fish & chips <3".into()],
+        }];
+        let text = write(&cues);
+        assert_eq!(
+            text,
+            "WEBVTT\n\n00:00:00.500 --> 01:02:05.000\nFish &amp; chips &lt;3\n\n"
+        );
+        assert_eq!(read(&text).unwrap(), cues);
+
+        let file = "WEBVTT - Episode 1\nKind: captions\n\nNOTE a comment\nspanning lines\n\nintro\n00:01.000 --> 00:02.000 line:90%\n<v Ann>Hi!</v>\n";
+        let read_back = read(file).unwrap();
+        assert_eq!(read_back.len(), 1);
+        assert_eq!((read_back[0].start, read_back[0].end), (1.0, 2.0));
+        assert_eq!(read_back[0].lines, ["Hi!"]);
+
+        assert!(matches!(
+            read("1\n00:00:01,000 --> 00:00:02,000\nSRT\n"),
+            Err(CaptionError::Malformed { line: 1, .. })
+        ));
+    }
+}
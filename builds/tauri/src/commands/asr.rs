@@ -0,0 +1,127 @@
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::run_blocking;
+use crate::asr::live::LiveEvent;
+use crate::asr::{self, align, AsrError, ModelInfo, RecognitionConfig, Recognizer, Transcript};
+use crate::audio::buffer::{BufferId, BufferStore};
+use crate::audio::capture::CaptureEngine;
+use crate::audio::AudioError;
+use crate::config::ConfigManager;
+use crate::project::WordTiming;
+use crate::transcripts::TranscriptStore;
+
+pub const RESULT_EVENT: &str = "asr:result";
+pub const ERROR_EVENT: &str = "asr:error";
+pub const END_EVENT: &str = "asr:end";
+
+/// Stores a finished recognition within the `storage` limits, unless
+/// nothing was heard. A failure to store doesn't fail the recognition.
+fn keep(app: &AppHandle, transcript: &Transcript, language: &str, audio: Option<&str>) {
+    if transcript.text.trim().is_empty() {
+        return;
+    }
+    let limits = app.state::<ConfigManager>().get().storage;
+    let stored = app
+        .state::<TranscriptStore>()
+        .insert(transcript, language, audio, &limits);
+    if let Err(e) = stored {
+        log::error!("storing transcript: {e}");
+    }
+}
+
+/// The `voice.recognition` decoding settings.
+fn recognition(app: &AppHandle) -> RecognitionConfig {
+    let config = app.state::<ConfigManager>().get();
+    config.voice.recognition.decoding
+}
+
+#[tauri::command]
+pub fn list_speech_models(recognizer: State<'_, Recognizer>) -> Result<Vec<ModelInfo>, AsrError> {
+    recognizer.models()
+}
+
+/// Transcribes a buffer with `model` (the loaded or first installed model
+/// when omitted) and `config`, or the configured `voice.recognition`. The
+/// transcript is also stored, with `audio` as where the buffer's recording
+/// is saved, if it is.
+#[tauri::command]
+pub async fn transcribe_buffer(
+    app: AppHandle,
+    recognizer: State<'_, Recognizer>,
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    model: Option<String>,
+    config: Option<RecognitionConfig>,
+    audio: Option<String>,
+) -> Result<Transcript, AsrError> {
+    let buffer = store.get(buffer_id)?;
+    let recognizer = recognizer.inner().clone();
+    let config = config.unwrap_or_else(|| recognition(&app));
+    let language = config.language.clone();
+    let transcript = run_blocking(move || {
+        let model = recognizer.model(model.as_deref())?;
+        asr::transcribe(model.as_ref(), &buffer, &config)
+    })
+    .await?;
+    keep(&app, &transcript, &language, audio.as_deref());
+    Ok(transcript)
+}
+
+/// Times each whitespace-separated word of `transcript` against the
+/// buffer, for the word timeline.
+#[tauri::command]
+pub async fn align_transcript(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    transcript: String,
+) -> Result<Vec<WordTiming>, AudioError> {
+    let buffer = store.get(buffer_id)?;
+    Ok(run_blocking(move || align::align(buffer.sample_rate, &buffer.channels, &transcript)).await)
+}
+
+/// Starts recognizing captured audio with `config`, or the configured
+/// `voice.recognition`. Results arrive as `asr:result`
+/// events shaped like `VoiceRecognizer.onResult`, followed by `asr:end`,
+/// when the session's text is stored.
+#[tauri::command]
+pub async fn start_recognition(
+    app: AppHandle,
+    recognizer: State<'_, Recognizer>,
+    engine: State<'_, CaptureEngine>,
+    model: Option<String>,
+    config: Option<RecognitionConfig>,
+) -> Result<(), AsrError> {
+    let loader = recognizer.inner().clone();
+    let model = run_blocking(move || loader.model(model.as_deref())).await?;
+    let config = config.unwrap_or_else(|| recognition(&app));
+    let language = config.language.clone();
+    let mut confidence = 0.0;
+    recognizer.start_live(&engine, model, config, move |event| {
+        let _ = match event {
+            LiveEvent::Result(result) => {
+                confidence = result.confidence;
+                app.emit(RESULT_EVENT, result)
+            }
+            LiveEvent::Error(error) => app.emit(ERROR_EVENT, &error),
+            LiveEvent::End(full) => {
+                // Live audio isn't kept, so there is nothing to time
+                // segments against.
+                let transcript = Transcript {
+                    text: full,
+                    confidence,
+                    alternatives: Vec::new(),
+                    segments: Vec::new(),
+                };
+                keep(&app, &transcript, &language, None);
+                app.emit(END_EVENT, transcript.text)
+            }
+        };
+    })
+}
+
+/// Stops listening. The last result and `asr:end` follow once the final
+/// decode finishes.
+#[tauri::command]
+pub fn stop_recognition(recognizer: State<'_, Recognizer>, engine: State<'_, CaptureEngine>) {
+    recognizer.stop_live(&engine);
+}
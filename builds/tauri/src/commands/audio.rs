@@ -0,0 +1,128 @@
+use serde::Serialize;
+use tauri::ipc::{InvokeBody, Request};
+use tauri::{AppHandle, Emitter, State};
+
+use super::run_blocking;
+use crate::audio::buffer::{BufferId, BufferInfo, BufferStore};
+use crate::audio::export::{self, ExportError, ExportOptions};
+use crate::audio::wav::{self, SampleFormat};
+use crate::audio::{resample, AudioError};
+use crate::sandbox::ProjectRoot;
+
+pub const EXPORT_PROGRESS_EVENT: &str = "export:progress";
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportProgress {
+    pub path: String,
+    /// 0 to 1.
+    pub progress: f32,
+}
+
+/// Decodes a WAV file inside the project root into a new buffer.
+#[tauri::command]
+pub async fn decode_wav(
+    root: State<'_, ProjectRoot>,
+    store: State<'_, BufferStore>,
+    path: String,
+) -> Result<BufferInfo, AudioError> {
+    let bytes = root.read(&path)?;
+    let buffer = run_blocking(move || wav::decode(&bytes)).await?;
+    Ok(store.insert(buffer))
+}
+
+/// Decodes WAV bytes sent as a raw invoke body, e.g. a file the user picked
+/// from outside the project root.
+#[tauri::command]
+pub async fn import_wav(
+    store: State<'_, BufferStore>,
+    request: Request<'_>,
+) -> Result<BufferInfo, AudioError> {
+    let InvokeBody::Raw(bytes) = request.body() else {
+        return Err(AudioError::InvalidArgument(
+            "expected the WAV file as a raw binary body".into(),
+        ));
+    };
+    let bytes = bytes.clone();
+    let buffer = run_blocking(move || wav::decode(&bytes)).await?;
+    Ok(store.insert(buffer))
+}
+
+/// Writes a buffer to `path` in the project root, converting to
+/// `sample_rate` first when given.
+#[tauri::command]
+pub async fn encode_wav(
+    root: State<'_, ProjectRoot>,
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    path: String,
+    format: Option<SampleFormat>,
+    sample_rate: Option<u32>,
+) -> Result<(), AudioError> {
+    if sample_rate == Some(0) {
+        return Err(AudioError::InvalidArgument(
+            "sample rate must be positive".into(),
+        ));
+    }
+    let buffer = store.get(buffer_id)?;
+    let format = format.unwrap_or_default();
+    let bytes = run_blocking(move || match sample_rate {
+        Some(rate) if rate != buffer.sample_rate => {
+            wav::encode(&resample::resample(&buffer, rate), format)
+        }
+        _ => wav::encode(&buffer, format),
+    })
+    .await?;
+    root.write(&path, bytes)?;
+    Ok(())
+}
+
+/// Encodes a buffer (typically a mixdown) to `path` in the project root,
+/// emitting [`EXPORT_PROGRESS_EVENT`] as it goes.
+#[tauri::command]
+pub async fn export_audio(
+    app: AppHandle,
+    root: State<'_, ProjectRoot>,
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    path: String,
+    options: ExportOptions,
+) -> Result<(), ExportError> {
+    let buffer = store.get(buffer_id)?;
+    let event_path = path.clone();
+    let bytes = run_blocking(move || {
+        // Report whole percents only; FLAC calls back for every block.
+        let mut reported = -1.0f32;
+        export::export(&buffer, &options, |progress| {
+            if progress - reported >= 0.01 || progress >= 1.0 {
+                reported = progress;
+                let _ = app.emit(
+                    EXPORT_PROGRESS_EVENT,
+                    ExportProgress {
+                        path: event_path.clone(),
+                        progress,
+                    },
+                );
+            }
+        })
+    })
+    .await?;
+    root.write_atomic(&path, bytes).map_err(AudioError::from)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn buffer_info(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+) -> Result<BufferInfo, AudioError> {
+    store.info(buffer_id)
+}
+
+#[tauri::command]
+pub fn release_buffer(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+) -> Result<(), AudioError> {
+    store.remove(buffer_id)
+}
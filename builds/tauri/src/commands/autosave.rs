@@ -0,0 +1,119 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager, State};
+
+use super::project::{current_session, OpenedProject};
+use super::run_blocking;
+use crate::audio::buffer::BufferStore;
+use crate::autosave::{Autosave, AutosaveError, Record, SessionInfo};
+use crate::config::{ConfigManager, Scope};
+use crate::history::History;
+use crate::mixer::Mixer;
+use crate::project::{self, Session};
+
+/// How often the autosave settings are checked and a snapshot considered.
+const TICK: Duration = Duration::from_secs(1);
+
+/// Snapshots the project as it stands, between edits.
+fn snapshot(app: &AppHandle) -> Result<(), AutosaveError> {
+    let (mixer, store) = (app.state::<Mixer>(), app.state::<BufferStore>());
+    let (history, config) = (app.state::<History>(), app.state::<ConfigManager>());
+    let history = history.inner();
+    let changing = history.hold();
+    app.state::<Autosave>().snapshot(
+        move |session| {
+            let session = current_session(session.clone(), history, &config);
+            let layers = mixer.layers();
+            let audio = project::layer_audio(&layers, &store)?;
+            let saved = history.saved();
+            drop(changing);
+            Ok((session, layers, audio, saved))
+        },
+        |(session, layers, audio, saved)| project::encode(&session, &layers, &audio, &saved),
+    )
+}
+
+/// Journals every change to the history, writes the audio it holds in the
+/// background, and snapshots the project every `storage.autoSaveInterval`
+/// while `storage.autoSave` is on.
+pub fn start(app: &AppHandle) -> io::Result<()> {
+    let handle = app.clone();
+    app.state::<History>().subscribe(move |change| {
+        if let Err(e) = handle.state::<Autosave>().append(&Record::from(change)) {
+            log::error!("autosave: {e}");
+        }
+    });
+
+    let app = app.clone();
+    thread::Builder::new()
+        .name("autosave".into())
+        .spawn(move || {
+            let mut last = Instant::now();
+            loop {
+                let autosave = app.state::<Autosave>();
+                autosave.wait_for_clips(TICK);
+                if let Err(e) = autosave.write_clips() {
+                    log::error!("autosave: {e}");
+                }
+                let storage = app.state::<ConfigManager>().get().storage;
+                autosave.set_enabled(storage.auto_save);
+                let elapsed = last.elapsed() >= Duration::from_millis(storage.auto_save_interval);
+                if !autosave.is_due(elapsed) {
+                    continue;
+                }
+                last = Instant::now();
+                if let Err(e) = snapshot(&app) {
+                    log::error!("autosave: {e}");
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Records the frontend's parts of the project, e.g. after the transcript
+/// or name changes, so they survive a crash.
+#[tauri::command]
+pub fn set_session(autosave: State<'_, Autosave>, session: Session) -> Result<(), AutosaveError> {
+    autosave.set_session(session)
+}
+
+/// Sessions earlier runs left with unsaved changes, newest first, for the
+/// frontend to offer at startup.
+#[tauri::command]
+pub fn recover_sessions(autosave: State<'_, Autosave>) -> Result<Vec<SessionInfo>, AutosaveError> {
+    autosave.list()
+}
+
+/// Replaces the open project with the left-behind session `id`, which
+/// becomes this run's and stays unsaved until saved.
+#[tauri::command]
+pub async fn restore_session(app: AppHandle, id: String) -> Result<OpenedProject, AutosaveError> {
+    run_blocking(move || {
+        let autosave = app.state::<Autosave>();
+        let recovered = autosave.load(&id)?;
+        let path = recovered.info.path.clone();
+        let (session, layers) = recovered.restore(
+            &app.state::<Mixer>(),
+            &app.state::<BufferStore>(),
+            &app.state::<History>(),
+        );
+        let config = app.state::<ConfigManager>();
+        if let Err(e) = config.set_project(session.config.clone()) {
+            log::warn!("session {id}: ignoring its config: {e}");
+            let _ = config.reset(Scope::Project, None);
+        }
+        autosave.recovered(path, session.clone());
+        snapshot(&app)?;
+        autosave.discard(&id)?;
+        Ok(OpenedProject { session, layers })
+    })
+    .await
+}
+
+/// Deletes the left-behind session `id` without restoring it.
+#[tauri::command]
+pub fn discard_session(autosave: State<'_, Autosave>, id: String) -> Result<(), AutosaveError> {
+    autosave.discard(&id)
+}
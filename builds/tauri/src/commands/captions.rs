@@ -0,0 +1,34 @@
+use tauri::State;
+
+use crate::captions::{self, CaptionError, CaptionFormat, CaptionOptions, ImportedCaptions};
+use crate::project::WordTiming;
+use crate::sandbox::ProjectRoot;
+
+/// Lays the word timeline out as captions and writes them to `path` in the
+/// project root. `language` is recorded in TTML files.
+#[tauri::command]
+pub fn export_captions(
+    root: State<'_, ProjectRoot>,
+    words: Vec<WordTiming>,
+    path: String,
+    format: CaptionFormat,
+    options: Option<CaptionOptions>,
+    language: Option<String>,
+) -> Result<(), CaptionError> {
+    let cues = captions::layout(&words, &options.unwrap_or_default())?;
+    let text = captions::write(format, &cues, language.as_deref().unwrap_or_default());
+    root.write(&path, text)?;
+    Ok(())
+}
+
+/// Reads captions from `path` in the project root, in `format` or
+/// whichever the file looks like. The transcript that comes back can be
+/// passed to `align_transcript` to re-time them.
+#[tauri::command]
+pub fn import_captions(
+    root: State<'_, ProjectRoot>,
+    path: String,
+    format: Option<CaptionFormat>,
+) -> Result<ImportedCaptions, CaptionError> {
+    captions::read(&root.read_to_string(&path)?, format)
+}
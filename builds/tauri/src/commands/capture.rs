@@ -0,0 +1,60 @@
+use tauri::{AppHandle, Emitter, State};
+
+use crate::audio::capture::{
+    CaptureConfig, CaptureEngine, CaptureError, CaptureEvent, CaptureStatus, DeviceInfo,
+};
+use crate::config::ConfigManager;
+
+pub const FRAME_EVENT: &str = "capture:frame";
+pub const ERROR_EVENT: &str = "capture:error";
+
+/// Forwards captured frames and stream errors to the webview as events.
+pub fn forward_events(app: &AppHandle, engine: &CaptureEngine) {
+    let app = app.clone();
+    engine.subscribe(move |event| {
+        let _ = match event {
+            CaptureEvent::Frame(frame) => app.emit(FRAME_EVENT, frame),
+            CaptureEvent::Error(error) => app.emit(ERROR_EVENT, error),
+        };
+    });
+}
+
+#[tauri::command]
+pub fn list_input_devices(
+    engine: State<'_, CaptureEngine>,
+) -> Result<Vec<DeviceInfo>, CaptureError> {
+    engine.list_devices()
+}
+
+/// Opens `device_id` (the default device when omitted) with `config`, or
+/// the configured `audio.input` settings.
+#[tauri::command]
+pub fn open_capture(
+    engine: State<'_, CaptureEngine>,
+    settings: State<'_, ConfigManager>,
+    device_id: Option<String>,
+    config: Option<CaptureConfig>,
+) -> Result<CaptureStatus, CaptureError> {
+    let config = config.unwrap_or_else(|| settings.get().audio.input);
+    engine.open(device_id.as_deref(), config)
+}
+
+#[tauri::command]
+pub fn start_capture(engine: State<'_, CaptureEngine>) -> Result<CaptureStatus, CaptureError> {
+    engine.start()
+}
+
+#[tauri::command]
+pub fn stop_capture(engine: State<'_, CaptureEngine>) -> Result<CaptureStatus, CaptureError> {
+    engine.stop()
+}
+
+#[tauri::command]
+pub fn close_capture(engine: State<'_, CaptureEngine>) -> CaptureStatus {
+    engine.close()
+}
+
+#[tauri::command]
+pub fn capture_status(engine: State<'_, CaptureEngine>) -> CaptureStatus {
+    engine.status()
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use serde_json::Value;
+use tauri::plugin::TauriPlugin;
+use tauri::{AppHandle, Emitter, Runtime, State};
+use tauri_plugin_log::{RotationStrategy, Target, TargetKind};
+
+use crate::config::schema::LoggingConfig;
+use crate::config::{Config, ConfigError, ConfigEvent, ConfigManager, Scope};
+
+pub const CHANGED_EVENT: &str = "config:changed";
+pub const INVALID_EVENT: &str = "config:invalid";
+
+/// How often the user config file is checked for outside edits.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Forwards outside edits of the user config to the webview as events:
+/// the new config, or why the edit couldn't be used.
+pub fn watch(app: &AppHandle, config: &ConfigManager) -> std::io::Result<()> {
+    let app = app.clone();
+    config.watch(WATCH_INTERVAL, move |event| {
+        let _ = match event {
+            ConfigEvent::Changed(config) => app.emit(CHANGED_EVENT, config),
+            ConfigEvent::Invalid(message) => app.emit(INVALID_EVENT, message),
+        };
+    })
+}
+
+/// The logger `logging` asks for: stderr and/or rotated files in the app's
+/// log directory. It is set up once, so changes apply on the next launch.
+pub fn logger<R: Runtime>(logging: &LoggingConfig) -> TauriPlugin<R> {
+    let mut targets = Vec::new();
+    if logging.console {
+        targets.push(Target::new(TargetKind::Stderr));
+    }
+    if logging.file {
+        targets.push(Target::new(TargetKind::LogDir { file_name: None }));
+    }
+    tauri_plugin_log::Builder::new()
+        .level(logging.level)
+        .targets(targets)
+        .max_file_size(logging.max_file_size.into())
+        .rotation_strategy(RotationStrategy::KeepSome(logging.max_files))
+        .build()
+}
+
+/// The config in effect, with every layer applied.
+#[tauri::command]
+pub fn get_config(config: State<'_, ConfigManager>) -> Config {
+    config.get()
+}
+
+/// Just the settings overridden in `scope` (the user's when omitted).
+#[tauri::command]
+pub fn get_config_overrides(config: State<'_, ConfigManager>, scope: Option<Scope>) -> Value {
+    config.overrides(scope.unwrap_or_default())
+}
+
+/// Sets the setting at `path`, a dotted name like `audio.output.volume`,
+/// and returns the config in effect.
+#[tauri::command]
+pub fn set_config(
+    config: State<'_, ConfigManager>,
+    path: String,
+    value: Value,
+    scope: Option<Scope>,
+) -> Result<Config, ConfigError> {
+    config.set(scope.unwrap_or_default(), &path, value)
+}
+
+/// Drops the override at `path`, or every override when omitted, falling
+/// back to the layer below.
+#[tauri::command]
+pub fn reset_config(
+    config: State<'_, ConfigManager>,
+    path: Option<String>,
+    scope: Option<Scope>,
+) -> Result<Config, ConfigError> {
+    config.reset(scope.unwrap_or_default(), path.as_deref())
+}
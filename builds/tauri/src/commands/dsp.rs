@@ -0,0 +1,399 @@
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::run_blocking;
+use crate::audio::buffer::{AudioBuffer, BufferId, BufferInfo, BufferStore};
+use crate::audio::AudioError;
+use crate::config::ConfigManager;
+use crate::dsp::breath::{self, BreathRegion};
+use crate::dsp::denoise::{self, NoiseProfile, NoiseReductionMethod};
+use crate::dsp::loudness::{self, LoudnessReport};
+use crate::dsp::normalize;
+use crate::dsp::pitch::PitchQuality;
+use crate::dsp::stft::Stft;
+use crate::dsp::stretch::{self, SPEED_RANGE};
+use crate::dsp::voice::{self, VoiceShift, VoiceShifter};
+use crate::dsp::ProcessingConfig;
+
+/// A span of a buffer in seconds. Without `buffer_id` it refers to the
+/// buffer being processed.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub buffer_id: Option<BufferId>,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Denoises a buffer into a new one, driven by `settings` or the configured
+/// `audio.processing`. The noise profile comes from `noise_region` when
+/// given, otherwise it is estimated from the quietest parts of the buffer.
+/// Returns the source buffer untouched when noise reduction is disabled.
+#[tauri::command]
+pub async fn reduce_noise(
+    store: State<'_, BufferStore>,
+    config: State<'_, ConfigManager>,
+    buffer_id: BufferId,
+    settings: Option<ProcessingConfig>,
+    noise_region: Option<Region>,
+    method: Option<NoiseReductionMethod>,
+) -> Result<BufferInfo, AudioError> {
+    let settings = settings.unwrap_or_else(|| config.get().audio.processing);
+    if !settings.noise_reduction {
+        return store.info(buffer_id);
+    }
+    let buffer = store.get(buffer_id)?;
+    let noise_source = match noise_region {
+        Some(region) => {
+            let source = store.get(region.buffer_id.unwrap_or(buffer_id))?;
+            if source.sample_rate != buffer.sample_rate {
+                return Err(AudioError::SampleRateMismatch(
+                    source.sample_rate,
+                    buffer.sample_rate,
+                ));
+            }
+            let range = source.region(region.start, region.end)?;
+            Some((source, range))
+        }
+        None => None,
+    };
+
+    let denoised = run_blocking(move || {
+        let stft = Stft::for_sample_rate(buffer.sample_rate);
+        let profile = match &noise_source {
+            Some((source, range)) => {
+                let slices: Vec<&[f32]> =
+                    source.channels.iter().map(|c| &c[range.clone()]).collect();
+                NoiseProfile::learn(&stft, &slices)
+            }
+            None => {
+                let slices: Vec<&[f32]> = buffer.channels.iter().map(Vec::as_slice).collect();
+                NoiseProfile::estimate(&stft, &slices)
+            }
+        }
+        .ok_or_else(|| {
+            AudioError::InvalidArgument("noise region is too short to profile".into())
+        })?;
+
+        let channels = denoise::reduce_channels(
+            &stft,
+            &buffer.channels,
+            &profile,
+            settings.noise_reduction_level,
+            method.unwrap_or_default(),
+        );
+        Ok::<_, AudioError>(AudioBuffer::new(buffer.sample_rate, channels))
+    })
+    .await?;
+
+    Ok(store.insert(denoised))
+}
+
+#[tauri::command]
+pub async fn measure_loudness(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+) -> Result<LoudnessReport, AudioError> {
+    let buffer = store.get(buffer_id)?;
+    Ok(run_blocking(move || loudness::measure(buffer.sample_rate, &buffer.channels)).await)
+}
+
+/// True-peak ceiling used when the frontend doesn't pass one; the usual
+/// delivery spec for podcast and streaming platforms.
+const DEFAULT_CEILING_DBTP: f64 = -1.0;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizeResult {
+    pub buffer: BufferInfo,
+    pub gain_db: f64,
+    pub limited: bool,
+    pub loudness: LoudnessReport,
+}
+
+/// Normalizes a buffer into a new one at the `normalizationTarget` LUFS of
+/// `settings` or the configured `audio.processing`, without exceeding
+/// `ceiling` dBTP. With normalization disabled, or for silent buffers, the
+/// source buffer is returned with a zero gain.
+#[tauri::command]
+pub async fn normalize_loudness(
+    store: State<'_, BufferStore>,
+    config: State<'_, ConfigManager>,
+    buffer_id: BufferId,
+    settings: Option<ProcessingConfig>,
+    ceiling: Option<f64>,
+) -> Result<NormalizeResult, AudioError> {
+    let settings = settings.unwrap_or_else(|| config.get().audio.processing);
+    let ceiling = ceiling.unwrap_or(DEFAULT_CEILING_DBTP);
+    if !ceiling.is_finite() || ceiling > 0.0 {
+        return Err(AudioError::InvalidArgument(format!(
+            "true-peak ceiling must be at or below 0 dBTP, got {ceiling}"
+        )));
+    }
+    let buffer = store.get(buffer_id)?;
+
+    let (normalized, report) = run_blocking(move || {
+        let normalized = settings
+            .normalization
+            .then(|| {
+                normalize::normalize(
+                    buffer.sample_rate,
+                    &buffer.channels,
+                    settings.normalization_target,
+                    ceiling,
+                )
+            })
+            .flatten()
+            .map(|n| {
+                (
+                    AudioBuffer::new(buffer.sample_rate, n.channels),
+                    n.gain_db,
+                    n.limited,
+                )
+            });
+        let measured = normalized.as_ref().map_or(&*buffer, |(b, ..)| b);
+        let report = loudness::measure(measured.sample_rate, &measured.channels);
+        (normalized, report)
+    })
+    .await;
+
+    Ok(match normalized {
+        Some((output, gain_db, limited)) => NormalizeResult {
+            buffer: store.insert(output),
+            gain_db,
+            limited,
+            loudness: report,
+        },
+        None => NormalizeResult {
+            buffer: store.info(buffer_id)?,
+            gain_db: 0.0,
+            limited: false,
+            loudness: report,
+        },
+    })
+}
+
+/// Range of the pitch slider, in semitones.
+const MAX_PITCH_SHIFT: f32 = 24.0;
+/// Range of the formant slider, in semitones.
+const MAX_FORMANT_SHIFT: f32 = 12.0;
+
+fn check_shift(name: &str, semitones: f32, max: f32) -> Result<(), AudioError> {
+    if semitones.is_finite() && semitones.abs() <= max {
+        Ok(())
+    } else {
+        Err(AudioError::InvalidArgument(format!(
+            "{name} shift must be within ±{max} semitones, got {semitones}"
+        )))
+    }
+}
+
+/// Renders a voice shift into a new buffer, or returns the source buffer
+/// when there is nothing to do.
+async fn render_shift(
+    store: &BufferStore,
+    buffer_id: BufferId,
+    shift: VoiceShift,
+    quality: Option<PitchQuality>,
+) -> Result<BufferInfo, AudioError> {
+    check_shift("pitch", shift.pitch, MAX_PITCH_SHIFT)?;
+    if let Some(formant) = shift.formant {
+        check_shift("formant", formant, MAX_FORMANT_SHIFT)?;
+    }
+    if shift.is_identity() {
+        return store.info(buffer_id);
+    }
+    let buffer = store.get(buffer_id)?;
+    let shifted = run_blocking(move || {
+        let shifter = VoiceShifter::new(
+            buffer.sample_rate,
+            buffer.channels.len(),
+            shift,
+            quality.unwrap_or_default(),
+        );
+        let channels = voice::render(shifter, &buffer.channels);
+        AudioBuffer::new(buffer.sample_rate, channels)
+    })
+    .await;
+    Ok(store.insert(shifted))
+}
+
+/// Shifts a buffer by `semitones` into a new one, keeping its duration.
+/// The formants move with the pitch; see [`render_voice`] to keep them.
+#[tauri::command]
+pub async fn shift_pitch(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    semitones: f32,
+    quality: Option<PitchQuality>,
+) -> Result<BufferInfo, AudioError> {
+    let shift = VoiceShift {
+        pitch: semitones,
+        formant: None,
+        ..VoiceShift::default()
+    };
+    render_shift(&store, buffer_id, shift, quality).await
+}
+
+/// Moves the formants of a buffer by `semitones` into a new one, keeping
+/// its pitch.
+#[tauri::command]
+pub async fn shift_formants(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    semitones: f32,
+    quality: Option<PitchQuality>,
+) -> Result<BufferInfo, AudioError> {
+    let shift = VoiceShift {
+        pitch: 0.0,
+        formant: Some(semitones),
+        ..VoiceShift::default()
+    };
+    render_shift(&store, buffer_id, shift, quality).await
+}
+
+/// Applies the pitch and formant sliders to a buffer in one pass. Without a
+/// `formant` value the formants are kept where they are.
+#[tauri::command]
+pub async fn render_voice(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    pitch: f32,
+    formant: Option<f32>,
+    quality: Option<PitchQuality>,
+) -> Result<BufferInfo, AudioError> {
+    let shift = VoiceShift {
+        pitch,
+        formant: Some(formant.unwrap_or(0.0)),
+        ..VoiceShift::default()
+    };
+    render_shift(&store, buffer_id, shift, quality).await
+}
+
+fn check_speed(speed: f64) -> Result<(), AudioError> {
+    let (min, max) = SPEED_RANGE;
+    if (min..=max).contains(&speed) {
+        Ok(())
+    } else {
+        Err(AudioError::InvalidArgument(format!(
+            "speed must be between {min}x and {max}x, got {speed}"
+        )))
+    }
+}
+
+/// Plays a buffer at `speed` (the timing slider) into a new one, keeping
+/// its pitch. A speed of 1 returns the source buffer.
+#[tauri::command]
+pub async fn stretch_time(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    speed: f64,
+) -> Result<BufferInfo, AudioError> {
+    check_speed(speed)?;
+    if speed == 1.0 {
+        return store.info(buffer_id);
+    }
+    let buffer = store.get(buffer_id)?;
+    let stretched = run_blocking(move || {
+        let channels = stretch::stretch(buffer.sample_rate, &buffer.channels, speed);
+        AudioBuffer::new(buffer.sample_rate, channels)
+    })
+    .await;
+    Ok(store.insert(stretched))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StretchedRegion {
+    pub buffer: BufferInfo,
+    /// Where the stretched region now starts and ends, in seconds.
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Stretches the span `start..end` (in seconds) of a buffer, such as a
+/// single word, into a new buffer; everything around it is kept as is.
+#[tauri::command]
+pub async fn stretch_region(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    start: f64,
+    end: f64,
+    speed: f64,
+) -> Result<StretchedRegion, AudioError> {
+    check_speed(speed)?;
+    let buffer = store.get(buffer_id)?;
+    let range = buffer.region(start, end)?;
+    let rate = buffer.sample_rate as f64;
+    if speed == 1.0 {
+        return Ok(StretchedRegion {
+            buffer: store.info(buffer_id)?,
+            start: range.start as f64 / rate,
+            end: range.end as f64 / rate,
+        });
+    }
+    let region_start = range.start;
+    let (stretched, region_end) = run_blocking(move || {
+        let (channels, end) =
+            stretch::stretch_region(buffer.sample_rate, &buffer.channels, range, speed);
+        (AudioBuffer::new(buffer.sample_rate, channels), end)
+    })
+    .await;
+    Ok(StretchedRegion {
+        buffer: store.insert(stretched),
+        start: region_start as f64 / rate,
+        end: region_end as f64 / rate,
+    })
+}
+
+/// Lists the breaths in a buffer for the timeline.
+#[tauri::command]
+pub async fn detect_breaths(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+) -> Result<Vec<BreathRegion>, AudioError> {
+    let buffer = store.get(buffer_id)?;
+    Ok(run_blocking(move || breath::detect(buffer.sample_rate, &buffer.channels)).await)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreathResult {
+    pub buffer: BufferInfo,
+    pub regions: Vec<BreathRegion>,
+}
+
+/// Attenuates (negative `amount`, down to -100 for silence) or boosts
+/// (positive, up to +100) the breaths of a buffer into a new one. Uses
+/// `regions` as edited on the timeline when given, otherwise detects them.
+#[tauri::command]
+pub async fn process_breaths(
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    amount: f32,
+    regions: Option<Vec<BreathRegion>>,
+) -> Result<BreathResult, AudioError> {
+    if !(-100.0..=100.0).contains(&amount) {
+        return Err(AudioError::InvalidArgument(format!(
+            "breath amount must be between -100 and 100, got {amount}"
+        )));
+    }
+    let buffer = store.get(buffer_id)?;
+    let (processed, regions) = run_blocking(move || {
+        let regions =
+            regions.unwrap_or_else(|| breath::detect(buffer.sample_rate, &buffer.channels));
+        let mut channels = buffer.channels.clone();
+        breath::apply(
+            buffer.sample_rate,
+            &mut channels,
+            &regions,
+            breath::amount_to_gain(amount),
+        );
+        (AudioBuffer::new(buffer.sample_rate, channels), regions)
+    })
+    .await;
+    Ok(BreathResult {
+        buffer: store.insert(processed),
+        regions,
+    })
+}
@@ -0,0 +1,74 @@
+use tauri::State;
+
+use super::run_blocking;
+use crate::audio::buffer::BufferStore;
+use crate::config::ConfigManager;
+use crate::edit::cleanup::{self, CleanupOptions};
+use crate::edit::{CutId, CutSpan};
+use crate::history::{Edit, History};
+use crate::mixer::{Layer, LayerId, Mixer, MixerError};
+use crate::project::WordTiming;
+
+/// Proposes cuts that tighten a layer's long pauses and remove filler
+/// words. `words` are the layer's aligned transcript, when there is one.
+/// Without `options`, pauses are found with the configured
+/// `voice.activation.vad`. Nothing changes until the proposal is applied
+/// with `apply_cuts`.
+#[tauri::command]
+pub async fn propose_cleanup(
+    mixer: State<'_, Mixer>,
+    store: State<'_, BufferStore>,
+    config: State<'_, ConfigManager>,
+    layer_id: LayerId,
+    words: Option<Vec<WordTiming>>,
+    options: Option<CleanupOptions>,
+) -> Result<Vec<CutSpan>, MixerError> {
+    let layer = mixer.layer(layer_id)?;
+    let Some(buffer_id) = layer.buffer else {
+        return Ok(Vec::new());
+    };
+    let buffer = store.get(buffer_id)?;
+    let existing: Vec<CutSpan> = layer.cuts.into_iter().map(|cut| cut.span).collect();
+    let options = options.unwrap_or_else(|| CleanupOptions {
+        vad: config.get().voice.activation.vad,
+        ..Default::default()
+    });
+    Ok(run_blocking(move || {
+        cleanup::propose(&buffer, &words.unwrap_or_default(), &options, &existing)
+    })
+    .await)
+}
+
+/// Cuts `cuts` out of a layer without touching its audio. Each one gets
+/// an id that `remove_cut` takes to undo it.
+#[tauri::command]
+pub fn apply_cuts(
+    mixer: State<'_, Mixer>,
+    store: State<'_, BufferStore>,
+    history: State<'_, History>,
+    layer_id: LayerId,
+    cuts: Vec<CutSpan>,
+) -> Result<Layer, MixerError> {
+    history.edit(|| {
+        let before = mixer.layer(layer_id)?;
+        let layer = mixer.add_cuts(layer_id, cuts)?;
+        let edit = Edit::update_layer(before, layer.clone(), &store)?;
+        Ok((layer, edit))
+    })
+}
+
+#[tauri::command]
+pub fn remove_cut(
+    mixer: State<'_, Mixer>,
+    store: State<'_, BufferStore>,
+    history: State<'_, History>,
+    layer_id: LayerId,
+    cut_id: CutId,
+) -> Result<Layer, MixerError> {
+    history.edit(|| {
+        let before = mixer.layer(layer_id)?;
+        let layer = mixer.remove_cut(layer_id, cut_id)?;
+        let edit = Edit::update_layer(before, layer.clone(), &store)?;
+        Ok((layer, edit))
+    })
+}
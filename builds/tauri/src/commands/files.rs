@@ -0,0 +1,17 @@
+use tauri::State;
+
+use crate::sandbox::{ProjectRoot, SandboxError};
+
+#[tauri::command]
+pub fn read_file(root: State<'_, ProjectRoot>, path: String) -> Result<String, SandboxError> {
+    root.read_to_string(&path)
+}
+
+#[tauri::command]
+pub fn write_file(
+    root: State<'_, ProjectRoot>,
+    path: String,
+    content: String,
+) -> Result<(), SandboxError> {
+    root.write(&path, content)
+}
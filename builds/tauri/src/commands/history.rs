@@ -0,0 +1,73 @@
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use super::run_blocking;
+use crate::audio::buffer::BufferStore;
+use crate::history::{History, HistoryItem};
+use crate::mixer::{Layer, Mixer, MixerError};
+use crate::project::EffectSettings;
+
+/// The project after an undo or redo, for the frontend to redraw from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryState {
+    /// The entry undone or redone; `None` if there was nothing to do.
+    pub label: Option<String>,
+    pub effects: EffectSettings,
+    pub layers: Vec<Layer>,
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+fn state(label: Option<String>, history: &History, mixer: &Mixer) -> HistoryState {
+    HistoryState {
+        label,
+        effects: history.effects(),
+        layers: mixer.layers(),
+        can_undo: history.can_undo(),
+        can_redo: history.can_redo(),
+    }
+}
+
+#[tauri::command]
+pub fn can_undo(history: State<'_, History>) -> bool {
+    history.can_undo()
+}
+
+#[tauri::command]
+pub fn can_redo(history: State<'_, History>) -> bool {
+    history.can_redo()
+}
+
+#[tauri::command]
+pub async fn undo(app: AppHandle) -> Result<HistoryState, MixerError> {
+    run_blocking(move || {
+        let (history, mixer) = (app.state::<History>(), app.state::<Mixer>());
+        let label = history.undo(&mixer, &app.state::<BufferStore>())?;
+        Ok(state(label, &history, &mixer))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn redo(app: AppHandle) -> Result<HistoryState, MixerError> {
+    run_blocking(move || {
+        let (history, mixer) = (app.state::<History>(), app.state::<Mixer>());
+        let label = history.redo(&mixer, &app.state::<BufferStore>())?;
+        Ok(state(label, &history, &mixer))
+    })
+    .await
+}
+
+/// Every entry, oldest first, marked with whether it is applied.
+#[tauri::command]
+pub fn history_list(history: State<'_, History>) -> Vec<HistoryItem> {
+    history.list()
+}
+
+/// Records new effect settings. Calls while a slider is being dragged
+/// merge into one entry.
+#[tauri::command]
+pub fn set_effects(history: State<'_, History>, effects: EffectSettings) {
+    history.set_effects(effects);
+}
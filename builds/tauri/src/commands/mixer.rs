@@ -0,0 +1,74 @@
+use tauri::State;
+
+use super::run_blocking;
+use crate::audio::buffer::{BufferInfo, BufferStore};
+use crate::history::{Edit, History};
+use crate::mixer::{self, Layer, LayerId, LayerUpdate, Mixer, MixerError};
+
+#[tauri::command]
+pub fn list_layers(mixer: State<'_, Mixer>) -> Vec<Layer> {
+    mixer.layers()
+}
+
+#[tauri::command]
+pub fn add_layer(
+    mixer: State<'_, Mixer>,
+    store: State<'_, BufferStore>,
+    history: State<'_, History>,
+    name: Option<String>,
+) -> Result<Layer, MixerError> {
+    history.edit(|| {
+        let layer = mixer.add_layer(name)?;
+        let index = mixer.layers().len() - 1;
+        let edit = Edit::add_layer(index, layer.clone(), &store)?;
+        Ok((layer, edit))
+    })
+}
+
+#[tauri::command]
+pub fn update_layer(
+    mixer: State<'_, Mixer>,
+    store: State<'_, BufferStore>,
+    history: State<'_, History>,
+    layer_id: LayerId,
+    update: LayerUpdate,
+) -> Result<Layer, MixerError> {
+    if let Some(buffer_id) = update.buffer {
+        store.info(buffer_id)?;
+    }
+    history.edit(|| {
+        let before = mixer.layer(layer_id)?;
+        let layer = mixer.update_layer(layer_id, update)?;
+        let edit = Edit::update_layer(before, layer.clone(), &store)?;
+        Ok((layer, edit))
+    })
+}
+
+#[tauri::command]
+pub fn remove_layer(
+    mixer: State<'_, Mixer>,
+    store: State<'_, BufferStore>,
+    history: State<'_, History>,
+    layer_id: LayerId,
+) -> Result<(), MixerError> {
+    history.edit(|| {
+        let (index, layer) = mixer.remove_layer(layer_id)?;
+        Ok(((), Edit::remove_layer(index, layer, &store)?))
+    })
+}
+
+/// Renders every audible layer into a new stereo buffer.
+#[tauri::command]
+pub async fn mixdown(
+    mixer: State<'_, Mixer>,
+    store: State<'_, BufferStore>,
+) -> Result<BufferInfo, MixerError> {
+    let mut layers = Vec::new();
+    for layer in mixer.audible() {
+        if let Some(buffer_id) = layer.buffer {
+            layers.push((layer, store.get(buffer_id)?));
+        }
+    }
+    let buffer = run_blocking(move || mixer::render(&layers)).await?;
+    Ok(store.insert(buffer))
+}
@@ -0,0 +1,28 @@
+//! Tauri command handlers, grouped by subsystem. Handlers stay thin: they
+//! pull managed state, call into the core modules and map the result.
+
+pub mod asr;
+pub mod audio;
+pub mod autosave;
+pub mod captions;
+pub mod capture;
+pub mod config;
+pub mod dsp;
+pub mod edit;
+pub mod files;
+pub mod history;
+pub mod mixer;
+pub mod presets;
+pub mod project;
+pub mod search;
+pub mod transcripts;
+pub mod tts;
+pub mod vad;
+pub mod wake;
+
+/// Runs CPU-heavy work on the blocking pool so long renders don't stall IPC.
+pub async fn run_blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> T {
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .expect("blocking task panicked")
+}
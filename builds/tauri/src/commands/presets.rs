@@ -0,0 +1,62 @@
+use tauri::State;
+
+use super::run_blocking;
+use crate::audio::buffer::{AudioBuffer, BufferId, BufferInfo, BufferStore};
+use crate::dsp::emotion::{self, EmotionParams};
+use crate::dsp::pitch::PitchQuality;
+use crate::presets::{EmotionPreset, PresetError, PresetStore};
+
+#[tauri::command]
+pub fn list_emotion_presets(presets: State<PresetStore>) -> Vec<EmotionPreset> {
+    presets.list()
+}
+
+#[tauri::command]
+pub fn create_emotion_preset(
+    presets: State<PresetStore>,
+    name: String,
+    params: EmotionParams,
+) -> Result<EmotionPreset, PresetError> {
+    presets.create(&name, params)
+}
+
+#[tauri::command]
+pub fn save_emotion_preset(
+    presets: State<PresetStore>,
+    preset: EmotionPreset,
+) -> Result<EmotionPreset, PresetError> {
+    presets.save(preset)
+}
+
+#[tauri::command]
+pub fn delete_emotion_preset(presets: State<PresetStore>, id: String) -> Result<(), PresetError> {
+    presets.delete(&id)
+}
+
+/// Renders a buffer with the preset `preset_id` into a new one. Returns the
+/// source buffer when the preset changes nothing.
+#[tauri::command]
+pub async fn apply_emotion_preset(
+    presets: State<'_, PresetStore>,
+    store: State<'_, BufferStore>,
+    buffer_id: BufferId,
+    preset_id: String,
+    quality: Option<PitchQuality>,
+) -> Result<BufferInfo, PresetError> {
+    let preset = presets.get(&preset_id)?;
+    let buffer = store.get(buffer_id)?;
+    let rendered = run_blocking(move || {
+        emotion::render(
+            buffer.sample_rate,
+            &buffer.channels,
+            &preset.params,
+            quality.unwrap_or_default(),
+        )
+        .map(|channels| AudioBuffer::new(buffer.sample_rate, channels))
+    })
+    .await;
+    match rendered {
+        Some(buffer) => Ok(store.insert(buffer)),
+        None => Ok(store.info(buffer_id)?),
+    }
+}
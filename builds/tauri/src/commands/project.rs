@@ -0,0 +1,115 @@
+use serde::Serialize;
+use tauri::State;
+
+use super::run_blocking;
+use crate::audio::buffer::BufferStore;
+use crate::autosave::{Autosave, AutosaveError};
+use crate::config::{ConfigManager, Scope};
+use crate::history::History;
+use crate::mixer::{Layer, Mixer};
+use crate::project::{self, ProjectError, Session};
+use crate::sandbox::ProjectRoot;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenedProject {
+    pub session: Session,
+    pub layers: Vec<Layer>,
+}
+
+/// `session` with its effects taken from the history and its config from
+/// the project-level overrides, as it would be saved.
+pub fn current_session(mut session: Session, history: &History, config: &ConfigManager) -> Session {
+    session.effects = history.effects();
+    if let serde_json::Value::Object(overrides) = config.overrides(Scope::Project) {
+        session.config = overrides;
+    }
+    session
+}
+
+/// Clears the layers, history and project config and returns a fresh
+/// session.
+#[tauri::command]
+pub fn new_project(
+    mixer: State<'_, Mixer>,
+    history: State<'_, History>,
+    config: State<'_, ConfigManager>,
+    autosave: State<'_, Autosave>,
+) -> Session {
+    mixer.replace(Vec::new());
+    if let Err(e) = config.reset(Scope::Project, None) {
+        log::error!("clearing the project config: {e}");
+    }
+    let session = Session::default();
+    history.load(Default::default(), session.effects.clone());
+    let checkpoint = project::encode(&session, &[], &Default::default(), &Default::default())
+        .map_err(AutosaveError::from)
+        .and_then(|bytes| autosave.checkpoint(None, session.clone(), bytes));
+    if let Err(e) = checkpoint {
+        log::error!("autosave: {e}");
+    }
+    session
+}
+
+/// Saves `session` with the current layers, their audio and the undo
+/// history to `path` in the project root, replacing any existing file
+/// atomically. The session's effects are taken from the history and its
+/// config from the project-level overrides.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_project(
+    root: State<'_, ProjectRoot>,
+    mixer: State<'_, Mixer>,
+    store: State<'_, BufferStore>,
+    history: State<'_, History>,
+    config: State<'_, ConfigManager>,
+    autosave: State<'_, Autosave>,
+    path: String,
+    session: Session,
+) -> Result<(), ProjectError> {
+    let session = current_session(session, &history, &config);
+    let saved = history.saved();
+    let layers = mixer.layers();
+    let audio = project::layer_audio(&layers, &store)?;
+    let encoded = session.clone();
+    let bytes = run_blocking(move || project::encode(&encoded, &layers, &audio, &saved)).await?;
+    root.write_atomic(&path, &bytes)?;
+    if let Err(e) = autosave.checkpoint(Some(path), session, bytes) {
+        log::error!("autosave: {e}");
+    }
+    Ok(())
+}
+
+/// Opens the project at `path`, replacing the current layers, history and
+/// project config. Project config the current user config can't take is
+/// dropped rather than keeping the project closed.
+#[tauri::command]
+pub async fn open_project(
+    root: State<'_, ProjectRoot>,
+    mixer: State<'_, Mixer>,
+    store: State<'_, BufferStore>,
+    history: State<'_, History>,
+    config: State<'_, ConfigManager>,
+    autosave: State<'_, Autosave>,
+    path: String,
+) -> Result<OpenedProject, ProjectError> {
+    let bytes = root.read(&path)?;
+    let (contents, bytes) = run_blocking(move || {
+        let contents = project::decode(&bytes);
+        (contents, bytes)
+    })
+    .await;
+    let contents = contents?;
+    let session = contents.session.clone();
+    history.load(contents.history.clone(), session.effects.clone());
+    if let Err(e) = config.set_project(session.config.clone()) {
+        log::warn!("{path}: ignoring its config: {e}");
+        let _ = config.reset(Scope::Project, None);
+    }
+    let layers = contents.load(&store);
+    mixer.replace(layers.clone());
+    if let Err(e) = autosave.checkpoint(Some(path), session.clone(), bytes) {
+        log::error!("autosave: {e}");
+    }
+    Ok(OpenedProject { session, layers })
+}
@@ -0,0 +1,26 @@
+use tauri::State;
+
+use super::run_blocking;
+use crate::sandbox::ProjectRoot;
+use crate::search::{SearchError, SearchHit, SearchIndex, SearchOptions};
+use crate::transcripts::TranscriptStore;
+
+/// Stored transcripts and saved projects matching `query`, best first.
+/// Words may be quoted together to match them as a phrase.
+#[tauri::command]
+pub async fn search(
+    index: State<'_, SearchIndex>,
+    transcripts: State<'_, TranscriptStore>,
+    root: State<'_, ProjectRoot>,
+    query: String,
+    options: Option<SearchOptions>,
+) -> Result<Vec<SearchHit>, SearchError> {
+    let index = index.inner().clone();
+    let transcripts = transcripts.inner().clone();
+    let root = root.inner().clone();
+    run_blocking(move || {
+        index.refresh(&transcripts, &root)?;
+        Ok(index.search(&query, &options.unwrap_or_default()))
+    })
+    .await
+}
@@ -0,0 +1,29 @@
+use tauri::State;
+
+use crate::transcripts::{
+    StoredTranscript, TranscriptError, TranscriptId, TranscriptStore, TranscriptSummary,
+};
+
+/// Every stored transcript without its body, newest first.
+#[tauri::command]
+pub fn list_transcripts(
+    transcripts: State<'_, TranscriptStore>,
+) -> Result<Vec<TranscriptSummary>, TranscriptError> {
+    transcripts.list()
+}
+
+#[tauri::command]
+pub fn get_transcript(
+    transcripts: State<'_, TranscriptStore>,
+    id: TranscriptId,
+) -> Result<StoredTranscript, TranscriptError> {
+    transcripts.get(id)
+}
+
+#[tauri::command]
+pub fn delete_transcript(
+    transcripts: State<'_, TranscriptStore>,
+    id: TranscriptId,
+) -> Result<(), TranscriptError> {
+    transcripts.delete(id)
+}
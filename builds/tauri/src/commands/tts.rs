@@ -0,0 +1,63 @@
+use serde::Serialize;
+use tauri::State;
+
+use super::run_blocking;
+use crate::audio::buffer::{BufferInfo, BufferStore};
+use crate::history::{Edit, History};
+use crate::mixer::{Layer, LayerUpdate, Mixer};
+use crate::project::WordTiming;
+use crate::tts::{self, SynthesisOptions, Synthesizer, TtsError, VoiceProfile};
+
+#[tauri::command]
+pub fn list_voices(synthesizer: State<'_, Synthesizer>) -> Result<Vec<VoiceProfile>, TtsError> {
+    synthesizer.voices()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SynthesisResult {
+    pub layer: Layer,
+    pub buffer: BufferInfo,
+    /// Timings for the word timeline, one per whitespace-separated word.
+    pub words: Vec<WordTiming>,
+}
+
+/// Speaks `text` with `voice` (the loaded or first installed voice when
+/// omitted) onto a new layer named after the voice.
+#[tauri::command]
+pub async fn synthesize_speech(
+    synthesizer: State<'_, Synthesizer>,
+    store: State<'_, BufferStore>,
+    mixer: State<'_, Mixer>,
+    history: State<'_, History>,
+    text: String,
+    voice: Option<String>,
+    options: Option<SynthesisOptions>,
+) -> Result<SynthesisResult, TtsError> {
+    let synthesizer = synthesizer.inner().clone();
+    let (name, speech) = run_blocking(move || {
+        let voice = synthesizer.voice(voice.as_deref())?;
+        let speech = tts::synthesize(&voice, &text, &options.unwrap_or_default())?;
+        Ok::<_, TtsError>((voice.profile.name.clone(), speech))
+    })
+    .await?;
+    let buffer = store.insert(speech.buffer);
+    let layer = history.edit(|| {
+        let layer = mixer.add_layer(Some(name))?;
+        let layer = mixer.update_layer(
+            layer.id,
+            LayerUpdate {
+                buffer: Some(buffer.id),
+                ..Default::default()
+            },
+        )?;
+        let index = mixer.layers().len() - 1;
+        let edit = Edit::add_layer(index, layer.clone(), &store)?;
+        Ok::<_, TtsError>((layer, edit))
+    })?;
+    Ok(SynthesisResult {
+        layer,
+        buffer,
+        words: speech.words,
+    })
+}
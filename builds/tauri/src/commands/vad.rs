@@ -0,0 +1,102 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::run_blocking;
+use crate::audio::buffer::{AudioBuffer, BufferId, BufferInfo, BufferStore};
+use crate::audio::capture::{CaptureEngine, CaptureError};
+use crate::audio::AudioError;
+use crate::config::ConfigManager;
+use crate::vad::{self, SpeechSegment, VadConfig, VadEvent, VoiceActivity};
+
+pub const SPEECH_START_EVENT: &str = "vad:speech-start";
+pub const SPEECH_END_EVENT: &str = "vad:speech-end";
+pub const TIMEOUT_EVENT: &str = "vad:timeout";
+
+/// Finds the speech in a buffer with `config`, or the configured
+/// `voice.activation` settings.
+#[tauri::command]
+pub async fn detect_speech_segments(
+    store: State<'_, BufferStore>,
+    settings: State<'_, ConfigManager>,
+    buffer_id: BufferId,
+    config: Option<VadConfig>,
+) -> Result<Vec<SpeechSegment>, AudioError> {
+    let buffer = store.get(buffer_id)?;
+    let config = config.unwrap_or_else(|| settings.get().voice.activation.vad);
+    Ok(run_blocking(move || vad::segments(&buffer, &config)).await)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentBuffer {
+    pub segment: SpeechSegment,
+    pub buffer: BufferInfo,
+}
+
+/// Splits a recording into one new buffer per speech segment, found as
+/// `detect_speech_segments` finds them.
+#[tauri::command]
+pub async fn split_speech_segments(
+    store: State<'_, BufferStore>,
+    settings: State<'_, ConfigManager>,
+    buffer_id: BufferId,
+    config: Option<VadConfig>,
+) -> Result<Vec<SegmentBuffer>, AudioError> {
+    let buffer = store.get(buffer_id)?;
+    let config = config.unwrap_or_else(|| settings.get().voice.activation.vad);
+    let pieces = run_blocking(move || {
+        vad::segments(&buffer, &config)
+            .into_iter()
+            .filter_map(|segment| {
+                let range = buffer.region(segment.start, segment.end).ok()?;
+                let channels = buffer
+                    .channels
+                    .iter()
+                    .map(|channel| channel[range.clone()].to_vec())
+                    .collect();
+                Some((segment, AudioBuffer::new(buffer.sample_rate, channels)))
+            })
+            .collect::<Vec<_>>()
+    })
+    .await;
+    Ok(pieces
+        .into_iter()
+        .map(|(segment, piece)| SegmentBuffer {
+            segment,
+            buffer: store.insert(piece),
+        })
+        .collect())
+}
+
+/// Follows capture with `config`, or the configured `voice.activation`
+/// settings, emitting `vad:speech-start` and `vad:speech-end` as speech
+/// comes and goes. After `silenceTimeout` without speech, capture
+/// is stopped and `vad:timeout` emitted. Does nothing when detection is
+/// disabled.
+#[tauri::command]
+pub fn start_voice_activity(
+    app: AppHandle,
+    activity: State<'_, VoiceActivity>,
+    engine: State<'_, CaptureEngine>,
+    settings: State<'_, ConfigManager>,
+    config: Option<VadConfig>,
+) -> Result<(), CaptureError> {
+    let config = config.unwrap_or_else(|| settings.get().voice.activation.vad);
+    activity.start(&engine, &config, move |event| {
+        let _ = match event {
+            VadEvent::SpeechStart { .. } => app.emit(SPEECH_START_EVENT, event),
+            VadEvent::SpeechEnd { .. } => app.emit(SPEECH_END_EVENT, event),
+            VadEvent::SilenceTimeout { .. } => {
+                if let Err(error) = app.state::<CaptureEngine>().stop() {
+                    let _ = app.emit(super::capture::ERROR_EVENT, error);
+                }
+                app.emit(TIMEOUT_EVENT, event)
+            }
+        };
+    })
+}
+
+#[tauri::command]
+pub fn stop_voice_activity(activity: State<'_, VoiceActivity>, engine: State<'_, CaptureEngine>) {
+    activity.stop(&engine);
+}
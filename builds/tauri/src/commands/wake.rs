@@ -0,0 +1,59 @@
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::run_blocking;
+use crate::audio::buffer::{BufferId, BufferStore};
+use crate::audio::capture::CaptureEngine;
+use crate::config::ConfigManager;
+use crate::wake::{WakeConfig, WakeError, WakeWordInfo, WakeWords};
+
+pub const DETECTED_EVENT: &str = "wake:detected";
+
+#[tauri::command]
+pub fn list_wake_words(words: State<'_, WakeWords>) -> Vec<WakeWordInfo> {
+    words.list()
+}
+
+/// Enrolls `phrase` from 3 to 10 buffers, each one recording of it.
+#[tauri::command]
+pub async fn enroll_wake_word(
+    app: AppHandle,
+    store: State<'_, BufferStore>,
+    phrase: String,
+    buffer_ids: Vec<BufferId>,
+) -> Result<WakeWordInfo, WakeError> {
+    let buffers = buffer_ids
+        .into_iter()
+        .map(|id| store.get(id))
+        .collect::<Result<Vec<_>, _>>()?;
+    run_blocking(move || {
+        let recordings: Vec<_> = buffers.iter().map(|buffer| buffer.as_ref()).collect();
+        app.state::<WakeWords>().enroll(&phrase, &recordings)
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn delete_wake_word(words: State<'_, WakeWords>, phrase: String) -> Result<(), WakeError> {
+    words.delete(&phrase)
+}
+
+/// Listens to capture for the wake word of `config`, or the configured
+/// `voice.activation`, and emits `wake:detected` each time it is said.
+#[tauri::command]
+pub fn start_wake_word(
+    app: AppHandle,
+    words: State<'_, WakeWords>,
+    engine: State<'_, CaptureEngine>,
+    settings: State<'_, ConfigManager>,
+    config: Option<WakeConfig>,
+) -> Result<(), WakeError> {
+    let config = config.unwrap_or_else(|| settings.get().voice.activation.wake);
+    words.start(&engine, &config, move |detection| {
+        let _ = app.emit(DETECTED_EVENT, detection);
+    })
+}
+
+#[tauri::command]
+pub fn stop_wake_word(words: State<'_, WakeWords>, engine: State<'_, CaptureEngine>) {
+    words.stop(&engine);
+}
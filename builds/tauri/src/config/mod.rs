@@ -0,0 +1,483 @@
+//! App configuration in three layers: the defaults bundled from
+//! `config/default.json`, the user's overrides in the app config dir, and
+//! the open project's overrides. Later layers win key by key, and the
+//! result is checked against the typed [`Config`] schema before it takes
+//! effect, so a bad value is refused rather than half-applied.
+//!
+//! Overrides are kept as sparse JSON, holding only what was set, so a
+//! user file never pins a default that a later version changes. The user
+//! file is polled for edits made outside the app.
+
+pub mod schema;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::sandbox::write_atomic;
+
+pub use self::schema::Config;
+
+const DEFAULTS: &str = include_str!("../../../../config/default.json");
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("unknown config setting {0:?}")]
+    UnknownKey(String),
+    #[error("invalid config: {0}")]
+    Invalid(String),
+    #[error("{path} can't be used, so it won't be overwritten until it's fixed: {message}")]
+    UserFileUnusable { path: PathBuf, message: String },
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl ConfigError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ConfigError::UnknownKey(_) => "unknownConfigKey",
+            ConfigError::Invalid(_) => "invalidConfig",
+            ConfigError::UserFileUnusable { .. } => "userConfigUnusable",
+            ConfigError::Io { .. } => "io",
+        }
+    }
+}
+
+impl Serialize for ConfigError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// Which overrides a change goes into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Scope {
+    /// Saved in the app config dir, for every project.
+    #[default]
+    User,
+    /// Saved with the open project.
+    Project,
+}
+
+/// What the watcher saw in the user file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigEvent {
+    /// The file changed and the config now reads as this.
+    Changed(Box<Config>),
+    /// The file changed but can't be used; the config is unchanged.
+    Invalid(String),
+}
+
+/// Copies every key of `overlay` onto `base`, merging objects key by key.
+fn merge(base: &mut Value, overlay: &Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(key) {
+                    Some(existing) if existing.is_object() && value.is_object() => {
+                        merge(existing, value)
+                    }
+                    _ => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+/// Keys of `path`, a dotted setting name like `audio.output.volume`, if
+/// the defaults have such a setting.
+fn keys<'a>(defaults: &Value, path: &'a str) -> Result<Vec<&'a str>, ConfigError> {
+    let keys: Vec<&str> = path.split('.').collect();
+    let mut node = defaults;
+    for key in &keys {
+        node = node
+            .get(key)
+            .ok_or_else(|| ConfigError::UnknownKey(path.into()))?;
+    }
+    Ok(keys)
+}
+
+fn set(overrides: &mut Value, keys: &[&str], value: Value) {
+    let (last, parents) = keys.split_last().expect("paths have a key");
+    let mut node = overrides;
+    for key in parents {
+        if !node.get(key).is_some_and(Value::is_object) {
+            node[*key] = Value::Object(Map::new());
+        }
+        node = &mut node[*key];
+    }
+    node[*last] = value;
+}
+
+/// Removes the override at `keys`, and any objects it leaves empty.
+fn unset(overrides: &mut Value, keys: &[&str]) {
+    let Some(object) = overrides.as_object_mut() else {
+        return;
+    };
+    match keys {
+        [] => object.clear(),
+        [key] => {
+            object.remove(*key);
+        }
+        [key, rest @ ..] => {
+            if let Some(child) = object.get_mut(*key) {
+                unset(child, rest);
+                if child.as_object().is_some_and(Map::is_empty) {
+                    object.remove(*key);
+                }
+            }
+        }
+    }
+}
+
+struct State {
+    user: Value,
+    project: Value,
+    config: Config,
+    /// The user file as last read or written, to tell outside edits apart.
+    user_file: Option<Vec<u8>>,
+    /// Why the user file as last read can't be used. User changes are
+    /// refused meanwhile, rather than overwriting what the user is fixing.
+    user_unusable: Option<String>,
+}
+
+struct Shared {
+    defaults: Value,
+    user_path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The config with `user` and `project` over the defaults.
+    fn resolve(&self, user: &Value, project: &Value) -> Result<Config, ConfigError> {
+        let mut effective = self.defaults.clone();
+        merge(&mut effective, user);
+        merge(&mut effective, project);
+        let config: Config =
+            serde_json::from_value(effective).map_err(|e| ConfigError::Invalid(e.to_string()))?;
+        config.validate().map_err(ConfigError::Invalid)?;
+        Ok(config)
+    }
+
+    fn poll(&self) -> Option<ConfigEvent> {
+        self.refresh(&mut self.state())
+    }
+
+    fn unusable(&self, state: &State) -> Option<ConfigError> {
+        let message = state.user_unusable.clone()?;
+        Some(ConfigError::UserFileUnusable {
+            path: self.user_path.clone(),
+            message,
+        })
+    }
+
+    /// Rereads the user file if it changed since it was last read or
+    /// written. The caller holds the lock, so the read can't race a write.
+    fn refresh(&self, state: &mut State) -> Option<ConfigEvent> {
+        let bytes = read(&self.user_path).ok()?;
+        if bytes == state.user_file {
+            return None;
+        }
+        state.user_file = bytes.clone();
+        let resolved = bytes
+            .map(|bytes| parse(&bytes))
+            .transpose()
+            .map(|user| user.unwrap_or_else(|| Value::Object(Map::new())))
+            .and_then(|user| {
+                let config = self
+                    .resolve(&user, &state.project)
+                    .map_err(|e| e.to_string())?;
+                Ok((user, config))
+            });
+        match resolved {
+            Ok((user, config)) => {
+                state.user = user;
+                state.config = config.clone();
+                state.user_unusable = None;
+                Some(ConfigEvent::Changed(Box::new(config)))
+            }
+            Err(message) => {
+                state.user_unusable = Some(message.clone());
+                Some(ConfigEvent::Invalid(message))
+            }
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Option<Vec<u8>>, ConfigError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+fn parse(bytes: &[u8]) -> Result<Value, String> {
+    match serde_json::from_slice(bytes) {
+        Ok(Value::Object(overrides)) => Ok(Value::Object(overrides)),
+        Ok(_) => Err("the user config must be a JSON object".into()),
+        Err(e) => Err(format!("the user config isn't valid JSON: {e}")),
+    }
+}
+
+fn write(path: &Path, bytes: &[u8]) -> Result<(), ConfigError> {
+    write_atomic(path, bytes).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+pub struct ConfigManager {
+    shared: Arc<Shared>,
+}
+
+impl ConfigManager {
+    /// Loads the user overrides from `user_path`. A missing file means
+    /// there are none; one that can't be used is left for the user to fix
+    /// and ignored until they do.
+    pub fn open(user_path: impl Into<PathBuf>) -> Result<Self, ConfigError> {
+        let defaults: Value = serde_json::from_str(DEFAULTS).expect("bundled defaults parse");
+        let shared = Arc::new(Shared {
+            defaults,
+            user_path: user_path.into(),
+            state: Mutex::new(State {
+                user: Value::Object(Map::new()),
+                project: Value::Object(Map::new()),
+                config: Config::default(),
+                user_file: None,
+                user_unusable: None,
+            }),
+        });
+        let empty = Value::Object(Map::new());
+        shared.state().config = shared.resolve(&empty, &empty)?;
+        shared.poll();
+        Ok(Self { shared })
+    }
+
+    /// Why the user file is being ignored, if it is: it was there but
+    /// couldn't be parsed or validated when it was last read.
+    pub fn user_file_error(&self) -> Option<ConfigError> {
+        self.shared.unusable(&self.shared.state())
+    }
+
+    pub fn get(&self) -> Config {
+        self.shared.state().config.clone()
+    }
+
+    /// The overrides in `scope`, as sparse JSON.
+    pub fn overrides(&self, scope: Scope) -> Value {
+        let state = self.shared.state();
+        match scope {
+            Scope::User => state.user.clone(),
+            Scope::Project => state.project.clone(),
+        }
+    }
+
+    /// Sets the setting at `path` in `scope`, all or nothing. User
+    /// overrides are written back to disk, and refused while the user file
+    /// on disk can't be used.
+    pub fn set(&self, scope: Scope, path: &str, value: Value) -> Result<Config, ConfigError> {
+        let keys = keys(&self.shared.defaults, path)?;
+        self.change(scope, |overrides| set(overrides, &keys, value))
+    }
+
+    /// Drops the override at `path` in `scope`, or all of them.
+    pub fn reset(&self, scope: Scope, path: Option<&str>) -> Result<Config, ConfigError> {
+        let keys = match path {
+            Some(path) => keys(&self.shared.defaults, path)?,
+            None => Vec::new(),
+        };
+        self.change(scope, |overrides| unset(overrides, &keys))
+    }
+
+    /// Replaces the project overrides, e.g. with those of an opened
+    /// project. Overrides that would make the config invalid are refused.
+    pub fn set_project(&self, overrides: Map<String, Value>) -> Result<Config, ConfigError> {
+        self.change(Scope::Project, |project| {
+            *project = Value::Object(overrides)
+        })
+    }
+
+    fn change(&self, scope: Scope, edit: impl FnOnce(&mut Value)) -> Result<Config, ConfigError> {
+        let shared = &self.shared;
+        let mut state = shared.state();
+        if scope == Scope::User {
+            // Pick up outside edits first, so they aren't written over.
+            shared.refresh(&mut state);
+            if let Some(error) = shared.unusable(&state) {
+                return Err(error);
+            }
+        }
+        let (mut user, mut project) = (state.user.clone(), state.project.clone());
+        edit(match scope {
+            Scope::User => &mut user,
+            Scope::Project => &mut project,
+        });
+        let config = shared.resolve(&user, &project)?;
+        if scope == Scope::User {
+            let bytes = serde_json::to_vec_pretty(&user).expect("JSON values serialize");
+            write(&shared.user_path, &bytes)?;
+            state.user_file = Some(bytes);
+        }
+        state.user = user;
+        state.project = project;
+        state.config = config.clone();
+        Ok(config)
+    }
+
+    /// Checks the user file for outside edits every `interval` until the
+    /// manager is dropped, passing what it finds to `emit`.
+    pub fn watch(
+        &self,
+        interval: Duration,
+        emit: impl Fn(ConfigEvent) + Send + 'static,
+    ) -> io::Result<()> {
+        let shared: Weak<Shared> = Arc::downgrade(&self.shared);
+        thread::Builder::new()
+            .name("config-watch".into())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                let Some(shared) = shared.upgrade() else {
+                    return;
+                };
+                if let Some(event) = shared.poll() {
+                    emit(event);
+                }
+            })?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn poll(&self) -> Option<ConfigEvent> {
+        self.shared.poll()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn the_schema_mirrors_the_bundled_defaults() {
+        let defaults: Value = serde_json::from_str(DEFAULTS).unwrap();
+        let parsed: Config = serde_json::from_value(defaults.clone()).unwrap();
+        assert_eq!(parsed, Config::default());
+        // Every key and no more; numbers may come back as floats.
+        fn keys_of(value: &Value) -> Value {
+            match value {
+                Value::Object(object) => object
+                    .iter()
+                    .map(|(key, value)| (key.clone(), keys_of(value)))
+                    .collect(),
+                _ => Value::Null,
+            }
+        }
+        let serialized = serde_json::to_value(&parsed).unwrap();
+        assert_eq!(keys_of(&serialized), keys_of(&defaults));
+    }
+
+    #[test]
+    fn layers_validates_writes_back_and_notices_outside_edits() {
+        let dir = std::env::temp_dir().join(format!("config-layers-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("config.json");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, r#"{ "audio": { "output": { "volume": 0.5 } } }"#).unwrap();
+
+        let config = ConfigManager::open(&path).unwrap();
+        assert_eq!(config.get().audio.output.volume, 0.5);
+        assert_eq!(config.get().audio.output.sample_rate, 44100);
+
+        // Project overrides win over the user's, and go when reset.
+        config
+            .set(Scope::Project, "audio.output.volume", json!(0.25))
+            .unwrap();
+        assert_eq!(config.get().audio.output.volume, 0.25);
+        config.reset(Scope::Project, None).unwrap();
+        assert_eq!(config.get().audio.output.volume, 0.5);
+
+        assert!(matches!(
+            config.set(Scope::User, "audio.output.volume", json!(3.0)),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            config.set(Scope::User, "ui.theme", json!("plaid")),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            config.set(Scope::User, "audio.output.volum", json!(1.0)),
+            Err(ConfigError::UnknownKey(_))
+        ));
+        assert_eq!(config.get().audio.output.volume, 0.5);
+
+        config.set(Scope::User, "ui.theme", json!("light")).unwrap();
+        config
+            .reset(Scope::User, Some("audio.output.volume"))
+            .unwrap();
+        let saved: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved, json!({ "ui": { "theme": "light" } }));
+        // The app's own writes aren't outside edits.
+        assert_eq!(config.poll(), None);
+
+        fs::write(&path, r#"{ "storage": { "autoSave": false } }"#).unwrap();
+        match config.poll() {
+            Some(ConfigEvent::Changed(changed)) => {
+                assert!(!changed.storage.auto_save);
+                assert_eq!(changed.ui.theme, schema::Theme::Dark);
+            }
+            event => panic!("{event:?}"),
+        }
+        fs::write(&path, "{ not json").unwrap();
+        assert!(matches!(config.poll(), Some(ConfigEvent::Invalid(_))));
+        assert!(!config.get().storage.auto_save);
+
+        // The broken file is left alone until it's fixed; the project
+        // scope isn't affected.
+        assert!(matches!(
+            config.set(Scope::User, "ui.theme", json!("light")),
+            Err(ConfigError::UserFileUnusable { .. })
+        ));
+        assert_eq!(fs::read(&path).unwrap(), b"{ not json");
+        assert!(config.user_file_error().is_some());
+        config
+            .set(Scope::Project, "ui.theme", json!("light"))
+            .unwrap();
+        // Nor is a file that parses but doesn't validate, even one the
+        // watcher hasn't seen yet.
+        fs::write(&path, r#"{ "audio": { "output": { "volume": 3 } } }"#).unwrap();
+        assert!(matches!(
+            config.reset(Scope::User, None),
+            Err(ConfigError::UserFileUnusable { .. })
+        ));
+        fs::write(&path, "{}").unwrap();
+        config.set(Scope::User, "ui.theme", json!("light")).unwrap();
+        let saved: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved, json!({ "ui": { "theme": "light" } }));
+        assert!(config.user_file_error().is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,457 @@
+//! The shape of `config/default.json`.
+//!
+//! Sections that a subsystem already reads are that subsystem's own config
+//! type, flattened in where the JSON keeps several together, so a config
+//! value can be handed straight to the code it configures.
+
+use serde::{Deserialize, Serialize};
+
+use crate::asr::RecognitionConfig;
+use crate::audio::capture::CaptureConfig;
+use crate::dsp::ProcessingConfig;
+use crate::vad::VadConfig;
+use crate::wake::WakeConfig;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    pub app: AppInfo,
+    pub voice: VoiceConfig,
+    pub audio: AudioConfig,
+    pub ui: UiConfig,
+    pub keyboard: KeyboardConfig,
+    pub storage: StorageConfig,
+    pub performance: PerformanceConfig,
+    pub logging: LoggingConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppInfo {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub author: String,
+    pub license: String,
+}
+
+impl Default for AppInfo {
+    fn default() -> Self {
+        Self {
+            name: "Neural Voice OS".into(),
+            version: "1.0.0".into(),
+            description: "Local AI Voice Operating System".into(),
+            author: "MiniMax Agent".into(),
+            license: "MIT".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VoiceConfig {
+    pub recognition: VoiceRecognitionConfig,
+    pub activation: ActivationConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VoiceRecognitionConfig {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub decoding: RecognitionConfig,
+    pub profanity_filter: bool,
+    pub audio_input: AudioInputConfig,
+}
+
+impl Default for VoiceRecognitionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            decoding: RecognitionConfig::default(),
+            profanity_filter: false,
+            audio_input: AudioInputConfig::default(),
+        }
+    }
+}
+
+/// The constraints the frontend asks `getUserMedia` for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AudioInputConfig {
+    pub sample_rate: u32,
+    pub channel_count: u16,
+    pub echo_cancellation: bool,
+    pub noise_suppression: bool,
+    pub auto_gain_control: bool,
+}
+
+impl Default for AudioInputConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 16000,
+            channel_count: 1,
+            echo_cancellation: true,
+            noise_suppression: true,
+            auto_gain_control: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ActivationConfig {
+    #[serde(flatten)]
+    pub wake: WakeConfig,
+    #[serde(flatten)]
+    pub vad: VadConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AudioConfig {
+    pub input: CaptureConfig,
+    pub output: OutputConfig,
+    pub processing: ProcessingConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OutputConfig {
+    pub sample_rate: u32,
+    pub channel_count: u16,
+    /// 0 to 1.
+    pub volume: f32,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            channel_count: 2,
+            volume: 0.8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    System,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UiConfig {
+    pub theme: Theme,
+    pub animations: bool,
+    pub compact_mode: bool,
+    pub language: String,
+    pub notifications: NotificationConfig,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            theme: Theme::Dark,
+            animations: true,
+            compact_mode: false,
+            language: "en-US".into(),
+            notifications: NotificationConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+    pub sound: bool,
+    pub toast: bool,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sound: true,
+            toast: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KeyboardConfig {
+    pub shortcuts: ShortcutConfig,
+}
+
+/// Accelerators such as `Ctrl+Space`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ShortcutConfig {
+    pub toggle_recording: String,
+    pub clear_transcript: String,
+    pub copy_transcript: String,
+    pub undo_last: String,
+    pub redo_last: String,
+    pub export_transcript: String,
+}
+
+impl Default for ShortcutConfig {
+    fn default() -> Self {
+        Self {
+            toggle_recording: "Ctrl+Space".into(),
+            clear_transcript: "Ctrl+Backspace".into(),
+            copy_transcript: "Ctrl+C".into(),
+            undo_last: "Ctrl+Z".into(),
+            redo_last: "Ctrl+Y".into(),
+            export_transcript: "Ctrl+E".into(),
+        }
+    }
+}
+
+impl ShortcutConfig {
+    pub fn all(&self) -> [(&'static str, &str); 6] {
+        [
+            ("toggleRecording", &self.toggle_recording),
+            ("clearTranscript", &self.clear_transcript),
+            ("copyTranscript", &self.copy_transcript),
+            ("undoLast", &self.undo_last),
+            ("redoLast", &self.redo_last),
+            ("exportTranscript", &self.export_transcript),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StorageConfig {
+    /// Most transcripts kept.
+    pub transcript_limit: usize,
+    pub auto_save: bool,
+    /// Milliseconds between autosave snapshots.
+    pub auto_save_interval: u64,
+    /// Bytes one transcript may take.
+    pub max_file_size: u64,
+    /// Bytes all stored transcripts may take together.
+    pub max_store_size: u64,
+    pub compression: bool,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            transcript_limit: 1000,
+            auto_save: true,
+            auto_save_interval: 30000,
+            max_file_size: 52_428_800,
+            max_store_size: 524_288_000,
+            compression: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PerformanceConfig {
+    pub max_workers: usize,
+    pub gpu_acceleration: bool,
+    /// Bytes.
+    pub model_cache_size: u64,
+    /// Frames.
+    pub streaming_buffer_size: usize,
+}
+
+impl Default for PerformanceConfig {
+    fn default() -> Self {
+        Self {
+            max_workers: 4,
+            gpu_acceleration: true,
+            model_cache_size: 536_870_912,
+            streaming_buffer_size: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LoggingConfig {
+    pub level: LogLevel,
+    pub console: bool,
+    pub file: bool,
+    /// Bytes per log file.
+    pub max_file_size: u64,
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            console: true,
+            file: false,
+            max_file_size: 10_485_760,
+            max_files: 5,
+        }
+    }
+}
+
+impl Config {
+    /// Checks ranges the types alone don't, naming the first bad setting.
+    pub fn validate(&self) -> Result<(), String> {
+        fn within<T: PartialOrd + std::fmt::Display>(
+            path: &str,
+            value: T,
+            min: T,
+            max: T,
+        ) -> Result<(), String> {
+            if value >= min && value <= max {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{path} must be between {min} and {max}, got {value}"
+                ))
+            }
+        }
+        let recognition = &self.voice.recognition;
+        within(
+            "voice.recognition.maxAlternatives",
+            recognition.decoding.max_alternatives,
+            1,
+            10,
+        )?;
+        within(
+            "voice.recognition.audioInput.sampleRate",
+            recognition.audio_input.sample_rate,
+            8000,
+            192_000,
+        )?;
+        within(
+            "voice.recognition.audioInput.channelCount",
+            recognition.audio_input.channel_count,
+            1,
+            8,
+        )?;
+        let activation = &self.voice.activation;
+        if activation.wake.wake_word.trim().is_empty() {
+            return Err("voice.activation.wakeWord must not be empty".into());
+        }
+        within(
+            "voice.activation.wakeWordSensitivity",
+            activation.wake.wake_word_sensitivity,
+            0.0,
+            1.0,
+        )?;
+        within(
+            "voice.activation.silenceThreshold",
+            activation.vad.silence_threshold,
+            1.0,
+            32768.0,
+        )?;
+        within(
+            "voice.activation.silenceTimeout",
+            activation.vad.silence_timeout,
+            100,
+            60_000,
+        )?;
+        self.audio
+            .input
+            .validate()
+            .map_err(|e| format!("audio.input: {e}"))?;
+        within(
+            "audio.output.sampleRate",
+            self.audio.output.sample_rate,
+            8000,
+            192_000,
+        )?;
+        within(
+            "audio.output.channelCount",
+            self.audio.output.channel_count,
+            1,
+            8,
+        )?;
+        within("audio.output.volume", self.audio.output.volume, 0.0, 1.0)?;
+        within(
+            "audio.processing.noiseReductionLevel",
+            self.audio.processing.noise_reduction_level,
+            0.0,
+            1.0,
+        )?;
+        within(
+            "audio.processing.normalizationTarget",
+            self.audio.processing.normalization_target,
+            -70.0,
+            0.0,
+        )?;
+        for (name, shortcut) in self.keyboard.shortcuts.all() {
+            if shortcut.trim().is_empty() {
+                return Err(format!("keyboard.shortcuts.{name} must not be empty"));
+            }
+        }
+        within(
+            "storage.transcriptLimit",
+            self.storage.transcript_limit,
+            1,
+            1_000_000,
+        )?;
+        within(
+            "storage.autoSaveInterval",
+            self.storage.auto_save_interval,
+            1000,
+            3_600_000,
+        )?;
+        within(
+            "storage.maxFileSize",
+            self.storage.max_file_size,
+            1,
+            u64::MAX,
+        )?;
+        within(
+            "storage.maxStoreSize",
+            self.storage.max_store_size,
+            self.storage.max_file_size,
+            u64::MAX,
+        )?;
+        within(
+            "performance.maxWorkers",
+            self.performance.max_workers,
+            1,
+            256,
+        )?;
+        within(
+            "performance.streamingBufferSize",
+            self.performance.streaming_buffer_size,
+            64,
+            65536,
+        )?;
+        within("logging.maxFiles", self.logging.max_files, 1, 100)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,284 @@
+//! Breath detection and level control.
+//!
+//! Breaths are picked out frame by frame (40 ms frames, 10 ms apart) as
+//! audio that is
+//!
+//! * clearly above the room noise but well below the level of speech,
+//! * aperiodic: no strong autocorrelation peak at a voice pitch period, and
+//! * noise-like: a high spectral flatness,
+//!
+//! and candidate frames are then joined into regions of plausible breath
+//! length. Unvoiced consonants share the last two traits but are usually
+//! louder and much shorter than breaths, which the level and duration limits
+//! account for.
+
+use std::ops::Range;
+
+use realfft::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+use super::mixdown;
+use super::periodicity::PeriodicityAnalyzer;
+use super::stft::Stft;
+
+/// How far below the speech level a breath sits, in dB.
+const BELOW_SPEECH_DB: (f32, f32) = (12.0, 50.0);
+/// Margin a breath needs above the noise floor, in dB.
+const ABOVE_FLOOR_DB: f32 = 6.0;
+/// Anything quieter is silence whatever the floor.
+const SILENCE_DBFS: f32 = -70.0;
+/// Highest normalized autocorrelation at a pitch period still counted as
+/// unvoiced.
+const MAX_VOICING: f32 = 0.45;
+const MIN_FLATNESS: f32 = 0.1;
+/// Flatness is measured over this band, where breath noise lives.
+const FLATNESS_BAND: (f32, f32) = (100.0, 8000.0);
+/// Gaps up to this long between candidate frames are bridged.
+const MAX_GAP_SECONDS: f64 = 0.04;
+const DURATION_RANGE: (f64, f64) = (0.1, 1.2);
+/// Gain ramps at region edges, inside the region.
+const FADE_SECONDS: f64 = 0.01;
+/// Gain at +100%.
+const MAX_BOOST_DB: f32 = 12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreathRegion {
+    /// Seconds from the start of the buffer.
+    pub start: f64,
+    pub end: f64,
+    /// Mean level relative to the speech level, in dB.
+    pub level: f32,
+}
+
+struct FrameFeatures {
+    level: f32,
+    voicing: f32,
+    flatness: f32,
+}
+
+struct Analyzer {
+    periodicity: PeriodicityAnalyzer,
+    window_energy: f32,
+    size: f32,
+    band: Range<usize>,
+}
+
+impl Analyzer {
+    fn new(stft: &Stft, sample_rate: u32) -> Self {
+        let bin_hz = sample_rate as f32 / stft.size() as f32;
+        let to_bin = |hz: f32| ((hz / bin_hz) as usize).min(stft.bins());
+        Self {
+            periodicity: PeriodicityAnalyzer::new(stft, sample_rate),
+            window_energy: stft.window().iter().map(|w| w * w).sum(),
+            size: stft.size() as f32,
+            band: to_bin(FLATNESS_BAND.0).max(1)..to_bin(FLATNESS_BAND.1),
+        }
+    }
+
+    fn features(&mut self, spectrum: &[Complex32]) -> FrameFeatures {
+        let total: f32 = spectrum.iter().map(|bin| bin.norm_sqr()).sum();
+        // Parseval over the one-sided spectrum, undoing the window.
+        let mean_square = 2.0 * total / (self.size * self.window_energy);
+        let level = 10.0 * mean_square.max(1e-12).log10();
+
+        let band = &spectrum[self.band.clone()];
+        let mean = band.iter().map(|bin| bin.norm_sqr()).sum::<f32>() / band.len() as f32;
+        let log_mean = band
+            .iter()
+            .map(|bin| bin.norm_sqr().max(1e-20).ln())
+            .sum::<f32>()
+            / band.len() as f32;
+        let flatness = log_mean.exp() / mean.max(1e-20);
+
+        FrameFeatures {
+            level,
+            voicing: self.periodicity.analyze(spectrum).strength,
+            flatness,
+        }
+    }
+}
+
+fn percentile(values: &[f32], fraction: f32) -> f32 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    sorted[((sorted.len() - 1) as f32 * fraction) as usize]
+}
+
+/// Finds breaths in `channels`, analysed as one mixed-down signal.
+pub fn detect(sample_rate: u32, channels: &[Vec<f32>]) -> Vec<BreathRegion> {
+    let signal = mixdown(channels);
+    let stft = Stft::for_sample_rate(sample_rate);
+    if signal.len() < stft.size() {
+        return Vec::new();
+    }
+    let mut analyzer = Analyzer::new(&stft, sample_rate);
+    let mut features = Vec::with_capacity(stft.frame_count(signal.len()));
+    stft.analyze(&signal, |_, spectrum| {
+        features.push(analyzer.features(spectrum))
+    });
+
+    let levels: Vec<f32> = features.iter().map(|f| f.level).collect();
+    let speech = percentile(&levels, 0.95);
+    let floor = percentile(&levels, 0.05).max(SILENCE_DBFS);
+    let is_breath = |f: &FrameFeatures| {
+        f.level > floor + ABOVE_FLOOR_DB
+            && f.level < speech - BELOW_SPEECH_DB.0
+            && f.level > speech - BELOW_SPEECH_DB.1
+            && f.voicing < MAX_VOICING
+            && f.flatness > MIN_FLATNESS
+    };
+
+    // Frame centres in seconds.
+    let rate = sample_rate as f64;
+    let centre = |index: usize| (stft.frame_start(index) as f64 + stft.size() as f64 / 2.0) / rate;
+    let half_hop = stft.hop() as f64 / rate / 2.0;
+    let duration = signal.len() as f64 / rate;
+
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (index, frame) in features.iter().enumerate() {
+        if !is_breath(frame) {
+            continue;
+        }
+        match runs.last_mut() {
+            Some((_, last))
+                if centre(index) - centre(*last) <= MAX_GAP_SECONDS + 2.0 * half_hop =>
+            {
+                *last = index;
+            }
+            _ => runs.push((index, index)),
+        }
+    }
+
+    runs.into_iter()
+        .map(|(first, last)| {
+            let frames = &features[first..=last];
+            let level = frames.iter().map(|f| f.level).sum::<f32>() / frames.len() as f32;
+            BreathRegion {
+                start: (centre(first) - half_hop).max(0.0),
+                end: (centre(last) + half_hop).min(duration),
+                level: level - speech,
+            }
+        })
+        .filter(|region| {
+            let length = region.end - region.start;
+            (DURATION_RANGE.0..=DURATION_RANGE.1).contains(&length)
+        })
+        .collect()
+}
+
+/// Gain for a breath amount from -100 (silenced) through 0 (untouched) to
+/// +100 (boosted by [`MAX_BOOST_DB`]).
+pub fn amount_to_gain(amount: f32) -> f32 {
+    let amount = amount.clamp(-100.0, 100.0) / 100.0;
+    if amount <= 0.0 {
+        1.0 + amount
+    } else {
+        10f32.powf(amount * MAX_BOOST_DB / 20.0)
+    }
+}
+
+/// Scales the breath `regions` of `channels` by `gain`, ramping in and out
+/// so the edits don't click.
+pub fn apply(sample_rate: u32, channels: &mut [Vec<f32>], regions: &[BreathRegion], gain: f32) {
+    let rate = sample_rate as f64;
+    let frames = channels.first().map_or(0, Vec::len);
+    for region in regions {
+        let start = ((region.start * rate).round() as usize).min(frames);
+        let end = ((region.end * rate).round() as usize).clamp(start, frames);
+        let fade = ((FADE_SECONDS * rate) as usize)
+            .min((end - start) / 2)
+            .max(1);
+        for n in start..end {
+            let edge = (n - start).min(end - 1 - n);
+            let ramp = if edge < fade {
+                // Raised cosine from unity to the target gain.
+                0.5 - 0.5 * (std::f32::consts::PI * edge as f32 / fade as f32).cos()
+            } else {
+                1.0
+            };
+            let g = 1.0 + (gain - 1.0) * ramp;
+            for channel in channels.iter_mut() {
+                channel[n] *= g;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn noise(len: usize, amplitude: f32, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn voiced(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                (1..20)
+                    .map(|h| (std::f32::consts::TAU * 140.0 * h as f32 * t).sin() * 0.3 / h as f32)
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Speech, a pause, a 400 ms breath at 0.9..1.3 s, a pause, speech.
+    fn phrase() -> Vec<f32> {
+        let seconds = |s: f32| (s * RATE as f32) as usize;
+        let mut signal = [
+            voiced(seconds(0.8)),
+            vec![0.0; seconds(0.1)],
+            noise(seconds(0.4), 0.02, 0x2545_f491),
+            vec![0.0; seconds(0.1)],
+            voiced(seconds(0.8)),
+        ]
+        .concat();
+        // A faint room noise.
+        for (s, n) in signal.iter_mut().zip(noise(seconds(2.2), 0.0005, 7)) {
+            *s += n;
+        }
+        signal
+    }
+
+    #[test]
+    fn finds_the_breath_between_phrases() {
+        let regions = detect(RATE, &[phrase()]);
+        assert_eq!(regions.len(), 1, "{regions:?}");
+        let breath = regions[0];
+        assert!((breath.start - 0.9).abs() < 0.05, "{breath:?}");
+        assert!((breath.end - 1.3).abs() < 0.05, "{breath:?}");
+        assert!(breath.level < -BELOW_SPEECH_DB.0);
+    }
+
+    #[test]
+    fn attenuates_only_inside_regions() {
+        let input = phrase();
+        let regions = detect(RATE, std::slice::from_ref(&input));
+        let mut output = vec![input.clone()];
+        apply(RATE, &mut output, &regions, amount_to_gain(-100.0));
+
+        let (start, end) = (
+            (regions[0].start * RATE as f64) as usize,
+            (regions[0].end * RATE as f64) as usize,
+        );
+        assert_eq!(output[0][..start], input[..start]);
+        assert_eq!(output[0][end..], input[end..]);
+        let fade = (FADE_SECONDS * RATE as f64) as usize;
+        assert!(output[0][start + fade..end - fade]
+            .iter()
+            .all(|&s| s == 0.0));
+        assert_eq!(amount_to_gain(0.0), 1.0);
+    }
+}
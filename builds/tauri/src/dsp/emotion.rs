@@ -0,0 +1,149 @@
+//! Emotion rendering: one bundle of voice parameters applied in one go.
+//!
+//! Breath levels are adjusted first, on the untouched take where breaths
+//! are easiest to find. Pitch, intonation and warmth then share a single
+//! pass of the voice shifter, with the formants held in place so the
+//! speaker still sounds like themselves.
+
+use serde::{Deserialize, Serialize};
+
+use super::pitch::PitchQuality;
+use super::voice::{self, VoiceShift, VoiceShifter};
+use super::{breath, mixdown, prosody};
+
+/// Tilt at warmth 0 (brightest) and, negated, at 100 (warmest), in dB per
+/// octave.
+const MAX_WARMTH_TILT: f32 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EmotionParams {
+    /// Pitch shift in semitones.
+    pub pitch: f32,
+    /// Spectral tilt from 0 (bright) through 50 (neutral) to 100 (warm).
+    pub warmth: f32,
+    /// Gain applied to breaths, in dB.
+    pub breath_gain: f32,
+    /// Scale of the intonation around the speaker's usual pitch: 1 leaves
+    /// it, 0 is monotone, 2 doubles every rise and fall.
+    pub prosody: f32,
+}
+
+impl Default for EmotionParams {
+    fn default() -> Self {
+        Self {
+            pitch: 0.0,
+            warmth: 50.0,
+            breath_gain: 0.0,
+            prosody: 1.0,
+        }
+    }
+}
+
+impl EmotionParams {
+    /// Checks every field is in range, naming the first that isn't.
+    pub fn validate(&self) -> Result<(), String> {
+        let fields = [
+            ("pitch", self.pitch, -12.0, 12.0),
+            ("warmth", self.warmth, 0.0, 100.0),
+            ("breathGain", self.breath_gain, -60.0, 12.0),
+            ("prosody", self.prosody, 0.0, 3.0),
+        ];
+        for (name, value, min, max) in fields {
+            if !(min..=max).contains(&value) {
+                return Err(format!(
+                    "{name} must be between {min} and {max}, got {value}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn tilt(&self) -> f32 {
+        (50.0 - self.warmth) / 50.0 * MAX_WARMTH_TILT
+    }
+}
+
+/// Renders `channels` with `params`. Returns `None` when the parameters
+/// leave the audio unchanged.
+pub fn render(
+    sample_rate: u32,
+    channels: &[Vec<f32>],
+    params: &EmotionParams,
+    quality: PitchQuality,
+) -> Option<Vec<Vec<f32>>> {
+    let pitched = params.pitch != 0.0 || params.prosody != 1.0;
+    let shift = VoiceShift {
+        pitch: params.pitch,
+        formant: pitched.then_some(0.0),
+        tilt: params.tilt(),
+    };
+    let voiced = pitched || !shift.is_identity();
+    if !voiced && params.breath_gain == 0.0 {
+        return None;
+    }
+
+    let mut channels = channels.to_vec();
+    if params.breath_gain != 0.0 {
+        let regions = breath::detect(sample_rate, &channels);
+        let gain = 10f32.powf(params.breath_gain / 20.0);
+        breath::apply(sample_rate, &mut channels, &regions, gain);
+    }
+
+    if !voiced {
+        return Some(channels);
+    }
+    let mut shifter = VoiceShifter::new(sample_rate, channels.len(), shift, quality);
+    if params.prosody != 1.0 {
+        let contour = prosody::contour(
+            shifter.stft(),
+            sample_rate,
+            &mixdown(&channels),
+            params.prosody,
+        );
+        shifter = shifter.with_contour(params.pitch, contour);
+    }
+    Some(voice::render(shifter, &channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neutral_is_a_no_op_and_warmth_darkens() {
+        let rate = 16000;
+        let mut state = 0x2545_f491u32;
+        let noise: Vec<f32> = (0..rate as usize)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 - 0.5) * 0.2
+            })
+            .collect();
+        let input = std::slice::from_ref(&noise);
+        assert!(render(
+            rate,
+            input,
+            &EmotionParams::default(),
+            PitchQuality::Preview
+        )
+        .is_none());
+
+        // Mean squared slope rises with high-frequency content.
+        let brightness = |signal: &[f32]| {
+            signal
+                .windows(2)
+                .map(|w| (w[1] - w[0]).powi(2))
+                .sum::<f32>()
+                / signal.iter().map(|s| s * s).sum::<f32>()
+        };
+        let warm = EmotionParams {
+            warmth: 100.0,
+            ..EmotionParams::default()
+        };
+        let output = render(rate, input, &warm, PitchQuality::Preview).unwrap();
+        assert!(brightness(&output[0]) < brightness(&noise) * 0.7);
+    }
+}
@@ -0,0 +1,118 @@
+//! Harmonic exciter.
+//!
+//! The top of the spectrum is split off with a steep high-pass and driven
+//! through an asymmetric soft clipper, which adds both even and odd
+//! harmonics of whatever is up there. Only the newly generated content is
+//! returned: the linear part of the clipper's response is subtracted and the
+//! result high-passed again, removing the DC and low intermodulation products
+//! the asymmetry leaves behind. Mixed back in under the original, it adds
+//! presence and air without boosting what was already there.
+
+use std::f32::consts::PI;
+
+/// Corner of the band that gets excited, in Hz.
+const CUTOFF_HZ: f32 = 3000.0;
+/// Gain into the clipper; higher values give denser harmonics.
+const DRIVE: f32 = 4.0;
+/// Offset into the clipper's curve that makes it asymmetric, so even
+/// harmonics appear alongside odd ones.
+const BIAS: f32 = 0.2;
+
+/// Second-order Butterworth high-pass (RBJ cookbook).
+struct HighPass {
+    b: [f32; 3],
+    a: [f32; 2],
+    state: [f32; 4],
+}
+
+impl HighPass {
+    fn new(sample_rate: u32, cutoff: f32) -> Self {
+        let w0 = 2.0 * PI * cutoff / sample_rate as f32;
+        let alpha = w0.sin() / 2.0f32.sqrt();
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b: [
+                (1.0 + cos) / 2.0 / a0,
+                -(1.0 + cos) / a0,
+                (1.0 + cos) / 2.0 / a0,
+            ],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            state: [0.0; 4],
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let [x1, x2, y1, y2] = self.state;
+        let y = self.b[0] * x + self.b[1] * x1 + self.b[2] * x2 - self.a[0] * y1 - self.a[1] * y2;
+        self.state = [x, x1, y, y1];
+        y
+    }
+}
+
+/// Harmonics generated from the high band of `signal`, to be added to it
+/// (or to whatever it should brighten).
+pub fn harmonics(sample_rate: u32, signal: &[f32]) -> Vec<f32> {
+    // Keep the corner well below Nyquist at low sample rates.
+    let cutoff = CUTOFF_HZ.min(sample_rate as f32 * 0.2);
+    let mut split = [
+        HighPass::new(sample_rate, cutoff),
+        HighPass::new(sample_rate, cutoff),
+    ];
+    let mut cleanup = [
+        HighPass::new(sample_rate, cutoff),
+        HighPass::new(sample_rate, cutoff),
+    ];
+    let rest = (DRIVE * BIAS).tanh();
+    // Small-signal slope of the clipper, so the linear part can be removed.
+    let slope = 1.0 - rest * rest;
+
+    signal
+        .iter()
+        .map(|&x| {
+            let band = split.iter_mut().fold(x, |s, filter| filter.process(s));
+            let clipped = ((DRIVE * (band + BIAS)).tanh() - rest) / DRIVE;
+            let generated = clipped - band * slope;
+            cleanup
+                .iter_mut()
+                .fold(generated, |s, filter| filter.process(s))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Magnitude of `signal` at `frequency` (Goertzel).
+    fn level(rate: u32, signal: &[f32], frequency: f32) -> f32 {
+        let w = 2.0 * PI * frequency / rate as f32;
+        let (mut s1, mut s2) = (0.0f32, 0.0f32);
+        for &x in signal {
+            let s = x + 2.0 * w.cos() * s1 - s2;
+            s2 = s1;
+            s1 = s;
+        }
+        (s1 * s1 + s2 * s2 - 2.0 * w.cos() * s1 * s2).sqrt() / signal.len() as f32
+    }
+
+    #[test]
+    fn adds_harmonics_above_the_band_but_not_the_fundamentals() {
+        let rate = 48000;
+        let input: Vec<f32> = (0..rate as usize)
+            .map(|i| {
+                let t = i as f32 / rate as f32;
+                0.5 * (2.0 * PI * 4000.0 * t).sin() + 0.5 * (2.0 * PI * 200.0 * t).sin()
+            })
+            .collect();
+        let output = harmonics(rate, &input);
+
+        let fundamental = level(rate, &input, 4000.0);
+        // Second and third harmonics of the high tone.
+        assert!(level(rate, &output, 8000.0) > fundamental * 0.01);
+        assert!(level(rate, &output, 12000.0) > fundamental * 0.01);
+        // Neither the tone itself nor the low one comes through.
+        assert!(level(rate, &output, 4000.0) < level(rate, &output, 8000.0));
+        assert!(level(rate, &output, 200.0) < fundamental * 0.001);
+    }
+}
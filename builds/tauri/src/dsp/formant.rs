@@ -0,0 +1,142 @@
+//! Spectral envelope estimation and warping for formant shifting.
+//!
+//! The envelope is the cepstrally smoothed log magnitude spectrum: the
+//! low-quefrency part of the real cepstrum describes the vocal tract
+//! resonances, while the harmonics of the voice sit at the quefrency of the
+//! pitch period and are liftered away. Shifting formants means stretching
+//! that envelope along the frequency axis and re-imposing it on the
+//! spectrum, which leaves the harmonics, and so the pitch, where they are.
+
+use std::sync::Arc;
+
+use realfft::num_complex::Complex32;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+
+use super::stft::Stft;
+
+/// Quefrency cutoff of the lifter. Shorter than the pitch period of voices
+/// up to about 500 Hz, long enough to resolve neighbouring formants.
+const LIFTER_SECONDS: f32 = 0.002;
+/// Refinement passes of the true-envelope estimate.
+const ITERATIONS: usize = 8;
+/// Keeps the log of empty bins finite.
+const LOG_FLOOR: f32 = 1e-9;
+
+/// Cepstral envelope estimator for one STFT frame size.
+pub struct Envelope {
+    lifter: usize,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    log_magnitude: Vec<f32>,
+    spectrum: Vec<Complex32>,
+    cepstrum: Vec<f32>,
+}
+
+impl Envelope {
+    pub fn new(stft: &Stft, sample_rate: u32) -> Self {
+        let mut planner = RealFftPlanner::new();
+        let forward = planner.plan_fft_forward(stft.size());
+        let inverse = planner.plan_fft_inverse(stft.size());
+        let lifter = ((sample_rate as f32 * LIFTER_SECONDS) as usize).clamp(1, stft.size() / 2 - 1);
+        Self {
+            lifter,
+            log_magnitude: vec![0.0; stft.bins()],
+            spectrum: forward.make_output_vec(),
+            cepstrum: inverse.make_output_vec(),
+            forward,
+            inverse,
+        }
+    }
+
+    /// Writes the natural-log envelope of `spectrum` into `envelope`.
+    ///
+    /// Plain cepstral smoothing averages the harmonics with the gaps between
+    /// them and so flattens the resonances. Iterating it on the maximum of
+    /// the spectrum and the previous estimate (the "true envelope" of Röbel
+    /// and Rodet) instead converges on a curve through the harmonic peaks.
+    pub fn estimate(&mut self, spectrum: &[Complex32], envelope: &mut [f32]) {
+        for (slot, bin) in self.log_magnitude.iter_mut().zip(spectrum) {
+            *slot = bin.norm().max(LOG_FLOOR).ln();
+        }
+        envelope.copy_from_slice(&self.log_magnitude);
+        for _ in 0..ITERATIONS {
+            for ((slot, &current), &measured) in self
+                .spectrum
+                .iter_mut()
+                .zip(envelope.iter())
+                .zip(&self.log_magnitude)
+            {
+                *slot = Complex32::new(current.max(measured), 0.0);
+            }
+            self.smooth(envelope);
+        }
+    }
+
+    /// Lifters the log spectrum held in `self.spectrum` into `envelope`.
+    fn smooth(&mut self, envelope: &mut [f32]) {
+        self.inverse
+            .process(&mut self.spectrum, &mut self.cepstrum)
+            .expect("buffers come from the planner");
+
+        // The cepstrum of a real spectrum is even, so keep both ends.
+        let size = self.cepstrum.len();
+        self.cepstrum[self.lifter + 1..size - self.lifter].fill(0.0);
+        self.forward
+            .process(&mut self.cepstrum, &mut self.spectrum)
+            .expect("buffers come from the planner");
+        for (slot, bin) in envelope.iter_mut().zip(&self.spectrum) {
+            *slot = bin.re / size as f32;
+        }
+    }
+}
+
+/// Stretches `envelope` by `factor` along the frequency axis: what was at
+/// bin `k` moves to bin `k * factor`. Bins past the end hold the last value.
+pub fn warp(envelope: &[f32], factor: f32, warped: &mut [f32]) {
+    let last = envelope.len() - 1;
+    for (k, slot) in warped.iter_mut().enumerate() {
+        let position = k as f32 / factor;
+        let index = position as usize;
+        *slot = if index >= last {
+            envelope[last]
+        } else {
+            let frac = position - index as f32;
+            envelope[index] + (envelope[index + 1] - envelope[index]) * frac
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_follows_resonance_and_warps_with_it() {
+        let rate = 16000;
+        let stft = Stft::new(1024);
+        let bin_hz = rate as f32 / stft.size() as f32;
+        // Harmonics of 125 Hz under a resonance at 1 kHz.
+        let spectrum: Vec<Complex32> = (0..stft.bins())
+            .map(|k| {
+                let hz = k as f32 * bin_hz;
+                let harmonic = if k % 8 == 0 { 1.0 } else { 1e-3 };
+                let resonance = 1.0 / (1.0 + ((hz - 1000.0) / 200.0).powi(2));
+                Complex32::new(harmonic * resonance, 0.0)
+            })
+            .collect();
+
+        let mut envelope = vec![0.0; stft.bins()];
+        Envelope::new(&stft, rate).estimate(&spectrum, &mut envelope);
+        let argmax = |e: &[f32]| (0..e.len()).max_by(|&a, &b| e[a].total_cmp(&e[b])).unwrap();
+        let peak = argmax(&envelope) as f32 * bin_hz;
+        assert!((peak - 1000.0).abs() < 150.0, "envelope peak at {peak} Hz");
+
+        let mut warped = vec![0.0; stft.bins()];
+        warp(&envelope, 1.5, &mut warped);
+        let moved = argmax(&warped) as f32 * bin_hz;
+        assert!(
+            (moved - peak * 1.5).abs() < 2.0 * bin_hz,
+            "warped peak at {moved} Hz"
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! ITU-R BS.1770-4 / EBU R128 loudness measurement.
+//!
+//! Channels are K-weighted (high-shelf plus high-pass), squared and summed
+//! with the BS.1770 channel weights. Momentary loudness uses 400 ms blocks,
+//! short-term 3 s blocks, both updated every 100 ms. Integrated loudness
+//! gates the 400 ms blocks at -70 LUFS absolute and -10 LU relative. True
+//! peak is measured on a 4x oversampled signal using the Annex 2
+//! interpolation filter.
+
+use std::f64::consts::PI;
+
+use serde::Serialize;
+
+/// Update interval of the momentary and short-term series, in seconds.
+pub const STEP_SECONDS: f64 = 0.1;
+const MOMENTARY_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// BS.1770-4 Annex 2 polyphase FIR for 4x oversampling, one row per phase.
+const TRUE_PEAK_FILTER: [[f64; 12]; 4] = [
+    [
+        0.001708984375,
+        0.010986328125,
+        -0.0196533203125,
+        0.033203125,
+        -0.0594482421875,
+        0.1373291015625,
+        0.97216796875,
+        -0.102294921875,
+        0.047607421875,
+        -0.026611328125,
+        0.014892578125,
+        -0.00830078125,
+    ],
+    [
+        -0.0291748046875,
+        0.029296875,
+        -0.0517578125,
+        0.089111328125,
+        -0.16650390625,
+        0.465087890625,
+        0.77978515625,
+        -0.2003173828125,
+        0.1015625,
+        -0.0582275390625,
+        0.0330810546875,
+        -0.0189208984375,
+    ],
+    [
+        -0.0189208984375,
+        0.0330810546875,
+        -0.0582275390625,
+        0.1015625,
+        -0.2003173828125,
+        0.77978515625,
+        0.465087890625,
+        -0.16650390625,
+        0.089111328125,
+        -0.0517578125,
+        0.029296875,
+        -0.0291748046875,
+    ],
+    [
+        -0.00830078125,
+        0.014892578125,
+        -0.026611328125,
+        0.047607421875,
+        -0.102294921875,
+        0.97216796875,
+        0.1373291015625,
+        -0.0594482421875,
+        0.033203125,
+        -0.0196533203125,
+        0.010986328125,
+        0.001708984375,
+    ],
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessReport {
+    /// Gated integrated loudness in LUFS; `None` for silence.
+    pub integrated: Option<f64>,
+    pub momentary_max: Option<f64>,
+    pub short_term_max: Option<f64>,
+    /// Maximum true peak across channels in dBTP; `None` for silence.
+    pub true_peak: Option<f64>,
+    /// Momentary loudness every [`STEP_SECONDS`], `None` where silent.
+    pub momentary: Vec<Option<f64>>,
+    /// Short-term loudness every [`STEP_SECONDS`], `None` where silent.
+    pub short_term: Vec<Option<f64>>,
+    pub step: f64,
+}
+
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+}
+
+impl Biquad {
+    fn run(&self, signal: &[f32], output: &mut [f64]) {
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        for (x, y) in signal.iter().zip(output.iter_mut()) {
+            let x = *x as f64;
+            let out =
+                self.b[0] * x + self.b[1] * x1 + self.b[2] * x2 - self.a[1] * y1 - self.a[2] * y2;
+            x2 = x1;
+            x1 = x;
+            y2 = y1;
+            y1 = out;
+            *y = out;
+        }
+    }
+
+    fn run_in_place(&self, signal: &mut [f64]) {
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        for sample in signal.iter_mut() {
+            let x = *sample;
+            let out =
+                self.b[0] * x + self.b[1] * x1 + self.b[2] * x2 - self.a[1] * y1 - self.a[2] * y2;
+            x2 = x1;
+            x1 = x;
+            y2 = y1;
+            y1 = out;
+            *sample = out;
+        }
+    }
+}
+
+/// The two K-weighting stages, re-derived for any sample rate (the
+/// coefficients in the standard are only given for 48 kHz).
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain_db = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    [shelf, high_pass]
+}
+
+/// BS.1770 channel weights assuming the usual L, R, C, LFE, Ls, Rs order
+/// for 5.1; the LFE channel is excluded.
+fn channel_weight(index: usize, count: usize) -> f64 {
+    match (count, index) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+fn to_lufs(power: f64) -> Option<f64> {
+    (power > 0.0).then(|| -0.691 + 10.0 * power.log10())
+}
+
+/// Mean weighted power of every 100 ms step, summed over channels. Below
+/// 5 Hz a step is a single sample.
+fn step_powers(sample_rate: u32, channels: &[Vec<f32>]) -> Vec<f64> {
+    let step = ((sample_rate as f64 * STEP_SECONDS).round() as usize).max(1);
+    let frames = channels.first().map_or(0, Vec::len);
+    let mut powers = vec![0.0f64; frames / step];
+    let [shelf, high_pass] = k_weighting(sample_rate);
+    let mut filtered = vec![0.0f64; frames];
+
+    for (index, channel) in channels.iter().enumerate() {
+        let weight = channel_weight(index, channels.len());
+        if weight == 0.0 {
+            continue;
+        }
+        shelf.run(channel, &mut filtered);
+        high_pass.run_in_place(&mut filtered);
+        for (power, chunk) in powers.iter_mut().zip(filtered.chunks_exact(step)) {
+            *power += weight * chunk.iter().map(|s| s * s).sum::<f64>() / step as f64;
+        }
+    }
+    powers
+}
+
+/// Sliding means of `width` consecutive steps.
+fn block_powers(steps: &[f64], width: usize) -> Vec<f64> {
+    steps
+        .windows(width)
+        .map(|window| window.iter().sum::<f64>() / width as f64)
+        .collect()
+}
+
+fn integrated(momentary_blocks: &[f64]) -> Option<f64> {
+    let above_absolute: Vec<f64> = momentary_blocks
+        .iter()
+        .copied()
+        .filter(|&p| to_lufs(p).is_some_and(|l| l > ABSOLUTE_GATE))
+        .collect();
+    if above_absolute.is_empty() {
+        return None;
+    }
+    let relative_gate =
+        to_lufs(above_absolute.iter().sum::<f64>() / above_absolute.len() as f64)? + RELATIVE_GATE;
+    let gated: Vec<f64> = above_absolute
+        .into_iter()
+        .filter(|&p| to_lufs(p).is_some_and(|l| l > relative_gate))
+        .collect();
+    if gated.is_empty() {
+        return None;
+    }
+    to_lufs(gated.iter().sum::<f64>() / gated.len() as f64)
+}
+
+/// Per-sample true-peak envelope: each sample's magnitude or that of the
+/// 4x interpolated points next to it, whichever is larger.
+pub fn true_peak_envelope(channel: &[f32]) -> Vec<f32> {
+    let taps = TRUE_PEAK_FILTER[0].len();
+    // The filter's main lobe sits half its length behind the newest input.
+    let delay = taps / 2;
+    let mut envelope: Vec<f32> = channel.iter().map(|s| s.abs()).collect();
+    let mut history = [0.0f32; 12];
+    // Run the filter past the end so the last samples' interpolants are seen.
+    let tail = std::iter::repeat_n(&0.0f32, delay);
+    for (n, sample) in channel.iter().chain(tail).enumerate() {
+        history.copy_within(0..taps - 1, 1);
+        history[0] = *sample;
+        let Some(at) = n.checked_sub(delay) else {
+            continue;
+        };
+        let peak = TRUE_PEAK_FILTER
+            .iter()
+            .map(|phase| {
+                phase
+                    .iter()
+                    .zip(&history)
+                    .map(|(&c, x)| c as f32 * x)
+                    .sum::<f32>()
+                    .abs()
+            })
+            .fold(0.0f32, f32::max);
+        for slot in &mut envelope[at.saturating_sub(1)..=at] {
+            *slot = slot.max(peak);
+        }
+    }
+    envelope
+}
+
+/// Largest absolute value of the 4x oversampled channel.
+pub fn true_peak(channel: &[f32]) -> f32 {
+    true_peak_envelope(channel).into_iter().fold(0.0, f32::max)
+}
+
+pub fn gain_to_db(gain: f64) -> Option<f64> {
+    (gain > 0.0).then(|| 20.0 * gain.log10())
+}
+
+pub fn measure(sample_rate: u32, channels: &[Vec<f32>]) -> LoudnessReport {
+    let steps = step_powers(sample_rate, channels);
+    let momentary = block_powers(&steps, MOMENTARY_STEPS);
+    let short_term = block_powers(&steps, SHORT_TERM_STEPS);
+    let max_power = |blocks: &[f64]| blocks.iter().copied().fold(0.0f64, f64::max);
+    let peak = channels.iter().map(|c| true_peak(c)).fold(0.0f32, f32::max);
+
+    LoudnessReport {
+        integrated: integrated(&momentary),
+        momentary_max: to_lufs(max_power(&momentary)),
+        short_term_max: to_lufs(max_power(&short_term)),
+        true_peak: gain_to_db(peak as f64),
+        momentary: momentary.iter().map(|&p| to_lufs(p)).collect(),
+        short_term: short_term.iter().map(|&p| to_lufs(p)).collect(),
+        step: STEP_SECONDS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(rate: u32, frequency: f64, amplitude_db: f64, seconds: f64, phase: f64) -> Vec<f32> {
+        let amplitude = 10f64.powf(amplitude_db / 20.0);
+        (0..(rate as f64 * seconds) as usize)
+            .map(|i| {
+                (amplitude * (2.0 * PI * frequency * i as f64 / rate as f64 + phase).sin()) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ebu_reference_tone_reads_minus_23() {
+        // EBU Tech 3341 case 1: stereo 1 kHz at -23 dBFS reads -23.0 LUFS.
+        for rate in [44100, 48000] {
+            let tone = sine(rate, 1000.0, -23.0, 20.0, 0.0);
+            let report = measure(rate, &[tone.clone(), tone]);
+            let integrated = report.integrated.unwrap();
+            assert!((integrated + 23.0).abs() < 0.1, "{rate}: {integrated}");
+            assert!((report.short_term_max.unwrap() + 23.0).abs() < 0.1);
+            assert!((report.momentary_max.unwrap() + 23.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn relative_gate_ignores_quiet_passages() {
+        // EBU Tech 3341 case 3 style: -36 dBFS then -23 dBFS then -36 dBFS.
+        let rate = 48000;
+        let quiet = sine(rate, 1000.0, -36.0, 10.0, 0.0);
+        let loud = sine(rate, 1000.0, -23.0, 60.0, 0.0);
+        let tone: Vec<f32> = [quiet.clone(), loud, quiet].concat();
+        let integrated = measure(rate, &[tone.clone(), tone]).integrated.unwrap();
+        assert!((integrated + 23.0).abs() < 0.1, "{integrated}");
+    }
+
+    #[test]
+    fn true_peak_sees_inter_sample_peaks() {
+        // A quarter-rate sine sampled 45 degrees off its crest peaks between
+        // samples, 3 dB above the sample peak.
+        let tone = sine(48000, 12000.0, 0.0, 1.0, PI / 4.0);
+        let sample_peak = tone.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+        assert!((gain_to_db(sample_peak as f64).unwrap() + 3.01).abs() < 0.05);
+        let true_peak_db = gain_to_db(true_peak(&tone) as f64).unwrap();
+        assert!(true_peak_db > -0.6, "{true_peak_db}");
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let report = measure(48000, &[vec![0.0; 48000]]);
+        assert_eq!(report.integrated, None);
+        assert_eq!(report.true_peak, None);
+    }
+
+    #[test]
+    fn very_low_sample_rates_do_not_panic() {
+        for rate in [0, 1, 4] {
+            let report = measure(rate, &[vec![0.5; 40]]);
+            assert_eq!(report.momentary.len(), 40 - MOMENTARY_STEPS + 1, "{rate}");
+        }
+    }
+}
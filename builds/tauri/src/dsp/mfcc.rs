@@ -0,0 +1,210 @@
+//! Mel-frequency cepstral coefficients.
+//!
+//! 25 ms Hamming windows every 10 ms, a 26-band mel filterbank from 20 Hz
+//! to 7.6 kHz (or Nyquist) and a DCT of the log band energies. The first
+//! coefficient tracks loudness; the rest describe the spectral envelope,
+//! which is what tells one speech sound from another.
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use realfft::num_complex::Complex32;
+use realfft::{RealFftPlanner, RealToComplex};
+
+pub const COEFFICIENTS: usize = 13;
+const FILTERS: usize = 26;
+const WINDOW_SECONDS: f32 = 0.025;
+const HOP_SECONDS: f32 = 0.01;
+const BAND: (f32, f32) = (20.0, 7600.0);
+
+pub type Features = [f32; COEFFICIENTS];
+
+/// Mean band energy in dB, recovered from the first coefficient.
+pub fn level_db(features: &Features) -> f32 {
+    let mean_ln = features[0] / (2.0 * FILTERS as f32).sqrt();
+    10.0 * mean_ln / std::f32::consts::LN_10
+}
+
+fn to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn from_mel(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+pub struct Mfcc {
+    window: Vec<f32>,
+    hop: usize,
+    fft: Arc<dyn RealToComplex<f32>>,
+    /// First bin and weights of each triangular filter.
+    filters: Vec<(usize, Vec<f32>)>,
+    frame: Vec<f32>,
+    spectrum: Vec<Complex32>,
+}
+
+impl Mfcc {
+    pub fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate as f32;
+        let len = (WINDOW_SECONDS * rate) as usize;
+        let size = len.next_power_of_two();
+        let window = (0..len)
+            .map(|n| 0.54 - 0.46 * (2.0 * PI * n as f32 / (len - 1) as f32).cos())
+            .collect();
+        let fft = RealFftPlanner::new().plan_fft_forward(size);
+
+        let bin_hz = rate / size as f32;
+        let (low, high) = (to_mel(BAND.0), to_mel(BAND.1.min(rate / 2.0)));
+        let edges: Vec<f32> = (0..FILTERS + 2)
+            .map(|i| from_mel(low + (high - low) * i as f32 / (FILTERS + 1) as f32) / bin_hz)
+            .collect();
+        let filters = edges
+            .windows(3)
+            .map(|edge| {
+                let first = edge[0].ceil() as usize;
+                let weights = (first..=edge[2].floor() as usize)
+                    .map(|bin| {
+                        let bin = bin as f32;
+                        if bin <= edge[1] {
+                            (bin - edge[0]) / (edge[1] - edge[0])
+                        } else {
+                            (edge[2] - bin) / (edge[2] - edge[1])
+                        }
+                    })
+                    .collect();
+                (first, weights)
+            })
+            .collect();
+
+        Self {
+            window,
+            hop: (HOP_SECONDS * rate) as usize,
+            frame: fft.make_input_vec(),
+            spectrum: fft.make_output_vec(),
+            fft,
+            filters,
+        }
+    }
+
+    /// Samples per analysis window.
+    pub fn window_len(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    /// Coefficients of one window's worth of `samples`.
+    pub fn frame(&mut self, samples: &[f32]) -> Features {
+        self.frame.fill(0.0);
+        for ((slot, sample), w) in self.frame.iter_mut().zip(samples).zip(&self.window) {
+            *slot = sample * w;
+        }
+        self.fft
+            .process(&mut self.frame, &mut self.spectrum)
+            .expect("buffers come from the planner");
+
+        let mut energies = [0.0f32; FILTERS];
+        for (energy, (first, weights)) in energies.iter_mut().zip(&self.filters) {
+            let band = self.spectrum.iter().skip(*first);
+            let sum: f32 = band.zip(weights).map(|(bin, w)| bin.norm_sqr() * w).sum();
+            *energy = sum.max(1e-10).ln();
+        }
+        let mut features = [0.0; COEFFICIENTS];
+        for (k, coefficient) in features.iter_mut().enumerate() {
+            *coefficient = energies
+                .iter()
+                .enumerate()
+                .map(|(n, e)| e * (PI * k as f32 * (n as f32 + 0.5) / FILTERS as f32).cos())
+                .sum::<f32>()
+                * (2.0 / FILTERS as f32).sqrt();
+        }
+        features
+    }
+
+    /// Coefficients of every full window in `signal`.
+    pub fn compute(&mut self, signal: &[f32]) -> Vec<Features> {
+        let (len, hop) = (self.window_len(), self.hop);
+        (0..)
+            .map(|frame| frame * hop)
+            .take_while(|start| start + len <= signal.len())
+            .map(|start| self.frame(&signal[start..start + len]))
+            .collect()
+    }
+}
+
+/// Frames incoming audio as it arrives.
+pub struct MfccStream {
+    mfcc: Mfcc,
+    /// Unconsumed input, starting at the next window.
+    pending: Vec<f32>,
+}
+
+impl MfccStream {
+    pub fn new(mfcc: Mfcc) -> Self {
+        Self {
+            mfcc,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Feeds `samples` and returns the frames that are now complete.
+    pub fn push(&mut self, samples: &[f32]) -> Vec<Features> {
+        self.pending.extend_from_slice(samples);
+        let frames = self.mfcc.compute(&self.pending);
+        self.pending.drain(..frames.len() * self.mfcc.hop());
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_sounds_but_not_levels() {
+        let rate = 16000;
+        let tone = |hz: f32, gain: f32| -> Vec<f32> {
+            (0..rate)
+                .map(|i| (2.0 * PI * hz * i as f32 / rate as f32).sin() * gain)
+                .collect()
+        };
+        let mut mfcc = Mfcc::new(rate);
+        let mean = |frames: Vec<Features>| {
+            let mut mean = [0.0; COEFFICIENTS];
+            for frame in &frames {
+                for (m, c) in mean.iter_mut().zip(frame) {
+                    *m += c / frames.len() as f32;
+                }
+            }
+            mean
+        };
+        let frames = mfcc.compute(&tone(400.0, 0.5));
+        assert_eq!(frames.len(), 98);
+        let (low, quiet, high) = (
+            mean(frames),
+            mean(mfcc.compute(&tone(400.0, 0.05))),
+            mean(mfcc.compute(&tone(2500.0, 0.5))),
+        );
+        let shape = |a: &Features, b: &Features| {
+            a[1..]
+                .iter()
+                .zip(&b[1..])
+                .map(|(x, y)| (x - y).powi(2))
+                .sum::<f32>()
+                .sqrt()
+        };
+        // 20 dB quieter moves only the loudness coefficient.
+        assert!((level_db(&low) - level_db(&quiet) - 20.0).abs() < 0.5);
+        assert!(shape(&low, &quiet) < 0.5);
+        assert!(shape(&low, &high) > 5.0);
+
+        // Streaming in odd-sized pieces gives the same frames.
+        let signal = tone(1000.0, 0.3);
+        let whole = mfcc.compute(&signal);
+        let mut stream = MfccStream::new(mfcc);
+        let pieces: Vec<Features> = signal.chunks(1234).flat_map(|c| stream.push(c)).collect();
+        assert_eq!(pieces, whole);
+    }
+}
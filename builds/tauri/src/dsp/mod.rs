@@ -0,0 +1,58 @@
+//! Offline signal processing on project buffers.
+
+pub mod breath;
+pub mod denoise;
+pub mod emotion;
+pub mod exciter;
+pub mod formant;
+pub mod loudness;
+pub mod mfcc;
+pub mod normalize;
+pub mod periodicity;
+pub mod pitch;
+pub mod prosody;
+pub mod stft;
+pub mod stretch;
+pub mod voice;
+
+use serde::{Deserialize, Serialize};
+
+/// Mirrors the `audio.processing` section of `config/default.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProcessingConfig {
+    pub noise_reduction: bool,
+    /// 0.0 (gentle) to 1.0 (aggressive).
+    pub noise_reduction_level: f32,
+    pub echo_cancellation: bool,
+    pub auto_gain: bool,
+    pub normalization: bool,
+    /// Integrated loudness target in LUFS.
+    pub normalization_target: f64,
+}
+
+impl Default for ProcessingConfig {
+    fn default() -> Self {
+        Self {
+            noise_reduction: true,
+            noise_reduction_level: 0.7,
+            echo_cancellation: true,
+            auto_gain: true,
+            normalization: true,
+            normalization_target: -16.0,
+        }
+    }
+}
+
+/// Averages planar channels into one, for analysis that should see the
+/// whole signal at once.
+pub fn mixdown(channels: &[Vec<f32>]) -> Vec<f32> {
+    let mut mono = vec![0.0f32; channels.first().map_or(0, Vec::len)];
+    let scale = 1.0 / channels.len().max(1) as f32;
+    for channel in channels {
+        for (sum, sample) in mono.iter_mut().zip(channel) {
+            *sum += sample * scale;
+        }
+    }
+    mono
+}
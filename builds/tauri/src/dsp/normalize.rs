@@ -0,0 +1,205 @@
+//! Loudness normalization to an integrated LUFS target under a true-peak
+//! ceiling.
+//!
+//! The gain needed to reach the target is applied first. If that pushes the
+//! true peak over the ceiling, a lookahead limiter (linked across channels)
+//! catches the overs, and the gain is re-trimmed, since limiting shaves a
+//! little loudness off.
+
+use std::collections::VecDeque;
+
+use super::loudness;
+
+/// Limiter lookahead; also the attack time.
+const LOOKAHEAD_SECONDS: f64 = 0.005;
+const RELEASE_SECONDS: f64 = 0.1;
+/// How close to the target the re-trim loop has to land, in LU.
+const TOLERANCE: f64 = 0.1;
+const MAX_PASSES: usize = 4;
+
+pub struct Normalized {
+    pub channels: Vec<Vec<f32>>,
+    /// Gain applied before limiting, in dB.
+    pub gain_db: f64,
+    /// Whether the limiter had to act.
+    pub limited: bool,
+}
+
+fn db_to_gain(db: f64) -> f32 {
+    10f64.powf(db / 20.0) as f32
+}
+
+/// Gain that keeps every sample's true-peak envelope at or below `ceiling`,
+/// ramping down over the lookahead and back up over the release.
+fn limiter_gain(envelope: &[f32], ceiling: f32, sample_rate: u32) -> Vec<f32> {
+    let lookahead = ((sample_rate as f64 * LOOKAHEAD_SECONDS) as usize).max(1);
+    let required: Vec<f32> = envelope
+        .iter()
+        .map(|&peak| if peak > ceiling { ceiling / peak } else { 1.0 })
+        .collect();
+
+    // Minimum of the required gain over the next `lookahead` samples.
+    let mut ahead_min = vec![1.0f32; required.len()];
+    let mut window: VecDeque<usize> = VecDeque::new();
+    for n in (0..required.len()).rev() {
+        while window.back().is_some_and(|&k| required[k] >= required[n]) {
+            window.pop_back();
+        }
+        window.push_back(n);
+        while window.front().is_some_and(|&k| k >= n + lookahead) {
+            window.pop_front();
+        }
+        ahead_min[n] = required[window[0]];
+    }
+
+    // Averaging the last `lookahead` minima gives a linear attack ramp, and
+    // every averaged value already covers sample n, so the ceiling holds.
+    let release = 1.0 - (-1.0 / (RELEASE_SECONDS * sample_rate as f64)).exp() as f32;
+    let mut gain = Vec::with_capacity(required.len());
+    let mut sum = 0.0f32;
+    let mut previous = 1.0f32;
+    for n in 0..ahead_min.len() {
+        sum += ahead_min[n];
+        if n >= lookahead {
+            sum -= ahead_min[n - lookahead];
+        }
+        let attack = sum / (n + 1).min(lookahead) as f32;
+        previous = if attack < previous {
+            attack
+        } else {
+            previous + (attack - previous) * release
+        };
+        gain.push(previous);
+    }
+    gain
+}
+
+fn linked_envelope(channels: &[Vec<f32>]) -> Vec<f32> {
+    let mut linked = vec![0.0f32; channels.first().map_or(0, Vec::len)];
+    for channel in channels {
+        for (slot, peak) in linked.iter_mut().zip(loudness::true_peak_envelope(channel)) {
+            *slot = slot.max(peak);
+        }
+    }
+    linked
+}
+
+fn render(
+    channels: &[Vec<f32>],
+    gain_db: f64,
+    ceiling: f32,
+    sample_rate: u32,
+) -> (Vec<Vec<f32>>, bool) {
+    let gain = db_to_gain(gain_db);
+    let mut output: Vec<Vec<f32>> = channels
+        .iter()
+        .map(|c| c.iter().map(|s| s * gain).collect())
+        .collect();
+    let envelope = linked_envelope(&output);
+    if envelope.iter().all(|&peak| peak <= ceiling) {
+        return (output, false);
+    }
+    let limiter = limiter_gain(&envelope, ceiling, sample_rate);
+    for channel in &mut output {
+        for (sample, g) in channel.iter_mut().zip(&limiter) {
+            *sample *= g;
+        }
+    }
+    // Gain modulation can leave slivers of overshoot between samples; trim
+    // them with a final static gain.
+    let peak = linked_envelope(&output).into_iter().fold(0.0, f32::max);
+    if peak > ceiling {
+        let trim = ceiling / peak;
+        output.iter_mut().flatten().for_each(|s| *s *= trim);
+    }
+    (output, true)
+}
+
+/// Normalizes to `target_lufs` keeping the true peak at or below
+/// `ceiling_dbtp`. Returns `None` for silence, which has no loudness.
+pub fn normalize(
+    sample_rate: u32,
+    channels: &[Vec<f32>],
+    target_lufs: f64,
+    ceiling_dbtp: f64,
+) -> Option<Normalized> {
+    let measured = loudness::measure(sample_rate, channels).integrated?;
+    let ceiling = db_to_gain(ceiling_dbtp);
+    let mut gain_db = target_lufs - measured;
+
+    let mut pass = 0;
+    loop {
+        let (output, limited) = render(channels, gain_db, ceiling, sample_rate);
+        pass += 1;
+        if !limited || pass == MAX_PASSES {
+            return Some(Normalized {
+                channels: output,
+                gain_db,
+                limited,
+            });
+        }
+        let reached = loudness::measure(sample_rate, &output).integrated?;
+        let shortfall = target_lufs - reached;
+        if shortfall.abs() <= TOLERANCE {
+            return Some(Normalized {
+                channels: output,
+                gain_db,
+                limited,
+            });
+        }
+        gain_db += shortfall;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speechlike(rate: u32, seconds: f64) -> Vec<f32> {
+        // A tone with a slow amplitude envelope and occasional transients.
+        (0..(rate as f64 * seconds) as usize)
+            .map(|i| {
+                let t = i as f64 / rate as f64;
+                let envelope = 0.3 + 0.25 * (t * 1.7).sin();
+                let transient = if i % (rate as usize / 2) < 40 {
+                    0.6
+                } else {
+                    0.0
+                };
+                ((2.0 * std::f64::consts::PI * 220.0 * t).sin() * envelope + transient) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hits_target_under_ceiling() {
+        let rate = 48000;
+        let signal = speechlike(rate, 10.0);
+        for target in [-23.0, -16.0, -9.0] {
+            let result = normalize(rate, &[signal.clone(), signal.clone()], target, -1.0).unwrap();
+            let report = loudness::measure(rate, &result.channels);
+            let integrated = report.integrated.unwrap();
+            assert!(
+                (integrated - target).abs() <= 0.2,
+                "target {target}: {integrated}"
+            );
+            assert!(
+                report.true_peak.unwrap() <= -1.0 + 0.05,
+                "target {target}: {:?}",
+                report.true_peak
+            );
+        }
+    }
+
+    #[test]
+    fn quiet_targets_skip_the_limiter() {
+        let rate = 48000;
+        let result = normalize(rate, &[speechlike(rate, 5.0)], -30.0, -1.0).unwrap();
+        assert!(!result.limited);
+    }
+
+    #[test]
+    fn silence_is_left_alone() {
+        assert!(normalize(48000, &[vec![0.0; 48000]], -16.0, -1.0).is_none());
+    }
+}
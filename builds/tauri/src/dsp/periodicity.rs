@@ -0,0 +1,168 @@
+//! Frame periodicity from the autocorrelation of the power spectrum.
+//!
+//! The autocorrelation of a windowed frame is the inverse FFT of its power
+//! spectrum. Dividing it by the autocorrelation of the window itself undoes
+//! the window's taper (Boersma 1993), so a perfectly periodic frame scores
+//! close to 1 at its period regardless of how far into the frame that lies.
+
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use realfft::num_complex::Complex32;
+use realfft::{ComplexToReal, RealFftPlanner};
+
+use super::stft::Stft;
+
+/// Range of voice pitch searched, in Hz.
+const PITCH_RANGE: (f32, f32) = (60.0, 400.0);
+/// Share of the best score a shorter period needs to be preferred over it.
+const OCTAVE_TOLERANCE: f32 = 0.9;
+
+/// The strongest period of a frame within the voice pitch range.
+#[derive(Debug, Clone, Copy)]
+pub struct Periodicity {
+    /// Normalized autocorrelation at the period: near 1 for voiced frames,
+    /// near 0 for noise.
+    pub strength: f32,
+    /// Fundamental frequency in Hz; meaningful only when `strength` is high.
+    pub frequency: f32,
+}
+
+pub struct PeriodicityAnalyzer {
+    sample_rate: u32,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    window_autocorrelation: Vec<f32>,
+    lags: RangeInclusive<usize>,
+    power: Vec<Complex32>,
+    autocorrelation: Vec<f32>,
+}
+
+impl PeriodicityAnalyzer {
+    pub fn new(stft: &Stft, sample_rate: u32) -> Self {
+        let size = stft.size();
+        let mut planner = RealFftPlanner::new();
+        let forward = planner.plan_fft_forward(size);
+        let inverse = planner.plan_fft_inverse(size);
+
+        let mut window = stft.window().to_vec();
+        let mut power = forward.make_output_vec();
+        forward
+            .process(&mut window, &mut power)
+            .expect("buffers come from the planner");
+        power
+            .iter_mut()
+            .for_each(|bin| *bin = Complex32::new(bin.norm_sqr(), 0.0));
+        let mut window_autocorrelation = inverse.make_output_vec();
+        inverse
+            .process(&mut power, &mut window_autocorrelation)
+            .expect("buffers come from the planner");
+
+        // Leave room on both sides of the longest lag for interpolation.
+        let to_lag = |hz: f32| ((sample_rate as f32 / hz) as usize).clamp(2, size / 2 - 1);
+        Self {
+            sample_rate,
+            lags: to_lag(PITCH_RANGE.1)..=to_lag(PITCH_RANGE.0),
+            power,
+            autocorrelation: inverse.make_output_vec(),
+            inverse,
+            window_autocorrelation,
+        }
+    }
+
+    pub fn analyze(&mut self, spectrum: &[Complex32]) -> Periodicity {
+        for (slot, bin) in self.power.iter_mut().zip(spectrum) {
+            *slot = Complex32::new(bin.norm_sqr(), 0.0);
+        }
+        self.inverse
+            .process(&mut self.power, &mut self.autocorrelation)
+            .expect("buffers come from the planner");
+        let zero = self.autocorrelation[0].max(f32::MIN_POSITIVE);
+        let normalized = |lag: usize| {
+            self.autocorrelation[lag] / zero * self.window_autocorrelation[0]
+                / self.window_autocorrelation[lag]
+        };
+
+        // Multiples of the period correlate about as well as the period
+        // itself; take the first peak that comes close to the best one.
+        let scores: Vec<(usize, f32)> = self
+            .lags
+            .clone()
+            .map(|lag| (lag, normalized(lag)))
+            .collect();
+        let best = scores.iter().copied().fold((0, 0.0f32), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        });
+        let (lag, strength) = scores
+            .windows(3)
+            .find(|w| w[1].1 >= w[0].1 && w[1].1 >= w[2].1 && w[1].1 >= best.1 * OCTAVE_TOLERANCE)
+            .map_or(best, |w| w[1]);
+        if lag == 0 {
+            return Periodicity {
+                strength: 0.0,
+                frequency: 0.0,
+            };
+        }
+        // Parabolic interpolation around the peak for sub-sample periods.
+        let (before, after) = (normalized(lag - 1), normalized(lag + 1));
+        let curvature = before - 2.0 * strength + after;
+        let offset = if curvature < 0.0 {
+            (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        Periodicity {
+            strength,
+            frequency: self.sample_rate as f32 / (lag as f32 + offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_period_of_voiced_frames_only() {
+        let rate = 16000;
+        let stft = Stft::for_sample_rate(rate);
+        let mut analyzer = PeriodicityAnalyzer::new(&stft, rate);
+        let voiced: Vec<f32> = (0..rate as usize)
+            .map(|i| {
+                let t = i as f32 / rate as f32;
+                (1..10)
+                    .map(|h| (std::f32::consts::TAU * 173.0 * h as f32 * t).sin() / h as f32)
+                    .sum()
+            })
+            .collect();
+        let mut state = 0x2545_f491u32;
+        let noise: Vec<f32> = (0..rate as usize)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect();
+
+        let mut results = Vec::new();
+        for signal in [&voiced, &noise] {
+            let mut frames = Vec::new();
+            stft.analyze(&signal[4000..8000], |_, spectrum| {
+                frames.push(analyzer.analyze(spectrum));
+            });
+            // Skip the frames that run off either end of the excerpt.
+            results.push(frames[4..frames.len() - 4].to_vec());
+        }
+        for frame in &results[0] {
+            assert!(frame.strength > 0.9, "{frame:?}");
+            assert!((frame.frequency - 173.0).abs() < 1.5, "{frame:?}");
+        }
+        for frame in &results[1] {
+            assert!(frame.strength < 0.4, "{frame:?}");
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Pitch shifting without changing duration.
+//!
+//! A phase vocoder working directly in the frequency domain (Laroche and
+//! Dolson, "New phase-vocoder techniques for pitch-shifting, harmonizing and
+//! other exotic effects", 1999). Each spectral peak is moved, together with
+//! the bins around it, to its shifted frequency, and its phase is advanced
+//! so that it keeps rotating at the new frequency from frame to frame. The
+//! bins of a region share the peak's phase rotation, which preserves the
+//! phase relations within the region and avoids the "phasiness" of a plain
+//! bin-by-bin vocoder.
+//!
+//! Because the length never changes, frames can be processed as they arrive;
+//! see [`super::voice`] for the streaming driver.
+
+use std::f32::consts::{PI, TAU};
+
+use realfft::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+use super::stft::Stft;
+
+/// Peaks quieter than this relative to the loudest bin of the frame are
+/// folded into their neighbours' regions.
+const PEAK_FLOOR: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PitchQuality {
+    /// Fourfold overlap; about half the work of `Final`, fine for auditioning.
+    Preview,
+    /// Eightfold overlap, which tracks fast pitch movement more closely.
+    #[default]
+    Final,
+}
+
+impl PitchQuality {
+    pub(super) fn stft(self, sample_rate: u32) -> Stft {
+        let size = Stft::for_sample_rate(sample_rate).size();
+        match self {
+            Self::Preview => Stft::with_overlap(size, 4),
+            Self::Final => Stft::with_overlap(size, 8),
+        }
+    }
+}
+
+pub fn semitones_to_ratio(semitones: f32) -> f32 {
+    2f32.powf(semitones / 12.0)
+}
+
+fn wrap_phase(phase: f32) -> f32 {
+    phase - TAU * ((phase + PI) / TAU).floor()
+}
+
+/// Per-channel frame processor.
+pub(super) struct PeakShifter {
+    ratio: f32,
+    /// Expected phase advance per hop of a sinusoid centred on bin 1.
+    bin_advance: f32,
+    magnitude: Vec<f32>,
+    phase: Vec<f32>,
+    previous_phase: Vec<f32>,
+    /// Accumulated phase rotation of the region each input bin belonged to
+    /// in the previous frame.
+    rotation: Vec<f32>,
+    next_rotation: Vec<f32>,
+    peaks: Vec<usize>,
+    shifted: Vec<Complex32>,
+}
+
+impl PeakShifter {
+    pub(super) fn new(stft: &Stft, ratio: f32) -> Self {
+        let bins = stft.bins();
+        Self {
+            ratio,
+            bin_advance: TAU * stft.hop() as f32 / stft.size() as f32,
+            magnitude: vec![0.0; bins],
+            phase: vec![0.0; bins],
+            previous_phase: vec![0.0; bins],
+            rotation: vec![0.0; bins],
+            next_rotation: vec![0.0; bins],
+            peaks: Vec::new(),
+            shifted: vec![Complex32::default(); bins],
+        }
+    }
+
+    pub(super) fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio;
+    }
+
+    fn find_peaks(&mut self) {
+        let magnitude = &self.magnitude;
+        let floor = magnitude.iter().fold(0.0f32, |max, &m| max.max(m)) * PEAK_FLOOR;
+        self.peaks.clear();
+        for k in 1..magnitude.len().saturating_sub(1) {
+            let m = magnitude[k];
+            let above = |j: Option<usize>| j.and_then(|j| magnitude.get(j)).is_none_or(|&n| m > n);
+            if m > floor
+                && m >= magnitude[k + 1]
+                && above(k.checked_sub(1))
+                && above(k.checked_sub(2))
+                && above(Some(k + 2))
+            {
+                self.peaks.push(k);
+            }
+        }
+    }
+
+    /// Bins belonging to peak `i`: up to the quietest bin between it and
+    /// each neighbouring peak.
+    fn region(&self, i: usize) -> std::ops::Range<usize> {
+        let trough = |a: usize, b: usize| {
+            (a..b)
+                .min_by(|&x, &y| self.magnitude[x].total_cmp(&self.magnitude[y]))
+                .unwrap_or(a)
+        };
+        let start = match i {
+            0 => 0,
+            _ => trough(self.peaks[i - 1] + 1, self.peaks[i]),
+        };
+        let end = match self.peaks.get(i + 1) {
+            Some(&next) => trough(self.peaks[i] + 1, next),
+            None => self.magnitude.len(),
+        };
+        start..end
+    }
+
+    pub(super) fn process(&mut self, spectrum: &mut [Complex32]) {
+        for (k, bin) in spectrum.iter().enumerate() {
+            self.magnitude[k] = bin.norm();
+            self.phase[k] = bin.arg();
+        }
+        self.find_peaks();
+        self.shifted.fill(Complex32::default());
+        self.next_rotation.fill(0.0);
+
+        let bins = spectrum.len() as isize;
+        for i in 0..self.peaks.len() {
+            let peak = self.peaks[i];
+            // True frequency of the peak in bins, from its phase advance.
+            let expected = self.bin_advance * peak as f32;
+            let deviation = wrap_phase(self.phase[peak] - self.previous_phase[peak] - expected);
+            let frequency = peak as f32 + deviation / self.bin_advance;
+
+            let shift = (frequency * self.ratio - peak as f32).round() as isize;
+            let rotation =
+                wrap_phase(self.rotation[peak] + frequency * (self.ratio - 1.0) * self.bin_advance);
+            let turn = Complex32::from_polar(1.0, rotation);
+            for k in self.region(i) {
+                self.next_rotation[k] = rotation;
+                let target = k as isize + shift;
+                if (0..bins).contains(&target) {
+                    self.shifted[target as usize] += spectrum[k] * turn;
+                }
+            }
+        }
+
+        spectrum.copy_from_slice(&self.shifted);
+        std::mem::swap(&mut self.previous_phase, &mut self.phase);
+        std::mem::swap(&mut self.rotation, &mut self.next_rotation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(rate: u32, frequency: f32, seconds: f32) -> Vec<f32> {
+        (0..(rate as f32 * seconds) as usize)
+            .map(|i| (TAU * frequency * i as f32 / rate as f32).sin() * 0.5)
+            .collect()
+    }
+
+    /// Frequency from upward zero crossings, ignoring the edges.
+    fn frequency(rate: u32, signal: &[f32]) -> f32 {
+        let middle = &signal[signal.len() / 4..signal.len() * 3 / 4];
+        let crossings = middle
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f32 * rate as f32 / middle.len() as f32
+    }
+
+    #[test]
+    fn moves_tones_by_the_requested_interval() {
+        let rate = 48000;
+        let input = tone(rate, 220.0, 2.0);
+        for quality in [PitchQuality::Preview, PitchQuality::Final] {
+            for semitones in [-12.0, -5.0, 7.0, 12.0, 19.0] {
+                let stft = quality.stft(rate);
+                let mut shifter = PeakShifter::new(&stft, semitones_to_ratio(semitones));
+                let output = stft.process(&input, |_, spectrum| shifter.process(spectrum));
+                let expected = 220.0 * semitones_to_ratio(semitones);
+                let measured = frequency(rate, &output);
+                assert!(
+                    (measured / expected - 1.0).abs() < 0.01,
+                    "{quality:?} {semitones}: {measured} Hz, expected {expected} Hz"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Intonation scaling.
+//!
+//! The pitch of each voiced frame is tracked and expressed in semitones from
+//! the median pitch of the whole take. Scaling those offsets widens (by a
+//! factor above 1) or flattens (below 1) the melody of the speech around its
+//! usual level; the result is a per-frame pitch correction for
+//! [`super::voice::VoiceShifter::with_contour`].
+
+use super::periodicity::PeriodicityAnalyzer;
+use super::stft::Stft;
+
+/// Periodicity above which a frame counts as voiced.
+const VOICED: f32 = 0.6;
+/// Correction is smoothed over this long so pitch-tracking jitter doesn't
+/// turn into vibrato.
+const SMOOTHING_SECONDS: f64 = 0.05;
+const MAX_CORRECTION: f32 = 12.0;
+
+/// Per-frame pitch corrections, in semitones, that scale the intonation of
+/// `signal` by `variation`. Frames are those of `stft`.
+pub fn contour(stft: &Stft, sample_rate: u32, signal: &[f32], variation: f32) -> Vec<f32> {
+    let mut analyzer = PeriodicityAnalyzer::new(stft, sample_rate);
+    let mut pitch: Vec<Option<f32>> = Vec::with_capacity(stft.frame_count(signal.len()));
+    stft.analyze(signal, |_, spectrum| {
+        let frame = analyzer.analyze(spectrum);
+        pitch.push((frame.strength >= VOICED).then(|| 12.0 * frame.frequency.log2()));
+    });
+
+    let mut voiced: Vec<f32> = pitch.iter().flatten().copied().collect();
+    if voiced.is_empty() || variation == 1.0 {
+        return vec![0.0; pitch.len()];
+    }
+    voiced.sort_by(f32::total_cmp);
+    let median = voiced[voiced.len() / 2];
+
+    // Corrections for voiced frames; unvoiced stretches are bridged linearly
+    // between their neighbours so the pitch doesn't jump at their edges.
+    let raw: Vec<Option<f32>> = pitch
+        .iter()
+        .map(|p| p.map(|semitones| (semitones - median) * (variation - 1.0)))
+        .collect();
+    let mut filled = vec![0.0f32; raw.len()];
+    let mut previous: Option<(usize, f32)> = None;
+    for (index, value) in raw.iter().enumerate() {
+        let Some(value) = *value else {
+            continue;
+        };
+        let (from, start) = previous.map_or((0, value), |(i, v)| (i, v));
+        for (offset, slot) in filled[from..index].iter_mut().enumerate() {
+            let t = offset as f32 / (index - from).max(1) as f32;
+            *slot = start + (value - start) * t;
+        }
+        filled[index] = value;
+        previous = Some((index, value));
+    }
+    if let Some((last, value)) = previous {
+        filled[last..].fill(value);
+    }
+
+    let radius = ((SMOOTHING_SECONDS * sample_rate as f64 / stft.hop() as f64) as usize / 2).max(1);
+    (0..filled.len())
+        .map(|i| {
+            let window = &filled[i.saturating_sub(radius)..(i + radius + 1).min(filled.len())];
+            let mean = window.iter().sum::<f32>() / window.len() as f32;
+            mean.clamp(-MAX_CORRECTION, MAX_CORRECTION)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use super::*;
+
+    #[test]
+    fn scales_offsets_from_the_median_pitch() {
+        let rate = 16000;
+        // Pitch swinging 2 semitones either side of 200 Hz once a second.
+        let mut phase = 0.0f64;
+        let signal: Vec<f32> = (0..rate as usize * 3)
+            .map(|i| {
+                let t = i as f64 / rate as f64;
+                let semitones = 2.0 * (TAU * t).sin();
+                phase += TAU * 200.0 * 2f64.powf(semitones / 12.0) / rate as f64;
+                (1..6)
+                    .map(|h| (phase * h as f64).sin() / h as f64)
+                    .sum::<f64>() as f32
+                    * 0.3
+            })
+            .collect();
+        let stft = Stft::for_sample_rate(rate);
+
+        assert!(contour(&stft, rate, &signal, 1.0).iter().all(|&c| c == 0.0));
+
+        // Doubling the variation adds the offset once more: +2 semitones at
+        // the crest (t = 1.25 s), -2 at the trough (t = 1.75 s).
+        let doubled = contour(&stft, rate, &signal, 2.0);
+        let frame_at = |seconds: f64| {
+            (0..doubled.len())
+                .min_by_key(|&i| {
+                    let centre = stft.frame_start(i) as f64 + stft.size() as f64 / 2.0;
+                    (centre - seconds * rate as f64).abs() as usize
+                })
+                .unwrap()
+        };
+        let (crest, trough) = (doubled[frame_at(1.25)], doubled[frame_at(1.75)]);
+        assert!((crest - 2.0).abs() < 0.4, "crest {crest}");
+        assert!((trough + 2.0).abs() < 0.4, "trough {trough}");
+    }
+}
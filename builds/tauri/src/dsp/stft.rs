@@ -0,0 +1,242 @@
+//! Short-time Fourier transform with overlap-add resynthesis.
+//!
+//! Frames are windowed with a periodic Hann window on both analysis and
+//! synthesis and hop by a quarter of the frame or less, which sums to a
+//! constant and so reconstructs the input exactly when the spectrum is left
+//! untouched.
+//! Processing is streamed frame by frame so memory stays proportional to
+//! the signal, not to the spectrogram.
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use realfft::num_complex::Complex32;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+
+#[derive(Clone)]
+pub struct Stft {
+    size: usize,
+    hop: usize,
+    window: Vec<f32>,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+}
+
+impl Stft {
+    /// Frames of `size` samples overlapping four times.
+    pub fn new(size: usize) -> Self {
+        Self::with_overlap(size, 4)
+    }
+
+    /// Frames of `size` samples, each overlapped by `overlap` others. The
+    /// overlap must be at least 4 for the windows to sum to a constant.
+    pub fn with_overlap(size: usize, overlap: usize) -> Self {
+        assert!(
+            overlap >= 4 && size >= 4 * overlap && size.is_multiple_of(overlap),
+            "STFT size must be a multiple of the overlap"
+        );
+        let window = (0..size)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / size as f32).cos())
+            .collect();
+        let mut planner = RealFftPlanner::new();
+        Self {
+            size,
+            hop: size / overlap,
+            window,
+            forward: planner.plan_fft_forward(size),
+            inverse: planner.plan_fft_inverse(size),
+        }
+    }
+
+    /// A frame of roughly 40 ms, rounded up to a power of two.
+    pub fn for_sample_rate(sample_rate: u32) -> Self {
+        Self::new(((sample_rate as usize) / 25).next_power_of_two().max(256))
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    /// The analysis (and synthesis) window.
+    pub fn window(&self) -> &[f32] {
+        &self.window
+    }
+
+    pub fn bins(&self) -> usize {
+        self.size / 2 + 1
+    }
+
+    /// Leading padding so the first samples are covered by a full set of
+    /// overlapping frames.
+    fn padding(&self) -> usize {
+        self.size - self.hop
+    }
+
+    pub fn frame_count(&self, len: usize) -> usize {
+        (len + self.padding()).div_ceil(self.hop)
+    }
+
+    /// Start of frame `index` in signal coordinates; negative before the
+    /// signal begins.
+    pub fn frame_start(&self, index: usize) -> isize {
+        (index * self.hop) as isize - self.padding() as isize
+    }
+
+    fn load_frame(&self, signal: &[f32], index: usize, frame: &mut [f32]) {
+        let start = self.frame_start(index);
+        for (n, (slot, w)) in frame.iter_mut().zip(&self.window).enumerate() {
+            let at = start + n as isize;
+            *slot = if at >= 0 && (at as usize) < signal.len() {
+                signal[at as usize] * w
+            } else {
+                0.0
+            };
+        }
+    }
+
+    /// Calls `f` with the spectrum of every frame without resynthesizing.
+    pub fn analyze(&self, signal: &[f32], mut f: impl FnMut(usize, &[Complex32])) {
+        let mut frame = self.forward.make_input_vec();
+        let mut spectrum = self.forward.make_output_vec();
+        for index in 0..self.frame_count(signal.len()) {
+            self.load_frame(signal, index, &mut frame);
+            self.forward
+                .process(&mut frame, &mut spectrum)
+                .expect("buffers come from the planner");
+            f(index, &spectrum);
+        }
+    }
+
+    /// Transforms every frame, lets `f` modify the spectrum in place and
+    /// overlap-adds the result back into a signal of the same length.
+    pub fn process(&self, signal: &[f32], mut f: impl FnMut(usize, &mut [Complex32])) -> Vec<f32> {
+        let mut stream = StftStream::new(self.clone());
+        let mut output = stream.push(signal, &mut f);
+        output.extend(stream.finish(&mut f));
+        output
+    }
+}
+
+/// [`Stft::process`] fed in blocks of any size.
+///
+/// Output lags input by up to one frame; the samples held back are released
+/// by [`StftStream::finish`], after which the concatenated output is
+/// identical to processing the whole signal at once.
+pub struct StftStream {
+    stft: Stft,
+    /// Unconsumed input, starting at the next frame.
+    input: Vec<f32>,
+    /// Overlap-add accumulator, starting at the next output sample.
+    output: Vec<f32>,
+    frame: Vec<f32>,
+    spectrum: Vec<Complex32>,
+    scale: f32,
+    index: usize,
+    /// Leading output samples still to drop to undo the padding.
+    skip: usize,
+    received: usize,
+    emitted: usize,
+}
+
+impl StftStream {
+    pub fn new(stft: Stft) -> Self {
+        // Hann squared overlapped four or more times sums to a constant;
+        // fold it and the unnormalized inverse FFT into one scale factor.
+        let overlap_gain: f32 = stft.window.iter().map(|w| w * w).sum::<f32>() / stft.hop as f32;
+        let scale = 1.0 / (overlap_gain * stft.size as f32);
+        Self {
+            input: vec![0.0; stft.padding()],
+            output: vec![0.0; stft.size],
+            frame: stft.forward.make_input_vec(),
+            spectrum: stft.forward.make_output_vec(),
+            scale,
+            index: 0,
+            skip: stft.padding(),
+            received: 0,
+            emitted: 0,
+            stft,
+        }
+    }
+
+    /// Feeds `block` and returns the output that is now complete.
+    pub fn push(&mut self, block: &[f32], mut f: impl FnMut(usize, &mut [Complex32])) -> Vec<f32> {
+        self.input.extend_from_slice(block);
+        self.received += block.len();
+        let mut output = Vec::with_capacity(block.len() + self.stft.hop);
+        while self.input.len() >= self.stft.size {
+            self.step(&mut f, &mut output);
+        }
+        output
+    }
+
+    /// Runs the remaining frames and returns the rest of the output.
+    pub fn finish(mut self, mut f: impl FnMut(usize, &mut [Complex32])) -> Vec<f32> {
+        let mut output = Vec::new();
+        while self.index < self.stft.frame_count(self.received) {
+            self.input.resize(self.input.len().max(self.stft.size), 0.0);
+            self.step(&mut f, &mut output);
+        }
+        debug_assert_eq!(self.emitted, self.received);
+        output
+    }
+
+    fn step(&mut self, f: &mut impl FnMut(usize, &mut [Complex32]), output: &mut Vec<f32>) {
+        let stft = &self.stft;
+        for ((slot, sample), w) in self.frame.iter_mut().zip(&self.input).zip(&stft.window) {
+            *slot = sample * w;
+        }
+        stft.forward
+            .process(&mut self.frame, &mut self.spectrum)
+            .expect("buffers come from the planner");
+        f(self.index, &mut self.spectrum);
+        // A real signal has purely real DC and Nyquist bins.
+        self.spectrum[0].im = 0.0;
+        if let Some(last) = self.spectrum.last_mut() {
+            last.im = 0.0;
+        }
+        stft.inverse
+            .process(&mut self.spectrum, &mut self.frame)
+            .expect("buffers come from the planner");
+        for ((acc, sample), w) in self.output.iter_mut().zip(&self.frame).zip(&stft.window) {
+            *acc += sample * w * self.scale;
+        }
+
+        // The first hop of the accumulator has now seen every frame that
+        // overlaps it.
+        let hop = stft.hop;
+        let skipped = self.skip.min(hop);
+        self.skip -= skipped;
+        let ready = (hop - skipped).min(self.received - self.emitted);
+        output.extend_from_slice(&self.output[skipped..skipped + ready]);
+        self.emitted += ready;
+        self.output.drain(..hop);
+        self.output.resize(stft.size, 0.0);
+        self.input.drain(..hop);
+        self.index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_round_trip_reconstructs_input() {
+        let stft = Stft::new(512);
+        let signal: Vec<f32> = (0..5000)
+            .map(|i| ((i * 7919) % 1000) as f32 / 500.0 - 1.0)
+            .collect();
+        let output = stft.process(&signal, |_, _| {});
+        assert_eq!(output.len(), signal.len());
+        let error = signal
+            .iter()
+            .zip(&output)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(error < 1e-4, "reconstruction error {error}");
+    }
+}
@@ -0,0 +1,231 @@
+//! Time stretching without changing pitch.
+//!
+//! WSOLA (waveform-similarity overlap-add, Verhelst and Roelands 1993):
+//! windowed frames are read from the input at a hop scaled by the speed and
+//! overlap-added at a fixed output hop. Each frame's read position may move
+//! by up to a quarter frame to wherever the input best continues the frame
+//! before it, so periodic waveforms stay in phase and voices keep their
+//! pitch and timbre. Working in the time domain, it keeps transients crisper
+//! than a phase vocoder, which suits speech.
+
+use std::f32::consts::PI;
+use std::ops::Range;
+
+use super::mixdown;
+
+/// Slowest and fastest supported playback speed.
+pub const SPEED_RANGE: (f64, f64) = (0.25, 4.0);
+
+/// Frame length; a few pitch periods of even a low voice.
+const FRAME_SECONDS: f64 = 0.03;
+/// Length of the crossfade back into the original audio after a stretched
+/// region.
+const SPLICE_SECONDS: f64 = 0.005;
+
+struct Frames {
+    size: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+}
+
+impl Frames {
+    fn new(sample_rate: u32) -> Self {
+        let size = ((sample_rate as f64 * FRAME_SECONDS) as usize / 4 * 4).max(16);
+        // Sampled half a sample off so no tap is exactly zero: a lone
+        // frame at either end can then be divided back out of the output.
+        let window = (0..size)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * (n as f32 + 0.5) / size as f32).cos())
+            .collect();
+        Self {
+            size,
+            hop: size / 2,
+            tolerance: size / 4,
+            window,
+        }
+    }
+}
+
+/// Offset in `-tolerance..=tolerance` that makes `signal` at `position`
+/// look most like `target`. Positions outside the signal read as silence.
+fn best_offset(signal: &[f32], target: &[f32], position: isize, tolerance: usize) -> isize {
+    let at = |i: isize| {
+        if i >= 0 && (i as usize) < signal.len() {
+            signal[i as usize]
+        } else {
+            0.0
+        }
+    };
+    let tolerance = tolerance as isize;
+    let mut best = (0isize, f32::MIN);
+    for offset in -tolerance..=tolerance {
+        let start = position + offset;
+        let mut correlation = 0.0f32;
+        let mut energy = 0.0f32;
+        for (n, t) in target.iter().enumerate() {
+            let x = at(start + n as isize);
+            correlation += x * t;
+            energy += x * x;
+        }
+        let score = correlation / energy.sqrt().max(f32::MIN_POSITIVE);
+        // Ties (e.g. silence) prefer staying on the nominal position.
+        if score > best.1 || (score == best.1 && offset.abs() < best.0.abs()) {
+            best = (offset, score);
+        }
+    }
+    best.0
+}
+
+/// Plays `channels` at `speed` times the original rate; 2.0 halves the
+/// duration. Channels share frame positions so they stay aligned.
+pub fn stretch(sample_rate: u32, channels: &[Vec<f32>], speed: f64) -> Vec<Vec<f32>> {
+    let frames = Frames::new(sample_rate);
+    let len = channels.first().map_or(0, Vec::len);
+    let output_len = (len as f64 / speed).round() as usize;
+    let mono = mixdown(channels);
+
+    let mut output = vec![vec![0.0f32; output_len + frames.size]; channels.len()];
+    let mut weight = vec![0.0f32; output_len + frames.size];
+    let overlap = frames.size - frames.hop;
+    // Read position of the previous frame, after its similarity offset.
+    let mut previous: isize = 0;
+    for k in 0..output_len.div_ceil(frames.hop) + 1 {
+        let nominal = (k as f64 * frames.hop as f64 * speed).round() as isize;
+        let position = if k == 0 {
+            0
+        } else {
+            // The previous frame's natural continuation is what the start
+            // of this frame should resemble.
+            let start = previous + frames.hop as isize;
+            let target: Vec<f32> = (start..start + overlap as isize)
+                .map(|i| {
+                    usize::try_from(i)
+                        .ok()
+                        .and_then(|i| mono.get(i))
+                        .map_or(0.0, |x| *x)
+                })
+                .collect();
+            nominal + best_offset(&mono, &target, nominal, frames.tolerance)
+        };
+        previous = position;
+
+        let out_start = k * frames.hop;
+        for (n, w) in frames.window.iter().enumerate() {
+            let read = position + n as isize;
+            let write = out_start + n;
+            if write >= weight.len() {
+                break;
+            }
+            if read < 0 || read as usize >= len {
+                continue;
+            }
+            weight[write] += w;
+            for (out, channel) in output.iter_mut().zip(channels) {
+                out[write] += channel[read as usize] * w;
+            }
+        }
+    }
+
+    for channel in &mut output {
+        channel.truncate(output_len);
+        for (sample, w) in channel.iter_mut().zip(&weight) {
+            // Away from the edges the windows sum to one; at the edges a
+            // single frame, or the part of one inside the input, covers.
+            if *w > 0.0 {
+                *sample /= w;
+            }
+        }
+    }
+    output
+}
+
+/// Stretches only `range` of `channels`, leaving the audio around it as is.
+/// Returns the new channels and where the stretched range now ends.
+pub fn stretch_region(
+    sample_rate: u32,
+    channels: &[Vec<f32>],
+    range: Range<usize>,
+    speed: f64,
+) -> (Vec<Vec<f32>>, usize) {
+    let region: Vec<Vec<f32>> = channels.iter().map(|c| c[range.clone()].to_vec()).collect();
+    let stretched = stretch(sample_rate, &region, speed);
+    let stretched_len = stretched.first().map_or(0, Vec::len);
+    let splice = ((sample_rate as f64 * SPLICE_SECONDS) as usize)
+        .min(stretched_len)
+        .min(range.len());
+
+    let output = channels
+        .iter()
+        .zip(stretched)
+        .map(|(channel, mut middle)| {
+            // The stretched tail may sit a little off the original waveform;
+            // fade into the region's real ending so the join is seamless.
+            let tail = stretched_len - splice;
+            for i in 0..splice {
+                let g = (i + 1) as f32 / (splice + 1) as f32;
+                let original = channel[range.end - splice + i];
+                middle[tail + i] = middle[tail + i] * (1.0 - g) + original * g;
+            }
+            let mut out = Vec::with_capacity(channel.len() - range.len() + stretched_len);
+            out.extend_from_slice(&channel[..range.start]);
+            out.extend(middle);
+            out.extend_from_slice(&channel[range.end..]);
+            out
+        })
+        .collect();
+    (output, range.start + stretched_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(rate: u32, frequency: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * PI * frequency * i as f32 / rate as f32).sin() * 0.5)
+            .collect()
+    }
+
+    fn frequency(rate: u32, signal: &[f32]) -> f32 {
+        let middle = &signal[signal.len() / 4..signal.len() * 3 / 4];
+        let crossings = middle
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f32 * rate as f32 / middle.len() as f32
+    }
+
+    #[test]
+    fn changes_duration_but_not_pitch() {
+        let rate = 16000;
+        let input = tone(rate, 220.0, rate as usize * 2);
+        for speed in [SPEED_RANGE.0, 0.5, 0.8, 1.5, SPEED_RANGE.1] {
+            let output = stretch(rate, &[input.clone(), input.clone()], speed);
+            let expected = (input.len() as f64 / speed).round() as usize;
+            assert_eq!(output[0].len(), expected);
+            assert_eq!(output[0], output[1]);
+            let measured = frequency(rate, &output[0]);
+            assert!(
+                (measured / 220.0 - 1.0).abs() < 0.01,
+                "speed {speed}: {measured} Hz"
+            );
+        }
+    }
+
+    #[test]
+    fn region_stretch_leaves_the_rest_alone() {
+        let rate = 16000;
+        let input = tone(rate, 300.0, rate as usize);
+        let range = 4000..8000;
+        let (output, end) = stretch_region(rate, std::slice::from_ref(&input), range.clone(), 0.5);
+        assert_eq!(end, 4000 + 8000);
+        assert_eq!(output[0].len(), input.len() + 4000);
+        assert_eq!(output[0][..range.start], input[..range.start]);
+        assert_eq!(output[0][end..], input[range.end..]);
+        // No click at either join.
+        for join in [range.start, end] {
+            let step = (output[0][join] - output[0][join - 1]).abs();
+            assert!(step < 0.15, "jump of {step} at {join}");
+        }
+    }
+}
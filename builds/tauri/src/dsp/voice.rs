@@ -0,0 +1,338 @@
+//! Pitch, formant and spectral tilt in a single STFT pass.
+//!
+//! Each frame is analysed once. The envelope of the original frame is taken
+//! before the pitch shifter moves the partials, then the shifted frame is
+//! reshaped so its envelope matches the original one stretched by the
+//! formant amount. The two controls are therefore independent: pitch alone
+//! keeps the voice's formants, formant alone keeps its pitch. The pitch may
+//! also follow a per-frame contour, and a tilt is applied last.
+
+use std::sync::Arc;
+
+use realfft::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+use super::formant::{self, Envelope};
+use super::pitch::{semitones_to_ratio, PeakShifter, PitchQuality};
+use super::stft::{Stft, StftStream};
+
+/// Largest boost or cut the envelope correction applies to a bin, so empty
+/// parts of the shifted spectrum aren't blown up into noise.
+const MAX_CORRECTION_DB: f32 = 24.0;
+/// Frames handed to the stream at a time by [`render`].
+const BLOCK_FRAMES: usize = 1 << 16;
+/// Frequency the tilt pivots around, in Hz.
+const TILT_PIVOT: f32 = 1000.0;
+/// Frequencies below this get the tilt gain of this frequency, so DC and
+/// rumble aren't boosted without bound.
+const TILT_LOWEST: f32 = 50.0;
+const MAX_TILT_DB: f32 = 12.0;
+
+/// The pitch and formant sliders, both in semitones, and a spectral tilt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VoiceShift {
+    pub pitch: f32,
+    /// Shift of the spectral envelope relative to the source. `None` lets
+    /// the formants move with the pitch, as a plain pitch shifter does.
+    pub formant: Option<f32>,
+    /// Spectral tilt in dB per octave around 1 kHz; negative is darker.
+    pub tilt: f32,
+}
+
+impl VoiceShift {
+    pub fn is_identity(&self) -> bool {
+        self.pitch == 0.0 && self.formant.unwrap_or(0.0) == 0.0 && self.tilt == 0.0
+    }
+}
+
+fn tilt_gains(sample_rate: u32, bins: usize, tilt: f32) -> Vec<f32> {
+    let bin_hz = sample_rate as f32 / (2 * (bins - 1)) as f32;
+    (0..bins)
+        .map(|k| {
+            let octaves = ((k as f32 * bin_hz).max(TILT_LOWEST) / TILT_PIVOT).log2();
+            let db = (tilt * octaves).clamp(-MAX_TILT_DB, MAX_TILT_DB);
+            10f32.powf(db / 20.0)
+        })
+        .collect()
+}
+
+struct FormantCorrection {
+    envelope: Envelope,
+    factor: f32,
+    source: Vec<f32>,
+    target: Vec<f32>,
+    shifted: Vec<f32>,
+}
+
+/// Per-channel frame processor.
+struct FrameShifter {
+    pitch: Option<PeakShifter>,
+    /// Base pitch and per-frame offsets from it, in semitones.
+    contour: Option<(f32, Arc<[f32]>)>,
+    formant: Option<FormantCorrection>,
+    tilt: Option<Arc<[f32]>>,
+}
+
+impl FrameShifter {
+    fn process(&mut self, index: usize, spectrum: &mut [Complex32]) {
+        if let (Some(pitch), Some((base, contour))) = (&mut self.pitch, &self.contour) {
+            let offset = contour.get(index).copied().unwrap_or(0.0);
+            pitch.set_ratio(semitones_to_ratio(base + offset));
+        }
+        if let Some(correction) = &mut self.formant {
+            correction
+                .envelope
+                .estimate(spectrum, &mut correction.source);
+        }
+        if let Some(pitch) = &mut self.pitch {
+            pitch.process(spectrum);
+        }
+        if let Some(correction) = &mut self.formant {
+            correction.apply(self.pitch.is_some(), spectrum);
+        }
+        if let Some(gains) = &self.tilt {
+            for (bin, gain) in spectrum.iter_mut().zip(gains.iter()) {
+                *bin *= *gain;
+            }
+        }
+    }
+}
+
+impl FormantCorrection {
+    /// Reshapes `spectrum` to the warped source envelope. `pitched` says
+    /// whether the spectrum has been moved since `source` was estimated.
+    fn apply(&mut self, pitched: bool, spectrum: &mut [Complex32]) {
+        formant::warp(&self.source, self.factor, &mut self.target);
+        let shifted = if pitched {
+            self.envelope.estimate(spectrum, &mut self.shifted);
+            &self.shifted
+        } else {
+            &self.source
+        };
+        let limit = MAX_CORRECTION_DB / 20.0 * std::f32::consts::LN_10;
+        for ((bin, target), current) in spectrum.iter_mut().zip(&self.target).zip(shifted) {
+            *bin *= (target - current).clamp(-limit, limit).exp();
+        }
+    }
+}
+
+/// Voice shifter for planar audio fed in blocks.
+pub struct VoiceShifter {
+    stft: Stft,
+    channels: Vec<(StftStream, FrameShifter)>,
+}
+
+impl VoiceShifter {
+    pub fn new(
+        sample_rate: u32,
+        channels: usize,
+        shift: VoiceShift,
+        quality: PitchQuality,
+    ) -> Self {
+        let stft = quality.stft(sample_rate);
+        let tilt: Option<Arc<[f32]>> =
+            (shift.tilt != 0.0).then(|| tilt_gains(sample_rate, stft.bins(), shift.tilt).into());
+        let channels = (0..channels)
+            .map(|_| {
+                let frame = FrameShifter {
+                    pitch: (shift.pitch != 0.0)
+                        .then(|| PeakShifter::new(&stft, semitones_to_ratio(shift.pitch))),
+                    contour: None,
+                    formant: shift.formant.map(|semitones| FormantCorrection {
+                        envelope: Envelope::new(&stft, sample_rate),
+                        factor: semitones_to_ratio(semitones),
+                        source: vec![0.0; stft.bins()],
+                        target: vec![0.0; stft.bins()],
+                        shifted: vec![0.0; stft.bins()],
+                    }),
+                    tilt: tilt.clone(),
+                };
+                (StftStream::new(stft.clone()), frame)
+            })
+            .collect();
+        Self { stft, channels }
+    }
+
+    /// Adds `contour[i]` semitones to the pitch of STFT frame `i`, as
+    /// counted by [`super::stft::Stft::analyze`] with this shifter's STFT.
+    pub fn with_contour(mut self, base: f32, contour: Vec<f32>) -> Self {
+        let contour: Arc<[f32]> = contour.into();
+        for (_, frame) in &mut self.channels {
+            frame
+                .pitch
+                .get_or_insert_with(|| PeakShifter::new(&self.stft, semitones_to_ratio(base)));
+            frame.contour = Some((base, contour.clone()));
+        }
+        self
+    }
+
+    pub fn stft(&self) -> &Stft {
+        &self.stft
+    }
+
+    /// Feeds one block per channel and returns the output now complete,
+    /// which may be shorter than the input while the stream fills up.
+    pub fn push(&mut self, block: &[&[f32]]) -> Vec<Vec<f32>> {
+        self.channels
+            .iter_mut()
+            .zip(block)
+            .map(|((stream, frame), input)| {
+                stream.push(input, |index, spectrum| frame.process(index, spectrum))
+            })
+            .collect()
+    }
+
+    /// Flushes the stream. Across all calls, each channel's output is as
+    /// long as its input.
+    pub fn finish(self) -> Vec<Vec<f32>> {
+        self.channels
+            .into_iter()
+            .map(|(stream, mut frame)| {
+                stream.finish(|index, spectrum| frame.process(index, spectrum))
+            })
+            .collect()
+    }
+}
+
+/// Renders whole channels through `shifter`.
+pub fn render(mut shifter: VoiceShifter, channels: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let mut output: Vec<Vec<f32>> = channels
+        .iter()
+        .map(|c| Vec::with_capacity(c.len()))
+        .collect();
+    let frames = channels.first().map_or(0, Vec::len);
+    for start in (0..frames).step_by(BLOCK_FRAMES) {
+        let end = (start + BLOCK_FRAMES).min(frames);
+        let block: Vec<&[f32]> = channels.iter().map(|c| &c[start..end]).collect();
+        for (out, chunk) in output.iter_mut().zip(shifter.push(&block)) {
+            out.extend(chunk);
+        }
+    }
+    for (out, chunk) in output.iter_mut().zip(shifter.finish()) {
+        out.extend(chunk);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    /// A 1 s vowel-like signal: harmonics of `f0` weighted by a single
+    /// resonance at `formant` Hz.
+    fn vowel(f0: f32, formant: f32) -> Vec<f32> {
+        let harmonics: Vec<(f32, f32)> = (1..)
+            .map(|h| h as f32 * f0)
+            .take_while(|&hz| hz < RATE as f32 / 2.0)
+            .map(|hz| (hz, 0.05 / (1.0 + ((hz - formant) / 150.0).powi(2))))
+            .collect();
+        (0..RATE as usize)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                harmonics
+                    .iter()
+                    .map(|(hz, a)| a * (TAU * hz * t).sin())
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn magnitude_at(signal: &[f32], hz: f32) -> f32 {
+        let middle = &signal[signal.len() / 4..signal.len() * 3 / 4];
+        let (re, im) = middle
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, x)| {
+                let phase = TAU * hz * n as f32 / RATE as f32;
+                (re + x * phase.cos(), im - x * phase.sin())
+            });
+        (re * re + im * im).sqrt() / middle.len() as f32
+    }
+
+    /// Frequency of the strongest multiple of `f0`.
+    fn strongest_harmonic(signal: &[f32], f0: f32) -> f32 {
+        (1..(RATE as f32 / 2.0 / f0) as usize)
+            .map(|h| h as f32 * f0)
+            .max_by(|&a, &b| magnitude_at(signal, a).total_cmp(&magnitude_at(signal, b)))
+            .unwrap()
+    }
+
+    #[test]
+    fn pitch_and_formant_move_independently() {
+        let input = vowel(150.0, 1000.0);
+        let cases = [
+            // Formant up an octave, pitch untouched.
+            (
+                VoiceShift {
+                    pitch: 0.0,
+                    formant: Some(12.0),
+                    ..VoiceShift::default()
+                },
+                150.0,
+                2000.0,
+            ),
+            // Pitch up a fifth, formant kept.
+            (
+                VoiceShift {
+                    pitch: 7.0,
+                    formant: Some(0.0),
+                    ..VoiceShift::default()
+                },
+                150.0 * semitones_to_ratio(7.0),
+                1000.0,
+            ),
+            // Both at once, in one pass.
+            (
+                VoiceShift {
+                    pitch: -5.0,
+                    formant: Some(5.0),
+                    ..VoiceShift::default()
+                },
+                150.0 * semitones_to_ratio(-5.0),
+                1000.0 * semitones_to_ratio(5.0),
+            ),
+        ];
+        for (shift, f0, formant) in cases {
+            let shifter = VoiceShifter::new(RATE, 1, shift, PitchQuality::Final);
+            let output = render(shifter, std::slice::from_ref(&input));
+            // Harmonic energy sits on the expected pitch, not between.
+            let on = magnitude_at(&output[0], f0 * 4.0);
+            let off = magnitude_at(&output[0], f0 * 4.5);
+            assert!(on > off * 10.0, "{shift:?}: harmonic {on}, between {off}");
+            let peak = strongest_harmonic(&output[0], f0);
+            assert!(
+                (peak - formant).abs() <= formant * 0.15 + f0,
+                "{shift:?}: strongest harmonic at {peak} Hz, formant expected at {formant} Hz"
+            );
+        }
+    }
+
+    #[test]
+    fn block_size_does_not_change_the_output() {
+        let input = vowel(200.0, 800.0);
+        let shift = VoiceShift {
+            pitch: 3.0,
+            formant: Some(-2.0),
+            tilt: -2.0,
+        };
+        let contour: Vec<f32> = (0..200).map(|i| (i as f32 * 0.1).sin()).collect();
+        let shifter = || {
+            VoiceShifter::new(RATE, 1, shift, PitchQuality::Preview)
+                .with_contour(shift.pitch, contour.clone())
+        };
+        let whole = render(shifter(), std::slice::from_ref(&input));
+
+        let mut shifter = shifter();
+        let mut streamed = Vec::new();
+        for block in input.chunks(777) {
+            streamed.extend(shifter.push(&[block]).remove(0));
+        }
+        streamed.extend(shifter.finish().remove(0));
+        assert_eq!(streamed, whole[0]);
+    }
+}
@@ -0,0 +1,217 @@
+//! One-click cleanup for spoken-word projects: proposes cuts that shorten
+//! long pauses, found by voice activity detection, and remove filler
+//! words, found in the aligned transcript.
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::buffer::AudioBuffer;
+use crate::project::WordTiming;
+use crate::vad::{self, VadConfig};
+
+use super::{CutReason, CutSpan};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CleanupOptions {
+    /// Pauses longer than this many seconds are shortened…
+    pub max_pause: f64,
+    /// …to this many seconds.
+    pub keep_pause: f64,
+    /// Words that are cut wherever they appear, compared without case or
+    /// punctuation.
+    pub fillers: Vec<String>,
+    /// Words that are only fillers when set off from their neighbours by a
+    /// pause or a comma, as in "it was, like, huge" but not "I like it".
+    pub set_off_fillers: Vec<String>,
+    pub vad: VadConfig,
+}
+
+impl Default for CleanupOptions {
+    fn default() -> Self {
+        Self {
+            max_pause: 1.0,
+            keep_pause: 0.4,
+            fillers: ["um", "umm", "uh", "uhh", "er", "erm", "ah", "hmm"]
+                .map(String::from)
+                .into(),
+            set_off_fillers: vec!["like".into()],
+            vad: VadConfig::default(),
+        }
+    }
+}
+
+/// Gap either side of a word that sets it off like a comma would.
+const SET_OFF_GAP: f64 = 0.15;
+
+fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// Cuts for the filler words in `words`, each running from the filler to
+/// the next word so the pause before it is kept as it was.
+fn fillers(words: &[WordTiming], options: &CleanupOptions, duration: f64) -> Vec<CutSpan> {
+    let listed = |list: &[String], word: &str| list.iter().any(|w| normalize(w) == word);
+    let mut cuts = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        let text = normalize(&word.text);
+        let previous = i.checked_sub(1).map(|p| &words[p]);
+        let next = words.get(i + 1);
+        let set_off = || {
+            let after_comma = previous.is_some_and(|p| p.text.trim_end().ends_with(','));
+            let gap_before = previous.map_or(f64::INFINITY, |p| word.start - p.end);
+            let gap_after = next.map_or(f64::INFINITY, |n| n.start - word.end);
+            after_comma
+                || word.text.trim_end().ends_with(',')
+                || (gap_before >= SET_OFF_GAP && gap_after >= SET_OFF_GAP)
+        };
+        if listed(&options.fillers, &text) || (listed(&options.set_off_fillers, &text) && set_off())
+        {
+            cuts.push(CutSpan {
+                start: word.start,
+                end: next.map_or(word.end, |n| n.start).min(duration),
+                reason: CutReason::Filler,
+                text: Some(word.text.trim().to_string()),
+            });
+        }
+    }
+    cuts
+}
+
+/// Cuts that shorten every pause between speech segments, and any silence
+/// before the first or after the last, to `keep_pause`.
+fn pauses(buffer: &AudioBuffer, options: &CleanupOptions) -> Vec<CutSpan> {
+    let duration = buffer.duration();
+    let segments = vad::segments(
+        buffer,
+        &VadConfig {
+            voice_activity_detection: true,
+            ..options.vad
+        },
+    );
+    if segments.is_empty() {
+        return Vec::new();
+    }
+    let keep = options.keep_pause.clamp(0.0, options.max_pause);
+    // Each gap with how much of the pause to keep at its start and end.
+    let mut gaps = vec![(0.0, segments[0].start, 0.0, keep)];
+    gaps.extend(
+        segments
+            .windows(2)
+            .map(|pair| (pair[0].end, pair[1].start, keep / 2.0, keep / 2.0)),
+    );
+    gaps.push((segments[segments.len() - 1].end, duration, keep, 0.0));
+    gaps.into_iter()
+        .filter(|(start, end, ..)| end - start > options.max_pause)
+        .map(|(start, end, keep_at_start, keep_at_end)| CutSpan {
+            start: start + keep_at_start,
+            end: end - keep_at_end,
+            reason: CutReason::Pause,
+            text: None,
+        })
+        .collect()
+}
+
+/// Proposed cuts for `buffer`, sorted and without overlaps, leaving out
+/// anything that overlaps a span in `existing`. `words` are the aligned
+/// transcript of the buffer, if there is one.
+pub fn propose(
+    buffer: &AudioBuffer,
+    words: &[WordTiming],
+    options: &CleanupOptions,
+    existing: &[CutSpan],
+) -> Vec<CutSpan> {
+    let mut proposed = pauses(buffer, options);
+    proposed.extend(fillers(words, options, buffer.duration()));
+    proposed.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut cuts: Vec<CutSpan> = Vec::new();
+    for cut in proposed {
+        if cut.end <= cut.start || existing.iter().any(|e| e.overlaps(&cut)) {
+            continue;
+        }
+        match cuts.last_mut() {
+            // A filler next to a long pause goes with it.
+            Some(last) if last.overlaps(&cut) || last.end == cut.start => {
+                last.end = last.end.max(cut.end);
+                if last.reason == CutReason::Pause {
+                    last.reason = cut.reason;
+                }
+                last.text = last.text.take().or(cut.text);
+            }
+            _ => cuts.push(cut),
+        }
+    }
+    cuts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, start: f64, end: f64) -> WordTiming {
+        WordTiming {
+            text: text.into(),
+            start,
+            end,
+            confidence: None,
+        }
+    }
+
+    /// A buzz that reads as speech over the given spans, silence elsewhere.
+    fn speech(rate: u32, seconds: f64, spans: &[(f64, f64)]) -> AudioBuffer {
+        let signal = (0..(seconds * rate as f64) as usize)
+            .map(|i| {
+                let t = i as f64 / rate as f64;
+                if spans.iter().any(|&(start, end)| (start..end).contains(&t)) {
+                    (1..=10)
+                        .map(|k| (std::f64::consts::TAU * 150.0 * k as f64 * t).sin() / k as f64)
+                        .sum::<f64>() as f32
+                        * 0.1
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        AudioBuffer::new(rate, vec![signal])
+    }
+
+    #[test]
+    fn proposes_pause_and_filler_cuts() {
+        let buffer = speech(16000, 6.0, &[(0.5, 1.5), (1.6, 2.0), (3.5, 4.5)]);
+        let words = [
+            word("So,", 0.5, 0.8),
+            word("um,", 0.9, 1.2),
+            word("I", 1.3, 1.5),
+            word("like", 1.6, 2.0),
+            word("it.", 3.5, 3.8),
+            word("Like,", 3.9, 4.1),
+            word("huge.", 4.3, 4.5),
+        ];
+        let options = CleanupOptions::default();
+        let cuts = propose(&buffer, &words, &options, &[]);
+        let summary: Vec<(CutReason, Option<&str>)> = cuts
+            .iter()
+            .map(|cut| (cut.reason, cut.text.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (CutReason::Filler, Some("um,")),
+                (CutReason::Pause, None),
+                (CutReason::Filler, Some("Like,")),
+                (CutReason::Pause, None),
+            ],
+            "{cuts:?}"
+        );
+        // "um," runs up to "I"; the pause after "like", 1.3 s once VAD's
+        // padding is counted as speech, keeps 0.4 s.
+        assert_eq!((cuts[0].start, cuts[0].end), (0.9, 1.3));
+        assert!((cuts[1].end - cuts[1].start - 0.9).abs() < 0.15, "{cuts:?}");
+        assert!((cuts[3].start - 4.6 - 0.4).abs() < 0.15, "{cuts:?}");
+        assert_eq!(cuts[3].end, 6.0);
+
+        // Nothing is proposed twice.
+        assert!(propose(&buffer, &words, &options, &cuts).is_empty());
+    }
+}
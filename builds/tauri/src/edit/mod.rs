@@ -0,0 +1,152 @@
+//! Non-destructive cuts.
+//!
+//! A cut removes a span of a layer's buffer when the layer is rendered; the
+//! buffer itself is never touched, so removing the cut brings the audio
+//! back. The audio either side of a cut is joined with a short equal-power
+//! crossfade so the splice doesn't click.
+
+pub mod cleanup;
+
+use std::f32::consts::FRAC_PI_2;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::buffer::AudioBuffer;
+
+pub type CutId = u64;
+
+/// Length of the crossfade at each splice, in seconds.
+pub const CROSSFADE: f64 = 0.02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CutReason {
+    /// A pause longer than the cleanup allows.
+    Pause,
+    Filler,
+    Manual,
+}
+
+/// A span to cut, in seconds from the start of the layer's buffer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CutSpan {
+    pub start: f64,
+    pub end: f64,
+    pub reason: CutReason,
+    /// The filler word, for cuts that remove one.
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+impl CutSpan {
+    pub fn overlaps(&self, other: &CutSpan) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cut {
+    pub id: CutId,
+    #[serde(flatten)]
+    pub span: CutSpan,
+}
+
+/// `buffer` with `cuts` taken out. Cuts must be sorted and must not
+/// overlap; the mixer keeps them that way.
+pub fn splice(buffer: &AudioBuffer, cuts: &[Cut]) -> AudioBuffer {
+    let len = buffer.frames();
+    let ranges: Vec<(usize, usize)> = cuts
+        .iter()
+        .filter_map(|cut| buffer.region(cut.span.start, cut.span.end).ok())
+        .map(|range| (range.start, range.end))
+        .collect();
+    let half_fade = ((CROSSFADE * buffer.sample_rate as f64) as usize / 2).max(1);
+
+    // Each crossfade overlaps the `half` frames either side of the cut's
+    // start with those either side of its end, shrunk to fit the audio
+    // kept around it.
+    let mut joins = Vec::with_capacity(ranges.len());
+    let mut kept_from = 0;
+    for (i, &(start, end)) in ranges.iter().enumerate() {
+        let next = ranges.get(i + 1).map_or(len, |next| next.0);
+        let half = half_fade
+            .min((end - start) / 2)
+            .min(start - kept_from)
+            .min((next - end) / 2);
+        joins.push((start, end, half));
+        kept_from = end + half;
+    }
+
+    let channels = buffer
+        .channels
+        .iter()
+        .map(|channel| {
+            let mut out = Vec::with_capacity(len);
+            let mut position = 0;
+            for &(start, end, half) in &joins {
+                out.extend_from_slice(&channel[position..start - half]);
+                let fade = 2 * half;
+                for i in 0..fade {
+                    let angle = (i as f32 + 0.5) / fade as f32 * FRAC_PI_2;
+                    out.push(
+                        channel[start - half + i] * angle.cos()
+                            + channel[end - half + i] * angle.sin(),
+                    );
+                }
+                position = end + half;
+            }
+            out.extend_from_slice(&channel[position..]);
+            out
+        })
+        .collect();
+    AudioBuffer::new(buffer.sample_rate, channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cut(id: CutId, start: f64, end: f64) -> Cut {
+        Cut {
+            id,
+            span: CutSpan {
+                start,
+                end,
+                reason: CutReason::Manual,
+                text: None,
+            },
+        }
+    }
+
+    #[test]
+    fn splices_out_cuts_with_crossfades() {
+        let rate = 1000;
+        // 1.0 for the first 0.4 s, 0.0 to 0.6 s, then 0.5 to 1 s.
+        let signal: Vec<f32> = (0..rate)
+            .map(|i| match i {
+                0..400 => 1.0,
+                400..600 => 0.0,
+                _ => 0.5,
+            })
+            .collect();
+        let buffer = AudioBuffer::new(rate, vec![signal.clone(), signal]);
+
+        let spliced = splice(&buffer, &[cut(1, 0.4, 0.6), cut(2, 0.9, 1.0)]);
+        assert_eq!(spliced.frames(), 700);
+        assert_eq!(spliced.channels[0], spliced.channels[1]);
+        let out = &spliced.channels[0];
+        assert_eq!(out[..390], [1.0; 390]);
+        assert_eq!(out[410..690], [0.5; 280]);
+        // An equal-power fade from 1.0 down to 0.5 across the join.
+        assert!(out[390..410].windows(2).all(|w| w[1] <= w[0] + 0.1));
+        assert!((out[399] - (FRAC_PI_2 * 0.475).cos()).abs() < 0.05);
+        // A cut running to the end has nothing to fade into.
+        assert_eq!(out[699], 0.5);
+
+        assert_eq!(splice(&buffer, &[]), buffer);
+        // A cut from the very start is a plain trim.
+        assert_eq!(splice(&buffer, &[cut(1, 0.0, 0.6)]).channels[0], [0.5; 400]);
+    }
+}
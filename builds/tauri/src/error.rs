@@ -0,0 +1,18 @@
+//! Helpers for errors that cross the IPC boundary into the webview.
+
+use std::fmt::Display;
+
+use serde::ser::{SerializeStruct, Serializer};
+
+/// Serializes an error as `{ kind, message }` so the frontend can branch on
+/// `kind` instead of parsing message text.
+pub fn serialize<S: Serializer>(
+    kind: &'static str,
+    error: &dyn Display,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut state = serializer.serialize_struct("Error", 2)?;
+    state.serialize_field("kind", kind)?;
+    state.serialize_field("message", &error.to_string())?;
+    state.end()
+}
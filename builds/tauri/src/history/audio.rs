@@ -0,0 +1,198 @@
+//! Audio carried by history entries.
+//!
+//! Buffer ids don't survive a save and reopen, so edits hold on to the
+//! audio itself: whole buffers as shared [`Clip`]s for layers that come and
+//! go, and for changed buffers an [`AudioDiff`] of just the frames that
+//! differ. A clip serializes as the SHA-256 of its audio, not the samples:
+//! whoever stores a history stores the audio beside it once per hash
+//! ([`SavedHistory::clips`]) and attaches it again when reading it back
+//! ([`SavedHistory::attach`]).
+//!
+//! [`SavedHistory::clips`]: super::SavedHistory::clips
+//! [`SavedHistory::attach`]: super::SavedHistory::attach
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::audio::buffer::AudioBuffer;
+use crate::mixer::MixerError;
+
+#[derive(Debug, Clone)]
+pub struct Clip {
+    hash: String,
+    /// `None` when read back without its audio attached.
+    audio: Option<Arc<AudioBuffer>>,
+}
+
+impl Clip {
+    pub fn new(audio: Arc<AudioBuffer>) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(audio.sample_rate.to_le_bytes());
+        hasher.update(audio.channel_count().to_le_bytes());
+        for sample in audio.interleaved() {
+            hasher.update(sample.to_le_bytes());
+        }
+        let hash = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Self {
+            hash,
+            audio: Some(audio),
+        }
+    }
+
+    /// Hex SHA-256 of the sample rate, channel count and interleaved
+    /// samples, all little-endian.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    pub fn audio(&self) -> Option<&Arc<AudioBuffer>> {
+        self.audio.as_ref()
+    }
+
+    /// Gives a clip read back without its audio the audio stored for its
+    /// hash.
+    pub fn attach(&mut self, audio: Arc<AudioBuffer>) {
+        self.audio.get_or_insert(audio);
+    }
+
+    /// The audio, which edits need to be applied or taken back.
+    pub fn require(&self) -> Result<&Arc<AudioBuffer>, MixerError> {
+        self.audio
+            .as_ref()
+            .ok_or_else(|| MixerError::Invalid(format!("history audio {} is missing", self.hash)))
+    }
+}
+
+/// Clips are the same audio if their hashes match.
+impl PartialEq for Clip {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncodedClip {
+    hash: String,
+}
+
+impl Serialize for Clip {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EncodedClip {
+            hash: self.hash.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Clip {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let EncodedClip { hash } = EncodedClip::deserialize(deserializer)?;
+        Ok(Clip { hash, audio: None })
+    }
+}
+
+/// The frames that changed between two versions of a buffer: `before`
+/// and `after` both start at frame `start`, and everything around them is
+/// shared. Versions at different rates or channel counts differ whole.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioDiff {
+    pub start: usize,
+    pub before: Clip,
+    pub after: Clip,
+}
+
+fn frames(buffer: &AudioBuffer, start: usize, end: usize) -> AudioBuffer {
+    AudioBuffer::new(
+        buffer.sample_rate,
+        buffer
+            .channels
+            .iter()
+            .map(|channel| channel[start..end].to_vec())
+            .collect(),
+    )
+}
+
+impl AudioDiff {
+    pub fn between(before: &AudioBuffer, after: &AudioBuffer) -> Self {
+        if before.sample_rate != after.sample_rate || before.channels.len() != after.channels.len()
+        {
+            return Self {
+                start: 0,
+                before: Clip::new(Arc::new(before.clone())),
+                after: Clip::new(Arc::new(after.clone())),
+            };
+        }
+        let same = |i: usize, j: usize| {
+            before
+                .channels
+                .iter()
+                .zip(&after.channels)
+                .all(|(b, a)| b[i].to_bits() == a[j].to_bits())
+        };
+        let shortest = before.frames().min(after.frames());
+        let prefix = (0..shortest).take_while(|&i| same(i, i)).count();
+        let suffix = (0..shortest - prefix)
+            .take_while(|&k| same(before.frames() - 1 - k, after.frames() - 1 - k))
+            .count();
+        Self {
+            start: prefix,
+            before: Clip::new(Arc::new(frames(before, prefix, before.frames() - suffix))),
+            after: Clip::new(Arc::new(frames(after, prefix, after.frames() - suffix))),
+        }
+    }
+
+    /// `current`, which must be the `before` version, as the `after` one.
+    pub fn apply(&self, current: &AudioBuffer) -> Result<AudioBuffer, MixerError> {
+        splice(current, self.start, self.before.require()?, self.after.require()?)
+    }
+
+    /// `current`, which must be the `after` version, as the `before` one.
+    pub fn revert(&self, current: &AudioBuffer) -> Result<AudioBuffer, MixerError> {
+        splice(current, self.start, self.after.require()?, self.before.require()?)
+    }
+}
+
+/// Replaces the `old` frames at `start` of `current` with `new`.
+fn splice(
+    current: &AudioBuffer,
+    start: usize,
+    old: &AudioBuffer,
+    new: &AudioBuffer,
+) -> Result<AudioBuffer, MixerError> {
+    let changed =
+        || MixerError::Invalid("the layer's audio was changed outside the history".into());
+    let end = start + old.frames();
+    if current.sample_rate != old.sample_rate
+        || current.channels.len() != old.channels.len()
+        || end > current.frames()
+    {
+        return Err(changed());
+    }
+    if start == 0 && end == current.frames() {
+        // Also covers versions at different rates or channel counts.
+        return Ok(new.clone());
+    }
+    if current.channels.len() != new.channels.len() {
+        return Err(changed());
+    }
+    let channels = current
+        .channels
+        .iter()
+        .zip(&new.channels)
+        .map(|(channel, replacement)| {
+            let mut spliced = Vec::with_capacity(channel.len() - old.frames() + replacement.len());
+            spliced.extend_from_slice(&channel[..start]);
+            spliced.extend_from_slice(replacement);
+            spliced.extend_from_slice(&channel[end..]);
+            spliced
+        })
+        .collect();
+    Ok(AudioBuffer::new(current.sample_rate, channels))
+}
@@ -0,0 +1,779 @@
+//! Undo and redo.
+//!
+//! Every undoable change to the project is recorded as an [`Edit`], a
+//! command object that holds enough to apply the change again or take it
+//! back: effect settings before and after, layer snapshots, and the audio
+//! involved (see [`audio`]). Consecutive drags of the same slider merge
+//! into one entry. The history is saved with the project.
+
+pub mod audio;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::buffer::{AudioBuffer, BufferStore};
+use crate::audio::AudioError;
+use crate::mixer::{Layer, Mixer, MixerError};
+use crate::project::EffectSettings;
+
+use self::audio::{AudioDiff, Clip};
+
+/// Oldest entries are dropped beyond this.
+const MAX_ENTRIES: usize = 500;
+/// Changes to the same slider this close together are one drag.
+const MERGE_WINDOW_MS: u64 = 1000;
+/// Layer fields set with sliders or by dragging.
+const CONTINUOUS_LAYER_FIELDS: &[&str] = &["gain", "pan", "offset"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Edit {
+    Effects {
+        before: EffectSettings,
+        after: EffectSettings,
+    },
+    AddLayer {
+        index: usize,
+        layer: Layer,
+        audio: Option<Clip>,
+    },
+    RemoveLayer {
+        index: usize,
+        layer: Layer,
+        audio: Option<Clip>,
+    },
+    /// Any change to one layer. `audio` is set when its buffer changed;
+    /// otherwise the layer keeps whatever audio it has.
+    UpdateLayer {
+        before: Layer,
+        after: Layer,
+        audio: Option<AudioDiff>,
+    },
+}
+
+fn effect_changes(a: &EffectSettings, b: &EffectSettings) -> Vec<&'static str> {
+    [
+        ("pitch", a.pitch != b.pitch),
+        ("formant", a.formant != b.formant),
+        ("breath", a.breath != b.breath),
+        ("timing", a.timing != b.timing),
+        ("emotion", a.emotion != b.emotion),
+    ]
+    .into_iter()
+    .filter_map(|(field, changed)| changed.then_some(field))
+    .collect()
+}
+
+fn layer_changes(a: &Layer, b: &Layer) -> Vec<&'static str> {
+    [
+        ("name", a.name != b.name),
+        ("audio", a.buffer != b.buffer),
+        ("gain", a.gain != b.gain),
+        ("pan", a.pan != b.pan),
+        ("solo", a.solo != b.solo),
+        ("mute", a.mute != b.mute),
+        ("blend", a.blend != b.blend),
+        ("offset", a.offset != b.offset),
+        ("cuts", a.cuts != b.cuts),
+    ]
+    .into_iter()
+    .filter_map(|(field, changed)| changed.then_some(field))
+    .collect()
+}
+
+fn clip(layer: &Layer, store: &BufferStore) -> Result<Option<Clip>, AudioError> {
+    layer
+        .buffer
+        .map(|id| store.get(id).map(Clip::new))
+        .transpose()
+}
+
+impl Edit {
+    /// Adding `layer` at `index`, with the audio it has in `store`.
+    pub fn add_layer(index: usize, layer: Layer, store: &BufferStore) -> Result<Self, AudioError> {
+        let audio = clip(&layer, store)?;
+        Ok(Edit::AddLayer {
+            index,
+            layer,
+            audio,
+        })
+    }
+
+    pub fn remove_layer(
+        index: usize,
+        layer: Layer,
+        store: &BufferStore,
+    ) -> Result<Self, AudioError> {
+        let audio = clip(&layer, store)?;
+        Ok(Edit::RemoveLayer {
+            index,
+            layer,
+            audio,
+        })
+    }
+
+    /// Changing a layer from `before` to `after`, diffing the audio in
+    /// `store` if the buffer changed.
+    pub fn update_layer(
+        before: Layer,
+        after: Layer,
+        store: &BufferStore,
+    ) -> Result<Self, AudioError> {
+        let audio = match (before.buffer, after.buffer) {
+            (old, Some(new)) if old != Some(new) => {
+                let new = store.get(new)?;
+                let old = match old {
+                    Some(old) => store.get(old)?,
+                    None => AudioBuffer::new(new.sample_rate, vec![Vec::new(); new.channels.len()])
+                        .into(),
+                };
+                Some(AudioDiff::between(&old, &new))
+            }
+            _ => None,
+        };
+        Ok(Edit::UpdateLayer {
+            before,
+            after,
+            audio,
+        })
+    }
+
+    /// The audio the edit holds.
+    pub fn clips(&self) -> Vec<&Clip> {
+        match self {
+            Edit::Effects { .. } => Vec::new(),
+            Edit::AddLayer { audio, .. } | Edit::RemoveLayer { audio, .. } => {
+                audio.iter().collect()
+            }
+            Edit::UpdateLayer { audio, .. } => audio
+                .iter()
+                .flat_map(|diff| [&diff.before, &diff.after])
+                .collect(),
+        }
+    }
+
+    /// Gives clips read back without their audio the audio stored for
+    /// their hashes in `audio`.
+    pub fn attach(&mut self, audio: &HashMap<String, Arc<AudioBuffer>>) {
+        let clips: Vec<&mut Clip> = match self {
+            Edit::Effects { .. } => Vec::new(),
+            Edit::AddLayer { audio, .. } | Edit::RemoveLayer { audio, .. } => {
+                audio.iter_mut().collect()
+            }
+            Edit::UpdateLayer { audio, .. } => audio
+                .iter_mut()
+                .flat_map(|diff| [&mut diff.before, &mut diff.after])
+                .collect(),
+        };
+        for clip in clips {
+            if let Some(found) = audio.get(clip.hash()) {
+                clip.attach(found.clone());
+            }
+        }
+    }
+
+    /// Whether the edit changes nothing.
+    fn is_empty(&self) -> bool {
+        match self {
+            Edit::Effects { before, after } => before == after,
+            Edit::UpdateLayer {
+                before,
+                after,
+                audio,
+            } => before == after && audio.is_none(),
+            Edit::AddLayer { .. } | Edit::RemoveLayer { .. } => false,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Edit::Effects { before, after } => match effect_changes(before, after).as_slice() {
+                ["emotion"] => format!("Set emotion to {}", after.emotion),
+                [field] => format!("Adjust {field}"),
+                _ => "Adjust effects".into(),
+            },
+            Edit::AddLayer { layer, .. } => format!("Add {}", layer.name),
+            Edit::RemoveLayer { layer, .. } => format!("Remove {}", layer.name),
+            Edit::UpdateLayer { before, after, .. } => {
+                match layer_changes(before, after).as_slice() {
+                    ["name"] => format!("Rename {} to {}", before.name, after.name),
+                    ["cuts"] if after.cuts.len() < before.cuts.len() => {
+                        format!("Restore cut on {}", after.name)
+                    }
+                    ["cuts"] => format!("Cut {}", after.name),
+                    ["audio", ..] => format!("Edit audio on {}", after.name),
+                    [field] => format!("Change {} {field}", after.name),
+                    _ => format!("Edit {}", after.name),
+                }
+            }
+        }
+    }
+
+    /// Folds `next` into this edit if both are steps of one slider drag.
+    fn merge(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (
+                Edit::Effects { before, after },
+                Edit::Effects {
+                    before: next_before,
+                    after: next_after,
+                },
+            ) => {
+                let fields = effect_changes(before, after);
+                if after != next_before
+                    || fields != effect_changes(next_before, next_after)
+                    || !matches!(fields.as_slice(), [field] if *field != "emotion")
+                {
+                    return false;
+                }
+                *after = next_after.clone();
+                true
+            }
+            (
+                Edit::UpdateLayer {
+                    before,
+                    after,
+                    audio: None,
+                },
+                Edit::UpdateLayer {
+                    before: next_before,
+                    after: next_after,
+                    audio: None,
+                },
+            ) => {
+                let fields = layer_changes(before, after);
+                if after != next_before
+                    || fields != layer_changes(next_before, next_after)
+                    || !fields.iter().all(|f| CONTINUOUS_LAYER_FIELDS.contains(f))
+                {
+                    return false;
+                }
+                *after = next_after.clone();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn apply(&self, target: &mut Target) -> Result<(), MixerError> {
+        match self {
+            Edit::Effects { after, .. } => *target.effects = after.clone(),
+            Edit::AddLayer {
+                index,
+                layer,
+                audio,
+            } => target.insert(*index, layer, audio)?,
+            Edit::RemoveLayer { layer, .. } => {
+                target.mixer.remove_layer(layer.id)?;
+            }
+            Edit::UpdateLayer { after, audio, .. } => {
+                target.update(after, audio.as_ref().map(|diff| (diff, true)))?
+            }
+        }
+        Ok(())
+    }
+
+    fn revert(&self, target: &mut Target) -> Result<(), MixerError> {
+        match self {
+            Edit::Effects { before, .. } => *target.effects = before.clone(),
+            Edit::AddLayer { layer, .. } => {
+                target.mixer.remove_layer(layer.id)?;
+            }
+            Edit::RemoveLayer {
+                index,
+                layer,
+                audio,
+            } => target.insert(*index, layer, audio)?,
+            Edit::UpdateLayer { before, audio, .. } => {
+                target.update(before, audio.as_ref().map(|diff| (diff, false)))?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What edits act on.
+struct Target<'a> {
+    mixer: &'a Mixer,
+    store: &'a BufferStore,
+    effects: &'a mut EffectSettings,
+}
+
+impl Target<'_> {
+    fn insert(
+        &mut self,
+        index: usize,
+        layer: &Layer,
+        audio: &Option<Clip>,
+    ) -> Result<(), MixerError> {
+        let mut layer = layer.clone();
+        layer.buffer = audio
+            .as_ref()
+            .map(|clip| {
+                Ok::<_, MixerError>(self.store.insert(AudioBuffer::clone(clip.require()?)).id)
+            })
+            .transpose()?;
+        self.mixer.insert_layer(index, layer)?;
+        Ok(())
+    }
+
+    /// Sets a layer to `snapshot`, moving its audio along `diff` forward
+    /// (`true`) or back.
+    fn update(
+        &mut self,
+        snapshot: &Layer,
+        diff: Option<(&AudioDiff, bool)>,
+    ) -> Result<(), MixerError> {
+        let current = self.mixer.layer(snapshot.id)?;
+        let mut layer = snapshot.clone();
+        layer.buffer = match (diff, snapshot.buffer) {
+            (_, None) => None,
+            (None, Some(_)) => current.buffer,
+            (Some((diff, forward)), Some(_)) => {
+                let audio = match current.buffer {
+                    Some(id) => self.store.get(id)?,
+                    None => diff.before.require()?.clone(),
+                };
+                let changed = if forward {
+                    diff.apply(&audio)?
+                } else {
+                    diff.revert(&audio)?
+                };
+                Some(self.store.insert(changed).id)
+            }
+        };
+        self.mixer.set_layer(layer)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub label: String,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub edit: Edit,
+}
+
+/// The history as saved in a project.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SavedHistory {
+    pub entries: Vec<Entry>,
+    /// How many of `entries` are applied; the rest can be redone.
+    pub position: usize,
+}
+
+impl SavedHistory {
+    /// The audio the entries hold, each clip once, to be stored beside
+    /// the history.
+    pub fn clips(&self) -> Vec<&Clip> {
+        let mut clips: Vec<&Clip> = Vec::new();
+        for clip in self.entries.iter().flat_map(|entry| entry.edit.clips()) {
+            if !clips.contains(&clip) {
+                clips.push(clip);
+            }
+        }
+        clips
+    }
+
+    /// Gives clips read back without their audio the audio stored for
+    /// their hashes in `audio`.
+    pub fn attach(&mut self, audio: &HashMap<String, Arc<AudioBuffer>>) {
+        for entry in &mut self.entries {
+            entry.edit.attach(audio);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryItem {
+    pub label: String,
+    pub timestamp: u64,
+    /// Whether the entry is applied, as opposed to undone.
+    pub done: bool,
+}
+
+/// A change to the history, as passed to subscribers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Recorded { edit: Box<Edit>, timestamp: u64 },
+    Undone,
+    Redone,
+}
+
+type Listener = Box<dyn Fn(&Change) + Send + Sync>;
+
+#[derive(Default)]
+struct Inner {
+    saved: SavedHistory,
+    /// Current effect settings, which effect edits act on.
+    effects: EffectSettings,
+}
+
+/// The project's undo history.
+#[derive(Default)]
+pub struct History {
+    inner: Mutex<Inner>,
+    listeners: Mutex<Vec<Listener>>,
+    /// Held from the start of a change to the project until its listeners
+    /// have seen it; see [`hold`](History::hold).
+    changing: Mutex<()>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn inner(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Calls `listener` after every recorded edit, undo and redo, outside
+    /// the history's lock but before [`hold`](Self::hold) lets anyone see
+    /// the change.
+    pub fn subscribe(&self, listener: impl Fn(&Change) + Send + Sync + 'static) {
+        self.listeners
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Box::new(listener));
+    }
+
+    fn notify(&self, change: Change) {
+        for listener in self
+            .listeners
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            listener(&change);
+        }
+    }
+
+    /// Waits for the change being made, if any, to be recorded and seen by
+    /// the listeners, and holds off the next until the guard is dropped.
+    /// While held, the project and the history agree, e.g. for a snapshot.
+    pub fn hold(&self) -> MutexGuard<'_, ()> {
+        self.changing.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replaces the history, e.g. with that of an opened project.
+    pub fn load(&self, mut saved: SavedHistory, effects: EffectSettings) {
+        saved.position = saved.position.min(saved.entries.len());
+        *self.inner() = Inner { saved, effects };
+    }
+
+    pub fn saved(&self) -> SavedHistory {
+        self.inner().saved.clone()
+    }
+
+    pub fn effects(&self) -> EffectSettings {
+        self.inner().effects.clone()
+    }
+
+    /// Sets the effect settings, recording the change.
+    pub fn set_effects(&self, effects: EffectSettings) {
+        let _changing = self.hold();
+        let before = self.effects();
+        self.record_at(
+            Edit::Effects {
+                before,
+                after: effects,
+            },
+            now(),
+        );
+    }
+
+    /// Records an edit that has already been made. Anything undone is
+    /// dropped, as redoing it would no longer make sense.
+    pub fn record(&self, edit: Edit) {
+        let _changing = self.hold();
+        self.record_at(edit, now());
+    }
+
+    /// Makes an edit with `make`, which changes the project and returns the
+    /// [`Edit`] it made, and records it. Nobody holding the history sees
+    /// the project changed but the edit not yet recorded.
+    pub fn edit<T, E>(&self, make: impl FnOnce() -> Result<(T, Edit), E>) -> Result<T, E> {
+        let _changing = self.hold();
+        let (made, edit) = make()?;
+        self.record_at(edit, now());
+        Ok(made)
+    }
+
+    /// Makes an edit read back from a log of recorded ones, e.g. when
+    /// recovering from a crash, and records it at its original time so
+    /// slider drags merge as they first did.
+    pub fn replay(
+        &self,
+        edit: Edit,
+        timestamp: u64,
+        mixer: &Mixer,
+        store: &BufferStore,
+    ) -> Result<(), MixerError> {
+        let _changing = self.hold();
+        {
+            let mut inner = self.inner();
+            edit.apply(&mut Target {
+                mixer,
+                store,
+                effects: &mut inner.effects,
+            })?;
+        }
+        self.record_at(edit, timestamp);
+        Ok(())
+    }
+
+    fn record_at(&self, edit: Edit, timestamp: u64) {
+        if edit.is_empty() {
+            return;
+        }
+        self.push(edit.clone(), timestamp);
+        self.notify(Change::Recorded {
+            edit: Box::new(edit),
+            timestamp,
+        });
+    }
+
+    fn push(&self, edit: Edit, timestamp: u64) {
+        let mut inner = self.inner();
+        if let Edit::Effects { after, .. } = &edit {
+            inner.effects = after.clone();
+        }
+        let saved = &mut inner.saved;
+        saved.entries.truncate(saved.position);
+        if let Some(last) = saved.entries.last_mut() {
+            if timestamp.saturating_sub(last.timestamp) <= MERGE_WINDOW_MS && last.edit.merge(&edit)
+            {
+                last.timestamp = timestamp;
+                last.label = last.edit.label();
+                if last.edit.is_empty() {
+                    saved.entries.pop();
+                }
+                saved.position = saved.entries.len();
+                return;
+            }
+        }
+        saved.entries.push(Entry {
+            label: edit.label(),
+            timestamp,
+            edit,
+        });
+        if saved.entries.len() > MAX_ENTRIES {
+            saved.entries.remove(0);
+        }
+        saved.position = saved.entries.len();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.inner().saved.position > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        let inner = self.inner();
+        inner.saved.position < inner.saved.entries.len()
+    }
+
+    /// Takes back the last applied entry and returns its label, or `None`
+    /// when there is nothing to undo.
+    pub fn undo(&self, mixer: &Mixer, store: &BufferStore) -> Result<Option<String>, MixerError> {
+        let _changing = self.hold();
+        let mut inner = self.inner();
+        let Inner { saved, effects } = &mut *inner;
+        let Some(entry) = saved.position.checked_sub(1).map(|i| &saved.entries[i]) else {
+            return Ok(None);
+        };
+        entry.edit.revert(&mut Target {
+            mixer,
+            store,
+            effects,
+        })?;
+        let label = entry.label.clone();
+        saved.position -= 1;
+        drop(inner);
+        self.notify(Change::Undone);
+        Ok(Some(label))
+    }
+
+    /// Applies the next undone entry again and returns its label, or
+    /// `None` when there is nothing to redo.
+    pub fn redo(&self, mixer: &Mixer, store: &BufferStore) -> Result<Option<String>, MixerError> {
+        let _changing = self.hold();
+        let mut inner = self.inner();
+        let Inner { saved, effects } = &mut *inner;
+        let Some(entry) = saved.entries.get(saved.position) else {
+            return Ok(None);
+        };
+        entry.edit.apply(&mut Target {
+            mixer,
+            store,
+            effects,
+        })?;
+        let label = entry.label.clone();
+        saved.position += 1;
+        drop(inner);
+        self.notify(Change::Redone);
+        Ok(Some(label))
+    }
+
+    pub fn list(&self) -> Vec<HistoryItem> {
+        let inner = self.inner();
+        inner
+            .saved
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| HistoryItem {
+                label: entry.label.clone(),
+                timestamp: entry.timestamp,
+                done: i < inner.saved.position,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mixer::LayerUpdate;
+
+    fn pitch(semitones: f32) -> EffectSettings {
+        EffectSettings {
+            pitch: semitones,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn undoes_and_redoes_effects_layers_and_audio() {
+        let (mixer, store, history) = (Mixer::new(), BufferStore::new(), History::new());
+
+        // A drag of the pitch slider is one entry; a later one is another.
+        for (i, semitones) in [1.0, 2.0, 3.0].into_iter().enumerate() {
+            let before = history.effects();
+            history.record_at(
+                Edit::Effects {
+                    before,
+                    after: pitch(semitones),
+                },
+                1000 + 200 * i as u64,
+            );
+        }
+        history.record_at(
+            Edit::Effects {
+                before: pitch(3.0),
+                after: pitch(4.0),
+            },
+            5000,
+        );
+        assert_eq!(history.list().len(), 2);
+        assert_eq!(history.list()[0].label, "Adjust pitch");
+
+        let voice = store.insert(AudioBuffer::new(100, vec![vec![0.5; 10]]));
+        let layer = mixer.add_layer(None).unwrap();
+        history.record(Edit::add_layer(0, layer.clone(), &store).unwrap());
+        let with_audio = mixer
+            .update_layer(
+                layer.id,
+                LayerUpdate {
+                    buffer: Some(voice.id),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        history.record(Edit::update_layer(layer, with_audio.clone(), &store).unwrap());
+
+        // An edit that changes two frames keeps just those in its diff.
+        let mut samples = vec![0.5; 10];
+        samples[4..6].copy_from_slice(&[0.0, 0.0]);
+        let edited = store.insert(AudioBuffer::new(100, vec![samples.clone()]));
+        let after = mixer
+            .update_layer(
+                with_audio.id,
+                LayerUpdate {
+                    buffer: Some(edited.id),
+                    gain: Some(-6.0),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let edit = Edit::update_layer(with_audio.clone(), after, &store).unwrap();
+        let Edit::UpdateLayer {
+            audio: Some(diff), ..
+        } = &edit
+        else {
+            panic!("no diff in {edit:?}");
+        };
+        assert_eq!((diff.start, diff.before.audio().unwrap().frames()), (4, 2));
+        history.record(edit);
+        assert_eq!(history.list()[4].label, "Edit audio on Voice Layer 1");
+
+        let audio = |mixer: &Mixer, store: &BufferStore| {
+            let id = mixer.layers()[0].buffer.unwrap();
+            store.get(id).unwrap().channels[0].clone()
+        };
+        assert_eq!(
+            history.undo(&mixer, &store).unwrap().as_deref(),
+            Some("Edit audio on Voice Layer 1")
+        );
+        assert_eq!(audio(&mixer, &store), [0.5; 10]);
+        assert_eq!(mixer.layers()[0].gain, 0.0);
+        history.redo(&mixer, &store).unwrap();
+        assert_eq!(audio(&mixer, &store), samples);
+        assert_eq!(mixer.layers()[0].gain, -6.0);
+
+        // Undo everything, then redo it from the saved history alone.
+        while history.undo(&mixer, &store).unwrap().is_some() {}
+        assert!(mixer.layers().is_empty());
+        assert_eq!(history.effects(), EffectSettings::default());
+        assert!(!history.can_undo() && history.can_redo());
+
+        // The saved history refers to its audio by hash; it's stored once
+        // per clip beside it.
+        let saved = history.saved();
+        let json = serde_json::to_string(&saved).unwrap();
+        assert!(!json.contains("samples"));
+        let clips: HashMap<String, Arc<AudioBuffer>> = saved
+            .clips()
+            .into_iter()
+            .map(|clip| (clip.hash().to_string(), clip.audio().unwrap().clone()))
+            .collect();
+        assert_eq!(clips.len(), 4);
+        let mut read_back: SavedHistory = serde_json::from_str(&json).unwrap();
+        let (mixer, store) = (Mixer::new(), BufferStore::new());
+        let unattached = History::new();
+        unattached.load(read_back.clone(), EffectSettings::default());
+        while unattached
+            .redo(&mixer, &store)
+            .is_ok_and(|redone| redone.is_some())
+        {}
+        assert!(unattached.redo(&mixer, &store).is_err());
+
+        read_back.attach(&clips);
+        assert_eq!(read_back, saved);
+        let reopened = History::new();
+        reopened.load(read_back, EffectSettings::default());
+        let (mixer, store) = (Mixer::new(), BufferStore::new());
+        while reopened.redo(&mixer, &store).unwrap().is_some() {}
+        assert_eq!(reopened.effects(), pitch(4.0));
+        assert_eq!(audio(&mixer, &store), samples);
+
+        // A new edit after undoing drops what could have been redone.
+        reopened.undo(&mixer, &store).unwrap();
+        reopened.set_effects(pitch(-1.0));
+        assert!(!reopened.can_redo());
+        assert_eq!(reopened.list().len(), 5);
+    }
+}
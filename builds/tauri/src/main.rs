@@ -1,6 +1,42 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use tauri::Manager;
+mod asr;
+mod audio;
+mod autosave;
+mod captions;
+mod commands;
+mod config;
+mod dsp;
+mod edit;
+mod error;
+mod history;
+mod mixer;
+mod presets;
+mod project;
+mod sandbox;
+mod search;
+mod text;
+mod transcripts;
+mod tts;
+mod vad;
+mod wake;
+
+use tauri::{Manager, RunEvent};
+
+use crate::asr::Recognizer;
+use crate::audio::buffer::BufferStore;
+use crate::audio::capture::{self, CaptureEngine};
+use crate::autosave::Autosave;
+use crate::config::ConfigManager;
+use crate::history::History;
+use crate::mixer::Mixer;
+use crate::presets::PresetStore;
+use crate::sandbox::ProjectRoot;
+use crate::search::SearchIndex;
+use crate::transcripts::TranscriptStore;
+use crate::tts::Synthesizer;
+use crate::vad::VoiceActivity;
+use crate::wake::WakeWords;
 
 #[tauri::command]
 fn get_platform() -> String {
@@ -13,24 +49,127 @@ fn get_version() -> String {
     version.to_string()
 }
 
-#[tauri::command]
-fn read_file(path: String) -> Result<String, String> {
-    std::fs::read_to_string(path).map_err(|e| e.to_string())
-}
-
-#[tauri::command]
-fn write_file(path: String, content: String) -> Result<(), String> {
-    std::fs::write(path, content).map_err(|e| e.to_string())
-}
-
 fn main() {
     tauri::Builder::default()
+        .setup(|app| {
+            let data = app.path().app_data_dir()?;
+            let config = ConfigManager::open(app.path().app_config_dir()?.join("config.json"))?;
+            app.handle()
+                .plugin(commands::config::logger(&config.get().logging))?;
+            if let Some(error) = config.user_file_error() {
+                log::warn!("{error}");
+            }
+            commands::config::watch(app.handle(), &config)?;
+            let auto_save = config.get().storage.auto_save;
+            app.manage(config);
+            app.manage(ProjectRoot::open(data.join("projects"))?);
+            app.manage(PresetStore::open(
+                data.join("presets").join("emotions.json"),
+            )?);
+            app.manage(BufferStore::new());
+            app.manage(Mixer::new());
+            app.manage(History::new());
+            app.manage(Autosave::open(data.join("autosave"), auto_save)?);
+            commands::autosave::start(app.handle())?;
+            app.manage(Recognizer::new(data.join("models").join("asr")));
+            app.manage(TranscriptStore::open(
+                data.join("transcripts").join("transcripts.db"),
+            )?);
+            app.manage(SearchIndex::new());
+            app.manage(Synthesizer::new(data.join("models").join("tts")));
+            app.manage(WakeWords::open(data.join("wake").join("words.json"))?);
+            app.manage(VoiceActivity::new());
+
+            let engine = CaptureEngine::new(capture::default_backend());
+            commands::capture::forward_events(app.handle(), &engine);
+            app.manage(engine);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_platform,
             get_version,
-            read_file,
-            write_file
+            commands::files::read_file,
+            commands::files::write_file,
+            commands::capture::list_input_devices,
+            commands::capture::open_capture,
+            commands::capture::start_capture,
+            commands::capture::stop_capture,
+            commands::capture::close_capture,
+            commands::capture::capture_status,
+            commands::audio::decode_wav,
+            commands::audio::import_wav,
+            commands::audio::encode_wav,
+            commands::audio::export_audio,
+            commands::audio::buffer_info,
+            commands::audio::release_buffer,
+            commands::dsp::reduce_noise,
+            commands::dsp::measure_loudness,
+            commands::dsp::normalize_loudness,
+            commands::dsp::shift_pitch,
+            commands::dsp::shift_formants,
+            commands::dsp::render_voice,
+            commands::dsp::stretch_time,
+            commands::dsp::stretch_region,
+            commands::dsp::detect_breaths,
+            commands::dsp::process_breaths,
+            commands::presets::list_emotion_presets,
+            commands::presets::create_emotion_preset,
+            commands::presets::save_emotion_preset,
+            commands::presets::delete_emotion_preset,
+            commands::presets::apply_emotion_preset,
+            commands::mixer::list_layers,
+            commands::mixer::add_layer,
+            commands::mixer::update_layer,
+            commands::mixer::remove_layer,
+            commands::mixer::mixdown,
+            commands::project::new_project,
+            commands::project::save_project,
+            commands::project::open_project,
+            commands::autosave::set_session,
+            commands::autosave::recover_sessions,
+            commands::autosave::restore_session,
+            commands::autosave::discard_session,
+            commands::asr::list_speech_models,
+            commands::asr::transcribe_buffer,
+            commands::asr::align_transcript,
+            commands::asr::start_recognition,
+            commands::asr::stop_recognition,
+            commands::captions::export_captions,
+            commands::captions::import_captions,
+            commands::transcripts::list_transcripts,
+            commands::transcripts::get_transcript,
+            commands::transcripts::delete_transcript,
+            commands::search::search,
+            commands::tts::list_voices,
+            commands::tts::synthesize_speech,
+            commands::wake::list_wake_words,
+            commands::wake::enroll_wake_word,
+            commands::wake::delete_wake_word,
+            commands::wake::start_wake_word,
+            commands::wake::stop_wake_word,
+            commands::vad::detect_speech_segments,
+            commands::vad::split_speech_segments,
+            commands::vad::start_voice_activity,
+            commands::vad::stop_voice_activity,
+            commands::edit::propose_cleanup,
+            commands::edit::apply_cuts,
+            commands::edit::remove_cut,
+            commands::history::can_undo,
+            commands::history::can_redo,
+            commands::history::undo,
+            commands::history::redo,
+            commands::history::history_list,
+            commands::history::set_effects,
+            commands::config::get_config,
+            commands::config::get_config_overrides,
+            commands::config::set_config,
+            commands::config::reset_config
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                app.state::<Autosave>().close();
+            }
+        });
 }
@@ -0,0 +1,602 @@
+//! Multitrack layers and their mixdown.
+//!
+//! A layer places a project buffer on the timeline with its own gain, pan
+//! and blend mode. Layers are combined in list order, each one onto the
+//! mix of the layers before it, into a stereo result at the highest sample
+//! rate among them.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::audio::buffer::{AudioBuffer, BufferId};
+use crate::audio::{resample, AudioError};
+use crate::dsp::{exciter, mixdown};
+use crate::edit::{self, Cut, CutId, CutSpan};
+
+pub type LayerId = u64;
+
+/// Allowed layer gain, in dB. Silence is what mute is for.
+const GAIN_RANGE_DB: (f32, f32) = (-60.0, 12.0);
+/// Longest mix rendered, in seconds, so a stray offset can't ask for more
+/// memory than the machine has.
+const MAX_MIX_SECONDS: f64 = 2.0 * 60.0 * 60.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MixerError {
+    #[error("no layer with id {0}")]
+    UnknownLayer(LayerId),
+    #[error("no cut with id {0}")]
+    UnknownCut(CutId),
+    #[error("invalid layer settings: {0}")]
+    Invalid(String),
+    #[error("nothing to mix: no audible layer has audio")]
+    NothingToMix,
+    #[error("the mix would be {seconds:.0} s long, more than the {limit:.0} s allowed")]
+    TooLong { seconds: f64, limit: f64 },
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+}
+
+impl MixerError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MixerError::UnknownLayer(_) => "unknownLayer",
+            MixerError::UnknownCut(_) => "unknownCut",
+            MixerError::Invalid(_) => "invalidLayer",
+            MixerError::NothingToMix => "nothingToMix",
+            MixerError::TooLong { .. } => "mixTooLong",
+            MixerError::Audio(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for MixerError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// How a layer combines with the mix of the layers before it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    /// Summed in like a mixer channel, with a constant-power pan law: a
+    /// centred mono layer sits 3 dB down on each side.
+    #[default]
+    Normal,
+    /// Summed in sample for sample with a linear pan law, so a centred mono
+    /// layer lands at full level on both sides and a layer stacked on an
+    /// identical one doubles it exactly.
+    Add,
+    /// Multiplies the mix over the layer's span (ring modulation); the mix
+    /// outside the span is left alone.
+    Multiply,
+    /// Not heard directly: harmonics generated from the layer's high band
+    /// are added to the mix, brightening it like a parallel exciter.
+    Exciter,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Layer {
+    pub id: LayerId,
+    pub name: String,
+    /// Audio on the layer; `None` until something is recorded or assigned.
+    pub buffer: Option<BufferId>,
+    /// Gain in dB.
+    pub gain: f32,
+    /// -1.0 (left) to 1.0 (right).
+    pub pan: f32,
+    pub solo: bool,
+    pub mute: bool,
+    pub blend: BlendMode,
+    /// Start of the layer on the timeline, in seconds.
+    pub offset: f64,
+    /// Spans of the buffer left out when the layer plays, sorted.
+    #[serde(default)]
+    pub cuts: Vec<Cut>,
+}
+
+/// Fields to change on a layer; absent fields are kept.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LayerUpdate {
+    pub name: Option<String>,
+    pub buffer: Option<BufferId>,
+    pub gain: Option<f32>,
+    pub pan: Option<f32>,
+    pub solo: Option<bool>,
+    pub mute: Option<bool>,
+    pub blend: Option<BlendMode>,
+    pub offset: Option<f64>,
+}
+
+impl Layer {
+    fn validate(&self) -> Result<(), MixerError> {
+        let invalid = |message: String| Err(MixerError::Invalid(message));
+        if self.name.trim().is_empty() {
+            return invalid("name must not be empty".into());
+        }
+        if !(GAIN_RANGE_DB.0..=GAIN_RANGE_DB.1).contains(&self.gain) {
+            return invalid(format!(
+                "gain must be between {} and {} dB, got {}",
+                GAIN_RANGE_DB.0, GAIN_RANGE_DB.1, self.gain
+            ));
+        }
+        if !(-1.0..=1.0).contains(&self.pan) {
+            return invalid(format!("pan must be between -1 and 1, got {}", self.pan));
+        }
+        if !(0.0..=MAX_MIX_SECONDS).contains(&self.offset) {
+            return invalid(format!(
+                "offset must be between 0 and {MAX_MIX_SECONDS} s, got {}",
+                self.offset
+            ));
+        }
+        Ok(())
+    }
+
+    /// Per-side gains for a mono source.
+    fn mono_gains(&self) -> [f32; 2] {
+        let linear = 10f32.powf(self.gain / 20.0);
+        match self.blend {
+            BlendMode::Add => [
+                linear * (1.0 - self.pan).min(1.0),
+                linear * (1.0 + self.pan).min(1.0),
+            ],
+            _ => {
+                let angle = (self.pan + 1.0) * std::f32::consts::FRAC_PI_4;
+                [linear * angle.cos(), linear * angle.sin()]
+            }
+        }
+    }
+
+    /// Per-side gains for a stereo source, where pan acts as balance.
+    fn stereo_gains(&self) -> [f32; 2] {
+        let linear = 10f32.powf(self.gain / 20.0);
+        [
+            linear * (1.0 - self.pan).min(1.0),
+            linear * (1.0 + self.pan).min(1.0),
+        ]
+    }
+}
+
+#[derive(Default)]
+struct MixerInner {
+    next_id: LayerId,
+    next_cut: CutId,
+    layers: Vec<Layer>,
+}
+
+/// The project's layers, in mix order.
+#[derive(Default)]
+pub struct Mixer {
+    inner: Mutex<MixerInner>,
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn inner(&self) -> MutexGuard<'_, MixerInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn layers(&self) -> Vec<Layer> {
+        self.inner().layers.clone()
+    }
+
+    /// Appends an empty layer at unity gain, centred, in normal mode.
+    pub fn add_layer(&self, name: Option<String>) -> Result<Layer, MixerError> {
+        let mut inner = self.inner();
+        let id = inner.next_id + 1;
+        let layer = Layer {
+            id,
+            name: name.unwrap_or_else(|| format!("Voice Layer {}", inner.layers.len() + 1)),
+            buffer: None,
+            gain: 0.0,
+            pan: 0.0,
+            solo: false,
+            mute: false,
+            blend: BlendMode::Normal,
+            offset: 0.0,
+            cuts: Vec::new(),
+        };
+        layer.validate()?;
+        inner.next_id = id;
+        inner.layers.push(layer.clone());
+        Ok(layer)
+    }
+
+    /// Applies `update` to a layer, all or nothing.
+    pub fn update_layer(&self, id: LayerId, update: LayerUpdate) -> Result<Layer, MixerError> {
+        let mut inner = self.inner();
+        let slot = inner
+            .layers
+            .iter_mut()
+            .find(|layer| layer.id == id)
+            .ok_or(MixerError::UnknownLayer(id))?;
+        let mut layer = slot.clone();
+        if let Some(name) = update.name {
+            layer.name = name.trim().into();
+        }
+        if update
+            .buffer
+            .is_some_and(|buffer| layer.buffer != Some(buffer))
+        {
+            // Cuts are times in the old audio.
+            layer.cuts.clear();
+        }
+        layer.buffer = update.buffer.or(layer.buffer);
+        layer.gain = update.gain.unwrap_or(layer.gain);
+        layer.pan = update.pan.unwrap_or(layer.pan);
+        layer.solo = update.solo.unwrap_or(layer.solo);
+        layer.mute = update.mute.unwrap_or(layer.mute);
+        layer.blend = update.blend.unwrap_or(layer.blend);
+        layer.offset = update.offset.unwrap_or(layer.offset);
+        layer.validate()?;
+        *slot = layer.clone();
+        Ok(layer)
+    }
+
+    pub fn layer(&self, id: LayerId) -> Result<Layer, MixerError> {
+        self.inner()
+            .layers
+            .iter()
+            .find(|layer| layer.id == id)
+            .cloned()
+            .ok_or(MixerError::UnknownLayer(id))
+    }
+
+    /// Replaces the layer with the same id as `layer`, cuts and all.
+    pub fn set_layer(&self, layer: Layer) -> Result<Layer, MixerError> {
+        layer.validate()?;
+        let mut inner = self.inner();
+        let slot = inner
+            .layers
+            .iter_mut()
+            .find(|slot| slot.id == layer.id)
+            .ok_or(MixerError::UnknownLayer(layer.id))?;
+        *slot = layer.clone();
+        inner.next_cut = inner.next_cut.max(cut_ids(&layer));
+        Ok(layer)
+    }
+
+    /// Puts a layer back at `index` in the mix order, keeping its id.
+    pub fn insert_layer(&self, index: usize, layer: Layer) -> Result<Layer, MixerError> {
+        layer.validate()?;
+        let mut inner = self.inner();
+        if inner.layers.iter().any(|other| other.id == layer.id) {
+            return Err(MixerError::Invalid(format!(
+                "there is already a layer with id {}",
+                layer.id
+            )));
+        }
+        inner.next_id = inner.next_id.max(layer.id);
+        inner.next_cut = inner.next_cut.max(cut_ids(&layer));
+        let index = index.min(inner.layers.len());
+        inner.layers.insert(index, layer.clone());
+        Ok(layer)
+    }
+
+    /// Removes a layer, returning it with the index it had.
+    pub fn remove_layer(&self, id: LayerId) -> Result<(usize, Layer), MixerError> {
+        let mut inner = self.inner();
+        let index = inner
+            .layers
+            .iter()
+            .position(|layer| layer.id == id)
+            .ok_or(MixerError::UnknownLayer(id))?;
+        Ok((index, inner.layers.remove(index)))
+    }
+
+    /// Adds cuts to a layer, all or nothing. Cuts may not overlap each
+    /// other or the layer's existing cuts.
+    pub fn add_cuts(&self, id: LayerId, spans: Vec<CutSpan>) -> Result<Layer, MixerError> {
+        let mut inner = self.inner();
+        let mut next_cut = inner.next_cut;
+        let slot = inner
+            .layers
+            .iter_mut()
+            .find(|layer| layer.id == id)
+            .ok_or(MixerError::UnknownLayer(id))?;
+        let mut cuts = slot.cuts.clone();
+        for span in spans {
+            if !(span.start.is_finite() && span.start >= 0.0 && span.end > span.start) {
+                return Err(MixerError::Invalid(format!(
+                    "cut {:.3}s..{:.3}s is empty or negative",
+                    span.start, span.end
+                )));
+            }
+            if cuts.iter().any(|cut| cut.span.overlaps(&span)) {
+                return Err(MixerError::Invalid(format!(
+                    "cut {:.3}s..{:.3}s overlaps another cut",
+                    span.start, span.end
+                )));
+            }
+            next_cut += 1;
+            cuts.push(Cut { id: next_cut, span });
+        }
+        cuts.sort_by(|a, b| a.span.start.total_cmp(&b.span.start));
+        slot.cuts = cuts;
+        let layer = slot.clone();
+        inner.next_cut = next_cut;
+        Ok(layer)
+    }
+
+    /// Removes a cut, bringing back the audio it left out.
+    pub fn remove_cut(&self, id: LayerId, cut_id: CutId) -> Result<Layer, MixerError> {
+        let mut inner = self.inner();
+        let layer = inner
+            .layers
+            .iter_mut()
+            .find(|layer| layer.id == id)
+            .ok_or(MixerError::UnknownLayer(id))?;
+        let index = layer
+            .cuts
+            .iter()
+            .position(|cut| cut.id == cut_id)
+            .ok_or(MixerError::UnknownCut(cut_id))?;
+        layer.cuts.remove(index);
+        Ok(layer.clone())
+    }
+
+    /// Swaps in a whole new set of layers, e.g. those of an opened project.
+    pub fn replace(&self, layers: Vec<Layer>) {
+        let mut inner = self.inner();
+        inner.next_id = layers.iter().map(|layer| layer.id).max().unwrap_or(0);
+        inner.next_cut = layers.iter().map(cut_ids).max().unwrap_or(0);
+        inner.layers = layers;
+    }
+
+    /// Layers that would be heard, in mix order: unmuted, and soloed if
+    /// any layer is.
+    pub fn audible(&self) -> Vec<Layer> {
+        let layers = self.layers();
+        let soloing = layers.iter().any(|layer| layer.solo && !layer.mute);
+        layers
+            .into_iter()
+            .filter(|layer| !layer.mute && (layer.solo || !soloing))
+            .collect()
+    }
+}
+
+/// The highest cut id on `layer`, or 0.
+fn cut_ids(layer: &Layer) -> CutId {
+    layer.cuts.iter().map(|cut| cut.id).max().unwrap_or(0)
+}
+
+/// A layer as a stereo pair at the mix rate, before its per-side gains.
+struct Source<'a> {
+    layer: &'a Layer,
+    /// First frame on the timeline.
+    start: usize,
+    pair: [Vec<f32>; 2],
+    gains: [f32; 2],
+}
+
+/// Mixes `layers`, each paired with its audio, into one stereo buffer.
+pub fn render(layers: &[(Layer, Arc<AudioBuffer>)]) -> Result<AudioBuffer, MixerError> {
+    let sample_rate = layers
+        .iter()
+        .map(|(_, buffer)| buffer.sample_rate)
+        .max()
+        .ok_or(MixerError::NothingToMix)?;
+    let rate = sample_rate as f64;
+
+    let sources: Vec<Source> = layers
+        .iter()
+        .map(|(layer, buffer)| {
+            let buffer = if layer.cuts.is_empty() {
+                Arc::clone(buffer)
+            } else {
+                Arc::new(edit::splice(buffer, &layer.cuts))
+            };
+            let buffer = if buffer.sample_rate == sample_rate {
+                buffer
+            } else {
+                Arc::new(resample::resample(&buffer, sample_rate))
+            };
+            let start = (layer.offset * rate).round() as usize;
+            let (pair, gains) = match buffer.channels.as_slice() {
+                [left, right] => ([left.clone(), right.clone()], layer.stereo_gains()),
+                channels => {
+                    let mono = mixdown(channels);
+                    ([mono.clone(), mono], layer.mono_gains())
+                }
+            };
+            Source {
+                layer,
+                start,
+                pair,
+                gains,
+            }
+        })
+        .collect();
+
+    let len = sources
+        .iter()
+        .map(|source| source.start.saturating_add(source.pair[0].len()))
+        .max()
+        .unwrap_or(0);
+    if len as f64 > MAX_MIX_SECONDS * rate {
+        return Err(MixerError::TooLong {
+            seconds: len as f64 / rate,
+            limit: MAX_MIX_SECONDS,
+        });
+    }
+    let mut mix = [vec![0.0f32; len], vec![0.0f32; len]];
+    for Source {
+        layer,
+        start,
+        pair,
+        gains,
+    } in sources
+    {
+        for ((out, source), gain) in mix.iter_mut().zip(pair).zip(gains) {
+            let span = &mut out[start..start + source.len()];
+            match layer.blend {
+                BlendMode::Normal | BlendMode::Add => {
+                    span.iter_mut()
+                        .zip(&source)
+                        .for_each(|(o, s)| *o += s * gain);
+                }
+                BlendMode::Multiply => {
+                    span.iter_mut()
+                        .zip(&source)
+                        .for_each(|(o, s)| *o *= s * gain);
+                }
+                BlendMode::Exciter => {
+                    // Gain sets how much is added, not how hard the
+                    // exciter is driven.
+                    let generated = exciter::harmonics(sample_rate, &source);
+                    span.iter_mut()
+                        .zip(&generated)
+                        .for_each(|(o, s)| *o += s * gain);
+                }
+            }
+        }
+    }
+    Ok(AudioBuffer::new(sample_rate, mix.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(rate: u32, channels: Vec<Vec<f32>>) -> Arc<AudioBuffer> {
+        Arc::new(AudioBuffer::new(rate, channels))
+    }
+
+    #[test]
+    fn solo_and_mute_pick_the_audible_layers() {
+        let mixer = Mixer::new();
+        let host = mixer.add_layer(Some("Host Voice".into())).unwrap();
+        let guest = mixer.add_layer(None).unwrap();
+        let music = mixer.add_layer(None).unwrap();
+        assert_eq!(guest.name, "Voice Layer 2");
+        assert_eq!(mixer.audible().len(), 3);
+
+        let solo = LayerUpdate {
+            solo: Some(true),
+            ..LayerUpdate::default()
+        };
+        mixer.update_layer(guest.id, solo.clone()).unwrap();
+        mixer.update_layer(music.id, solo).unwrap();
+        let mute = LayerUpdate {
+            mute: Some(true),
+            ..LayerUpdate::default()
+        };
+        mixer.update_layer(music.id, mute).unwrap();
+        let audible: Vec<LayerId> = mixer.audible().iter().map(|l| l.id).collect();
+        assert_eq!(audible, [guest.id]);
+
+        let bad_pan = LayerUpdate {
+            pan: Some(2.0),
+            gain: Some(-6.0),
+            ..LayerUpdate::default()
+        };
+        assert!(matches!(
+            mixer.update_layer(host.id, bad_pan),
+            Err(MixerError::Invalid(_))
+        ));
+        assert_eq!(mixer.layers()[0].gain, 0.0);
+        let far = LayerUpdate {
+            offset: Some(MAX_MIX_SECONDS + 1.0),
+            ..LayerUpdate::default()
+        };
+        assert!(matches!(
+            mixer.update_layer(host.id, far),
+            Err(MixerError::Invalid(_))
+        ));
+        mixer.remove_layer(host.id).unwrap();
+        assert!(matches!(
+            mixer.remove_layer(host.id),
+            Err(MixerError::UnknownLayer(_))
+        ));
+    }
+
+    #[test]
+    fn cuts_are_added_and_removed_one_by_one() {
+        let mixer = Mixer::new();
+        let layer = mixer.add_layer(None).unwrap();
+        let span = |start, end| CutSpan {
+            start,
+            end,
+            reason: edit::CutReason::Pause,
+            text: None,
+        };
+        let cut = mixer
+            .add_cuts(layer.id, vec![span(2.0, 3.0), span(0.5, 1.0)])
+            .unwrap();
+        let starts: Vec<f64> = cut.cuts.iter().map(|c| c.span.start).collect();
+        assert_eq!(starts, [0.5, 2.0]);
+        assert!(matches!(
+            mixer.add_cuts(layer.id, vec![span(4.0, 5.0), span(2.5, 3.5)]),
+            Err(MixerError::Invalid(_))
+        ));
+        assert_eq!(mixer.layers()[0].cuts.len(), 2);
+
+        let first = cut.cuts[0].id;
+        let restored = mixer.remove_cut(layer.id, first).unwrap();
+        assert_eq!(restored.cuts.len(), 1);
+        assert!(matches!(
+            mixer.remove_cut(layer.id, first),
+            Err(MixerError::UnknownCut(_))
+        ));
+        let again = mixer.add_cuts(layer.id, vec![span(0.5, 1.0)]).unwrap();
+        assert!(again.cuts[0].id > cut.cuts[1].id);
+    }
+
+    #[test]
+    fn blend_modes_combine_in_order() {
+        let rate = 8000;
+        let layer = |id, blend, pan, offset| Layer {
+            id,
+            name: format!("Layer {id}"),
+            buffer: None,
+            gain: 0.0,
+            pan,
+            solo: false,
+            mute: false,
+            blend,
+            offset,
+            cuts: Vec::new(),
+        };
+        let ones = buffer(rate, vec![vec![0.5; 8]]);
+
+        // Constant-power centre, then a hard-left linear add.
+        let mixed = render(&[
+            (layer(1, BlendMode::Normal, 0.0, 0.0), ones.clone()),
+            (layer(2, BlendMode::Add, -1.0, 0.0), ones.clone()),
+        ])
+        .unwrap();
+        let centre = 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        assert!((mixed.channels[0][0] - (centre + 0.5)).abs() < 1e-6);
+        assert!((mixed.channels[1][0] - centre).abs() < 1e-6);
+
+        // Multiplying by a later, shorter layer only touches its span.
+        let stereo = buffer(rate, vec![vec![0.5; 8], vec![0.25; 8]]);
+        let mixed = render(&[
+            (layer(1, BlendMode::Add, 0.0, 0.0), stereo),
+            (
+                layer(2, BlendMode::Multiply, 0.0, 4.0 / rate as f64),
+                buffer(rate, vec![vec![0.5; 2], vec![0.5; 2]]),
+            ),
+        ])
+        .unwrap();
+        assert_eq!(mixed.frames(), 8);
+        assert_eq!(mixed.channels[0][..6], [0.5, 0.5, 0.5, 0.5, 0.25, 0.25]);
+        assert_eq!(mixed.channels[1][3..6], [0.25, 0.125, 0.125]);
+
+        assert!(matches!(render(&[]), Err(MixerError::NothingToMix)));
+
+        // Layers loaded with a project skip validation, so the mix length
+        // is checked again before anything is allocated.
+        assert!(matches!(
+            render(&[(layer(1, BlendMode::Normal, 0.0, 1e12), ones)]),
+            Err(MixerError::TooLong { .. })
+        ));
+    }
+}
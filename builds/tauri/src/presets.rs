@@ -0,0 +1,296 @@
+//! Emotion presets: a built-in set plus user presets saved in app data.
+//!
+//! User presets live in a single JSON file that is rewritten atomically on
+//! every change. Built-ins are compiled in and can't be overwritten, so an
+//! update can retune them without migrating anyone's file.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::audio::AudioError;
+use crate::dsp::emotion::EmotionParams;
+use crate::sandbox::write_atomic;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PresetError {
+    #[error("no preset with id {0:?}")]
+    NotFound(String),
+    #[error("the built-in preset {0:?} can't be changed")]
+    BuiltIn(String),
+    #[error("invalid preset: {0}")]
+    Invalid(String),
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+}
+
+impl PresetError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PresetError::NotFound(_) => "presetNotFound",
+            PresetError::BuiltIn(_) => "builtInPreset",
+            PresetError::Invalid(_) => "invalidPreset",
+            PresetError::Io { .. } => "io",
+            PresetError::Audio(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for PresetError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmotionPreset {
+    pub id: String,
+    pub name: String,
+    /// Set on the compiled-in presets; never read back from the frontend
+    /// or from disk.
+    #[serde(default, skip_deserializing)]
+    pub built_in: bool,
+    #[serde(flatten)]
+    pub params: EmotionParams,
+}
+
+/// The emotions of the UI's emotion picker.
+fn built_in() -> Vec<EmotionPreset> {
+    let preset = |id: &str, name: &str, pitch, warmth, breath_gain, prosody| EmotionPreset {
+        id: id.into(),
+        name: name.into(),
+        built_in: true,
+        params: EmotionParams {
+            pitch,
+            warmth,
+            breath_gain,
+            prosody,
+        },
+    };
+    vec![
+        preset("neutral", "Neutral", 0.0, 50.0, 0.0, 1.0),
+        preset("happy", "Happy", 2.0, 60.0, 2.0, 1.3),
+        preset("sad", "Sad", -3.0, 40.0, -2.0, 0.7),
+        preset("angry", "Angry", -1.0, 70.0, -1.0, 1.2),
+        preset("excited", "Excited", 3.0, 55.0, 4.0, 1.5),
+    ]
+}
+
+/// Lowercase ASCII letters, digits and dashes from `name`.
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.ends_with('-') && !slug.is_empty() {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+fn validate(preset: &EmotionPreset) -> Result<(), PresetError> {
+    if preset.name.trim().is_empty() {
+        return Err(PresetError::Invalid("name must not be empty".into()));
+    }
+    preset.params.validate().map_err(PresetError::Invalid)
+}
+
+pub struct PresetStore {
+    path: PathBuf,
+    user: Mutex<Vec<EmotionPreset>>,
+}
+
+impl PresetStore {
+    /// Loads user presets from `path`. A missing file means there are none;
+    /// an unreadable one is moved aside rather than blocking startup.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, PresetError> {
+        let path = path.into();
+        let user = match fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<Vec<EmotionPreset>>(&bytes) {
+                Ok(presets) => presets,
+                Err(error) => {
+                    let aside = path.with_extension("json.corrupt");
+                    log::warn!("{}: {error}; moved to {}", path.display(), aside.display());
+                    fs::rename(&path, &aside).map_err(|source| PresetError::Io {
+                        path: path.clone(),
+                        source,
+                    })?;
+                    Vec::new()
+                }
+            },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(source) => return Err(PresetError::Io { path, source }),
+        };
+        Ok(Self {
+            path,
+            user: Mutex::new(user),
+        })
+    }
+
+    fn user(&self) -> std::sync::MutexGuard<'_, Vec<EmotionPreset>> {
+        self.user.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Built-in presets first, then the user's in creation order.
+    pub fn list(&self) -> Vec<EmotionPreset> {
+        let mut presets = built_in();
+        presets.extend(self.user().iter().cloned());
+        presets
+    }
+
+    pub fn get(&self, id: &str) -> Result<EmotionPreset, PresetError> {
+        self.list()
+            .into_iter()
+            .find(|p| p.id == id)
+            .ok_or_else(|| PresetError::NotFound(id.into()))
+    }
+
+    /// Adds a user preset with an id derived from `name`.
+    pub fn create(&self, name: &str, params: EmotionParams) -> Result<EmotionPreset, PresetError> {
+        let base = slug(name);
+        if base.is_empty() {
+            return Err(PresetError::Invalid(format!(
+                "name {name:?} needs at least one letter or digit"
+            )));
+        }
+        let mut user = self.user();
+        let taken = |id: &str| built_in().iter().chain(user.iter()).any(|p| p.id == id);
+        let id = (1..)
+            .map(|n| match n {
+                1 => base.clone(),
+                n => format!("{base}-{n}"),
+            })
+            .find(|id| !taken(id))
+            .expect("ids are unbounded");
+        let preset = EmotionPreset {
+            id,
+            name: name.trim().into(),
+            built_in: false,
+            params,
+        };
+        validate(&preset)?;
+
+        let mut updated = user.clone();
+        updated.push(preset.clone());
+        write(&self.path, &updated)?;
+        *user = updated;
+        Ok(preset)
+    }
+
+    /// Replaces the user preset with the same id.
+    pub fn save(&self, preset: EmotionPreset) -> Result<EmotionPreset, PresetError> {
+        if built_in().iter().any(|p| p.id == preset.id) {
+            return Err(PresetError::BuiltIn(preset.id));
+        }
+        validate(&preset)?;
+        let mut user = self.user();
+        let index = user
+            .iter()
+            .position(|p| p.id == preset.id)
+            .ok_or_else(|| PresetError::NotFound(preset.id.clone()))?;
+        let mut updated = user.clone();
+        updated[index] = preset.clone();
+        write(&self.path, &updated)?;
+        *user = updated;
+        Ok(preset)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), PresetError> {
+        if built_in().iter().any(|p| p.id == id) {
+            return Err(PresetError::BuiltIn(id.into()));
+        }
+        let mut user = self.user();
+        let mut updated = user.clone();
+        updated.retain(|p| p.id != id);
+        if updated.len() == user.len() {
+            return Err(PresetError::NotFound(id.into()));
+        }
+        write(&self.path, &updated)?;
+        *user = updated;
+        Ok(())
+    }
+}
+
+fn write(path: &Path, presets: &[EmotionPreset]) -> Result<(), PresetError> {
+    let json = serde_json::to_vec_pretty(presets).expect("presets always serialize");
+    write_atomic(path, &json).map_err(|source| PresetError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("presets-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn user_presets_round_trip_through_disk() {
+        let path = temp_dir("round-trip").join("emotions.json");
+        let store = PresetStore::open(&path).unwrap();
+        let params = EmotionParams {
+            pitch: 1.5,
+            ..EmotionParams::default()
+        };
+        let created = store.create("Calm Narrator", params).unwrap();
+        assert_eq!(created.id, "calm-narrator");
+        assert_eq!(
+            store.create("calm narrator!", params).unwrap().id,
+            "calm-narrator-2"
+        );
+
+        let mut edited = created.clone();
+        edited.params.warmth = 80.0;
+        store.save(edited.clone()).unwrap();
+        store.delete("calm-narrator-2").unwrap();
+
+        let reopened = PresetStore::open(&path).unwrap();
+        assert_eq!(reopened.get("calm-narrator").unwrap(), edited);
+        assert!(matches!(
+            reopened.get("calm-narrator-2"),
+            Err(PresetError::NotFound(_))
+        ));
+        assert_eq!(reopened.list().len(), built_in().len() + 1);
+    }
+
+    #[test]
+    fn built_ins_are_read_only_and_params_are_checked() {
+        let store = PresetStore::open(temp_dir("read-only").join("emotions.json")).unwrap();
+        let happy = store.get("happy").unwrap();
+        assert!(happy.built_in);
+        assert!(matches!(store.save(happy), Err(PresetError::BuiltIn(_))));
+        assert!(matches!(
+            store.delete("neutral"),
+            Err(PresetError::BuiltIn(_))
+        ));
+        assert_eq!(
+            store.create("Happy", EmotionParams::default()).unwrap().id,
+            "happy-2"
+        );
+
+        let loud = EmotionParams {
+            warmth: 140.0,
+            ..EmotionParams::default()
+        };
+        assert!(matches!(
+            store.create("Too warm", loud),
+            Err(PresetError::Invalid(_))
+        ));
+    }
+}
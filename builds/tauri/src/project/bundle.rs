@@ -0,0 +1,112 @@
+//! The single-file container a project is saved in.
+//!
+//! ```text
+//! magic    8 bytes   b"VOSPROJ\0"
+//! format   u32 LE    container layout version
+//! count    u32 LE    number of entries
+//! entries  count x { name length u16 LE, name (UTF-8), data length u64 LE, data }
+//! checksum 32 bytes  SHA-256 of everything above
+//! ```
+//!
+//! The checksum is verified before anything is parsed, so a truncated or
+//! bit-flipped file is rejected as a whole rather than half loaded.
+
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+
+use super::ProjectError;
+
+pub const MAGIC: &[u8; 8] = b"VOSPROJ\0";
+/// Layout version of the container itself, independent of the schema of
+/// the project document inside it.
+const FORMAT_VERSION: u32 = 1;
+const CHECKSUM_LEN: usize = 32;
+
+/// Serializes `entries` into a bundle.
+pub fn write(entries: &BTreeMap<String, Vec<u8>>) -> Vec<u8> {
+    let size = entries
+        .iter()
+        .map(|(name, data)| 2 + name.len() + 8 + data.len())
+        .sum::<usize>();
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 8 + size + CHECKSUM_LEN);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for (name, data) in entries {
+        bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(data);
+    }
+    let checksum = Sha256::digest(&bytes);
+    bytes.extend_from_slice(&checksum);
+    bytes
+}
+
+/// Cursor over the verified body of a bundle.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ProjectError> {
+        if self.bytes.len() < len {
+            return Err(ProjectError::Corrupt("unexpected end of file".into()));
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, ProjectError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ProjectError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProjectError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+/// Verifies and unpacks a bundle into its named entries.
+pub fn read(bytes: &[u8]) -> Result<BTreeMap<String, Vec<u8>>, ProjectError> {
+    if bytes.len() < MAGIC.len() + 8 + CHECKSUM_LEN || !bytes.starts_with(MAGIC) {
+        return Err(ProjectError::Corrupt("not a project file".into()));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if Sha256::digest(body).as_slice() != checksum {
+        return Err(ProjectError::ChecksumMismatch);
+    }
+
+    let mut reader = Reader {
+        bytes: &body[MAGIC.len()..],
+    };
+    let format = reader.u32()?;
+    if format != FORMAT_VERSION {
+        return Err(ProjectError::UnsupportedVersion(format));
+    }
+    let count = reader.u32()?;
+    let mut entries = BTreeMap::new();
+    for _ in 0..count {
+        let name_len = reader.u16()? as usize;
+        let name = std::str::from_utf8(reader.take(name_len)?)
+            .map_err(|_| ProjectError::Corrupt("entry name is not UTF-8".into()))?
+            .to_string();
+        let len = usize::try_from(reader.u64()?)
+            .map_err(|_| ProjectError::Corrupt(format!("entry {name:?} is too large")))?;
+        let data = reader.take(len)?.to_vec();
+        if entries.insert(name.clone(), data).is_some() {
+            return Err(ProjectError::Corrupt(format!("duplicate entry {name:?}")));
+        }
+    }
+    if !reader.bytes.is_empty() {
+        return Err(ProjectError::Corrupt(
+            "trailing data after the entries".into(),
+        ));
+    }
+    Ok(entries)
+}
@@ -0,0 +1,148 @@
+//! Upgrades project documents written by older versions.
+//!
+//! Migrations work on the raw JSON so they can rename, reshape or drop
+//! fields the current structs no longer have. Each one takes a document
+//! from one schema version to the next and they run in sequence, so a file
+//! from any earlier version ends up current.
+
+use serde_json::{json, Value};
+
+use super::{Project, ProjectError};
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` document to version `n + 2`.
+/// Appending one bumps [`SCHEMA_VERSION`].
+const MIGRATIONS: &[Migration] = &[effect_history];
+
+/// Version of the project documents this build writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+/// Parses a project document of any supported version.
+pub fn upgrade(document: Value) -> Result<Project, ProjectError> {
+    let document = apply(document, MIGRATIONS)?;
+    serde_json::from_value(document).map_err(|e| ProjectError::Corrupt(e.to_string()))
+}
+
+/// Version 1 kept the frontend's undo history in the session: effect
+/// snapshots taken after each change, and the index of the current one.
+/// Version 2 keeps edits with their settings before and after, at the top
+/// level.
+fn effect_history(document: &mut Value) -> Result<(), String> {
+    let object = document.as_object_mut().ok_or("not an object")?;
+    let snapshots = match object.remove("history") {
+        Some(Value::Array(snapshots)) => snapshots,
+        Some(Value::Null) | None => Vec::new(),
+        Some(_) => return Err("history is not a list".into()),
+    };
+    let index = object
+        .remove("historyIndex")
+        .and_then(|index| index.as_u64());
+    let mut before = json!({});
+    let mut entries = Vec::with_capacity(snapshots.len());
+    for snapshot in snapshots {
+        let after = snapshot
+            .get("state")
+            .cloned()
+            .ok_or("history entry without state")?;
+        entries.push(json!({
+            "label": snapshot.get("action").cloned().unwrap_or_else(|| json!("Adjust effects")),
+            "timestamp": snapshot.get("timestamp").cloned().unwrap_or_else(|| json!(0)),
+            "edit": { "type": "effects", "before": before, "after": after },
+        }));
+        before = after;
+    }
+    let position = index
+        .map_or(0, |index| index as usize + 1)
+        .min(entries.len());
+    object.insert(
+        "history".into(),
+        json!({ "entries": entries, "position": position }),
+    );
+    Ok(())
+}
+
+fn apply(mut document: Value, migrations: &[Migration]) -> Result<Value, ProjectError> {
+    let current = migrations.len() as u64 + 1;
+    let version = document
+        .get("version")
+        .and_then(Value::as_u64)
+        .filter(|&version| version >= 1)
+        .ok_or_else(|| ProjectError::Corrupt("missing schema version".into()))?;
+    if version > current {
+        return Err(ProjectError::UnsupportedVersion(version as u32));
+    }
+    for (from, migration) in (version..current).zip(&migrations[version as usize - 1..]) {
+        migration(&mut document).map_err(|message| {
+            ProjectError::Corrupt(format!("upgrading from version {from}: {message}"))
+        })?;
+        document["version"] = Value::from(from + 1);
+    }
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn runs_the_migrations_a_document_is_missing() {
+        let rename: Migration = |document| {
+            let title = document
+                .as_object_mut()
+                .and_then(|object| object.remove("title"))
+                .ok_or("no title")?;
+            document["name"] = title;
+            Ok(())
+        };
+        let split: Migration = |document| {
+            document["transcript"] = json!("");
+            Ok(())
+        };
+        let migrations = [rename, split];
+
+        let upgraded = apply(json!({ "version": 1, "title": "Pod" }), &migrations).unwrap();
+        assert_eq!(
+            upgraded,
+            json!({ "version": 3, "name": "Pod", "transcript": "" })
+        );
+        // A version 2 document skips the first migration.
+        let upgraded = apply(json!({ "version": 2, "name": "Pod" }), &migrations).unwrap();
+        assert_eq!(upgraded["version"], 3);
+
+        assert!(matches!(
+            apply(json!({ "version": 4 }), &migrations),
+            Err(ProjectError::UnsupportedVersion(4))
+        ));
+        assert!(matches!(
+            apply(json!({ "version": 1 }), &migrations),
+            Err(ProjectError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn turns_effect_snapshots_into_undoable_edits() {
+        let document = json!({
+            "version": 1,
+            "name": "Pod",
+            "history": [
+                { "action": "Adjust pitch", "timestamp": 10, "state": { "pitch": 2.0 } },
+                { "action": "Adjust breath", "timestamp": 20, "state": { "pitch": 2.0, "breath": 30.0 } },
+            ],
+            "historyIndex": 0,
+            "layers": [],
+            "audio": [],
+        });
+        let project = upgrade(document).unwrap();
+        let history = project.history;
+        assert_eq!(history.position, 1);
+        assert_eq!(history.entries[1].label, "Adjust breath");
+        let crate::history::Edit::Effects { before, after } = &history.entries[1].edit else {
+            panic!("{:?}", history.entries[1]);
+        };
+        assert_eq!((before.pitch, before.breath), (2.0, 0.0));
+        assert_eq!((after.pitch, after.breath), (2.0, 30.0));
+    }
+}
@@ -0,0 +1,375 @@
+//! Saved projects.
+//!
+//! A project file is a checksummed [`bundle`] holding `project.json`, the
+//! versioned [`Project`] document, one lossless WAV entry per audio
+//! buffer the layers use, and one per audio clip the undo history holds,
+//! named by its hash. Buffer ids are only meaningful within a session,
+//! so opening a project loads its audio into fresh buffers and rewrites the
+//! layers to point at them.
+
+pub mod bundle;
+pub mod migrate;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::audio::buffer::{AudioBuffer, BufferId, BufferStore};
+use crate::audio::wav::{self, SampleFormat};
+use crate::audio::AudioError;
+use crate::history::SavedHistory;
+use crate::mixer::Layer;
+use crate::sandbox::SandboxError;
+
+use self::migrate::SCHEMA_VERSION;
+
+const DOCUMENT_ENTRY: &str = "project.json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProjectError {
+    #[error("project file is damaged: {0}")]
+    Corrupt(String),
+    #[error("project file failed its checksum; it is damaged or incomplete")]
+    ChecksumMismatch,
+    #[error("project file version {0} is newer than this app supports")]
+    UnsupportedVersion(u32),
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+}
+
+impl ProjectError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProjectError::Corrupt(_) => "corruptProject",
+            ProjectError::ChecksumMismatch => "checksumMismatch",
+            ProjectError::UnsupportedVersion(_) => "unsupportedProjectVersion",
+            ProjectError::Audio(e) => e.kind(),
+            ProjectError::Sandbox(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for ProjectError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// The effect sliders' settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EffectSettings {
+    /// Semitones.
+    pub pitch: f32,
+    /// Semitones.
+    pub formant: f32,
+    /// Breath amount, -100 to 100.
+    pub breath: f32,
+    /// Playback speed.
+    pub timing: f32,
+    pub emotion: String,
+}
+
+impl Default for EffectSettings {
+    fn default() -> Self {
+        Self {
+            pitch: 0.0,
+            formant: 0.0,
+            breath: 0.0,
+            timing: 1.0,
+            emotion: "Neutral".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WordTiming {
+    pub text: String,
+    /// Seconds.
+    pub start: f64,
+    pub end: f64,
+    /// How well the timing fits the audio, 0 to 1; `None` for timings that
+    /// were never measured.
+    #[serde(default)]
+    pub confidence: Option<f32>,
+}
+
+/// The parts of a project the frontend owns and round-trips through
+/// save and open.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Session {
+    pub name: String,
+    pub effects: EffectSettings,
+    pub transcript: String,
+    pub words: Vec<WordTiming>,
+    /// App config settings that apply while the project is open, as
+    /// sparse JSON shaped like `config/default.json`.
+    pub config: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            name: "Untitled Project".into(),
+            effects: EffectSettings::default(),
+            transcript: String::new(),
+            words: Vec::new(),
+            config: serde_json::Map::new(),
+        }
+    }
+}
+
+/// Where a buffer's audio is stored in the bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioRef {
+    /// The id layers refer to it by within the file.
+    pub id: BufferId,
+    pub entry: String,
+}
+
+/// The `project.json` document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Project {
+    pub version: u32,
+    #[serde(flatten)]
+    pub session: Session,
+    pub layers: Vec<Layer>,
+    pub audio: Vec<AudioRef>,
+    #[serde(default)]
+    pub history: SavedHistory,
+}
+
+/// The audio `layers` use, fetched up front so encoding can run without
+/// holding on to the store.
+pub fn layer_audio(
+    layers: &[Layer],
+    store: &BufferStore,
+) -> Result<HashMap<BufferId, Arc<AudioBuffer>>, AudioError> {
+    layers
+        .iter()
+        .filter_map(|layer| layer.buffer)
+        .map(|id| Ok((id, store.get(id)?)))
+        .collect()
+}
+
+/// Packs `session`, `layers`, their `audio` and the undo `history` into a
+/// project file.
+pub fn encode(
+    session: &Session,
+    layers: &[Layer],
+    audio: &HashMap<BufferId, Arc<AudioBuffer>>,
+    history: &SavedHistory,
+) -> Result<Vec<u8>, ProjectError> {
+    let mut entries = BTreeMap::new();
+    let mut refs = Vec::new();
+    for id in layers.iter().filter_map(|layer| layer.buffer) {
+        if refs.iter().any(|r: &AudioRef| r.id == id) {
+            continue;
+        }
+        let buffer = audio.get(&id).ok_or(AudioError::UnknownBuffer(id))?;
+        let entry = format!("audio/{id}.wav");
+        entries.insert(entry.clone(), wav::encode(buffer, SampleFormat::Float32)?);
+        refs.push(AudioRef { id, entry });
+    }
+    for clip in history.clips() {
+        if let Some(audio) = clip.audio() {
+            let entry = clip_entry(clip.hash());
+            entries.insert(entry, wav::encode(audio, SampleFormat::Float32)?);
+        }
+    }
+    let project = Project {
+        version: SCHEMA_VERSION,
+        session: session.clone(),
+        layers: layers.to_vec(),
+        audio: refs,
+        history: history.clone(),
+    };
+    let document = serde_json::to_vec_pretty(&project).expect("projects always serialize");
+    entries.insert(DOCUMENT_ENTRY.into(), document);
+    Ok(bundle::write(&entries))
+}
+
+fn clip_entry(hash: &str) -> String {
+    format!("history/{hash}.wav")
+}
+
+/// A decoded project file whose audio isn't in the buffer store yet.
+pub struct Contents {
+    pub session: Session,
+    pub history: SavedHistory,
+    layers: Vec<Layer>,
+    audio: Vec<(BufferId, AudioBuffer)>,
+}
+
+impl Contents {
+    /// Moves the audio into `store` and returns the layers, rewritten to
+    /// point at the new buffers.
+    pub fn load(self, store: &BufferStore) -> Vec<Layer> {
+        let ids: HashMap<BufferId, BufferId> = self
+            .audio
+            .into_iter()
+            .map(|(saved, buffer)| (saved, store.insert(buffer).id))
+            .collect();
+        let mut layers = self.layers;
+        for layer in &mut layers {
+            layer.buffer = layer.buffer.map(|id| ids[&id]);
+        }
+        layers
+    }
+}
+
+/// The verified project document in `entries`, upgraded if older.
+fn document(entries: &mut BTreeMap<String, Vec<u8>>) -> Result<Project, ProjectError> {
+    let document = entries
+        .remove(DOCUMENT_ENTRY)
+        .ok_or_else(|| ProjectError::Corrupt(format!("missing {DOCUMENT_ENTRY}")))?;
+    let document = serde_json::from_slice(&document)
+        .map_err(|e| ProjectError::Corrupt(format!("{DOCUMENT_ENTRY}: {e}")))?;
+    migrate::upgrade(document)
+}
+
+/// Verifies and unpacks a project file, upgrading older documents.
+pub fn decode(bytes: &[u8]) -> Result<Contents, ProjectError> {
+    let mut entries = bundle::read(bytes)?;
+    let project = document(&mut entries)?;
+
+    let mut audio = Vec::with_capacity(project.audio.len());
+    for audio_ref in &project.audio {
+        let bytes = entries
+            .get(&audio_ref.entry)
+            .ok_or_else(|| ProjectError::Corrupt(format!("missing {}", audio_ref.entry)))?;
+        let buffer = wav::decode(bytes)
+            .map_err(|e| ProjectError::Corrupt(format!("{}: {e}", audio_ref.entry)))?;
+        audio.push((audio_ref.id, buffer));
+    }
+    if let Some(id) = project
+        .layers
+        .iter()
+        .filter_map(|layer| layer.buffer)
+        .find(|id| !audio.iter().any(|(saved, _)| saved == id))
+    {
+        return Err(ProjectError::Corrupt(format!(
+            "a layer refers to missing audio {id}"
+        )));
+    }
+
+    let mut history = project.history;
+    let mut clips = HashMap::new();
+    for clip in history.clips().into_iter().filter(|clip| clip.audio().is_none()) {
+        let entry = clip_entry(clip.hash());
+        let bytes = entries
+            .get(&entry)
+            .ok_or_else(|| ProjectError::Corrupt(format!("missing {entry}")))?;
+        let buffer =
+            wav::decode(bytes).map_err(|e| ProjectError::Corrupt(format!("{entry}: {e}")))?;
+        clips.insert(clip.hash().to_string(), Arc::new(buffer));
+    }
+    history.attach(&clips);
+    Ok(Contents {
+        session: project.session,
+        history,
+        layers: project.layers,
+        audio,
+    })
+}
+
+/// Just the session of a project file, without decoding its audio.
+pub fn read_session(bytes: &[u8]) -> Result<Session, ProjectError> {
+    Ok(document(&mut bundle::read(bytes)?)?.session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{Edit, History};
+    use crate::mixer::{LayerUpdate, Mixer};
+
+    #[test]
+    fn round_trips_layers_audio_and_session() {
+        let store = BufferStore::new();
+        let voice = store.insert(AudioBuffer::new(8000, vec![vec![0.25, -0.5, 0.125]]));
+        let mixer = Mixer::new();
+        let layer = mixer.add_layer(None).unwrap();
+        let layer = mixer
+            .update_layer(
+                layer.id,
+                LayerUpdate {
+                    buffer: Some(voice.id),
+                    pan: Some(-0.5),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        mixer.add_layer(Some("Empty".into())).unwrap();
+        let session = Session {
+            name: "Episode 1".into(),
+            transcript: "hello there".into(),
+            words: vec![WordTiming {
+                text: "hello".into(),
+                start: 0.0,
+                end: 0.4,
+                confidence: Some(0.8),
+            }],
+            ..Default::default()
+        };
+        let history = History::new();
+        history.record(Edit::add_layer(0, layer, &store).unwrap());
+        history.record(Edit::Effects {
+            before: EffectSettings::default(),
+            after: EffectSettings {
+                pitch: 2.0,
+                ..Default::default()
+            },
+        });
+
+        let layers = mixer.layers();
+        let audio = layer_audio(&layers, &store).unwrap();
+        let bytes = encode(&session, &layers, &audio, &history.saved()).unwrap();
+        let contents = decode(&bytes).unwrap();
+        assert_eq!(contents.session, session);
+        assert_eq!(contents.history, history.saved());
+        // History audio comes back from its own entry.
+        let clips = contents.history.clips();
+        assert_eq!(clips.len(), 1);
+        assert_eq!(clips[0].audio().unwrap().channels, [vec![0.25, -0.5, 0.125]]);
+        let reopened = BufferStore::new();
+        let layers = contents.load(&reopened);
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].pan, -0.5);
+        assert_eq!(layers[1].buffer, None);
+        let audio = reopened.get(layers[0].buffer.unwrap()).unwrap();
+        assert_eq!(audio.channels, [vec![0.25, -0.5, 0.125]]);
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        let bytes = encode(
+            &Session::default(),
+            &[],
+            &HashMap::new(),
+            &SavedHistory::default(),
+        )
+        .unwrap();
+        let mut flipped = bytes.clone();
+        flipped[20] ^= 1;
+        assert!(matches!(
+            decode(&flipped),
+            Err(ProjectError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(ProjectError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            decode(b"RIFF....WAVE"),
+            Err(ProjectError::Corrupt(_))
+        ));
+    }
+}
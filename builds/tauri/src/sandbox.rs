@@ -0,0 +1,373 @@
+//! Project filesystem confined to a single root directory.
+//!
+//! Every path coming from the webview is treated as relative to the project
+//! root. Paths are normalized lexically first, then the deepest existing
+//! ancestor is canonicalized so a symlink anywhere along the way cannot point
+//! the final path outside the root.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SandboxError {
+    #[error("path must not be empty")]
+    EmptyPath,
+    #[error("absolute paths are not allowed: {0}")]
+    AbsolutePath(String),
+    #[error("path escapes the project root: {0}")]
+    OutsideRoot(String),
+    #[error("file not found: {0}")]
+    NotFound(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("file is not valid UTF-8: {0}")]
+    InvalidUtf8(String),
+    #[error("I/O error on {path}: {source}")]
+    Io { path: String, source: io::Error },
+}
+
+impl SandboxError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SandboxError::EmptyPath => "emptyPath",
+            SandboxError::AbsolutePath(_) => "absolutePath",
+            SandboxError::OutsideRoot(_) => "outsideRoot",
+            SandboxError::NotFound(_) => "notFound",
+            SandboxError::PermissionDenied(_) => "permissionDenied",
+            SandboxError::InvalidUtf8(_) => "invalidUtf8",
+            SandboxError::Io { .. } => "io",
+        }
+    }
+
+    fn from_io(path: &Path, error: io::Error) -> Self {
+        let path = path.display().to_string();
+        match error.kind() {
+            io::ErrorKind::NotFound => SandboxError::NotFound(path),
+            io::ErrorKind::PermissionDenied => SandboxError::PermissionDenied(path),
+            io::ErrorKind::InvalidData => SandboxError::InvalidUtf8(path),
+            _ => SandboxError::Io {
+                path,
+                source: error,
+            },
+        }
+    }
+}
+
+impl Serialize for SandboxError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// A file under the root, with what it takes to tell whether it changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    /// Relative to the root, with `/` separators.
+    pub path: String,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// The directory all frontend file access is confined to.
+#[derive(Debug, Clone)]
+pub struct ProjectRoot {
+    root: PathBuf,
+}
+
+impl ProjectRoot {
+    /// Opens (creating if needed) the project root at `dir`.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, SandboxError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(|e| SandboxError::from_io(dir, e))?;
+        let root = fs::canonicalize(dir).map_err(|e| SandboxError::from_io(dir, e))?;
+        Ok(Self { root })
+    }
+
+    /// Maps a frontend-supplied relative path to an absolute path inside the
+    /// root. The target itself does not need to exist yet.
+    pub fn resolve(&self, relative: &str) -> Result<PathBuf, SandboxError> {
+        if relative.is_empty() {
+            return Err(SandboxError::EmptyPath);
+        }
+
+        let mut clean = PathBuf::new();
+        for component in Path::new(relative).components() {
+            match component {
+                Component::Normal(part) => clean.push(part),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !clean.pop() {
+                        return Err(SandboxError::OutsideRoot(relative.to_string()));
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    return Err(SandboxError::AbsolutePath(relative.to_string()));
+                }
+            }
+        }
+
+        // Walk up to the deepest ancestor that exists on disk and remember the
+        // components that don't exist yet.
+        let joined = self.root.join(&clean);
+        let mut existing = joined.as_path();
+        let mut missing = Vec::new();
+        loop {
+            match fs::symlink_metadata(existing) {
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    match (existing.file_name(), existing.parent()) {
+                        (Some(name), Some(parent)) => {
+                            missing.push(name.to_os_string());
+                            existing = parent;
+                        }
+                        _ => return Err(SandboxError::OutsideRoot(relative.to_string())),
+                    }
+                }
+                Err(e) => return Err(SandboxError::from_io(existing, e)),
+            }
+        }
+
+        // A dangling symlink fails to canonicalize; writing through it would
+        // create its target wherever it points, so reject it outright.
+        let mut resolved = fs::canonicalize(existing)
+            .map_err(|_| SandboxError::OutsideRoot(relative.to_string()))?;
+        if !resolved.starts_with(&self.root) {
+            return Err(SandboxError::OutsideRoot(relative.to_string()));
+        }
+
+        for name in missing.into_iter().rev() {
+            resolved.push(name);
+        }
+        Ok(resolved)
+    }
+
+    /// Every file under the root, by path. Symlinks are skipped rather
+    /// than followed.
+    pub fn files(&self) -> Result<Vec<FileEntry>, SandboxError> {
+        let mut files = Vec::new();
+        let mut pending = vec![(self.root.clone(), String::new())];
+        while let Some((dir, prefix)) = pending.pop() {
+            let entries = fs::read_dir(&dir).map_err(|e| SandboxError::from_io(&dir, e))?;
+            for entry in entries {
+                let entry = entry.map_err(|e| SandboxError::from_io(&dir, e))?;
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                let relative = format!("{prefix}{name}");
+                let metadata = entry
+                    .metadata()
+                    .map_err(|e| SandboxError::from_io(&entry.path(), e))?;
+                if metadata.is_dir() {
+                    pending.push((entry.path(), format!("{relative}/")));
+                } else if metadata.is_file() {
+                    files.push(FileEntry {
+                        path: relative,
+                        len: metadata.len(),
+                        modified: metadata.modified().ok(),
+                    });
+                }
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    /// The first `len` bytes of a file, or all of it if it is shorter.
+    pub fn read_prefix(&self, relative: &str, len: usize) -> Result<Vec<u8>, SandboxError> {
+        let path = self.resolve(relative)?;
+        let error = |e| SandboxError::from_io(Path::new(relative), e);
+        let mut prefix = Vec::with_capacity(len);
+        fs::File::open(&path)
+            .map_err(error)?
+            .take(len as u64)
+            .read_to_end(&mut prefix)
+            .map_err(error)?;
+        Ok(prefix)
+    }
+
+    pub fn read_to_string(&self, relative: &str) -> Result<String, SandboxError> {
+        let path = self.resolve(relative)?;
+        fs::read_to_string(&path).map_err(|e| SandboxError::from_io(Path::new(relative), e))
+    }
+
+    pub fn read(&self, relative: &str) -> Result<Vec<u8>, SandboxError> {
+        let path = self.resolve(relative)?;
+        fs::read(&path).map_err(|e| SandboxError::from_io(Path::new(relative), e))
+    }
+
+    pub fn write(&self, relative: &str, contents: impl AsRef<[u8]>) -> Result<(), SandboxError> {
+        let path = self.resolve(relative)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| SandboxError::from_io(Path::new(relative), e))?;
+        }
+        fs::write(&path, contents).map_err(|e| SandboxError::from_io(Path::new(relative), e))
+    }
+
+    /// Like [`write`](Self::write), but the file is written next to its
+    /// destination and renamed into place, so readers (and a crash halfway
+    /// through) only ever see the old contents or the new.
+    pub fn write_atomic(
+        &self,
+        relative: &str,
+        contents: impl AsRef<[u8]>,
+    ) -> Result<(), SandboxError> {
+        let path = self.resolve(relative)?;
+        write_atomic(&path, contents.as_ref())
+            .map_err(|e| SandboxError::from_io(Path::new(relative), e))
+    }
+}
+
+/// Writes `bytes` to a sibling temp file, syncs it and renames it over
+/// `path`, creating the parent directory if needed. Readers, and a crash
+/// halfway through, only ever see the old contents or the new; a failed
+/// write removes the temp file.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let written = fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sandbox-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn resolves_relative_paths_inside_the_root() {
+        let dir = temp_dir("inside");
+        let project = ProjectRoot::open(&dir).unwrap();
+        let root = fs::canonicalize(&dir).unwrap();
+        assert_eq!(
+            project.resolve("takes/one.wav").unwrap(),
+            root.join("takes/one.wav")
+        );
+        assert_eq!(
+            project.resolve("./takes/../notes.txt").unwrap(),
+            root.join("notes.txt")
+        );
+
+        project.write("takes/one.txt", "hello").unwrap();
+        assert_eq!(project.read_to_string("takes/one.txt").unwrap(), "hello");
+        assert_eq!(project.files().unwrap()[0].path, "takes/one.txt");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_paths_that_leave_the_root() {
+        let dir = temp_dir("escape");
+        let project = ProjectRoot::open(dir.join("project")).unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+
+        assert!(matches!(project.resolve(""), Err(SandboxError::EmptyPath)));
+        for relative in ["..", "../secret.txt", "takes/../../secret.txt"] {
+            assert!(
+                matches!(project.resolve(relative), Err(SandboxError::OutsideRoot(_))),
+                "{relative}"
+            );
+        }
+        let absolute = dir.join("secret.txt").display().to_string();
+        assert!(matches!(
+            project.resolve(&absolute),
+            Err(SandboxError::AbsolutePath(_))
+        ));
+        assert!(matches!(
+            project.write("../secret.txt", "overwritten"),
+            Err(SandboxError::OutsideRoot(_))
+        ));
+        assert_eq!(
+            fs::read_to_string(dir.join("secret.txt")).unwrap(),
+            "secret"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_that_point_outside_the_root() {
+        use std::os::unix::fs::symlink;
+
+        let dir = temp_dir("symlink");
+        let project = ProjectRoot::open(dir.join("project")).unwrap();
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        fs::write(dir.join("outside/secret.txt"), "secret").unwrap();
+        symlink(dir.join("outside"), dir.join("project/linked")).unwrap();
+        symlink(
+            dir.join("outside/secret.txt"),
+            dir.join("project/secret.txt"),
+        )
+        .unwrap();
+        symlink(dir.join("nowhere"), dir.join("project/dangling")).unwrap();
+        fs::create_dir_all(dir.join("project/takes")).unwrap();
+        symlink("../takes", dir.join("project/takes/up")).unwrap();
+
+        for relative in [
+            "linked/secret.txt",
+            "linked/new.txt",
+            "secret.txt",
+            "dangling",
+        ] {
+            assert!(
+                matches!(project.resolve(relative), Err(SandboxError::OutsideRoot(_))),
+                "{relative}"
+            );
+        }
+        assert!(matches!(
+            project.write("dangling", "escaped"),
+            Err(SandboxError::OutsideRoot(_))
+        ));
+        assert!(!dir.join("nowhere").exists());
+        // A link that stays inside the root is followed.
+        assert_eq!(
+            project.resolve("takes/up/notes.txt").unwrap(),
+            fs::canonicalize(dir.join("project/takes"))
+                .unwrap()
+                .join("notes.txt")
+        );
+        // Listing skips links rather than following them out.
+        project.write("takes/one.txt", "hello").unwrap();
+        let files: Vec<_> = project
+            .files()
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        assert_eq!(files, ["takes/one.txt"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn failed_atomic_writes_leave_nothing_behind() {
+        let dir = temp_dir("atomic");
+        let target = dir.join("taken");
+        fs::create_dir_all(target.join("inside")).unwrap();
+
+        assert!(write_atomic(&target, b"contents").is_err());
+        assert!(!dir.join("taken.tmp").exists());
+
+        write_atomic(&dir.join("new/file.json"), b"{}").unwrap();
+        assert_eq!(fs::read(dir.join("new/file.json")).unwrap(), b"{}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,626 @@
+//! Full-text search over stored transcripts and saved projects.
+//!
+//! Each transcript and project becomes a [`Document`]: its text, and the
+//! words in it reduced to stems, each with where it sits in the text and,
+//! where the text is timed, when it was said. An inverted index maps stems
+//! to their occurrences. Query words match by stem and, unless turned
+//! off, by Levenshtein distance to other stems; quoted phrases match
+//! consecutive words. Documents that match every part of a query are
+//! ranked with BM25.
+//!
+//! The index follows the transcript store and the project root: before
+//! each search, transcripts and project files that came, went or changed
+//! since the last one are re-read, and the rest are kept.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::asr::language_code;
+use crate::project::{self, bundle, Session};
+use crate::sandbox::{FileEntry, ProjectRoot, SandboxError};
+use crate::text::{self, Stemmer};
+use crate::transcripts::{StoredTranscript, TranscriptError, TranscriptId, TranscriptStore};
+
+/// BM25 term frequency saturation.
+const K1: f64 = 1.2;
+/// BM25 length normalization.
+const B: f64 = 0.75;
+/// Characters of context either side of a match.
+const CONTEXT_CHARS: usize = 40;
+/// Matches listed per hit.
+const MAX_MATCHES: usize = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error(transparent)]
+    Transcripts(#[from] TranscriptError),
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+}
+
+impl SearchError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SearchError::Transcripts(e) => e.kind(),
+            SearchError::Sandbox(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for SearchError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchOptions {
+    /// Let words match others a few edits away, for misspellings and
+    /// misrecognitions.
+    pub fuzzy: bool,
+    /// Most hits returned.
+    pub limit: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            fuzzy: true,
+            limit: 20,
+        }
+    }
+}
+
+/// Where a document's text comes from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Source {
+    Transcript {
+        id: TranscriptId,
+    },
+    /// A project file, by its path in the project root.
+    Project {
+        path: String,
+    },
+}
+
+/// One place a document matched, with its surroundings.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    pub before: String,
+    pub text: String,
+    pub after: String,
+    /// Seconds into the recording, when the text is timed.
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub source: Source,
+    pub title: String,
+    pub score: f32,
+    /// In order of appearance.
+    pub matches: Vec<SearchMatch>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    stem: String,
+    /// Bytes of the document text.
+    range: Range<usize>,
+    /// Seconds.
+    time: Option<(f64, f64)>,
+}
+
+/// The searchable text of a transcript or project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    source: Source,
+    title: String,
+    /// ISO 639-1 code, when known.
+    language: Option<String>,
+    text: String,
+    tokens: Vec<Token>,
+}
+
+impl Document {
+    fn new(source: Source, title: String, language: Option<String>) -> Self {
+        Self {
+            source,
+            title,
+            language,
+            text: String::new(),
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Appends `text`, said over `time`, spreading the time across its
+    /// words in proportion to where they sit.
+    fn push(&mut self, text: &str, time: Option<(f64, f64)>, stemmer: &Stemmer) {
+        if !self.text.is_empty() {
+            self.text.push(' ');
+        }
+        let offset = self.text.len();
+        self.text.push_str(text);
+        let len = text.len().max(1) as f64;
+        for range in text::words(text) {
+            let at =
+                |byte: usize| time.map(|(start, end)| start + (end - start) * byte as f64 / len);
+            self.tokens.push(Token {
+                stem: stemmer.stem(&text::normalize(&text[range.clone()])),
+                time: at(range.start).zip(at(range.end)),
+                range: offset + range.start..offset + range.end,
+            });
+        }
+    }
+
+    pub fn transcript(transcript: &StoredTranscript) -> Self {
+        let summary = &transcript.summary;
+        let language = language_code(&summary.language);
+        let stemmer = Stemmer::new(language.as_deref());
+        let mut document = Self::new(
+            Source::Transcript { id: summary.id },
+            summary.preview.clone(),
+            language,
+        );
+        if transcript.segments.is_empty() {
+            document.push(&transcript.text, None, &stemmer);
+        }
+        for segment in &transcript.segments {
+            document.push(&segment.text, Some((segment.start, segment.end)), &stemmer);
+        }
+        document
+    }
+
+    /// A project's transcript, timed by its word timings if it has them.
+    pub fn project(path: &str, session: &Session) -> Self {
+        let stemmer = Stemmer::new(None);
+        let mut document = Self::new(
+            Source::Project { path: path.into() },
+            session.name.clone(),
+            None,
+        );
+        if session.words.is_empty() {
+            document.push(&session.transcript, None, &stemmer);
+        }
+        for word in &session.words {
+            document.push(&word.text, Some((word.start, word.end)), &stemmer);
+        }
+        document
+    }
+
+    fn search_match(&self, first: usize, last: usize) -> SearchMatch {
+        let (first, last) = (&self.tokens[first], &self.tokens[last]);
+        let (start, end) = (first.range.start, last.range.end);
+        let mut before: Vec<char> = self.text[..start]
+            .chars()
+            .rev()
+            .take(CONTEXT_CHARS)
+            .collect();
+        before.reverse();
+        SearchMatch {
+            before: before.into_iter().collect(),
+            text: self.text[start..end].to_string(),
+            after: self.text[end..].chars().take(CONTEXT_CHARS).collect(),
+            start: first.time.map(|(start, _)| start),
+            end: last.time.map(|(_, end)| end),
+        }
+    }
+}
+
+/// A part of a query.
+#[derive(Debug, Clone, PartialEq)]
+enum Clause {
+    Word(String),
+    /// Two or more words, in order.
+    Phrase(Vec<String>),
+}
+
+/// Splits a query into words and quoted phrases.
+fn parse(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    for (i, part) in query.split('"').enumerate() {
+        let words: Vec<String> = text::words(part)
+            .into_iter()
+            .map(|range| text::normalize(&part[range]))
+            .collect();
+        if i % 2 == 1 && words.len() > 1 {
+            clauses.push(Clause::Phrase(words));
+        } else {
+            clauses.extend(words.into_iter().map(Clause::Word));
+        }
+    }
+    clauses
+}
+
+/// How many edits a word of `len` characters may be off by and still
+/// match fuzzily: none for short words, where one edit is another word.
+fn max_edits(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// A match of a clause: the tokens it spans and how well it matched.
+struct Occurrence {
+    first: usize,
+    last: usize,
+    weight: f64,
+}
+
+/// Documents and the inverted index over them.
+struct Index {
+    documents: Vec<Arc<Document>>,
+    /// Stem to (document, token) pairs.
+    postings: HashMap<String, Vec<(usize, usize)>>,
+    /// The languages documents are stemmed in.
+    languages: Vec<Option<String>>,
+    average_len: f64,
+}
+
+impl Index {
+    fn new(documents: Vec<Arc<Document>>) -> Self {
+        let mut postings: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        let mut languages = Vec::new();
+        for (d, document) in documents.iter().enumerate() {
+            if !languages.contains(&document.language) {
+                languages.push(document.language.clone());
+            }
+            for (t, token) in document.tokens.iter().enumerate() {
+                postings.entry(token.stem.clone()).or_default().push((d, t));
+            }
+        }
+        let tokens: usize = documents.iter().map(|d| d.tokens.len()).sum();
+        let average_len = tokens as f64 / documents.len().max(1) as f64;
+        Self {
+            documents,
+            postings,
+            languages,
+            average_len,
+        }
+    }
+
+    /// Stems `word` may match, with how closely: 1 for its own stem in any
+    /// document language, less for fuzzy matches.
+    fn candidates(&self, word: &str, fuzzy: bool) -> HashMap<&str, f64> {
+        let stems: HashSet<String> = self
+            .languages
+            .iter()
+            .map(|language| Stemmer::new(language.as_deref()).stem(word))
+            .collect();
+        let mut candidates = HashMap::new();
+        let edits = if fuzzy {
+            max_edits(word.chars().count())
+        } else {
+            0
+        };
+        for stem in self.postings.keys() {
+            let len = stem.chars().count();
+            let distance = stems
+                .iter()
+                .filter(|query| query.chars().count().abs_diff(len) <= edits)
+                .map(|query| text::levenshtein(query, stem))
+                .min();
+            if let Some(distance) = distance.filter(|&distance| distance <= edits) {
+                candidates.insert(stem.as_str(), 1.0 / (1 + distance) as f64);
+            }
+        }
+        candidates
+    }
+
+    /// Where `clause` occurs, by document.
+    fn occurrences(&self, clause: &Clause, fuzzy: bool) -> HashMap<usize, Vec<Occurrence>> {
+        let mut found: HashMap<usize, Vec<Occurrence>> = HashMap::new();
+        match clause {
+            Clause::Word(word) => {
+                for (stem, weight) in self.candidates(word, fuzzy) {
+                    for &(d, t) in &self.postings[stem] {
+                        found.entry(d).or_default().push(Occurrence {
+                            first: t,
+                            last: t,
+                            weight,
+                        });
+                    }
+                }
+            }
+            Clause::Phrase(words) => {
+                // Phrases are exact up to stemming.
+                let candidates: Vec<_> = words.iter().map(|w| self.candidates(w, false)).collect();
+                for stem in candidates[0].keys() {
+                    for &(d, t) in &self.postings[*stem] {
+                        let tokens = &self.documents[d].tokens;
+                        let last = t + words.len() - 1;
+                        let follows = last < tokens.len()
+                            && candidates[1..]
+                                .iter()
+                                .zip(&tokens[t + 1..=last])
+                                .all(|(stems, token)| stems.contains_key(token.stem.as_str()));
+                        if follows {
+                            found.entry(d).or_default().push(Occurrence {
+                                first: t,
+                                last,
+                                weight: 1.0,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    fn search(&self, query: &str, options: &SearchOptions) -> Vec<SearchHit> {
+        let clauses = parse(query);
+        if clauses.is_empty() {
+            return Vec::new();
+        }
+        let count = self.documents.len() as f64;
+        let mut scores: HashMap<usize, (f64, Vec<Occurrence>)> = HashMap::new();
+        for (i, clause) in clauses.iter().enumerate() {
+            let found = self.occurrences(clause, options.fuzzy);
+            let matched = found.len() as f64;
+            let idf = (1.0 + (count - matched + 0.5) / (matched + 0.5)).ln();
+            let boost = match clause {
+                Clause::Word(_) => 1.0,
+                Clause::Phrase(words) => words.len() as f64,
+            };
+            // Every clause has to match.
+            if i > 0 {
+                scores.retain(|d, _| found.contains_key(d));
+            }
+            for (d, occurrences) in found {
+                if i > 0 && !scores.contains_key(&d) {
+                    continue;
+                }
+                let len = self.documents[d].tokens.len() as f64;
+                let frequency: f64 = occurrences.iter().map(|o| o.weight).sum();
+                let norm = 1.0 - B + B * len / self.average_len.max(1.0);
+                let score = boost * idf * frequency * (K1 + 1.0) / (frequency + K1 * norm);
+                let entry = scores.entry(d).or_default();
+                entry.0 += score;
+                entry.1.extend(occurrences);
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .map(|(d, (score, mut occurrences))| {
+                let document = &self.documents[d];
+                // Where a phrase and a word start together, the phrase.
+                occurrences.sort_by_key(|o| (o.first, Reverse(o.last)));
+                occurrences.dedup_by_key(|o| o.first);
+                SearchHit {
+                    source: document.source.clone(),
+                    title: document.title.clone(),
+                    score: score as f32,
+                    matches: occurrences
+                        .iter()
+                        .take(MAX_MATCHES)
+                        .map(|o| document.search_match(o.first, o.last))
+                        .collect(),
+                }
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.source.cmp(&b.source))
+        });
+        hits.truncate(options.limit);
+        hits
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    documents: BTreeMap<Source, Arc<Document>>,
+    /// Every file in the project root as last seen, projects or not, so
+    /// only changed files are reread.
+    files: HashMap<String, FileEntry>,
+    /// Built on the first search after the documents change.
+    index: Option<Index>,
+}
+
+/// The search index. Clones share it, so commands can move one onto the
+/// blocking pool.
+#[derive(Clone, Default)]
+pub struct SearchIndex {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn inner(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Catches up with the transcripts in `transcripts` and the project
+    /// files in `root`.
+    pub fn refresh(
+        &self,
+        transcripts: &TranscriptStore,
+        root: &ProjectRoot,
+    ) -> Result<(), SearchError> {
+        let mut inner = self.inner();
+        let inner = &mut *inner;
+        let known = inner.documents.len();
+        let mut changed = false;
+
+        let stored = transcripts.list()?;
+        let ids: HashSet<TranscriptId> = stored.iter().map(|summary| summary.id).collect();
+        let files = root.files()?;
+        let paths: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        inner.documents.retain(|source, _| match source {
+            Source::Transcript { id } => ids.contains(id),
+            Source::Project { path } => paths.contains(path.as_str()),
+        });
+        inner.files.retain(|path, _| paths.contains(path.as_str()));
+        changed |= inner.documents.len() != known;
+
+        for summary in stored {
+            let source = Source::Transcript { id: summary.id };
+            if inner.documents.contains_key(&source) {
+                continue;
+            }
+            match transcripts.peek(summary.id) {
+                Ok(transcript) => {
+                    let document = Document::transcript(&transcript);
+                    inner.documents.insert(source, Arc::new(document));
+                    changed = true;
+                }
+                // Evicted since it was listed.
+                Err(TranscriptError::NotFound(_)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        for file in files {
+            if inner.files.get(&file.path) == Some(&file) {
+                continue;
+            }
+            let source = Source::Project {
+                path: file.path.clone(),
+            };
+            // Only files that start like a project are read in full.
+            let session = root
+                .read_prefix(&file.path, bundle::MAGIC.len())
+                .ok()
+                .filter(|prefix| prefix == bundle::MAGIC)
+                .and_then(|_| root.read(&file.path).ok())
+                .and_then(|bytes| project::read_session(&bytes).ok());
+            changed |= match session {
+                Some(session) => {
+                    let document = Document::project(&file.path, &session);
+                    inner.documents.insert(source, Arc::new(document));
+                    true
+                }
+                None => inner.documents.remove(&source).is_some(),
+            };
+            inner.files.insert(file.path.clone(), file);
+        }
+
+        if changed {
+            inner.index = None;
+        }
+        Ok(())
+    }
+
+    /// The documents that match `query`, best first.
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Vec<SearchHit> {
+        let mut inner = self.inner();
+        let inner = &mut *inner;
+        let documents = &inner.documents;
+        inner
+            .index
+            .get_or_insert_with(|| Index::new(documents.values().cloned().collect()))
+            .search(query, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asr::Segment;
+    use crate::project::WordTiming;
+    use crate::transcripts::TranscriptSummary;
+
+    fn interview() -> Document {
+        let segment = |text: &str, start, end| Segment {
+            text: text.into(),
+            start,
+            end,
+            confidence: 0.9,
+        };
+        Document::transcript(&StoredTranscript {
+            summary: TranscriptSummary {
+                id: 1,
+                created: 0,
+                language: "en-US".into(),
+                confidence: 0.9,
+                audio: None,
+                preview: "We recorded the interview".into(),
+                size: 0,
+            },
+            text: String::new(),
+            segments: vec![
+                segment("We recorded the interview downtown.", 0.0, 4.0),
+                segment("Then edited the recordings.", 4.0, 8.0),
+            ],
+            alternatives: Vec::new(),
+        })
+    }
+
+    fn episode() -> Document {
+        let words = ["The", "interview", "was", "recorded"];
+        let session = Session {
+            name: "Episode 1".into(),
+            words: words
+                .iter()
+                .enumerate()
+                .map(|(i, text)| WordTiming {
+                    text: text.to_string(),
+                    start: 10.0 + i as f64,
+                    end: 10.5 + i as f64,
+                    confidence: None,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        Document::project("episodes/one.vosproj", &session)
+    }
+
+    #[test]
+    fn ranks_stemmed_fuzzy_and_phrase_matches_with_times() {
+        let index = Index::new(vec![Arc::new(interview()), Arc::new(episode())]);
+        let options = SearchOptions::default();
+
+        // Stems match across forms; more occurrences rank higher.
+        let hits = index.search("recordings", &options);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].source, Source::Transcript { id: 1 });
+        assert_eq!(hits[0].matches.len(), 2);
+
+        // A match knows when it was said and what surrounds it.
+        let hits = index.search("editing", &options);
+        let found = &hits[0].matches[0];
+        assert_eq!(found.text, "edited");
+        assert!(found.before.ends_with("Then "));
+        let start = found.start.unwrap();
+        assert!(start > 4.0 && start < 6.0, "{start}");
+
+        // Phrases match words in order, timed from the first to the last.
+        let hits = index.search("\"the interview was\"", &options);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            hits[0].source,
+            Source::Project {
+                path: "episodes/one.vosproj".into()
+            }
+        );
+        let found = &hits[0].matches[0];
+        assert_eq!((found.start, found.end), (Some(10.0), Some(12.5)));
+        assert!(index.search("\"interview the\"", &options).is_empty());
+
+        // Misspellings match fuzzily, unless that's turned off.
+        assert_eq!(index.search("intervew downtown", &options).len(), 1);
+        let exact = SearchOptions {
+            fuzzy: false,
+            ..options
+        };
+        assert!(index.search("intervew", &exact).is_empty());
+        assert!(index.search("", &exact).is_empty());
+    }
+}
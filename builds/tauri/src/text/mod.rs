@@ -0,0 +1,160 @@
+//! Text analysis shared by search and captions: words, stems and edit
+//! distance, the Rust side of what `js/text.processor.js` does in the
+//! webview.
+
+use std::ops::Range;
+
+use rust_stemmers::Algorithm;
+
+/// Byte ranges of the words in `text`: runs of letters and digits, with
+/// apostrophes allowed inside them ("don't", "rock’n’roll").
+pub fn words(text: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let inside = c.is_alphanumeric()
+            || (matches!(c, '\'' | '’')
+                && start.is_some()
+                && chars
+                    .peek()
+                    .is_some_and(|&(_, next)| next.is_alphanumeric()));
+        match (inside, start) {
+            (true, None) => start = Some(i),
+            (false, Some(from)) => {
+                words.push(from..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        words.push(from..text.len());
+    }
+    words
+}
+
+/// Byte ranges of the sentences in `text`, as `TextProcessor.parseSentences`
+/// splits them: at runs of `.`, `!` and `?`, trimmed, with empty ones
+/// dropped. Unlike it, each sentence keeps its closing punctuation.
+pub fn sentences(text: &str) -> Vec<Range<usize>> {
+    let closes = |c: char| matches!(c, '.' | '!' | '?');
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, next)| next);
+        let ends = closes(c) && !next.is_some_and(closes);
+        if !ends && next.is_some() {
+            continue;
+        }
+        let end = i + c.len_utf8();
+        let sentence = &text[start..end];
+        if !sentence
+            .trim_matches(|c: char| c.is_whitespace() || closes(c))
+            .is_empty()
+        {
+            let from = start + (sentence.len() - sentence.trim_start().len());
+            sentences.push(from..start + sentence.trim_end().len());
+        }
+        start = end;
+    }
+    sentences
+}
+
+/// Lowercases `word` and straightens curly apostrophes, so the same word
+/// always normalizes the same.
+pub fn normalize(word: &str) -> String {
+    word.to_lowercase().replace('’', "'")
+}
+
+/// Reduces normalized words to their stems, so "recording", "recorded"
+/// and "records" all match "record".
+pub struct Stemmer(Option<rust_stemmers::Stemmer>);
+
+impl Stemmer {
+    /// The Snowball stemmer for `language`, an ISO 639-1 code. English
+    /// when none is known, as that's what recognition defaults to;
+    /// languages Snowball doesn't cover aren't stemmed.
+    pub fn new(language: Option<&str>) -> Self {
+        let algorithm = match language.unwrap_or("en") {
+            "ar" => Algorithm::Arabic,
+            "da" => Algorithm::Danish,
+            "de" => Algorithm::German,
+            "el" => Algorithm::Greek,
+            "en" => Algorithm::English,
+            "es" => Algorithm::Spanish,
+            "fi" => Algorithm::Finnish,
+            "fr" => Algorithm::French,
+            "hu" => Algorithm::Hungarian,
+            "it" => Algorithm::Italian,
+            "nl" => Algorithm::Dutch,
+            "no" | "nb" | "nn" => Algorithm::Norwegian,
+            "pt" => Algorithm::Portuguese,
+            "ro" => Algorithm::Romanian,
+            "ru" => Algorithm::Russian,
+            "sv" => Algorithm::Swedish,
+            "ta" => Algorithm::Tamil,
+            "tr" => Algorithm::Turkish,
+            _ => return Self(None),
+        };
+        Self(Some(rust_stemmers::Stemmer::create(algorithm)))
+    }
+
+    pub fn stem(&self, word: &str) -> String {
+        match &self.0 {
+            Some(stemmer) => stemmer.stem(word).into_owned(),
+            None => word.to_string(),
+        }
+    }
+}
+
+/// Edits (insertions, deletions, substitutions) between `a` and `b`,
+/// counted in characters, as `TextProcessor.levenshteinDistance` does.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            current[j + 1] = if ca == cb {
+                previous[j]
+            } else {
+                1 + previous[j].min(previous[j + 1]).min(current[j])
+            };
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_words_and_sentences_stems_and_measures_edits() {
+        let text = "Don't stop, rock’n’roll — 'quoted' 42x!";
+        let found: Vec<&str> = words(text).into_iter().map(|range| &text[range]).collect();
+        assert_eq!(found, ["Don't", "stop", "rock’n’roll", "quoted", "42x"]);
+        assert_eq!(normalize("Rock’n’Roll"), "rock'n'roll");
+
+        let text = "  Hello there... How are you?! Fine ";
+        let found: Vec<&str> = sentences(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect();
+        assert_eq!(found, ["Hello there...", "How are you?!", "Fine"]);
+        assert!(sentences(" ?! ").is_empty());
+
+        let english = Stemmer::new(None);
+        assert_eq!(english.stem("recording"), "record");
+        assert_eq!(english.stem("records"), "record");
+        assert_eq!(Stemmer::new(Some("ja")).stem("recording"), "recording");
+
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("naïve", "naive"), 1);
+    }
+}
@@ -0,0 +1,441 @@
+//! Stored transcripts.
+//!
+//! Every recognition is kept in an SQLite database in app data: its
+//! metadata in columns, and the text, segments and alternatives as a JSON
+//! body, zstd-compressed when `storage.compression` is on. A body may take
+//! at most `storage.maxFileSize` bytes, and the store holds at most
+//! `storage.transcriptLimit` transcripts and `storage.maxStoreSize` bytes of
+//! bodies; past either, the least recently read go first.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::asr::{Alternative, Segment, Transcript};
+use crate::config::schema::StorageConfig;
+
+pub type TranscriptId = i64;
+
+/// Version of the table layout, kept in SQLite's `user_version`.
+const SCHEMA_VERSION: i64 = 1;
+const ZSTD_LEVEL: i32 = 3;
+/// Characters of text kept uncompressed for listing.
+const PREVIEW_CHARS: usize = 120;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TranscriptError {
+    #[error("no transcript with id {0}")]
+    NotFound(TranscriptId),
+    #[error("transcript is {size} bytes, more than the {limit} one may take")]
+    TooLarge { size: u64, limit: u64 },
+    #[error("transcript store is damaged: {0}")]
+    Corrupt(String),
+    #[error("could not compress transcript: {0}")]
+    Compression(#[source] std::io::Error),
+    #[error("transcript store version {0} is newer than this app supports")]
+    UnsupportedVersion(i64),
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("transcript store: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+impl TranscriptError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TranscriptError::NotFound(_) => "transcriptNotFound",
+            TranscriptError::TooLarge { .. } => "transcriptTooLarge",
+            TranscriptError::Corrupt(_) => "corruptTranscripts",
+            TranscriptError::Compression(_) => "io",
+            TranscriptError::UnsupportedVersion(_) => "unsupportedTranscriptsVersion",
+            TranscriptError::Io { .. } => "io",
+            TranscriptError::Database(_) => "database",
+        }
+    }
+}
+
+impl Serialize for TranscriptError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// The compressed part of a stored transcript.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Body {
+    text: String,
+    segments: Vec<Segment>,
+    alternatives: Vec<Alternative>,
+}
+
+/// A transcript as listed, without its body.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptSummary {
+    pub id: TranscriptId,
+    /// Milliseconds since the Unix epoch.
+    pub created: u64,
+    /// BCP-47 tag it was recognized as, e.g. `en-US`, or `auto`.
+    pub language: String,
+    pub confidence: f32,
+    /// The recording it was made from, if it was saved, as a path in the
+    /// project root.
+    pub audio: Option<String>,
+    /// The start of the text.
+    pub preview: String,
+    /// Bytes the body takes in the store.
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredTranscript {
+    #[serde(flatten)]
+    pub summary: TranscriptSummary,
+    pub text: String,
+    /// Times are seconds into the recording.
+    pub segments: Vec<Segment>,
+    pub alternatives: Vec<Alternative>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn preview(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+fn summary(row: &rusqlite::Row) -> rusqlite::Result<TranscriptSummary> {
+    Ok(TranscriptSummary {
+        id: row.get("id")?,
+        created: row.get::<_, i64>("created")? as u64,
+        language: row.get("language")?,
+        confidence: row.get::<_, f64>("confidence")? as f32,
+        audio: row.get("audio")?,
+        preview: row.get("preview")?,
+        size: row.get::<_, i64>("size")? as u64,
+    })
+}
+
+const SUMMARY_COLUMNS: &str =
+    "id, created, language, confidence, audio, preview, length(body) AS size";
+
+/// The open store. Clones share the connection, so commands can move one
+/// onto the blocking pool.
+#[derive(Clone)]
+pub struct TranscriptStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl TranscriptStore {
+    /// Opens (creating if needed) the store at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, TranscriptError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|source| TranscriptError::Io {
+                path: parent.to_path_buf(),
+                source,
+            })?;
+        }
+        let connection = Connection::open(path)?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(TranscriptError::UnsupportedVersion(version));
+        }
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS transcripts (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 created INTEGER NOT NULL,
+                 accessed INTEGER NOT NULL,
+                 language TEXT NOT NULL,
+                 confidence REAL NOT NULL,
+                 audio TEXT,
+                 preview TEXT NOT NULL,
+                 compressed INTEGER NOT NULL,
+                 body BLOB NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS transcripts_accessed ON transcripts (accessed, id);",
+        )?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stores `transcript`, recognized as `language` from the recording at
+    /// `audio`, then evicts the least recently read transcripts until the
+    /// store is within `limits`.
+    pub fn insert(
+        &self,
+        transcript: &Transcript,
+        language: &str,
+        audio: Option<&str>,
+        limits: &StorageConfig,
+    ) -> Result<TranscriptId, TranscriptError> {
+        let body = serde_json::to_vec(&Body {
+            text: transcript.text.clone(),
+            segments: transcript.segments.clone(),
+            alternatives: transcript.alternatives.clone(),
+        })
+        .expect("transcripts serialize");
+        let body = if limits.compression {
+            zstd::encode_all(body.as_slice(), ZSTD_LEVEL).map_err(TranscriptError::Compression)?
+        } else {
+            body
+        };
+        let limit = limits.max_file_size.min(limits.max_store_size);
+        if body.len() as u64 > limit {
+            return Err(TranscriptError::TooLarge {
+                size: body.len() as u64,
+                limit,
+            });
+        }
+
+        let mut connection = self.connection();
+        let tx = connection.transaction()?;
+        let created = now() as i64;
+        tx.execute(
+            "INSERT INTO transcripts
+                 (created, accessed, language, confidence, audio, preview, compressed, body)
+             VALUES (?1, ?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                created,
+                language,
+                transcript.confidence as f64,
+                audio,
+                preview(&transcript.text),
+                limits.compression,
+                body,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        evict(&tx, limits)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Every transcript, newest first.
+    pub fn list(&self) -> Result<Vec<TranscriptSummary>, TranscriptError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(&format!(
+            "SELECT {SUMMARY_COLUMNS} FROM transcripts ORDER BY created DESC, id DESC"
+        ))?;
+        let summaries = statement
+            .query_map([], summary)?
+            .collect::<Result<_, _>>()?;
+        Ok(summaries)
+    }
+
+    /// The transcript `id` in full, which counts as reading it.
+    pub fn get(&self, id: TranscriptId) -> Result<StoredTranscript, TranscriptError> {
+        let transcript = self.peek(id)?;
+        self.connection().execute(
+            "UPDATE transcripts SET accessed = ?1 WHERE id = ?2",
+            params![now() as i64, id],
+        )?;
+        Ok(transcript)
+    }
+
+    /// The transcript `id` in full, without counting as reading it, e.g.
+    /// for indexing.
+    pub fn peek(&self, id: TranscriptId) -> Result<StoredTranscript, TranscriptError> {
+        let found = self
+            .connection()
+            .query_row(
+                &format!(
+                    "SELECT {SUMMARY_COLUMNS}, compressed, body FROM transcripts WHERE id = ?1"
+                ),
+                [id],
+                |row| {
+                    Ok((
+                        summary(row)?,
+                        row.get::<_, bool>("compressed")?,
+                        row.get::<_, Vec<u8>>("body")?,
+                    ))
+                },
+            )
+            .optional()?;
+        let (summary, compressed, body) = found.ok_or(TranscriptError::NotFound(id))?;
+        let body = if compressed {
+            zstd::decode_all(body.as_slice())
+                .map_err(|e| TranscriptError::Corrupt(format!("transcript {id}: {e}")))?
+        } else {
+            body
+        };
+        let body: Body = serde_json::from_slice(&body)
+            .map_err(|e| TranscriptError::Corrupt(format!("transcript {id}: {e}")))?;
+        Ok(StoredTranscript {
+            summary,
+            text: body.text,
+            segments: body.segments,
+            alternatives: body.alternatives,
+        })
+    }
+
+    pub fn delete(&self, id: TranscriptId) -> Result<(), TranscriptError> {
+        let deleted = self
+            .connection()
+            .execute("DELETE FROM transcripts WHERE id = ?1", [id])?;
+        if deleted == 0 {
+            return Err(TranscriptError::NotFound(id));
+        }
+        Ok(())
+    }
+}
+
+/// Deletes the least recently read transcripts beyond `limits`.
+fn evict(connection: &Connection, limits: &StorageConfig) -> Result<(), TranscriptError> {
+    let mut statement = connection
+        .prepare("SELECT id, length(body) FROM transcripts ORDER BY accessed DESC, id DESC")?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok((row.get::<_, TranscriptId>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut total = 0;
+    for (kept, (id, size)) in rows.into_iter().enumerate() {
+        total += size as u64;
+        if kept >= limits.transcript_limit || total > limits.max_store_size {
+            connection.execute("DELETE FROM transcripts WHERE id = ?1", [id])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("transcripts-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn transcript(text: &str) -> Transcript {
+        Transcript {
+            text: text.into(),
+            confidence: 0.75,
+            alternatives: vec![Alternative {
+                transcript: text.into(),
+                confidence: 0.75,
+            }],
+            segments: vec![Segment {
+                text: text.into(),
+                start: 1.5,
+                end: 3.0,
+                confidence: 0.75,
+            }],
+        }
+    }
+
+    #[test]
+    fn stores_compressed_bodies_and_reopens() {
+        let path = temp_dir("round-trip").join("transcripts.db");
+        let store = TranscriptStore::open(&path).unwrap();
+        let long = "the quick brown fox jumps over the lazy dog ".repeat(50);
+        let id = store
+            .insert(
+                &transcript(&long),
+                "en-US",
+                Some("takes/one.wav"),
+                &StorageConfig::default(),
+            )
+            .unwrap();
+        drop(store);
+
+        let store = TranscriptStore::open(&path).unwrap();
+        let listed = store.list().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].size < long.len() as u64 / 4);
+        assert!(listed[0].preview.ends_with('…'));
+        let stored = store.get(id).unwrap();
+        assert_eq!(stored.text, long);
+        assert_eq!(stored.summary.audio.as_deref(), Some("takes/one.wav"));
+        assert_eq!(stored.segments[0].start, 1.5);
+
+        store.delete(id).unwrap();
+        assert!(matches!(store.get(id), Err(TranscriptError::NotFound(_))));
+        assert!(matches!(
+            store.delete(id),
+            Err(TranscriptError::NotFound(_))
+        ));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn evicts_least_recently_read_past_the_limits() {
+        let path = temp_dir("evict").join("transcripts.db");
+        let store = TranscriptStore::open(&path).unwrap();
+        let limits = StorageConfig {
+            transcript_limit: 2,
+            compression: false,
+            ..Default::default()
+        };
+        let first = store
+            .insert(&transcript("one"), "en", None, &limits)
+            .unwrap();
+        let second = store
+            .insert(&transcript("two"), "en", None, &limits)
+            .unwrap();
+        // Reading the first makes the second the least recently read.
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        store.get(first).unwrap();
+        let third = store
+            .insert(&transcript("three"), "en", None, &limits)
+            .unwrap();
+        let ids: Vec<_> = store.list().unwrap().iter().map(|t| t.id).collect();
+        assert_eq!(ids, [third, first]);
+        assert!(matches!(
+            store.get(second),
+            Err(TranscriptError::NotFound(_))
+        ));
+
+        // The store size limit evicts the same way.
+        let size = store.list().unwrap()[0].size;
+        let tight = StorageConfig {
+            transcript_limit: 10,
+            max_store_size: size * 2,
+            ..limits
+        };
+        let fourth = store
+            .insert(&transcript("four"), "en", None, &tight)
+            .unwrap();
+        let ids: Vec<_> = store.list().unwrap().iter().map(|t| t.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids[0], fourth);
+        // The per-transcript limit refuses what is too large, evicting
+        // nothing.
+        let tiny = StorageConfig {
+            max_file_size: 8,
+            ..limits
+        };
+        assert!(matches!(
+            store.insert(&transcript("five"), "en", None, &tiny),
+            Err(TranscriptError::TooLarge { limit: 8, .. })
+        ));
+        assert_eq!(store.list().unwrap().len(), 2);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
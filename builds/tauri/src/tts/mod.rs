@@ -0,0 +1,505 @@
+//! Local text-to-speech.
+//!
+//! Voices are Piper models in the voices directory. Text is spoken a
+//! sentence at a time: each sentence is phonemized, run through the
+//! [`VoiceModel`] and then aligned word by word against its own audio, so
+//! the timings returned with the speech line up with the word timeline.
+
+#[cfg(feature = "tts")]
+mod onnx;
+pub mod piper;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::asr::align;
+use crate::audio::buffer::AudioBuffer;
+use crate::audio::AudioError;
+use crate::mixer::MixerError;
+use crate::project::WordTiming;
+
+use self::piper::{Scales, VoiceConfig};
+
+const MODEL_EXTENSION: &str = "onnx";
+const CONFIG_EXTENSION: &str = "onnx.json";
+/// Longest run of words given to the model at once when the text has no
+/// sentence punctuation.
+const MAX_SENTENCE_WORDS: usize = 40;
+/// Ceiling for the peak level of the spoken audio.
+const MAX_PEAK: f32 = 0.95;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TtsError {
+    #[error("no voices installed; add a Piper voice to {0}")]
+    NoVoices(PathBuf),
+    #[error("voice not found: {0}")]
+    VoiceNotFound(String),
+    #[error("invalid voice config: {0}")]
+    InvalidVoice(String),
+    #[error("the voice has no speaker {0:?}")]
+    UnknownSpeaker(String),
+    #[error("invalid synthesis options: {0}")]
+    Invalid(String),
+    #[error("phonemization failed: {0}")]
+    Phonemizer(String),
+    #[error("this build was made without speech synthesis")]
+    Unavailable,
+    #[error("speech synthesis failed: {0}")]
+    #[cfg_attr(not(feature = "tts"), allow(dead_code))]
+    Engine(String),
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+    #[error(transparent)]
+    Mixer(#[from] MixerError),
+}
+
+impl TtsError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TtsError::NoVoices(_) => "noVoices",
+            TtsError::VoiceNotFound(_) => "voiceNotFound",
+            TtsError::InvalidVoice(_) => "invalidVoice",
+            TtsError::UnknownSpeaker(_) => "unknownSpeaker",
+            TtsError::Invalid(_) => "invalidSynthesis",
+            TtsError::Phonemizer(_) => "phonemizerFailed",
+            TtsError::Unavailable => "synthesisUnavailable",
+            TtsError::Engine(_) => "synthesisFailed",
+            TtsError::Io { .. } => "io",
+            TtsError::Audio(e) => e.kind(),
+            TtsError::Mixer(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for TtsError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// An installed voice, as listed in the UI's voice profiles.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceProfile {
+    /// File name without the extension, e.g. `en_US-lessac-medium`.
+    pub id: String,
+    pub name: String,
+    /// BCP-47 tag, e.g. `en-US`.
+    pub language: String,
+    pub quality: Option<String>,
+    pub sample_rate: u32,
+    /// Speaker names of a multi-speaker voice, in id order.
+    pub speakers: Vec<String>,
+}
+
+impl VoiceProfile {
+    fn new(id: &str, config: &VoiceConfig) -> Self {
+        let mut speakers: Vec<(&String, &i64)> = config.speaker_id_map.iter().collect();
+        speakers.sort_by_key(|(_, id)| **id);
+        Self {
+            id: id.to_string(),
+            name: config.dataset.clone().unwrap_or_else(|| id.to_string()),
+            language: config.language.code.replace('_', "-"),
+            quality: config.audio.quality.clone(),
+            sample_rate: config.audio.sample_rate,
+            speakers: speakers.into_iter().map(|(name, _)| name.clone()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SynthesisOptions {
+    /// Speaking rate; 1.0 is the voice's natural pace.
+    pub speed: f32,
+    /// Speaker name for multi-speaker voices; the first when omitted.
+    pub speaker: Option<String>,
+    /// Seconds of silence between sentences.
+    pub sentence_pause: f64,
+}
+
+impl Default for SynthesisOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            speaker: None,
+            sentence_pause: 0.2,
+        }
+    }
+}
+
+impl SynthesisOptions {
+    pub fn validate(&self) -> Result<(), TtsError> {
+        if !(0.25..=4.0).contains(&self.speed) {
+            return Err(TtsError::Invalid(format!(
+                "speed {} is outside 0.25 to 4",
+                self.speed
+            )));
+        }
+        if !(0.0..=5.0).contains(&self.sentence_pause) {
+            return Err(TtsError::Invalid(format!(
+                "sentence pause {} s is outside 0 to 5 s",
+                self.sentence_pause
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub trait VoiceModel: Send + Sync {
+    /// Speaks `phoneme_ids`, returning mono audio at the voice's rate.
+    fn synthesize(
+        &self,
+        phoneme_ids: &[i64],
+        speaker: Option<i64>,
+        scales: Scales,
+    ) -> Result<Vec<f32>, TtsError>;
+}
+
+/// A loaded voice.
+pub struct Voice {
+    pub profile: VoiceProfile,
+    config: VoiceConfig,
+    model: Box<dyn VoiceModel>,
+}
+
+/// Spoken text and when each of its words is said.
+pub struct Speech {
+    pub buffer: AudioBuffer,
+    /// One per whitespace-separated word of the text.
+    pub words: Vec<WordTiming>,
+}
+
+/// Groups the words of `text` into sentences at closing punctuation.
+fn sentences(text: &str) -> Vec<Vec<&str>> {
+    let mut sentences = Vec::new();
+    let mut sentence = Vec::new();
+    for word in text.split_whitespace() {
+        sentence.push(word);
+        let end = word
+            .trim_end_matches(['"', '\'', ')', ']', '\u{201d}', '\u{2019}'])
+            .ends_with(['.', '!', '?', ';', ':']);
+        if end || sentence.len() >= MAX_SENTENCE_WORDS {
+            sentences.push(std::mem::take(&mut sentence));
+        }
+    }
+    if !sentence.is_empty() {
+        sentences.push(sentence);
+    }
+    sentences
+}
+
+/// Speaks `text` with `voice`.
+pub fn synthesize(
+    voice: &Voice,
+    text: &str,
+    options: &SynthesisOptions,
+) -> Result<Speech, TtsError> {
+    options.validate()?;
+    let speaker = match &options.speaker {
+        Some(name) => Some(
+            *voice
+                .config
+                .speaker_id_map
+                .get(name)
+                .ok_or_else(|| TtsError::UnknownSpeaker(name.clone()))?,
+        ),
+        None => None,
+    };
+    let scales = Scales {
+        length_scale: voice.config.inference.length_scale / options.speed,
+        ..voice.config.inference
+    };
+    let sentences = sentences(text);
+    if sentences.is_empty() {
+        return Err(TtsError::Invalid("there is no text to speak".into()));
+    }
+
+    let rate = voice.profile.sample_rate;
+    let pause = vec![0.0; (options.sentence_pause * rate as f64) as usize];
+    let mut samples = Vec::new();
+    let mut words = Vec::new();
+    for (index, sentence) in sentences.iter().enumerate() {
+        if index > 0 {
+            samples.extend_from_slice(&pause);
+        }
+        let sentence = sentence.join(" ");
+        let phonemes = voice.config.phonemize(&sentence)?;
+        let audio =
+            voice
+                .model
+                .synthesize(&voice.config.phoneme_ids(&phonemes), speaker, scales)?;
+        let offset = samples.len() as f64 / rate as f64;
+        let timings = align::align(rate, std::slice::from_ref(&audio), &sentence);
+        words.extend(timings.into_iter().map(|word| WordTiming {
+            start: word.start + offset,
+            end: word.end + offset,
+            ..word
+        }));
+        samples.extend(audio);
+    }
+
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if peak > MAX_PEAK {
+        let gain = MAX_PEAK / peak;
+        samples.iter_mut().for_each(|s| *s *= gain);
+    }
+    Ok(Speech {
+        buffer: AudioBuffer::new(rate, vec![samples]),
+        words,
+    })
+}
+
+type LoadedVoice = (String, Arc<Voice>);
+
+/// The voices directory and the loaded voice. Clones share the cache, so
+/// commands can move one onto the blocking pool.
+#[derive(Clone)]
+pub struct Synthesizer {
+    voices_dir: PathBuf,
+    loaded: Arc<Mutex<Option<LoadedVoice>>>,
+}
+
+impl Synthesizer {
+    pub fn new(voices_dir: PathBuf) -> Self {
+        Self {
+            voices_dir,
+            loaded: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn io_error(&self, path: &Path, source: io::Error) -> TtsError {
+        TtsError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    fn config(&self, id: &str) -> Result<VoiceConfig, TtsError> {
+        let path = self.voices_dir.join(format!("{id}.{CONFIG_EXTENSION}"));
+        let json = fs::read(&path).map_err(|e| self.io_error(&path, e))?;
+        VoiceConfig::parse(&json)
+    }
+
+    /// Installed voices, by id. Models without a readable config are left
+    /// out, and a missing directory just means none.
+    pub fn voices(&self) -> Result<Vec<VoiceProfile>, TtsError> {
+        let entries = match fs::read_dir(&self.voices_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(self.io_error(&self.voices_dir, e)),
+        };
+        let mut voices = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| self.io_error(&self.voices_dir, e))?
+                .path();
+            if path.extension().and_then(|e| e.to_str()) != Some(MODEL_EXTENSION) {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if let Ok(config) = self.config(id) {
+                voices.push(VoiceProfile::new(id, &config));
+            }
+        }
+        voices.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(voices)
+    }
+
+    /// The voice `id`, loading it if it isn't the one already loaded. With
+    /// no id, the loaded voice or else the first installed one.
+    pub fn voice(&self, id: Option<&str>) -> Result<Arc<Voice>, TtsError> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((loaded_id, voice)) = loaded.as_ref() {
+            if id.is_none_or(|id| id == loaded_id) {
+                return Ok(Arc::clone(voice));
+            }
+        }
+        // Only installed voices, so an id can't name a path outside the
+        // voices dir.
+        let voices = self.voices()?;
+        let id = match id {
+            Some(id) => voices
+                .into_iter()
+                .find(|voice| voice.id == id)
+                .ok_or_else(|| TtsError::VoiceNotFound(id.to_string()))?,
+            None => voices
+                .into_iter()
+                .next()
+                .ok_or_else(|| TtsError::NoVoices(self.voices_dir.clone()))?,
+        }
+        .id;
+        let path = self.voices_dir.join(format!("{id}.{MODEL_EXTENSION}"));
+        let config = self.config(&id)?;
+        *loaded = None;
+        let voice = Arc::new(Voice {
+            profile: VoiceProfile::new(&id, &config),
+            model: load(&path, config.num_speakers > 1)?,
+            config,
+        });
+        *loaded = Some((id, Arc::clone(&voice)));
+        Ok(voice)
+    }
+}
+
+#[cfg(feature = "tts")]
+fn load(path: &Path, multi_speaker: bool) -> Result<Box<dyn VoiceModel>, TtsError> {
+    Ok(Box::new(onnx::OnnxVoice::load(path, multi_speaker)?))
+}
+
+#[cfg(not(feature = "tts"))]
+fn load(_path: &Path, _multi_speaker: bool) -> Result<Box<dyn VoiceModel>, TtsError> {
+    Err(TtsError::Unavailable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hums 50 ms for every id but the sentence markers and spaces, which
+    /// are silent and spaces four times as long, so each word comes out as
+    /// a burst of sound as long as its padded spelling.
+    struct Hum;
+
+    impl VoiceModel for Hum {
+        fn synthesize(
+            &self,
+            phoneme_ids: &[i64],
+            _speaker: Option<i64>,
+            scales: Scales,
+        ) -> Result<Vec<f32>, TtsError> {
+            let per_phoneme = (800.0 * scales.length_scale) as usize;
+            Ok(phoneme_ids
+                .iter()
+                .flat_map(|&id| {
+                    let sound = !matches!(id, 1..=3);
+                    let len = if id == 3 {
+                        per_phoneme * 4
+                    } else {
+                        per_phoneme
+                    };
+                    (0..len).map(move |i| {
+                        if sound {
+                            (i as f32 * 0.2).sin() * 2.0
+                        } else {
+                            0.0
+                        }
+                    })
+                })
+                .collect())
+        }
+    }
+
+    fn voice() -> Voice {
+        let mut map = serde_json::Map::new();
+        for (id, symbol) in ["_", "^", "$", " "].iter().enumerate() {
+            map.insert(symbol.to_string(), serde_json::json!([id]));
+        }
+        for (id, letter) in ('a'..='z').enumerate() {
+            map.insert(letter.to_string(), serde_json::json!([id + 4]));
+        }
+        let config = VoiceConfig::parse(
+            serde_json::json!({
+                "audio": { "sample_rate": 16000 },
+                "language": { "code": "en_US" },
+                "phoneme_type": "text",
+                "phoneme_id_map": map,
+                "speaker_id_map": { "amy": 0, "ben": 1 },
+                "num_speakers": 2,
+            })
+            .to_string()
+            .as_bytes(),
+        )
+        .unwrap();
+        Voice {
+            profile: VoiceProfile::new("en_US-test-low", &config),
+            config,
+            model: Box::new(Hum),
+        }
+    }
+
+    #[test]
+    fn speaks_sentences_with_word_timings() {
+        let voice = voice();
+        assert_eq!(voice.profile.language, "en-US");
+        assert_eq!(voice.profile.speakers, ["amy", "ben"]);
+        // Unknown characters are dropped; every phoneme is padded.
+        assert_eq!(
+            voice.config.phoneme_ids("a b!"),
+            [1, 0, 4, 0, 3, 0, 5, 0, 2]
+        );
+
+        let text = "Hi there.  Good\nmorning";
+        let speech = synthesize(&voice, text, &SynthesisOptions::default()).unwrap();
+        let words: Vec<&str> = speech.words.iter().map(|w| w.text.as_str()).collect();
+        assert_eq!(words, text.split_whitespace().collect::<Vec<_>>());
+        assert!(speech.words.windows(2).all(|w| w[0].end <= w[1].start));
+        assert!(speech.words[3].end <= speech.buffer.duration() + 1e-9);
+        // "morning" is seven letters and eight pads at 50 ms each.
+        let morning = &speech.words[3];
+        assert!(
+            (morning.end - morning.start - 0.75).abs() < 0.05,
+            "{morning:?}"
+        );
+        assert!(speech.buffer.channels[0]
+            .iter()
+            .all(|s| s.abs() <= MAX_PEAK));
+
+        let slow = SynthesisOptions {
+            speed: 0.5,
+            speaker: Some("ben".into()),
+            ..Default::default()
+        };
+        let slower = synthesize(&voice, text, &slow).unwrap();
+        assert!(slower.buffer.duration() > speech.buffer.duration() * 1.8);
+        let nobody = SynthesisOptions {
+            speaker: Some("cat".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            synthesize(&voice, text, &nobody),
+            Err(TtsError::UnknownSpeaker(_))
+        ));
+    }
+
+    #[test]
+    fn loads_only_installed_voices() {
+        let root = std::env::temp_dir().join(format!("tts-voices-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let voices_dir = root.join("voices");
+        fs::create_dir_all(&voices_dir).unwrap();
+        // A voice beside the voices dir, where `../outside` would point.
+        fs::write(root.join("outside.onnx"), b"").unwrap();
+        fs::write(
+            root.join("outside.onnx.json"),
+            br#"{ "audio": { "sample_rate": 16000 }, "phoneme_id_map": {} }"#,
+        )
+        .unwrap();
+
+        let synthesizer = Synthesizer::new(voices_dir.clone());
+        assert!(matches!(
+            synthesizer.voice(None),
+            Err(TtsError::NoVoices(_))
+        ));
+        for id in ["../outside", "missing", ""] {
+            assert!(
+                matches!(synthesizer.voice(Some(id)), Err(TtsError::VoiceNotFound(_))),
+                "{id}"
+            );
+        }
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,74 @@
+//! Piper models through ONNX Runtime.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use ort::session::builder::GraphOptimizationLevel;
+use ort::session::Session;
+use ort::value::Tensor;
+
+use super::piper::Scales;
+use super::{TtsError, VoiceModel};
+
+pub struct OnnxVoice {
+    session: Mutex<Session>,
+    multi_speaker: bool,
+}
+
+fn engine(error: impl std::fmt::Display) -> TtsError {
+    TtsError::Engine(error.to_string())
+}
+
+impl OnnxVoice {
+    pub fn load(path: &Path, multi_speaker: bool) -> Result<Self, TtsError> {
+        let threads = std::thread::available_parallelism().map_or(4, |n| n.get().min(8));
+        let session = Session::builder()
+            .and_then(|builder| builder.with_optimization_level(GraphOptimizationLevel::Level3))
+            .and_then(|builder| builder.with_intra_threads(threads))
+            .and_then(|builder| builder.commit_from_file(path))
+            .map_err(engine)?;
+        Ok(Self {
+            session: Mutex::new(session),
+            multi_speaker,
+        })
+    }
+}
+
+impl VoiceModel for OnnxVoice {
+    fn synthesize(
+        &self,
+        phoneme_ids: &[i64],
+        speaker: Option<i64>,
+        scales: Scales,
+    ) -> Result<Vec<f32>, TtsError> {
+        let input =
+            Tensor::from_array(([1, phoneme_ids.len()], phoneme_ids.to_vec())).map_err(engine)?;
+        let lengths = Tensor::from_array(([1], vec![phoneme_ids.len() as i64])).map_err(engine)?;
+        let scales = Tensor::from_array((
+            [3],
+            vec![scales.noise_scale, scales.length_scale, scales.noise_w],
+        ))
+        .map_err(engine)?;
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        let outputs = if self.multi_speaker {
+            let sid = Tensor::from_array(([1], vec![speaker.unwrap_or(0)])).map_err(engine)?;
+            session.run(ort::inputs![
+                "input" => input,
+                "input_lengths" => lengths,
+                "scales" => scales,
+                "sid" => sid,
+            ])
+        } else {
+            session.run(ort::inputs![
+                "input" => input,
+                "input_lengths" => lengths,
+                "scales" => scales,
+            ])
+        }
+        .map_err(engine)?;
+        let (_, audio) = outputs["output"]
+            .try_extract_tensor::<f32>()
+            .map_err(engine)?;
+        Ok(audio.to_vec())
+    }
+}
@@ -0,0 +1,159 @@
+//! Piper voice configs and phonemization.
+//!
+//! A Piper voice is a VITS model exported to ONNX, `<voice>.onnx`, next to
+//! `<voice>.onnx.json`, which says how to turn text into the phoneme ids
+//! the model takes and what scales it was trained to run at. Most voices
+//! read IPA from espeak-ng; a few read characters of the text directly.
+
+use std::collections::BTreeMap;
+use std::process::{Command, Stdio};
+
+use serde::Deserialize;
+
+use super::TtsError;
+
+/// Path of the espeak-ng executable; defaults to `espeak-ng` on `PATH`.
+pub const ESPEAK_ENV: &str = "NEURALVOICE_ESPEAK";
+
+const PAD: &str = "_";
+const BOS: &str = "^";
+const EOS: &str = "$";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AudioSection {
+    pub sample_rate: u32,
+    pub quality: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EspeakSection {
+    pub voice: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LanguageSection {
+    /// e.g. `en_US`.
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Scales {
+    pub noise_scale: f32,
+    /// Phoneme duration multiplier; above 1 is slower.
+    pub length_scale: f32,
+    pub noise_w: f32,
+}
+
+impl Default for Scales {
+    fn default() -> Self {
+        Self {
+            noise_scale: 0.667,
+            length_scale: 1.0,
+            noise_w: 0.8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PhonemeType {
+    #[default]
+    Espeak,
+    Text,
+}
+
+/// The `.onnx.json` next to a Piper model.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct VoiceConfig {
+    pub audio: AudioSection,
+    pub espeak: EspeakSection,
+    pub language: LanguageSection,
+    pub inference: Scales,
+    pub phoneme_type: PhonemeType,
+    pub phoneme_id_map: BTreeMap<String, Vec<i64>>,
+    pub num_speakers: u32,
+    pub speaker_id_map: BTreeMap<String, i64>,
+    pub dataset: Option<String>,
+}
+
+impl VoiceConfig {
+    pub fn parse(json: &[u8]) -> Result<Self, TtsError> {
+        let config: Self =
+            serde_json::from_slice(json).map_err(|e| TtsError::InvalidVoice(e.to_string()))?;
+        if config.audio.sample_rate == 0 {
+            return Err(TtsError::InvalidVoice("no sample rate".into()));
+        }
+        for symbol in [PAD, BOS, EOS] {
+            if !config.phoneme_id_map.contains_key(symbol) {
+                return Err(TtsError::InvalidVoice(format!(
+                    "phoneme map has no {symbol:?}"
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Phonemes for one sentence, as characters the phoneme map is keyed by.
+    pub fn phonemize(&self, sentence: &str) -> Result<String, TtsError> {
+        match self.phoneme_type {
+            PhonemeType::Text => Ok(sentence.to_lowercase()),
+            PhonemeType::Espeak => espeak(&self.espeak.voice, sentence),
+        }
+    }
+
+    /// Model input for `phonemes`: each known phoneme's ids followed by
+    /// padding, between the sentence start and end markers. Phonemes the
+    /// voice wasn't trained on are dropped.
+    pub fn phoneme_ids(&self, phonemes: &str) -> Vec<i64> {
+        let map = &self.phoneme_id_map;
+        let mut ids = map[BOS].clone();
+        ids.extend(&map[PAD]);
+        let mut symbol = [0u8; 4];
+        for phoneme in phonemes.chars() {
+            if let Some(phoneme_ids) = map.get(&*phoneme.encode_utf8(&mut symbol)) {
+                ids.extend(phoneme_ids);
+                ids.extend(&map[PAD]);
+            }
+        }
+        ids.extend(&map[EOS]);
+        ids
+    }
+}
+
+/// IPA for `sentence` from the espeak-ng command line. Clause breaks come
+/// back as line breaks and are kept as commas; the sentence's own closing
+/// punctuation is put back so the model hears a statement or a question.
+fn espeak(voice: &str, sentence: &str) -> Result<String, TtsError> {
+    let program = std::env::var(ESPEAK_ENV).unwrap_or_else(|_| "espeak-ng".into());
+    let output = Command::new(&program)
+        .args(["-q", "--ipa", "-v", voice, "--", sentence])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| TtsError::Phonemizer(format!("{program}: {e}")))?;
+    if !output.status.success() {
+        return Err(TtsError::Phonemizer(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    let ipa = String::from_utf8_lossy(&output.stdout);
+    let mut phonemes = ipa
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Some(end) = sentence
+        .trim_end()
+        .chars()
+        .last()
+        .filter(|c| ".!?".contains(*c))
+    {
+        phonemes.push(end);
+    }
+    Ok(phonemes)
+}
@@ -0,0 +1,36 @@
+//! Voice activity detection on live capture, analysed on a worker fed
+//! through a [`FrameQueue`](crate::audio::capture::FrameQueue).
+
+use std::thread;
+
+use crate::audio::capture::{frame_queue, CaptureEngine, CaptureError, SubscriptionId};
+
+use super::{Detector, VadEvent, SAMPLE_RATE};
+
+pub struct VadSession {
+    subscription: SubscriptionId,
+}
+
+impl VadSession {
+    pub fn start(
+        engine: &CaptureEngine,
+        mut detector: Detector,
+        mut emit: impl FnMut(VadEvent) + Send + 'static,
+    ) -> Result<Self, CaptureError> {
+        let (sender, frames) = frame_queue();
+        thread::Builder::new()
+            .name("voice-activity".into())
+            .spawn(move || {
+                while let Some(frame) = frames.recv() {
+                    detector.push(&frame.mono(SAMPLE_RATE), &mut emit);
+                }
+            })
+            .map_err(|e| CaptureError::Backend(e.to_string()))?;
+        let subscription = engine.forward(sender);
+        Ok(Self { subscription })
+    }
+
+    pub fn stop(self, engine: &CaptureEngine) {
+        engine.unsubscribe(self.subscription);
+    }
+}
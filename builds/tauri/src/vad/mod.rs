@@ -0,0 +1,442 @@
+//! Voice activity detection.
+//!
+//! Audio is analysed at 16 kHz in 32 ms frames every 10 ms. A frame counts
+//! as speech when it is
+//!
+//! * louder than `silenceThreshold` and clearly above the tracked noise
+//!   floor,
+//! * mostly in the speech band, 250 Hz to 4 kHz, and
+//! * not noise-like: a low spectral flatness in that band.
+//!
+//! A run of speech frames opens a segment; a pause longer than a short
+//! hangover closes it, so consonants and brief gaps between words stay
+//! inside. The same [`Detector`] splits buffers offline and follows capture
+//! live, where a pause of `silenceTimeout` also ends the recording.
+
+pub mod live;
+
+use std::sync::{Arc, Mutex};
+
+use realfft::num_complex::Complex32;
+use realfft::{RealFftPlanner, RealToComplex};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::buffer::AudioBuffer;
+use crate::audio::capture::{CaptureEngine, CaptureError};
+use crate::audio::resample::resample;
+use crate::dsp::mixdown;
+
+use self::live::VadSession;
+
+/// Rate audio is analysed at.
+pub const SAMPLE_RATE: u32 = 16000;
+const WINDOW: usize = 512;
+const HOP: usize = 160;
+const SPEECH_BAND: (f32, f32) = (250.0, 4000.0);
+/// Energy below this is hum and rumble, left out of the band ratio.
+const LOW_CUT_HZ: f32 = 80.0;
+/// Share of the energy a speech frame has in the speech band. Voiced
+/// speech keeps much of its energy in the fundamental, below the band;
+/// this rules out rumble and hiss that sit wholly outside it.
+const MIN_BAND_RATIO: f32 = 0.3;
+/// Flatness of white noise is about 0.56; voiced speech is far lower.
+const MAX_FLATNESS: f32 = 0.4;
+/// Margin a speech frame needs above the noise floor, in dB.
+const ABOVE_FLOOR_DB: f32 = 9.0;
+/// How fast the floor follows non-speech frames down and up, per frame.
+const FLOOR_FALL: f32 = 0.2;
+const FLOOR_RISE: f32 = 0.01;
+/// Speech frames in a row that open a segment.
+const ONSET_FRAMES: usize = 5;
+/// Silent frames in a row that close one.
+const HANGOVER_FRAMES: usize = 30;
+/// Seconds kept around the detected speech, for soft onsets and decays.
+const PRE_ROLL: f64 = 0.1;
+const TAIL: f64 = 0.1;
+
+/// Mirrors the voice activity settings in the `voice.activation` section
+/// of `config/default.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VadConfig {
+    pub voice_activity_detection: bool,
+    /// Quietest speech, as an RMS level on a 16-bit sample scale.
+    pub silence_threshold: f32,
+    /// Milliseconds of silence after speech that end a recording.
+    pub silence_timeout: u32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            voice_activity_detection: true,
+            silence_threshold: 500.0,
+            silence_timeout: 1500,
+        }
+    }
+}
+
+impl VadConfig {
+    fn threshold_db(&self) -> f32 {
+        20.0 * (self.silence_threshold.max(1.0) / 32768.0).log10()
+    }
+}
+
+/// A stretch of speech, in seconds from the start of the audio.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeechSegment {
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum VadEvent {
+    /// Speech began at `time`.
+    SpeechStart {
+        time: f64,
+    },
+    SpeechEnd {
+        segment: SpeechSegment,
+    },
+    /// Nothing has been said for `silenceTimeout` since the last speech.
+    SilenceTimeout {
+        time: f64,
+    },
+}
+
+struct Frame {
+    level: f32,
+    band_ratio: f32,
+    flatness: f32,
+}
+
+struct Analyzer {
+    window: Vec<f32>,
+    window_energy: f32,
+    fft: Arc<dyn RealToComplex<f32>>,
+    input: Vec<f32>,
+    spectrum: Vec<Complex32>,
+    low_cut: usize,
+    band: (usize, usize),
+}
+
+impl Analyzer {
+    fn new() -> Self {
+        let window: Vec<f32> = (0..WINDOW)
+            .map(|n| 0.5 - 0.5 * (std::f32::consts::TAU * n as f32 / WINDOW as f32).cos())
+            .collect();
+        let fft = RealFftPlanner::new().plan_fft_forward(WINDOW);
+        let to_bin = |hz: f32| (hz * WINDOW as f32 / SAMPLE_RATE as f32) as usize;
+        Self {
+            window_energy: window.iter().map(|w| w * w).sum(),
+            window,
+            input: fft.make_input_vec(),
+            spectrum: fft.make_output_vec(),
+            fft,
+            low_cut: to_bin(LOW_CUT_HZ),
+            band: (to_bin(SPEECH_BAND.0), to_bin(SPEECH_BAND.1)),
+        }
+    }
+
+    fn frame(&mut self, samples: &[f32]) -> Frame {
+        for ((slot, sample), w) in self.input.iter_mut().zip(samples).zip(&self.window) {
+            *slot = sample * w;
+        }
+        self.fft
+            .process(&mut self.input, &mut self.spectrum)
+            .expect("buffers come from the planner");
+        let power: Vec<f32> = self.spectrum.iter().map(Complex32::norm_sqr).collect();
+
+        let total: f32 = power.iter().sum();
+        // Parseval over the one-sided spectrum, undoing the window.
+        let mean_square = 2.0 * total / (WINDOW as f32 * self.window_energy);
+        let band = &power[self.band.0..self.band.1];
+        let in_band: f32 = band.iter().sum();
+        let above_cut: f32 = power[self.low_cut..].iter().sum();
+        let log_mean = band.iter().map(|p| p.max(1e-20).ln()).sum::<f32>() / band.len() as f32;
+        Frame {
+            level: 10.0 * mean_square.max(1e-12).log10(),
+            band_ratio: in_band / above_cut.max(1e-20),
+            flatness: log_mean.exp() / (in_band / band.len() as f32).max(1e-20),
+        }
+    }
+}
+
+/// Finds speech in 16 kHz mono audio fed in blocks of any size.
+pub struct Detector {
+    analyzer: Analyzer,
+    threshold_db: f32,
+    timeout_frames: usize,
+    floor: f32,
+    pending: Vec<f32>,
+    /// Frames analysed so far.
+    frames: usize,
+    /// Speech frames in a row, and the first of them.
+    run: usize,
+    run_start: usize,
+    /// Start of the open segment, in seconds.
+    open: Option<f64>,
+    last_speech: Option<usize>,
+    /// End of the last closed segment, so segments never overlap.
+    closed_at: f64,
+    timed_out: bool,
+}
+
+fn frame_time(frame: usize) -> f64 {
+    (frame * HOP + WINDOW / 2) as f64 / SAMPLE_RATE as f64
+}
+
+impl Detector {
+    pub fn new(config: &VadConfig) -> Self {
+        let threshold_db = config.threshold_db();
+        Self {
+            analyzer: Analyzer::new(),
+            threshold_db,
+            timeout_frames: (config.silence_timeout as usize * SAMPLE_RATE as usize / 1000)
+                .div_ceil(HOP),
+            // Until the room has been heard, the threshold is all there is.
+            floor: threshold_db - ABOVE_FLOOR_DB,
+            pending: Vec::new(),
+            frames: 0,
+            run: 0,
+            run_start: 0,
+            open: None,
+            last_speech: None,
+            closed_at: 0.0,
+            timed_out: false,
+        }
+    }
+
+    /// Feeds `samples` and reports what they start or end.
+    pub fn push(&mut self, samples: &[f32], emit: &mut dyn FnMut(VadEvent)) {
+        self.pending.extend_from_slice(samples);
+        let mut start = 0;
+        while start + WINDOW <= self.pending.len() {
+            let frame = self.analyzer.frame(&self.pending[start..start + WINDOW]);
+            self.step(frame, emit);
+            start += HOP;
+        }
+        self.pending.drain(..start);
+    }
+
+    /// Closes a segment still open at the end of the audio, which lasts
+    /// `duration` seconds.
+    pub fn finish(&mut self, duration: f64, emit: &mut dyn FnMut(VadEvent)) {
+        if let (Some(start), Some(last)) = (self.open.take(), self.last_speech) {
+            let end = (frame_time(last) + TAIL).min(duration);
+            emit(VadEvent::SpeechEnd {
+                segment: SpeechSegment { start, end },
+            });
+        }
+    }
+
+    fn is_speech(&self, frame: &Frame) -> bool {
+        frame.level >= self.threshold_db
+            && frame.level >= self.floor + ABOVE_FLOOR_DB
+            && frame.band_ratio >= MIN_BAND_RATIO
+            && frame.flatness <= MAX_FLATNESS
+    }
+
+    fn step(&mut self, frame: Frame, emit: &mut dyn FnMut(VadEvent)) {
+        let index = self.frames;
+        self.frames += 1;
+        if self.is_speech(&frame) {
+            if self.run == 0 {
+                self.run_start = index;
+            }
+            self.run += 1;
+            self.last_speech = Some(index);
+            self.timed_out = false;
+            if self.open.is_none() && self.run >= ONSET_FRAMES {
+                let time = (frame_time(self.run_start) - PRE_ROLL).max(self.closed_at);
+                self.open = Some(time);
+                emit(VadEvent::SpeechStart { time });
+            }
+            return;
+        }
+
+        self.run = 0;
+        let rate = if frame.level < self.floor {
+            FLOOR_FALL
+        } else {
+            FLOOR_RISE
+        };
+        self.floor += (frame.level - self.floor) * rate;
+
+        let Some(last) = self.last_speech else {
+            return;
+        };
+        let silent = index - last;
+        if silent >= HANGOVER_FRAMES {
+            if let Some(start) = self.open.take() {
+                let end = frame_time(last) + TAIL;
+                self.closed_at = end;
+                emit(VadEvent::SpeechEnd {
+                    segment: SpeechSegment { start, end },
+                });
+            }
+        }
+        if silent >= self.timeout_frames && !self.timed_out {
+            self.timed_out = true;
+            emit(VadEvent::SilenceTimeout {
+                time: frame_time(index),
+            });
+        }
+    }
+}
+
+/// Speech segments of `buffer`, analysed as one mixed-down signal. With
+/// detection disabled the whole buffer is one segment.
+pub fn segments(buffer: &AudioBuffer, config: &VadConfig) -> Vec<SpeechSegment> {
+    let duration = buffer.duration();
+    if !config.voice_activity_detection {
+        return vec![SpeechSegment {
+            start: 0.0,
+            end: duration,
+        }];
+    }
+    let mono = AudioBuffer::new(buffer.sample_rate, vec![mixdown(&buffer.channels)]);
+    let signal = resample(&mono, SAMPLE_RATE)
+        .channels
+        .pop()
+        .unwrap_or_default();
+    let mut detector = Detector::new(config);
+    let mut segments = Vec::new();
+    let mut collect = |event| {
+        if let VadEvent::SpeechEnd { segment } = event {
+            segments.push(segment);
+        }
+    };
+    detector.push(&signal, &mut collect);
+    detector.finish(duration, &mut collect);
+    segments
+}
+
+/// The live detection session, if any.
+#[derive(Default)]
+pub struct VoiceActivity {
+    session: Mutex<Option<VadSession>>,
+}
+
+impl VoiceActivity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts following `engine`'s frames, replacing any earlier session.
+    /// With detection disabled nothing is started.
+    pub fn start(
+        &self,
+        engine: &CaptureEngine,
+        config: &VadConfig,
+        emit: impl FnMut(VadEvent) + Send + 'static,
+    ) -> Result<(), CaptureError> {
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(previous) = session.take() {
+            previous.stop(engine);
+        }
+        if config.voice_activity_detection {
+            *session = Some(VadSession::start(engine, Detector::new(config), emit)?);
+        }
+        Ok(())
+    }
+
+    /// Stops following capture. Stopping when nothing is running is a no-op.
+    pub fn stop(&self, engine: &CaptureEngine) {
+        let session = self
+            .session
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(session) = session {
+            session.stop(engine);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A voiced sound: a 140 Hz buzz with harmonics falling off like a
+    /// vowel's, at `gain`.
+    fn vowel(seconds: f64, gain: f32) -> Vec<f32> {
+        let rate = SAMPLE_RATE as f32;
+        (0..(seconds * SAMPLE_RATE as f64) as usize)
+            .map(|i| {
+                let t = i as f32 / rate;
+                (1..=20)
+                    .map(|k| (std::f32::consts::TAU * 140.0 * k as f32 * t).sin() / k as f32)
+                    .sum::<f32>()
+                    * gain
+                    / 3.0
+            })
+            .collect()
+    }
+
+    fn noise(seconds: f64, gain: f32, seed: &mut u32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f64) as usize)
+            .map(|_| {
+                *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                ((*seed >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0) * gain
+            })
+            .collect()
+    }
+
+    #[test]
+    fn segments_speech_and_ignores_noise() {
+        let mut seed = 1;
+        let mut signal = noise(0.5, 0.001, &mut seed);
+        // Two words 0.15 s apart are one segment.
+        signal.extend(vowel(0.6, 0.3));
+        signal.extend(noise(0.15, 0.001, &mut seed));
+        signal.extend(vowel(0.4, 0.3));
+        signal.extend(noise(1.0, 0.001, &mut seed));
+        // Loud hiss is not speech.
+        signal.extend(noise(0.5, 0.2, &mut seed));
+        signal.extend(noise(0.5, 0.001, &mut seed));
+        signal.extend(vowel(0.5, 0.1));
+        signal.extend(noise(2.0, 0.001, &mut seed));
+        let buffer = AudioBuffer::new(SAMPLE_RATE, vec![signal.clone(), signal.clone()]);
+
+        let config = VadConfig::default();
+        let found = segments(&buffer, &config);
+        let expected = [(0.5, 1.65), (3.65, 4.15)];
+        assert_eq!(found.len(), expected.len(), "{found:?}");
+        for (segment, (start, end)) in found.iter().zip(expected) {
+            assert!((segment.start - start).abs() < 0.12, "{found:?}");
+            assert!((segment.end - end).abs() < 0.12, "{found:?}");
+        }
+
+        // Live, the timeout fires once after each stretch of speech.
+        let mut detector = Detector::new(&config);
+        let mut events = Vec::new();
+        for block in signal.chunks(777) {
+            detector.push(block, &mut |event| events.push(event));
+        }
+        let timeouts: Vec<f64> = events
+            .iter()
+            .filter_map(|event| match event {
+                VadEvent::SilenceTimeout { time } => Some(*time),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(timeouts.len(), 2, "{events:?}");
+        assert!((timeouts[1] - 4.15 - 1.5).abs() < 0.12, "{events:?}");
+        assert!(matches!(events[0], VadEvent::SpeechStart { .. }));
+
+        let disabled = VadConfig {
+            voice_activity_detection: false,
+            ..config
+        };
+        assert_eq!(
+            segments(&buffer, &disabled),
+            [SpeechSegment {
+                start: 0.0,
+                end: buffer.duration()
+            }]
+        );
+    }
+}
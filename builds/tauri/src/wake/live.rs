@@ -0,0 +1,43 @@
+//! Wake-word spotting on live capture, matched on a worker fed through a
+//! [`FrameQueue`](crate::audio::capture::FrameQueue).
+
+use std::thread::{self, JoinHandle};
+
+use crate::audio::capture::{frame_queue, CaptureEngine, SubscriptionId};
+
+use super::{Spotter, WakeDetection, WakeError, SAMPLE_RATE};
+
+pub struct WakeSession {
+    subscription: SubscriptionId,
+    // Detached on stop; it exits once the subscription and its queue are gone.
+    _worker: JoinHandle<()>,
+}
+
+impl WakeSession {
+    pub fn start(
+        engine: &CaptureEngine,
+        mut spotter: Spotter,
+        mut emit: impl FnMut(WakeDetection) + Send + 'static,
+    ) -> Result<Self, WakeError> {
+        let (sender, frames) = frame_queue();
+        let worker = thread::Builder::new()
+            .name("wake-word".into())
+            .spawn(move || {
+                while let Some(frame) = frames.recv() {
+                    if let Some(detection) = spotter.push(&frame.mono(SAMPLE_RATE)) {
+                        emit(detection);
+                    }
+                }
+            })
+            .map_err(|e| WakeError::Listener(e.to_string()))?;
+        let subscription = engine.forward(sender);
+        Ok(Self {
+            subscription,
+            _worker: worker,
+        })
+    }
+
+    pub fn stop(self, engine: &CaptureEngine) {
+        engine.unsubscribe(self.subscription);
+    }
+}
@@ -0,0 +1,539 @@
+//! Wake-word spotting.
+//!
+//! A wake word is enrolled from a few recordings of it being said. Each
+//! recording is trimmed to the spoken part and kept as a template: its
+//! MFCC frames without the loudness coefficient, so how far away the
+//! speaker stands doesn't matter. While listening, the last couple of
+//! seconds of capture are matched against every template with subsequence
+//! dynamic time warping. A match closer than the enrolled recordings are
+//! to each other, loosened by the sensitivity, counts as the phrase.
+
+pub mod live;
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::audio::buffer::AudioBuffer;
+use crate::audio::capture::CaptureEngine;
+use crate::audio::resample::resample;
+use crate::audio::AudioError;
+use crate::dsp::mfcc::{self, Features, Mfcc, MfccStream, COEFFICIENTS};
+use crate::dsp::mixdown;
+use crate::sandbox::write_atomic;
+
+use self::live::WakeSession;
+
+/// Rate templates and capture are analysed at.
+pub const SAMPLE_RATE: u32 = 16000;
+const SAMPLES_RANGE: (usize, usize) = (3, 10);
+/// Seconds of speech an enrollment recording must hold once trimmed.
+const SPOKEN_RANGE: (f64, f64) = (0.2, 3.0);
+/// Frames this far below a recording's loudest are trimmed from its ends.
+const TRIM_BELOW_PEAK_DB: f32 = 25.0;
+/// Frames between matches while listening.
+const CHECK_EVERY: usize = 5;
+/// Frames to ignore after a detection, so one utterance fires once.
+const COOLDOWN_FRAMES: usize = 100;
+/// How much slower than the longest template the phrase may be said.
+const MAX_STRETCH: f32 = 1.6;
+/// Floor for the enrolled spread, for recordings that are near copies.
+const MIN_SPREAD: f32 = 1.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WakeError {
+    #[error("no wake word {0:?} has been enrolled")]
+    NotEnrolled(String),
+    #[error("invalid wake word: {0}")]
+    Invalid(String),
+    #[error("wake-word detection is already running")]
+    AlreadyRunning,
+    #[error("could not start listening: {0}")]
+    Listener(String),
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+}
+
+impl WakeError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WakeError::NotEnrolled(_) => "wakeWordNotEnrolled",
+            WakeError::Invalid(_) => "invalidWakeWord",
+            WakeError::AlreadyRunning => "wakeWordRunning",
+            WakeError::Listener(_) => "wakeWordListenerFailed",
+            WakeError::Io { .. } => "io",
+            WakeError::Audio(e) => e.kind(),
+        }
+    }
+}
+
+impl Serialize for WakeError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::error::serialize(self.kind(), self, serializer)
+    }
+}
+
+/// Mirrors the wake-word settings in the `voice.activation` section of
+/// `config/default.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WakeConfig {
+    pub wake_word: String,
+    /// 0 (strict) to 1 (eager).
+    pub wake_word_sensitivity: f32,
+}
+
+impl Default for WakeConfig {
+    fn default() -> Self {
+        Self {
+            wake_word: "hey neural".into(),
+            wake_word_sensitivity: 0.8,
+        }
+    }
+}
+
+/// The spectral shape of one frame: its MFCCs less the loudness.
+type Shape = [f32; COEFFICIENTS - 1];
+
+fn shape(features: &Features) -> Shape {
+    features[1..]
+        .try_into()
+        .expect("one less than COEFFICIENTS")
+}
+
+fn distance(a: &Shape, b: &Shape) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Dynamic time warping of `template` against `sequence`. Returns, for
+/// each frame of `sequence`, the mean frame distance along the best path
+/// that ends with the template's last frame there. With `open_start` the
+/// path may begin anywhere in `sequence`, otherwise only at its start.
+fn warp(template: &[Shape], sequence: &[Shape], open_start: bool) -> Vec<f32> {
+    // Accumulated cost and path length, one template row at a time.
+    let mut row: Vec<(f32, u32)> = Vec::with_capacity(sequence.len());
+    for (j, frame) in sequence.iter().enumerate() {
+        let d = distance(&template[0], frame);
+        row.push(match (open_start, j) {
+            (true, _) | (false, 0) => (d, 1),
+            (false, _) => (row[j - 1].0 + d, row[j - 1].1 + 1),
+        });
+    }
+    for expected in &template[1..] {
+        let mut next: Vec<(f32, u32)> = Vec::with_capacity(sequence.len());
+        for (j, frame) in sequence.iter().enumerate() {
+            let mut best = row[j];
+            if j > 0 {
+                for candidate in [row[j - 1], next[j - 1]] {
+                    if candidate.0 < best.0 {
+                        best = candidate;
+                    }
+                }
+            }
+            next.push((best.0 + distance(expected, frame), best.1 + 1));
+        }
+        row = next;
+    }
+    row.into_iter()
+        .map(|(cost, steps)| cost / steps as f32)
+        .collect()
+}
+
+/// An enrolled wake word.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WakeModel {
+    pub phrase: String,
+    templates: Vec<Vec<Shape>>,
+    /// Mean warped distance between the enrolled recordings.
+    spread: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WakeWordInfo {
+    pub phrase: String,
+    pub samples: usize,
+}
+
+impl WakeModel {
+    fn info(&self) -> WakeWordInfo {
+        WakeWordInfo {
+            phrase: self.phrase.clone(),
+            samples: self.templates.len(),
+        }
+    }
+
+    fn matches(&self, phrase: &str) -> bool {
+        self.phrase.eq_ignore_ascii_case(phrase.trim())
+    }
+}
+
+/// The spoken part of `buffer` as a template.
+fn template(mfcc: &mut Mfcc, buffer: &AudioBuffer) -> Result<Vec<Shape>, WakeError> {
+    let mono = AudioBuffer::new(buffer.sample_rate, vec![mixdown(&buffer.channels)]);
+    let signal = resample(&mono, SAMPLE_RATE)
+        .channels
+        .pop()
+        .unwrap_or_default();
+    let frames = mfcc.compute(&signal);
+    let levels: Vec<f32> = frames.iter().map(mfcc::level_db).collect();
+    let peak = levels.iter().copied().fold(f32::MIN, f32::max);
+    let loud = |level: &f32| *level > peak - TRIM_BELOW_PEAK_DB;
+    let (Some(first), Some(last)) = (levels.iter().position(loud), levels.iter().rposition(loud))
+    else {
+        return Err(WakeError::Invalid("a recording is empty".into()));
+    };
+    let seconds = (last + 1 - first) as f64 * mfcc.hop() as f64 / SAMPLE_RATE as f64;
+    if !(SPOKEN_RANGE.0..=SPOKEN_RANGE.1).contains(&seconds) {
+        return Err(WakeError::Invalid(format!(
+            "a recording holds {seconds:.1} s of speech; say the phrase once, in {} to {} s",
+            SPOKEN_RANGE.0, SPOKEN_RANGE.1
+        )));
+    }
+    Ok(frames[first..=last].iter().map(shape).collect())
+}
+
+/// Builds a wake word from `recordings` of `phrase`.
+pub fn enroll(phrase: &str, recordings: &[&AudioBuffer]) -> Result<WakeModel, WakeError> {
+    let phrase = phrase.trim();
+    if phrase.is_empty() {
+        return Err(WakeError::Invalid("the phrase must not be empty".into()));
+    }
+    if !(SAMPLES_RANGE.0..=SAMPLES_RANGE.1).contains(&recordings.len()) {
+        return Err(WakeError::Invalid(format!(
+            "enrolling takes {} to {} recordings, not {}",
+            SAMPLES_RANGE.0,
+            SAMPLES_RANGE.1,
+            recordings.len()
+        )));
+    }
+    let mut mfcc = Mfcc::new(SAMPLE_RATE);
+    let templates = recordings
+        .iter()
+        .map(|buffer| template(&mut mfcc, buffer))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut distances = Vec::new();
+    for (i, a) in templates.iter().enumerate() {
+        for b in &templates[i + 1..] {
+            distances.push(*warp(a, b, false).last().expect("templates aren't empty"));
+        }
+    }
+    let spread = distances.iter().sum::<f32>() / distances.len() as f32;
+    Ok(WakeModel {
+        phrase: phrase.to_string(),
+        templates,
+        spread: spread.max(MIN_SPREAD),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WakeDetection {
+    pub phrase: String,
+    /// 0.5 at the detection threshold up to 1 for a perfect match.
+    pub confidence: f32,
+    /// Seconds since listening started at which the phrase ended.
+    pub time: f64,
+}
+
+/// Listens for one wake word in a stream of 16 kHz mono audio.
+pub struct Spotter {
+    model: WakeModel,
+    threshold: f32,
+    stream: MfccStream,
+    history: VecDeque<Shape>,
+    capacity: usize,
+    shortest: usize,
+    since_check: usize,
+    cooldown: usize,
+    frames: u64,
+    hop: usize,
+}
+
+impl Spotter {
+    pub fn new(model: WakeModel, sensitivity: f32) -> Self {
+        let mfcc = Mfcc::new(SAMPLE_RATE);
+        let lengths = model.templates.iter().map(Vec::len);
+        let longest = lengths.clone().max().unwrap_or(1);
+        Self {
+            threshold: model.spread * (0.8 + 0.8 * sensitivity.clamp(0.0, 1.0)),
+            hop: mfcc.hop(),
+            stream: MfccStream::new(mfcc),
+            history: VecDeque::new(),
+            capacity: (longest as f32 * MAX_STRETCH) as usize + CHECK_EVERY,
+            shortest: lengths.min().unwrap_or(1),
+            since_check: 0,
+            cooldown: 0,
+            frames: 0,
+            model,
+        }
+    }
+
+    /// Feeds audio and reports the phrase if it has just been said.
+    pub fn push(&mut self, samples: &[f32]) -> Option<WakeDetection> {
+        let mut detection = None;
+        for features in self.stream.push(samples) {
+            self.frames += 1;
+            if self.cooldown > 0 {
+                self.cooldown -= 1;
+                continue;
+            }
+            if self.history.len() == self.capacity {
+                self.history.pop_front();
+            }
+            self.history.push_back(shape(&features));
+            self.since_check += 1;
+            if self.since_check >= CHECK_EVERY && self.history.len() >= self.shortest {
+                self.since_check = 0;
+                detection = detection.or_else(|| self.check());
+            }
+        }
+        detection
+    }
+
+    fn check(&mut self) -> Option<WakeDetection> {
+        let history = self.history.make_contiguous();
+        // Only paths ending since the last check, so each ending is judged
+        // once.
+        let recent = history.len().saturating_sub(CHECK_EVERY);
+        let best = self
+            .model
+            .templates
+            .iter()
+            .flat_map(|template| warp(template, history, true).split_off(recent))
+            .fold(f32::INFINITY, f32::min);
+        if best >= self.threshold {
+            return None;
+        }
+        self.history.clear();
+        self.cooldown = COOLDOWN_FRAMES;
+        Some(WakeDetection {
+            phrase: self.model.phrase.clone(),
+            confidence: 1.0 - 0.5 * best / self.threshold,
+            time: (self.frames as usize * self.hop) as f64 / SAMPLE_RATE as f64,
+        })
+    }
+}
+
+/// Enrolled wake words, kept in a JSON file, and the listening session.
+pub struct WakeWords {
+    path: PathBuf,
+    models: Mutex<Vec<WakeModel>>,
+    session: Mutex<Option<WakeSession>>,
+}
+
+impl WakeWords {
+    /// Loads wake words from `path`. A missing file means there are none;
+    /// an unreadable one is moved aside rather than blocking startup.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, WakeError> {
+        let path = path.into();
+        let models = match fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<Vec<WakeModel>>(&bytes) {
+                Ok(models) => models,
+                Err(error) => {
+                    let aside = path.with_extension("json.corrupt");
+                    log::warn!("{}: {error}; moved to {}", path.display(), aside.display());
+                    fs::rename(&path, &aside).map_err(|source| WakeError::Io {
+                        path: path.clone(),
+                        source,
+                    })?;
+                    Vec::new()
+                }
+            },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(source) => return Err(WakeError::Io { path, source }),
+        };
+        Ok(Self {
+            path,
+            models: Mutex::new(models),
+            session: Mutex::new(None),
+        })
+    }
+
+    fn models(&self) -> MutexGuard<'_, Vec<WakeModel>> {
+        self.models.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn list(&self) -> Vec<WakeWordInfo> {
+        self.models().iter().map(WakeModel::info).collect()
+    }
+
+    /// Enrolls `phrase` from `recordings`, replacing any earlier enrollment
+    /// of the same phrase.
+    pub fn enroll(
+        &self,
+        phrase: &str,
+        recordings: &[&AudioBuffer],
+    ) -> Result<WakeWordInfo, WakeError> {
+        let model = enroll(phrase, recordings)?;
+        let mut models = self.models();
+        let mut updated = models.clone();
+        updated.retain(|m| !m.matches(phrase));
+        updated.push(model.clone());
+        write(&self.path, &updated)?;
+        *models = updated;
+        Ok(model.info())
+    }
+
+    pub fn delete(&self, phrase: &str) -> Result<(), WakeError> {
+        let mut models = self.models();
+        let mut updated = models.clone();
+        updated.retain(|m| !m.matches(phrase));
+        if updated.len() == models.len() {
+            return Err(WakeError::NotEnrolled(phrase.into()));
+        }
+        write(&self.path, &updated)?;
+        *models = updated;
+        Ok(())
+    }
+
+    /// Starts listening to `engine`'s frames for `config.wake_word`.
+    pub fn start(
+        &self,
+        engine: &CaptureEngine,
+        config: &WakeConfig,
+        emit: impl FnMut(WakeDetection) + Send + 'static,
+    ) -> Result<(), WakeError> {
+        let model = self
+            .models()
+            .iter()
+            .find(|m| m.matches(&config.wake_word))
+            .cloned()
+            .ok_or_else(|| WakeError::NotEnrolled(config.wake_word.clone()))?;
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        if session.is_some() {
+            return Err(WakeError::AlreadyRunning);
+        }
+        let spotter = Spotter::new(model, config.wake_word_sensitivity);
+        *session = Some(WakeSession::start(engine, spotter, emit)?);
+        Ok(())
+    }
+
+    /// Stops listening. Stopping when nothing is running is a no-op.
+    pub fn stop(&self, engine: &CaptureEngine) {
+        let session = self
+            .session
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(session) = session {
+            session.stop(engine);
+        }
+    }
+}
+
+fn write(path: &Path, models: &[WakeModel]) -> Result<(), WakeError> {
+    let json = serde_json::to_vec(models).expect("wake words always serialize");
+    write_atomic(path, &json).map_err(|source| WakeError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steady vowels, each two formant-like tones, one after another and
+    /// `stretch` times their nominal length.
+    fn say(vowels: &[(f32, f32, f64)], stretch: f64, gain: f32) -> Vec<f32> {
+        let rate = SAMPLE_RATE as f32;
+        let mut signal = Vec::new();
+        for &(f1, f2, seconds) in vowels {
+            let len = (seconds * stretch * SAMPLE_RATE as f64) as usize;
+            signal.extend((0..len).map(|i| {
+                let t = i as f32 / rate;
+                gain * (0.6 * (std::f32::consts::TAU * f1 * t).sin()
+                    + 0.3 * (std::f32::consts::TAU * f2 * t).sin())
+            }));
+        }
+        signal
+    }
+
+    fn noise(seconds: f64, seed: &mut u32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f64) as usize)
+            .map(|_| {
+                *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (*seed >> 8) as f32 / (1 << 24) as f32 * 0.004 - 0.002
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spots_the_enrolled_phrase_and_nothing_else() {
+        let phrase = [
+            (300.0, 2300.0, 0.2),
+            (700.0, 1200.0, 0.25),
+            (500.0, 900.0, 0.2),
+        ];
+        let other = [
+            (700.0, 1200.0, 0.2),
+            (300.0, 2300.0, 0.2),
+            (250.0, 600.0, 0.3),
+        ];
+        let mut seed = 7;
+        let takes: Vec<AudioBuffer> = [0.9, 1.0, 1.15]
+            .iter()
+            .map(|&stretch| {
+                let mut take = noise(0.3, &mut seed);
+                take.extend(say(&phrase, stretch, 0.5));
+                take.extend(noise(0.3, &mut seed));
+                AudioBuffer::new(SAMPLE_RATE, vec![take])
+            })
+            .collect();
+        let model = enroll("  Hey Neural ", &takes.iter().collect::<Vec<_>>()).unwrap();
+        assert_eq!(
+            model.info(),
+            WakeWordInfo {
+                phrase: "Hey Neural".into(),
+                samples: 3
+            }
+        );
+        assert!(model.matches("hey neural"));
+
+        let mut stream = noise(0.5, &mut seed);
+        stream.extend(say(&other, 1.0, 0.5));
+        stream.extend(noise(0.5, &mut seed));
+        let said_at = stream.len();
+        // Quieter and at a pace between the enrolled takes.
+        stream.extend(say(&phrase, 1.05, 0.1));
+        let ended_at = stream.len() as f64 / SAMPLE_RATE as f64;
+        stream.extend(noise(1.0, &mut seed));
+
+        let mut spotter = Spotter::new(model, 0.8);
+        let detections: Vec<WakeDetection> = stream
+            .chunks(4096)
+            .filter_map(|chunk| spotter.push(chunk))
+            .collect();
+        assert_eq!(detections.len(), 1, "{detections:?}");
+        assert!(detections[0].time > said_at as f64 / SAMPLE_RATE as f64);
+        // A steady closing vowel can match before it has fully ended.
+        assert!(
+            (detections[0].time - ended_at).abs() < 0.25,
+            "{detections:?}"
+        );
+        assert!(detections[0].confidence > 0.5);
+
+        let too_few = [&takes[0], &takes[1]];
+        assert!(matches!(
+            enroll("hey", &too_few),
+            Err(WakeError::Invalid(_))
+        ));
+    }
+}